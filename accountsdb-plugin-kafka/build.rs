@@ -0,0 +1,10 @@
+fn main() -> Result<(), std::io::Error> {
+    let proto_base_path = std::path::PathBuf::from("proto");
+    let proto = proto_base_path.join("accountsdb_event.proto");
+    println!("cargo:rerun-if-changed={}", proto.display());
+
+    tonic_build::configure()
+        .build_client(false)
+        .build_server(false)
+        .compile(&[proto], &[proto_base_path])
+}
@@ -0,0 +1,485 @@
+/// Main entry for the Kafka plugin
+use {
+    crate::{
+        event::{account_update_event, slot_status_event, transaction_event},
+        publisher::Publisher,
+    },
+    log::*,
+    serde_derive::{Deserialize, Serialize},
+    serde_json,
+    solana_accountsdb_plugin_interface::accountsdb_plugin_interface::{
+        AccountsDbPlugin, AccountsDbPluginError, ReplicaAccountInfoVersions,
+        ReplicaTransactionInfoVersions, Result, SlotStatus,
+    },
+    solana_measure::measure::Measure,
+    solana_metrics::*,
+    std::{collections::HashMap, fs::File, io::Read, time::Duration},
+    thiserror::Error,
+};
+
+const DEFAULT_SEND_TIMEOUT_MS: u64 = 1000;
+/// How long to wait for the queued events to be delivered when the plugin is unloaded
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AccountsDbPluginKafkaConfig {
+    pub kafka: HashMap<String, String>,
+    pub account_topics: Option<HashMap<String, String>>,
+    pub default_account_topic: Option<String>,
+    pub publish_startup_accounts: Option<bool>,
+    pub slot_status_topic: Option<String>,
+    pub transaction_topic: Option<String>,
+    pub include_vote_transactions: Option<bool>,
+    pub send_timeout_ms: Option<u64>,
+}
+
+#[derive(Error, Debug)]
+pub enum AccountsDbPluginKafkaError {
+    #[error("Error connecting to Kafka. Error message: ({msg})")]
+    KafkaConnectionError { msg: String },
+}
+
+/// Routes the account updates to the topics by the owners of the accounts
+#[derive(Debug, Default)]
+pub(crate) struct AccountTopicRouter {
+    topics: HashMap<Vec<u8>, String>,
+    default_topic: Option<String>,
+}
+
+impl AccountTopicRouter {
+    pub fn new(
+        topics: &HashMap<String, String>,
+        default_topic: Option<String>,
+    ) -> std::result::Result<Self, String> {
+        let topics = topics
+            .iter()
+            .map(|(owner, topic)| {
+                bs58::decode(owner)
+                    .into_vec()
+                    .map(|owner| (owner, topic.clone()))
+                    .map_err(|err| format!("Invalid owner {:?}: {}", owner, err))
+            })
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(Self {
+            topics,
+            default_topic,
+        })
+    }
+
+    /// The topic of the accounts of the owner, if they are published
+    pub fn route(&self, owner: &[u8]) -> Option<&str> {
+        self.topics
+            .get(owner)
+            .or_else(|| self.default_topic.as_ref())
+            .map(|topic| topic.as_str())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.topics.is_empty() || self.default_topic.is_some()
+    }
+}
+
+#[derive(Default)]
+pub struct AccountsDbPluginKafka {
+    publisher: Option<Publisher>,
+    account_topic_router: AccountTopicRouter,
+    publish_startup_accounts: bool,
+    slot_status_topic: Option<String>,
+    transaction_topic: Option<String>,
+    include_vote_transactions: bool,
+}
+
+impl std::fmt::Debug for AccountsDbPluginKafka {
+    fn fmt(&self, _: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Ok(())
+    }
+}
+
+impl AccountsDbPluginKafka {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn publisher(&self) -> Result<&Publisher> {
+        self.publisher.as_ref().ok_or_else(|| {
+            AccountsDbPluginError::Custom(Box::new(
+                AccountsDbPluginKafkaError::KafkaConnectionError {
+                    msg: "There is no connection to Kafka.".to_string(),
+                },
+            ))
+        })
+    }
+}
+
+impl AccountsDbPlugin for AccountsDbPluginKafka {
+    fn name(&self) -> &'static str {
+        "AccountsDbPluginKafka"
+    }
+
+    /// Do initialization for the Kafka plugin.
+    /// # Arguments
+    ///
+    /// Format of the config file:
+    /// * "kafka", the configuration of the Kafka producer, passed to librdkafka as is.
+    /// Please refer to https://github.com/edenhill/librdkafka/blob/master/CONFIGURATION.md
+    /// for the properties. "bootstrap.servers" is required.
+    /// * "account_topics", optional, maps the owner programs to the topics their account
+    /// updates are published to:
+    /// "account_topics" : {
+    ///     "Stake11111111111111111111111111111111111111" : "stake-accounts",
+    ///     "Vote111111111111111111111111111111111111111" : "vote-accounts"
+    /// }
+    /// * "default_account_topic", optional, the topic the updates of the accounts whose owner
+    /// is not in "account_topics" are published to. When not given, these updates are not
+    /// published.
+    /// * "publish_startup_accounts", optional, controls if the accounts notified when the
+    /// validator restores the AccountsDb from a snapshot are published. The default is 'false'.
+    /// * "slot_status_topic", optional, the topic the slot status updates are published to.
+    /// * "transaction_topic", optional, the topic the transactions are published to.
+    /// * "include_vote_transactions", optional, controls if the vote transactions are
+    /// published as well. The default is 'false'.
+    /// * "send_timeout_ms", optional, how long publishing an event waits for room in the
+    /// producer queue, bounded by the "queue.buffering.max.messages" Kafka property, before
+    /// failing. The default is '1000'.
+    ///
+    /// The events are encoded using the protobuf schema in `proto/accountsdb_event.proto`.
+    /// The account updates are keyed by the account pubkey, the slot status updates by the
+    /// slot in little-endian, and the transactions by the signature.
+    /// # Examples
+    ///
+    /// {
+    ///    "libpath": "/home/solana/target/release/libsolana_accountsdb_plugin_kafka.so",
+    ///    "kafka": {
+    ///       "bootstrap.servers": "localhost:9092"
+    ///    },
+    ///    "account_topics" : {
+    ///       "Stake11111111111111111111111111111111111111" : "stake-accounts"
+    ///    },
+    ///    "slot_status_topic": "slots"
+    /// }
+
+    fn on_load(&mut self, config_file: &str) -> Result<()> {
+        solana_logger::setup_with_default("info");
+        info!(
+            "Loading plugin {:?} from config_file {:?}",
+            self.name(),
+            config_file
+        );
+        let mut file = File::open(config_file)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+
+        let config: AccountsDbPluginKafkaConfig =
+            serde_json::from_str(&contents).map_err(|err| {
+                AccountsDbPluginError::ConfigFileReadError {
+                    msg: format!(
+                        "The config file is not in the JSON format expected: {:?}",
+                        err
+                    ),
+                }
+            })?;
+
+        self.account_topic_router = AccountTopicRouter::new(
+            &config.account_topics.unwrap_or_default(),
+            config.default_account_topic,
+        )
+        .map_err(|msg| AccountsDbPluginError::ConfigFileReadError { msg })?;
+        self.publish_startup_accounts = config.publish_startup_accounts.unwrap_or_default();
+        self.slot_status_topic = config.slot_status_topic;
+        self.transaction_topic = config.transaction_topic;
+        self.include_vote_transactions = config.include_vote_transactions.unwrap_or_default();
+
+        let send_timeout =
+            Duration::from_millis(config.send_timeout_ms.unwrap_or(DEFAULT_SEND_TIMEOUT_MS));
+        let publisher = Publisher::new(&config.kafka, send_timeout).map_err(|err| {
+            AccountsDbPluginError::Custom(Box::new(
+                AccountsDbPluginKafkaError::KafkaConnectionError {
+                    msg: format!("Error in creating the Kafka producer: {:?}", err),
+                },
+            ))
+        })?;
+        self.publisher = Some(publisher);
+        Ok(())
+    }
+
+    fn on_unload(&mut self) {
+        info!("Unloading plugin: {:?}", self.name());
+
+        if let Some(publisher) = self.publisher.take() {
+            if let Err(err) = publisher.flush(FLUSH_TIMEOUT) {
+                error!("Failed to deliver the queued events to Kafka: ({})", err);
+            }
+        }
+    }
+
+    fn update_account(
+        &mut self,
+        account: ReplicaAccountInfoVersions,
+        slot: u64,
+        is_startup: bool,
+    ) -> Result<()> {
+        if is_startup && !self.publish_startup_accounts {
+            return Ok(());
+        }
+        let mut measure = Measure::start("accountsdb-plugin-kafka-update-account");
+        match account {
+            ReplicaAccountInfoVersions::V0_0_1(account) => {
+                let topic = match self.account_topic_router.route(account.owner) {
+                    Some(topic) => topic,
+                    None => return Ok(()),
+                };
+                let event = account_update_event(account, slot, is_startup);
+                self.publisher()?
+                    .publish(topic, account.pubkey, &event)
+                    .map_err(|err| AccountsDbPluginError::AccountsUpdateError {
+                        msg: format!(
+                            "Failed to publish the update of account to Kafka. Error: {:?}",
+                            err
+                        ),
+                    })?;
+            }
+        }
+        measure.stop();
+        inc_new_counter_debug!(
+            "accountsdb-plugin-kafka-update-account-us",
+            measure.as_us() as usize,
+            100000,
+            100000
+        );
+        Ok(())
+    }
+
+    fn update_slot_status(
+        &mut self,
+        slot: u64,
+        parent: Option<u64>,
+        status: SlotStatus,
+    ) -> Result<()> {
+        let topic = match &self.slot_status_topic {
+            Some(topic) => topic,
+            None => return Ok(()),
+        };
+        let event = slot_status_event(slot, parent, &status);
+        self.publisher()?
+            .publish(topic, &slot.to_le_bytes(), &event)
+            .map_err(|err| AccountsDbPluginError::SlotStatusUpdateError {
+                msg: format!(
+                    "Failed to publish the update of slot to Kafka. Error: {:?}",
+                    err
+                ),
+            })
+    }
+
+    fn notify_transaction(
+        &mut self,
+        transaction_info: ReplicaTransactionInfoVersions,
+        slot: u64,
+    ) -> Result<()> {
+        let topic = match &self.transaction_topic {
+            Some(topic) => topic,
+            None => return Ok(()),
+        };
+        match transaction_info {
+            ReplicaTransactionInfoVersions::V0_0_1(transaction_info) => {
+                if transaction_info.is_vote && !self.include_vote_transactions {
+                    return Ok(());
+                }
+                let event = transaction_event(transaction_info, slot);
+                self.publisher()?
+                    .publish(topic, transaction_info.signature.as_ref(), &event)
+                    .map_err(|err| AccountsDbPluginError::TransactionUpdateError {
+                        msg: format!(
+                            "Failed to publish the transaction to Kafka. Error: {:?}",
+                            err
+                        ),
+                    })
+            }
+        }
+    }
+
+    /// Check if the plugin is interested in account data
+    fn to_notify_account_data(&self) -> bool {
+        self.account_topic_router.is_enabled()
+    }
+
+    /// Check if the plugin is interested in transaction data
+    fn transaction_notifications_enabled(&self) -> bool {
+        self.transaction_topic.is_some()
+    }
+}
+
+#[no_mangle]
+#[allow(improper_ctypes_definitions)]
+/// # Safety
+///
+/// This function returns the AccountsDbPluginKafka pointer as trait AccountsDbPlugin.
+pub unsafe extern "C" fn _create_plugin() -> *mut dyn AccountsDbPlugin {
+    let plugin = AccountsDbPluginKafka::new();
+    let plugin: Box<dyn AccountsDbPlugin> = Box::new(plugin);
+    Box::into_raw(plugin)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use {
+        super::*,
+        crate::event::generated,
+        prost::Message as _,
+        rdkafka::{
+            config::ClientConfig,
+            consumer::{BaseConsumer, Consumer},
+            mocking::MockCluster,
+            Message, Offset, TopicPartitionList,
+        },
+        solana_accountsdb_plugin_interface::accountsdb_plugin_interface::ReplicaAccountInfo,
+        solana_sdk::pubkey::Pubkey,
+        std::{io::Write, time::Instant},
+    };
+
+    fn new_account<'a>(pubkey: &'a Pubkey, owner: &'a Pubkey) -> ReplicaAccountInfo<'a> {
+        ReplicaAccountInfo {
+            pubkey: pubkey.as_ref(),
+            lamports: 10,
+            owner: owner.as_ref(),
+            executable: false,
+            rent_epoch: 0,
+            data: &[1, 2, 3],
+            write_version: 1,
+        }
+    }
+
+    #[test]
+    fn test_account_topic_router() {
+        let owner = Pubkey::new_unique();
+        let topics = HashMap::from([(owner.to_string(), "owner-accounts".to_string())]);
+
+        let router = AccountTopicRouter::new(&topics, None).unwrap();
+        assert!(router.is_enabled());
+        assert_eq!(router.route(owner.as_ref()), Some("owner-accounts"));
+        assert_eq!(router.route(Pubkey::new_unique().as_ref()), None);
+
+        let router = AccountTopicRouter::new(&topics, Some("accounts".to_string())).unwrap();
+        assert_eq!(router.route(owner.as_ref()), Some("owner-accounts"));
+        assert_eq!(
+            router.route(Pubkey::new_unique().as_ref()),
+            Some("accounts")
+        );
+
+        assert!(!AccountTopicRouter::default().is_enabled());
+        let topics = HashMap::from([("invalid-owner".to_string(), "accounts".to_string())]);
+        assert!(AccountTopicRouter::new(&topics, None).is_err());
+    }
+
+    #[test]
+    fn test_publish_to_mock_broker() {
+        let cluster = MockCluster::new(1).unwrap();
+        for topic in ["owner-accounts", "accounts", "slots"] {
+            cluster.create_topic(topic, 1, 1).unwrap();
+        }
+
+        let owner = Pubkey::new_unique();
+        let account_topics = HashMap::from([(owner.to_string(), "owner-accounts")]);
+        let config = serde_json::json!({
+            "libpath": "",
+            "kafka": {
+                "bootstrap.servers": cluster.bootstrap_servers(),
+            },
+            "account_topics": account_topics,
+            "default_account_topic": "accounts",
+            "slot_status_topic": "slots",
+        });
+        let mut config_file = tempfile::NamedTempFile::new().unwrap();
+        write!(config_file, "{}", config).unwrap();
+
+        let mut plugin = AccountsDbPluginKafka::new();
+        plugin
+            .on_load(config_file.path().to_str().unwrap())
+            .unwrap();
+        assert!(plugin.to_notify_account_data());
+        assert!(!plugin.transaction_notifications_enabled());
+
+        let owned_pubkey = Pubkey::new_unique();
+        let other_owner = Pubkey::new_unique();
+        let other_pubkey = Pubkey::new_unique();
+
+        // Startup accounts are not published by default
+        plugin
+            .update_account(
+                ReplicaAccountInfoVersions::V0_0_1(&new_account(&owned_pubkey, &owner)),
+                1,
+                true,
+            )
+            .unwrap();
+        plugin
+            .update_account(
+                ReplicaAccountInfoVersions::V0_0_1(&new_account(&owned_pubkey, &owner)),
+                5,
+                false,
+            )
+            .unwrap();
+        plugin
+            .update_account(
+                ReplicaAccountInfoVersions::V0_0_1(&new_account(&other_pubkey, &other_owner)),
+                5,
+                false,
+            )
+            .unwrap();
+        plugin
+            .update_slot_status(5, Some(4), SlotStatus::Confirmed)
+            .unwrap();
+        plugin.on_unload();
+
+        let consumer: BaseConsumer = ClientConfig::new()
+            .set("bootstrap.servers", cluster.bootstrap_servers())
+            .set("group.id", "test")
+            .create()
+            .unwrap();
+        let mut partitions = TopicPartitionList::new();
+        for topic in ["owner-accounts", "accounts", "slots"] {
+            partitions
+                .add_partition_offset(topic, 0, Offset::Beginning)
+                .unwrap();
+        }
+        consumer.assign(&partitions).unwrap();
+
+        let mut messages: HashMap<String, Vec<(Vec<u8>, Vec<u8>)>> = HashMap::default();
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while messages.values().map(Vec::len).sum::<usize>() < 3 && Instant::now() < deadline {
+            if let Some(message) = consumer.poll(Duration::from_millis(100)) {
+                let message = message.unwrap();
+                messages
+                    .entry(message.topic().to_string())
+                    .or_default()
+                    .push((
+                        message.key().unwrap().to_vec(),
+                        message.payload().unwrap().to_vec(),
+                    ));
+            }
+        }
+
+        let owner_accounts = &messages["owner-accounts"];
+        assert_eq!(owner_accounts.len(), 1);
+        assert_eq!(owner_accounts[0].0, owned_pubkey.to_bytes().to_vec());
+        let event = generated::AccountUpdate::decode(owner_accounts[0].1.as_slice()).unwrap();
+        assert_eq!(event.owner, owner.to_bytes().to_vec());
+        assert_eq!(event.slot, 5);
+        assert!(!event.is_startup);
+
+        let accounts = &messages["accounts"];
+        assert_eq!(accounts.len(), 1);
+        let event = generated::AccountUpdate::decode(accounts[0].1.as_slice()).unwrap();
+        assert_eq!(event.pubkey, other_pubkey.to_bytes().to_vec());
+        assert_eq!(event.data, vec![1, 2, 3]);
+
+        let slots = &messages["slots"];
+        assert_eq!(slots.len(), 1);
+        assert_eq!(slots[0].0, 5u64.to_le_bytes().to_vec());
+        let event = generated::SlotStatusUpdate::decode(slots[0].1.as_slice()).unwrap();
+        assert_eq!(event.slot, 5);
+        assert_eq!(event.parent, Some(generated::ParentSlot { slot: 4 }));
+        assert_eq!(
+            generated::SlotStatus::from_i32(event.status),
+            Some(generated::SlotStatus::Confirmed)
+        );
+    }
+}
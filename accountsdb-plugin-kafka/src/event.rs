@@ -0,0 +1,123 @@
+/// Module responsible for converting the AccountsDb notifications into the protobuf
+/// events published to Kafka. The schema is in `proto/accountsdb_event.proto`.
+use solana_accountsdb_plugin_interface::accountsdb_plugin_interface::{
+    ReplicaAccountInfo, ReplicaTransactionInfo, SlotStatus,
+};
+
+pub mod generated {
+    include!(concat!(env!("OUT_DIR"), "/solana.accountsdb.event.rs"));
+}
+
+pub fn account_update_event(
+    account: &ReplicaAccountInfo,
+    slot: u64,
+    is_startup: bool,
+) -> generated::AccountUpdate {
+    generated::AccountUpdate {
+        pubkey: account.pubkey.to_vec(),
+        lamports: account.lamports,
+        owner: account.owner.to_vec(),
+        executable: account.executable,
+        rent_epoch: account.rent_epoch,
+        data: account.data.to_vec(),
+        write_version: account.write_version,
+        slot,
+        is_startup,
+    }
+}
+
+impl From<&SlotStatus> for generated::SlotStatus {
+    fn from(status: &SlotStatus) -> Self {
+        match status {
+            SlotStatus::Processed => generated::SlotStatus::Processed,
+            SlotStatus::Confirmed => generated::SlotStatus::Confirmed,
+            SlotStatus::Rooted => generated::SlotStatus::Rooted,
+        }
+    }
+}
+
+pub fn slot_status_event(
+    slot: u64,
+    parent: Option<u64>,
+    status: &SlotStatus,
+) -> generated::SlotStatusUpdate {
+    generated::SlotStatusUpdate {
+        slot,
+        parent: parent.map(|slot| generated::ParentSlot { slot }),
+        status: generated::SlotStatus::from(status) as i32,
+    }
+}
+
+pub fn transaction_event(
+    transaction_info: &ReplicaTransactionInfo,
+    slot: u64,
+) -> generated::TransactionUpdate {
+    let transaction = transaction_info.transaction;
+    let meta = transaction_info.transaction_status_meta;
+    generated::TransactionUpdate {
+        signature: transaction_info.signature.as_ref().to_vec(),
+        is_vote: transaction_info.is_vote,
+        slot,
+        message_hash: transaction.message_hash().as_ref().to_vec(),
+        signatures: transaction
+            .signatures()
+            .iter()
+            .map(|signature| signature.as_ref().to_vec())
+            .collect(),
+        account_keys: transaction
+            .message()
+            .account_keys_iter()
+            .map(|key| key.as_ref().to_vec())
+            .collect(),
+        fee: meta.fee,
+        err: meta
+            .status
+            .as_ref()
+            .err()
+            .map(|err| generated::TransactionError {
+                err: err.to_string(),
+            }),
+        pre_balances: meta.pre_balances.clone(),
+        post_balances: meta.post_balances.clone(),
+        log_messages: meta.log_messages.clone().unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use {super::*, prost::Message, solana_sdk::pubkey::Pubkey};
+
+    #[test]
+    fn test_event_encoding() {
+        let pubkey = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let account = ReplicaAccountInfo {
+            pubkey: pubkey.as_ref(),
+            lamports: 42,
+            owner: owner.as_ref(),
+            executable: false,
+            rent_epoch: 3,
+            data: &[1, 2, 3],
+            write_version: 7,
+        };
+        let event = account_update_event(&account, 11, true);
+        let decoded = generated::AccountUpdate::decode(event.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded, event);
+        assert_eq!(decoded.pubkey, pubkey.to_bytes().to_vec());
+        assert_eq!(decoded.slot, 11);
+        assert!(decoded.is_startup);
+
+        let event = slot_status_event(12, Some(11), &SlotStatus::Rooted);
+        let decoded =
+            generated::SlotStatusUpdate::decode(event.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded.parent, Some(generated::ParentSlot { slot: 11 }));
+        assert_eq!(
+            generated::SlotStatus::from_i32(decoded.status),
+            Some(generated::SlotStatus::Rooted)
+        );
+        assert_eq!(
+            slot_status_event(12, None, &SlotStatus::Processed).parent,
+            None
+        );
+    }
+}
@@ -0,0 +1,3 @@
+pub mod accountsdb_plugin_kafka;
+pub mod event;
+pub mod publisher;
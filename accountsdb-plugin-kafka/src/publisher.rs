@@ -0,0 +1,94 @@
+/// Module responsible for publishing the encoded events to Kafka.
+use {
+    log::*,
+    rdkafka::{
+        config::ClientConfig,
+        error::{KafkaError, KafkaResult, RDKafkaErrorCode},
+        message::Message,
+        producer::{BaseRecord, DeliveryResult, Producer, ProducerContext, ThreadedProducer},
+        ClientContext,
+    },
+    solana_metrics::*,
+    std::{
+        collections::HashMap,
+        thread::sleep,
+        time::{Duration, Instant},
+    },
+};
+
+/// How long to wait before retrying when the queue of the producer is full
+const QUEUE_FULL_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+pub struct PublisherContext;
+
+impl ClientContext for PublisherContext {}
+
+impl ProducerContext for PublisherContext {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, delivery_result: &DeliveryResult, _: Self::DeliveryOpaque) {
+        if let Err((err, message)) = delivery_result {
+            error!(
+                "Failed to deliver the event to the topic {}: ({})",
+                message.topic(),
+                err
+            );
+            inc_new_counter_error!("accountsdb-plugin-kafka-delivery-errors", 1);
+        }
+    }
+}
+
+pub struct Publisher {
+    producer: ThreadedProducer<PublisherContext>,
+    /// How long publishing an event waits for room in the queue of the producer before
+    /// failing
+    send_timeout: Duration,
+}
+
+impl Publisher {
+    /// `producer_config` is passed to librdkafka as is, e.g. "bootstrap.servers".
+    pub fn new(
+        producer_config: &HashMap<String, String>,
+        send_timeout: Duration,
+    ) -> KafkaResult<Self> {
+        let mut config = ClientConfig::new();
+        for (key, value) in producer_config {
+            config.set(key, value);
+        }
+        let producer = config.create_with_context(PublisherContext)?;
+        Ok(Self {
+            producer,
+            send_timeout,
+        })
+    }
+
+    /// Queue the event to be sent to the topic. The delivery happens in the background, the
+    /// delivery failures are only logged.
+    pub fn publish<M: prost::Message>(
+        &self,
+        topic: &str,
+        key: &[u8],
+        event: &M,
+    ) -> KafkaResult<()> {
+        let payload = event.encode_to_vec();
+        let mut record = BaseRecord::to(topic).key(key).payload(&payload);
+        let deadline = Instant::now() + self.send_timeout;
+        loop {
+            match self.producer.send(record) {
+                Ok(()) => return Ok(()),
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), returned))
+                    if Instant::now() < deadline =>
+                {
+                    record = returned;
+                    sleep(QUEUE_FULL_RETRY_INTERVAL);
+                }
+                Err((err, _)) => return Err(err),
+            }
+        }
+    }
+
+    /// Wait for the queued events to be delivered
+    pub fn flush(&self, timeout: Duration) -> KafkaResult<()> {
+        self.producer.flush(timeout)
+    }
+}
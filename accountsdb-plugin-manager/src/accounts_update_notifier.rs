@@ -1,6 +1,6 @@
 /// Module responsible for notifying plugins of account updates
 use {
-    crate::accountsdb_plugin_manager::AccountsDbPluginManager,
+    crate::{accountsdb_plugin_manager::AccountsDbPluginManager, plugin_monitor::PluginCallback},
     log::*,
     solana_accountsdb_plugin_interface::accountsdb_plugin_interface::{
         ReplicaAccountInfo, ReplicaAccountInfoVersions,
@@ -14,9 +14,32 @@ use {
     solana_sdk::{
         account::{AccountSharedData, ReadableAccount},
         clock::Slot,
+        pubkey::Pubkey,
     },
     std::sync::{Arc, RwLock},
 };
+
+/// A copy of an updated account, the plugins are notified from their worker threads
+struct AccountUpdate {
+    pubkey: Pubkey,
+    account: AccountSharedData,
+    write_version: u64,
+}
+
+impl AccountUpdate {
+    fn replica_account_info(&self) -> ReplicaAccountInfo {
+        ReplicaAccountInfo {
+            pubkey: self.pubkey.as_ref(),
+            lamports: self.account.lamports(),
+            owner: self.account.owner().as_ref(),
+            executable: self.account.executable(),
+            rent_epoch: self.account.rent_epoch(),
+            data: self.account.data(),
+            write_version: self.write_version,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct AccountsUpdateNotifierImpl {
     plugin_manager: Arc<RwLock<AccountsDbPluginManager>>,
@@ -24,12 +47,21 @@ pub(crate) struct AccountsUpdateNotifierImpl {
 
 impl AccountsUpdateNotifierInterface for AccountsUpdateNotifierImpl {
     fn notify_account_update(&self, slot: Slot, meta: &StoredMeta, account: &AccountSharedData) {
+        let plugin_manager = self.plugin_manager.read().unwrap();
+        if plugin_manager.is_empty() {
+            return;
+        }
         if let Some(account_info) = self.accountinfo_from_shared_account_data(meta, account) {
-            self.notify_plugins_of_account_update(account_info, slot, false);
+            Self::notify_plugins_of_account_update(&plugin_manager, account_info, slot, false);
         }
     }
 
     fn notify_account_restore_from_snapshot(&self, slot: Slot, account: &StoredAccountMeta) {
+        let plugin_manager = self.plugin_manager.read().unwrap();
+        // The account is only copied when there is a plugin to notify
+        if plugin_manager.is_empty() {
+            return;
+        }
         let mut measure_all = Measure::start("accountsdb-plugin-notify-account-restore-all");
         let mut measure_copy = Measure::start("accountsdb-plugin-copy-stored-account-info");
 
@@ -44,7 +76,7 @@ impl AccountsUpdateNotifierInterface for AccountsUpdateNotifierImpl {
         );
 
         if let Some(account_info) = account {
-            self.notify_plugins_of_account_update(account_info, slot, true);
+            Self::notify_plugins_of_account_update(&plugin_manager, account_info, slot, true);
         }
         measure_all.stop();
 
@@ -57,14 +89,15 @@ impl AccountsUpdateNotifierInterface for AccountsUpdateNotifierImpl {
     }
 
     fn notify_end_of_restore_from_snapshot(&self) {
-        let mut plugin_manager = self.plugin_manager.write().unwrap();
-        if plugin_manager.plugins.is_empty() {
+        let plugin_manager = self.plugin_manager.read().unwrap();
+        if plugin_manager.is_empty() {
             return;
         }
 
-        for plugin in plugin_manager.plugins.iter_mut() {
+        plugin_manager.notify_plugins(PluginCallback::NotifyEndOfStartup, 0, |plugin| {
             let mut measure = Measure::start("accountsdb-plugin-end-of-restore-from-snapshot");
-            match plugin.notify_end_of_startup() {
+            let result = plugin.notify_end_of_startup();
+            match &result {
                 Err(err) => {
                     error!(
                         "Failed to notify the end of restore from snapshot, error: {} to plugin {}",
@@ -84,7 +117,8 @@ impl AccountsUpdateNotifierInterface for AccountsUpdateNotifierImpl {
                 "accountsdb-plugin-end-of-restore-from-snapshot",
                 measure.as_us() as usize
             );
-        }
+            Some(result)
+        });
     }
 }
 
@@ -93,56 +127,47 @@ impl AccountsUpdateNotifierImpl {
         AccountsUpdateNotifierImpl { plugin_manager }
     }
 
-    fn accountinfo_from_shared_account_data<'a>(
+    fn accountinfo_from_shared_account_data(
         &self,
-        meta: &'a StoredMeta,
-        account: &'a AccountSharedData,
-    ) -> Option<ReplicaAccountInfo<'a>> {
-        Some(ReplicaAccountInfo {
-            pubkey: meta.pubkey.as_ref(),
-            lamports: account.lamports(),
-            owner: account.owner().as_ref(),
-            executable: account.executable(),
-            rent_epoch: account.rent_epoch(),
-            data: account.data(),
+        meta: &StoredMeta,
+        account: &AccountSharedData,
+    ) -> Option<AccountUpdate> {
+        Some(AccountUpdate {
+            pubkey: meta.pubkey,
+            // Shares the account data
+            account: account.clone(),
             write_version: meta.write_version,
         })
     }
 
-    fn accountinfo_from_stored_account_meta<'a>(
+    fn accountinfo_from_stored_account_meta(
         &self,
-        stored_account_meta: &'a StoredAccountMeta,
-    ) -> Option<ReplicaAccountInfo<'a>> {
-        Some(ReplicaAccountInfo {
-            pubkey: stored_account_meta.meta.pubkey.as_ref(),
-            lamports: stored_account_meta.account_meta.lamports,
-            owner: stored_account_meta.account_meta.owner.as_ref(),
-            executable: stored_account_meta.account_meta.executable,
-            rent_epoch: stored_account_meta.account_meta.rent_epoch,
-            data: stored_account_meta.data,
+        stored_account_meta: &StoredAccountMeta,
+    ) -> Option<AccountUpdate> {
+        Some(AccountUpdate {
+            pubkey: stored_account_meta.meta.pubkey,
+            account: stored_account_meta.clone_account(),
             write_version: stored_account_meta.meta.write_version,
         })
     }
 
     fn notify_plugins_of_account_update(
-        &self,
-        account: ReplicaAccountInfo,
+        plugin_manager: &AccountsDbPluginManager,
+        account_update: AccountUpdate,
         slot: Slot,
         is_startup: bool,
     ) {
         let mut measure2 = Measure::start("accountsdb-plugin-notify_plugins_of_account_update");
-        let mut plugin_manager = self.plugin_manager.write().unwrap();
-
-        if plugin_manager.plugins.is_empty() {
-            return;
-        }
-        for plugin in plugin_manager.plugins.iter_mut() {
+        let size = account_update.account.data().len();
+        plugin_manager.notify_plugins(PluginCallback::UpdateAccount, size, move |plugin| {
             let mut measure = Measure::start("accountsdb-plugin-update-account");
-            match plugin.update_account(
+            let account = account_update.replica_account_info();
+            let result = plugin.update_account(
                 ReplicaAccountInfoVersions::V0_0_1(&account),
                 slot,
                 is_startup,
-            ) {
+            );
+            match &result {
                 Err(err) => {
                     error!(
                         "Failed to update account {} at slot {}, error: {} to plugin {}",
@@ -168,7 +193,8 @@ impl AccountsUpdateNotifierImpl {
                 100000,
                 100000
             );
-        }
+            Some(result)
+        });
         measure2.stop();
         inc_new_counter_debug!(
             "accountsdb-plugin-notify_plugins_of_account_update-us",
@@ -1,22 +1,68 @@
 /// Managing the AccountsDb plugins
 use {
+    crate::plugin_monitor::{PluginCallback, PluginTask, PluginWatchdogConfig, PluginWorker},
     libloading::{Library, Symbol},
     log::*,
-    solana_accountsdb_plugin_interface::accountsdb_plugin_interface::AccountsDbPlugin,
-    std::error::Error,
+    serde_derive::{Deserialize, Serialize},
+    solana_accountsdb_plugin_interface::accountsdb_plugin_interface::{
+        AccountsDbPlugin, Result as PluginResult,
+    },
+    solana_metrics::*,
+    std::{
+        error::Error,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    },
 };
 
+/// The name and config file of a loaded plugin.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LoadedPluginInfo {
+    pub name: String,
+    pub config_file: String,
+}
+
+/// A loaded plugin, called from its worker thread. Built-in plugins have no library.
+#[derive(Debug)]
+struct LoadedPlugin {
+    name: String,
+    config_file: String,
+    to_notify_account_data: bool,
+    transaction_notifications_enabled: bool,
+    worker: PluginWorker,
+    /// Set when the plugin is disabled while notifying the plugins, it is removed the next
+    /// time the plugins are changed
+    disabled: AtomicBool,
+}
+
+impl LoadedPlugin {
+    fn new(plugin: Box<dyn AccountsDbPlugin>, config_file: &str, lib: Option<Library>) -> Self {
+        Self {
+            name: plugin.name().to_string(),
+            config_file: config_file.to_string(),
+            to_notify_account_data: plugin.to_notify_account_data(),
+            transaction_notifications_enabled: plugin.transaction_notifications_enabled(),
+            worker: PluginWorker::new(plugin, lib),
+            disabled: AtomicBool::new(false),
+        }
+    }
+
+    fn is_disabled(&self) -> bool {
+        self.disabled.load(Ordering::Relaxed)
+    }
+}
+
 #[derive(Default, Debug)]
 pub struct AccountsDbPluginManager {
-    pub plugins: Vec<Box<dyn AccountsDbPlugin>>,
-    libs: Vec<Library>,
+    plugins: Vec<LoadedPlugin>,
 }
 
 impl AccountsDbPluginManager {
     pub fn new() -> Self {
         AccountsDbPluginManager {
             plugins: Vec::default(),
-            libs: Vec::default(),
         }
     }
 
@@ -29,37 +75,503 @@ impl AccountsDbPluginManager {
         libpath: &str,
         config_file: &str,
     ) -> Result<(), Box<dyn Error>> {
+        let (plugin, lib) = Self::open_plugin_library(libpath)?;
+        self.add_plugin(plugin, config_file, Some(lib))
+    }
+
+    /// Create the plugin of the dynamically linked library specified in the path, without
+    /// loading it yet.
+    ///
+    /// # Safety
+    ///
+    /// The library must do necessary initializations, and outlive the plugin.
+    pub unsafe fn open_plugin_library(
+        libpath: &str,
+    ) -> Result<(Box<dyn AccountsDbPlugin>, Library), Box<dyn Error>> {
         type PluginConstructor = unsafe fn() -> *mut dyn AccountsDbPlugin;
         let lib = Library::new(libpath)?;
         let constructor: Symbol<PluginConstructor> = lib.get(b"_create_plugin")?;
         let plugin_raw = constructor();
-        let mut plugin = Box::from_raw(plugin_raw);
+        let plugin = Box::from_raw(plugin_raw);
+        Ok((plugin, lib))
+    }
+
+    /// Load a plugin compiled into the validator, see the `unix_socket_plugin` module.
+    pub fn load_builtin_plugin(
+        &mut self,
+        plugin: Box<dyn AccountsDbPlugin>,
+        config_file: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        self.add_plugin(plugin, config_file, None)
+    }
+
+    /// Load the plugin, created from `lib` if it is not built-in, and add it to the plugins
+    /// to notify.
+    pub fn add_plugin(
+        &mut self,
+        mut plugin: Box<dyn AccountsDbPlugin>,
+        config_file: &str,
+        lib: Option<Library>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.remove_disabled_plugins();
+        // Plugins are addressed by name when they are unloaded or reloaded
+        if self.find_plugin(plugin.name()).is_some() {
+            return Err(format!("The plugin {:?} is already loaded", plugin.name()).into());
+        }
         plugin.on_load(config_file)?;
-        self.plugins.push(plugin);
-        self.libs.push(lib);
+        self.plugins
+            .push(LoadedPlugin::new(plugin, config_file, lib));
+        Ok(())
+    }
+
+    fn find_plugin(&self, name: &str) -> Option<usize> {
+        self.plugins
+            .iter()
+            .position(|plugin| plugin.name == name && !plugin.is_disabled())
+    }
+
+    /// Drop the disabled plugins, their workers unload them once their running callbacks
+    /// return, if they ever do.
+    fn remove_disabled_plugins(&mut self) {
+        self.plugins.retain(|plugin| !plugin.is_disabled());
+    }
+
+    /// Check if there is no plugin loaded
+    pub fn is_empty(&self) -> bool {
+        self.plugins.iter().all(LoadedPlugin::is_disabled)
+    }
+
+    /// Set the watchdog of the plugin with the given name, see the `plugin_monitor` module.
+    pub fn set_plugin_watchdog(
+        &mut self,
+        name: &str,
+        watchdog: Option<PluginWatchdogConfig>,
+    ) -> Result<(), Box<dyn Error>> {
+        let index = self
+            .find_plugin(name)
+            .ok_or_else(|| format!("The plugin {:?} is not loaded", name))?;
+        self.plugins[index].worker.set_watchdog(watchdog);
         Ok(())
     }
 
+    /// Queue `notify` for every plugin, to be called from the worker thread of the plugin which
+    /// measures how long the plugin takes and counts the errors it returns. `size` is the size
+    /// of the data captured by `notify`, in bytes, which is held in the queues of the plugins.
+    /// `notify` returns None when it skips the plugin. The plugins detached by the watchdog, or
+    /// blowing their deadline, are disabled: they are not notified any more. Only a read lock of
+    /// the manager is needed, so the notifiers do not wait on each other.
+    pub fn notify_plugins<F>(&self, callback: PluginCallback, size: usize, notify: F)
+    where
+        F: Fn(&mut Box<dyn AccountsDbPlugin>) -> Option<PluginResult<()>> + Send + Sync + 'static,
+    {
+        let size = size + std::mem::size_of_val(&notify);
+        let task: PluginTask = Arc::new(notify);
+        for plugin in &self.plugins {
+            if plugin.is_disabled() || plugin.worker.notify(callback, size, task.clone()) {
+                continue;
+            }
+            // Another notifier may have disabled the plugin in the meantime
+            if plugin.disabled.swap(true, Ordering::Relaxed) {
+                continue;
+            }
+            error!(
+                "Disabling plugin {:?} detached by the watchdog or exceeding its deadline",
+                plugin.name
+            );
+            datapoint_error!(
+                "accountsdb-plugin-disabled",
+                ("plugin", plugin.name.clone(), String),
+                ("callback", callback.as_str().to_string(), String),
+            );
+            plugin.worker.disable();
+        }
+    }
+
+    /// List the loaded plugins in the order they are notified
+    pub fn list_plugins(&self) -> Vec<LoadedPluginInfo> {
+        self.plugins
+            .iter()
+            .filter(|plugin| !plugin.is_disabled())
+            .map(|plugin| LoadedPluginInfo {
+                name: plugin.name.clone(),
+                config_file: plugin.config_file.clone(),
+            })
+            .collect()
+    }
+
+    /// Unload the plugin with the given name, firing its `on_plugin_unload()` method once the
+    /// notifications queued for it are delivered. Returns the config file the plugin was loaded
+    /// with.
+    pub fn unload_plugin(&mut self, name: &str) -> Result<String, Box<dyn Error>> {
+        self.remove_disabled_plugins();
+        let index = self
+            .find_plugin(name)
+            .ok_or_else(|| format!("The plugin {:?} is not loaded", name))?;
+        let plugin = self.plugins.remove(index);
+        plugin.worker.shutdown();
+        Ok(plugin.config_file)
+    }
+
+    /// Load `plugin` in place of the plugin with the given name, which is only unloaded once
+    /// `plugin` is loaded successfully. Returns the config file the replaced plugin was loaded
+    /// with.
+    pub fn replace_plugin(
+        &mut self,
+        name: &str,
+        mut plugin: Box<dyn AccountsDbPlugin>,
+        config_file: &str,
+        lib: Option<Library>,
+    ) -> Result<String, Box<dyn Error>> {
+        self.remove_disabled_plugins();
+        let index = self
+            .find_plugin(name)
+            .ok_or_else(|| format!("The plugin {:?} is not loaded", name))?;
+        if plugin.name() != name && self.find_plugin(plugin.name()).is_some() {
+            return Err(format!("The plugin {:?} is already loaded", plugin.name()).into());
+        }
+        plugin.on_load(config_file)?;
+
+        let old_plugin = std::mem::replace(
+            &mut self.plugins[index],
+            LoadedPlugin::new(plugin, config_file, lib),
+        );
+        info!("Unloading replaced plugin for {:?}", old_plugin.name);
+        old_plugin.worker.shutdown();
+        Ok(old_plugin.config_file)
+    }
+
     /// Unload all plugins and loaded plugin libraries, making sure to fire
     /// their `on_plugin_unload()` methods so they can do any necessary cleanup.
     pub fn unload(&mut self) {
-        for mut plugin in self.plugins.drain(..) {
-            info!("Unloading plugin for {:?}", plugin.name());
-            plugin.on_unload();
-        }
-
-        for lib in self.libs.drain(..) {
-            drop(lib);
+        self.remove_disabled_plugins();
+        for plugin in self.plugins.drain(..) {
+            plugin.worker.shutdown();
         }
     }
 
     /// Check if there is any plugin interested in account data
     pub fn to_notify_account_data(&self) -> bool {
-        for plugin in &self.plugins {
-            if plugin.to_notify_account_data() {
-                return true;
+        self.plugins
+            .iter()
+            .any(|plugin| plugin.to_notify_account_data && !plugin.is_disabled())
+    }
+
+    /// Check if there is any plugin interested in transaction data
+    pub fn transaction_notifications_enabled(&self) -> bool {
+        self.plugins
+            .iter()
+            .any(|plugin| plugin.transaction_notifications_enabled && !plugin.is_disabled())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use {
+        super::*,
+        crate::plugin_monitor::{SlowPluginAction, PLUGIN_QUEUE_CAPACITY_BYTES},
+        crossbeam_channel::unbounded,
+        solana_accountsdb_plugin_interface::accountsdb_plugin_interface::{
+            AccountsDbPluginError, Result,
+        },
+        std::{
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Arc, Mutex,
+            },
+            thread::{self, sleep},
+            time::{Duration, Instant},
+        },
+    };
+
+    #[derive(Debug)]
+    struct TestPlugin {
+        name: &'static str,
+        unloads: Arc<AtomicUsize>,
+    }
+
+    impl AccountsDbPlugin for TestPlugin {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn on_load(&mut self, config_file: &str) -> Result<()> {
+            if config_file.is_empty() {
+                return Err(AccountsDbPluginError::ConfigFileReadError {
+                    msg: "empty config file".to_string(),
+                });
+            }
+            Ok(())
+        }
+
+        fn on_unload(&mut self) {
+            self.unloads.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_load_list_unload_plugins() {
+        let unloads = Arc::new(AtomicUsize::default());
+        let new_plugin = |name| {
+            Box::new(TestPlugin {
+                name,
+                unloads: unloads.clone(),
+            })
+        };
+        let mut plugin_manager = AccountsDbPluginManager::new();
+
+        plugin_manager
+            .load_builtin_plugin(new_plugin("plugin-a"), "a.json")
+            .unwrap();
+        plugin_manager
+            .load_builtin_plugin(new_plugin("plugin-b"), "b.json")
+            .unwrap();
+        // Names must be unique
+        assert!(plugin_manager
+            .load_builtin_plugin(new_plugin("plugin-a"), "c.json")
+            .is_err());
+        // A plugin failing on_load is not added
+        assert!(plugin_manager
+            .load_builtin_plugin(new_plugin("plugin-c"), "")
+            .is_err());
+        assert_eq!(
+            plugin_manager.list_plugins(),
+            vec![
+                LoadedPluginInfo {
+                    name: "plugin-a".to_string(),
+                    config_file: "a.json".to_string(),
+                },
+                LoadedPluginInfo {
+                    name: "plugin-b".to_string(),
+                    config_file: "b.json".to_string(),
+                },
+            ]
+        );
+
+        assert_eq!(plugin_manager.unload_plugin("plugin-a").unwrap(), "a.json");
+        assert_eq!(unloads.load(Ordering::Relaxed), 1);
+        assert!(plugin_manager.unload_plugin("plugin-a").is_err());
+        assert_eq!(plugin_manager.list_plugins().len(), 1);
+        assert_eq!(plugin_manager.plugins[0].name, "plugin-b");
+
+        plugin_manager.unload();
+        assert_eq!(unloads.load(Ordering::Relaxed), 2);
+        assert!(plugin_manager.list_plugins().is_empty());
+    }
+
+    #[test]
+    fn test_replace_plugin() {
+        let unloads = Arc::new(AtomicUsize::default());
+        let new_plugin = |name| {
+            Box::new(TestPlugin {
+                name,
+                unloads: unloads.clone(),
+            })
+        };
+        let mut plugin_manager = AccountsDbPluginManager::new();
+        for (name, config_file) in [("plugin-a", "a.json"), ("plugin-b", "b.json")] {
+            plugin_manager
+                .load_builtin_plugin(new_plugin(name), config_file)
+                .unwrap();
+        }
+
+        // The replaced plugin is kept when the new one fails to load
+        assert!(plugin_manager
+            .replace_plugin("plugin-a", new_plugin("plugin-a"), "", None)
+            .is_err());
+        assert!(plugin_manager
+            .replace_plugin("plugin-a", new_plugin("plugin-b"), "c.json", None)
+            .is_err());
+        assert!(plugin_manager
+            .replace_plugin("plugin-c", new_plugin("plugin-c"), "c.json", None)
+            .is_err());
+        assert_eq!(unloads.load(Ordering::Relaxed), 0);
+        assert_eq!(plugin_manager.list_plugins()[0].config_file, "a.json");
+
+        assert_eq!(
+            plugin_manager
+                .replace_plugin("plugin-a", new_plugin("plugin-a"), "c.json", None)
+                .unwrap(),
+            "a.json"
+        );
+        assert_eq!(unloads.load(Ordering::Relaxed), 1);
+        assert_eq!(
+            plugin_manager.list_plugins(),
+            vec![
+                LoadedPluginInfo {
+                    name: "plugin-a".to_string(),
+                    config_file: "c.json".to_string(),
+                },
+                LoadedPluginInfo {
+                    name: "plugin-b".to_string(),
+                    config_file: "b.json".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_notify_plugins_detaches_slow_plugin() {
+        let unloads = Arc::new(AtomicUsize::default());
+        let mut plugin_manager = AccountsDbPluginManager::new();
+        for name in ["slow-plugin", "fast-plugin"] {
+            plugin_manager
+                .load_builtin_plugin(
+                    Box::new(TestPlugin {
+                        name,
+                        unloads: unloads.clone(),
+                    }),
+                    "config.json",
+                )
+                .unwrap();
+        }
+        let watchdog = PluginWatchdogConfig {
+            latency_budget_us: 1000,
+            max_consecutive_slow_callbacks: 2,
+            action: SlowPluginAction::Detach,
+            call_deadline_ms: 10_000,
+        };
+        for name in ["slow-plugin", "fast-plugin"] {
+            plugin_manager
+                .set_plugin_watchdog(name, Some(watchdog.clone()))
+                .unwrap();
+        }
+        assert!(plugin_manager
+            .set_plugin_watchdog("missing-plugin", None)
+            .is_err());
+
+        let notified = Arc::new(Mutex::new(vec![]));
+        let notify = {
+            let notified = notified.clone();
+            move |plugin: &mut Box<dyn AccountsDbPlugin>| {
+                notified.lock().unwrap().push(plugin.name());
+                if plugin.name() == "slow-plugin" {
+                    sleep(Duration::from_millis(2));
+                }
+                Some(Ok(()))
             }
+        };
+        plugin_manager.notify_plugins(PluginCallback::UpdateSlotStatus, 0, notify.clone());
+        plugin_manager.notify_plugins(PluginCallback::UpdateSlotStatus, 0, notify.clone());
+        // The worker of the slow plugin detaches it after its second callback
+        let start = Instant::now();
+        while unloads.load(Ordering::Relaxed) == 0 {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            sleep(Duration::from_millis(1));
         }
-        false
+        assert_eq!(plugin_manager.list_plugins().len(), 2);
+        plugin_manager.notify_plugins(PluginCallback::UpdateSlotStatus, 0, notify);
+        assert_eq!(plugin_manager.list_plugins()[0].name, "fast-plugin");
+        assert_eq!(plugin_manager.list_plugins().len(), 1);
+
+        // Unloading delivers the queued notifications first
+        plugin_manager.unload();
+        assert_eq!(unloads.load(Ordering::Relaxed), 2);
+        let notified = notified.lock().unwrap();
+        let count = |name| {
+            notified
+                .iter()
+                .filter(|notified| **notified == name)
+                .count()
+        };
+        assert_eq!((count("slow-plugin"), count("fast-plugin")), (2, 3));
+    }
+
+    #[test]
+    fn test_notify_plugins_disables_hung_plugin() {
+        let unloads = Arc::new(AtomicUsize::default());
+        let mut plugin_manager = AccountsDbPluginManager::new();
+        for name in ["hung-plugin", "fast-plugin"] {
+            plugin_manager
+                .load_builtin_plugin(
+                    Box::new(TestPlugin {
+                        name,
+                        unloads: unloads.clone(),
+                    }),
+                    "config.json",
+                )
+                .unwrap();
+        }
+        let watchdog = PluginWatchdogConfig {
+            latency_budget_us: 1_000_000,
+            max_consecutive_slow_callbacks: 100,
+            action: SlowPluginAction::Log,
+            call_deadline_ms: 10,
+        };
+        plugin_manager
+            .set_plugin_watchdog("hung-plugin", Some(watchdog))
+            .unwrap();
+
+        let (release_sender, release_receiver) = unbounded::<()>();
+        let release_receiver = Arc::new(Mutex::new(release_receiver));
+        let notify = move |plugin: &mut Box<dyn AccountsDbPlugin>| {
+            if plugin.name() == "hung-plugin" {
+                let _ = release_receiver.lock().unwrap().recv();
+            }
+            Some(Ok(()))
+        };
+        let start = Instant::now();
+        plugin_manager.notify_plugins(PluginCallback::UpdateSlotStatus, 0, notify.clone());
+        sleep(Duration::from_millis(20));
+        // The notifiers do not wait for the hung plugin, it is disabled past its deadline
+        plugin_manager.notify_plugins(PluginCallback::UpdateSlotStatus, 0, notify);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(plugin_manager.list_plugins().len(), 1);
+        assert_eq!(plugin_manager.list_plugins()[0].name, "fast-plugin");
+        assert_eq!(unloads.load(Ordering::Relaxed), 0);
+
+        // The hung plugin is unloaded once its callback returns
+        drop(release_sender);
+        while unloads.load(Ordering::Relaxed) == 0 {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            sleep(Duration::from_millis(1));
+        }
+        plugin_manager.unload();
+        assert_eq!(unloads.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_notify_plugins_bounded_queue() {
+        let unloads = Arc::new(AtomicUsize::default());
+        let mut plugin_manager = AccountsDbPluginManager::new();
+        plugin_manager
+            .load_builtin_plugin(
+                Box::new(TestPlugin {
+                    name: "plugin",
+                    unloads: unloads.clone(),
+                }),
+                "config.json",
+            )
+            .unwrap();
+
+        let (release_sender, release_receiver) = unbounded::<()>();
+        let release_receiver = Arc::new(Mutex::new(release_receiver));
+        let notified = Arc::new(AtomicUsize::default());
+        let notify = {
+            let notified = notified.clone();
+            move |_plugin: &mut Box<dyn AccountsDbPlugin>| {
+                let _ = release_receiver.lock().unwrap().recv();
+                notified.fetch_add(1, Ordering::Relaxed);
+                Some(Ok(()))
+            }
+        };
+        // A callback filling the queue is queued, the next one waits for it to return
+        let start = Instant::now();
+        plugin_manager.notify_plugins(
+            PluginCallback::UpdateSlotStatus,
+            PLUGIN_QUEUE_CAPACITY_BYTES,
+            notify.clone(),
+        );
+        let releaser = thread::spawn(move || {
+            sleep(Duration::from_millis(20));
+            drop(release_sender);
+        });
+        plugin_manager.notify_plugins(PluginCallback::UpdateSlotStatus, 0, notify);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(plugin_manager.list_plugins().len(), 1);
+        releaser.join().unwrap();
+
+        plugin_manager.unload();
+        assert_eq!(notified.load(Ordering::Relaxed), 2);
+        assert_eq!(unloads.load(Ordering::Relaxed), 1);
     }
 }
@@ -1,24 +1,39 @@
 use {
     crate::{
         accounts_update_notifier::AccountsUpdateNotifierImpl,
-        accountsdb_plugin_manager::AccountsDbPluginManager,
-        slot_status_notifier::SlotStatusNotifierImpl, slot_status_observer::SlotStatusObserver,
+        accountsdb_plugin_manager::{AccountsDbPluginManager, LoadedPluginInfo},
+        block_metadata_notifier::{BlockMetadataNotifier, BlockMetadataNotifierImpl},
+        plugin_monitor::PluginWatchdogConfig,
+        slot_status_notifier::SlotStatusNotifierImpl,
+        slot_status_observer::SlotStatusObserver,
+        transaction_notifier::{TransactionNotifier, TransactionNotifierImpl},
+        unix_socket_plugin::{UnixSocketPlugin, UNIX_SOCKET_PLUGIN_NAME},
     },
     crossbeam_channel::Receiver,
+    libloading::Library,
     log::*,
     serde_json,
+    solana_accountsdb_plugin_interface::accountsdb_plugin_interface::AccountsDbPlugin,
     solana_rpc::optimistically_confirmed_bank_tracker::BankNotification,
     solana_runtime::accounts_update_notifier_interface::AccountsUpdateNotifier,
     std::{
-        fs::File,
+        collections::HashMap,
+        fs::{self, File},
         io::Read,
         path::{Path, PathBuf},
-        sync::{Arc, RwLock},
-        thread,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, RwLock,
+        },
+        thread::{self, Builder, JoinHandle},
+        time::{Duration, Instant, SystemTime},
     },
     thiserror::Error,
 };
 
+const CONFIG_WATCH_INTERVAL: Duration = Duration::from_secs(5);
+const CONFIG_WATCH_EXIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 #[derive(Error, Debug)]
 pub enum AccountsdbPluginServiceError {
     #[error("Cannot open the the plugin config file")]
@@ -38,6 +53,100 @@ pub enum AccountsdbPluginServiceError {
 
     #[error("Cannot load plugin shared library")]
     PluginLoadError(String),
+
+    #[error("Unknown built-in plugin")]
+    UnknownBuiltinPlugin(String),
+
+    #[error("Cannot unload plugin")]
+    PluginUnloadError(String),
+}
+
+/// A handle controlling the plugins of a running validator, e.g. from the admin RPC.
+///
+/// The notifications a plugin loaded at runtime can receive are limited to the kinds
+/// enabled by the plugins loaded at startup: when no plugin was interested in
+/// account data at startup, AccountsDb does not produce account notifications at all.
+#[derive(Clone)]
+pub struct AccountsDbPluginControl {
+    plugin_manager: Arc<RwLock<AccountsDbPluginManager>>,
+}
+
+impl AccountsDbPluginControl {
+    /// List the loaded plugins with the config files they were loaded with.
+    pub fn list_plugins(&self) -> Vec<LoadedPluginInfo> {
+        self.plugin_manager.read().unwrap().list_plugins()
+    }
+
+    /// Load the plugin defined by the config file and return its name.
+    pub fn load_plugin(
+        &self,
+        accountsdb_plugin_config_file: &Path,
+    ) -> Result<String, AccountsdbPluginServiceError> {
+        let mut plugin_manager = self.plugin_manager.write().unwrap();
+        AccountsDbPluginService::load_plugin(&mut plugin_manager, accountsdb_plugin_config_file)?;
+        let name = plugin_manager.list_plugins().pop().unwrap().name;
+        info!(
+            "Loaded plugin {:?} from config file: {:?}",
+            name, accountsdb_plugin_config_file
+        );
+        Ok(name)
+    }
+
+    /// Unload the plugin with the given name.
+    pub fn unload_plugin(&self, name: &str) -> Result<(), AccountsdbPluginServiceError> {
+        let mut plugin_manager = self.plugin_manager.write().unwrap();
+        plugin_manager
+            .unload_plugin(name)
+            .map(|_| ())
+            .map_err(|err| AccountsdbPluginServiceError::PluginUnloadError(err.to_string()))
+    }
+
+    /// Load the plugin with the given name again from the config file, which defaults to
+    /// the one the plugin was loaded with. The config file is read again, so changes to it
+    /// take effect. The plugin is only unloaded once the new one is loaded, it is left as it is
+    /// when loading fails. No notification is lost in between as the notifiers wait for the
+    /// reload to complete.
+    pub fn reload_plugin(
+        &self,
+        name: &str,
+        accountsdb_plugin_config_file: Option<&Path>,
+    ) -> Result<String, AccountsdbPluginServiceError> {
+        let mut plugin_manager = self.plugin_manager.write().unwrap();
+        let config_file = match accountsdb_plugin_config_file {
+            Some(config_file) => config_file.to_path_buf(),
+            None => plugin_manager
+                .list_plugins()
+                .into_iter()
+                .find(|plugin| plugin.name == name)
+                .map(|plugin| PathBuf::from(plugin.config_file))
+                .ok_or_else(|| {
+                    AccountsdbPluginServiceError::PluginUnloadError(format!(
+                        "The plugin {:?} is not loaded",
+                        name
+                    ))
+                })?,
+        };
+        let (config, config_file_str, watchdog) =
+            AccountsDbPluginService::read_plugin_config(&config_file)?;
+        let (plugin, lib) = AccountsDbPluginService::create_plugin(&config, config_file_str)?;
+        let new_name = plugin.name();
+        plugin_manager
+            .replace_plugin(name, plugin, config_file_str, lib)
+            .map_err(|err| {
+                AccountsdbPluginServiceError::PluginLoadError(format!(
+                    "Failed to reload the plugin {:?} from config file {:?}, error: {:?}",
+                    name, config_file, err
+                ))
+            })?;
+        plugin_manager
+            .set_plugin_watchdog(new_name, watchdog)
+            .map_err(|err| AccountsdbPluginServiceError::PluginLoadError(err.to_string()))?;
+        info!(
+            "Reloaded plugin {:?} from config file: {:?}",
+            new_name, config_file
+        );
+        Ok(new_name.to_string())
+    }
 }
 
 /// The service managing the AccountsDb plugin workflow.
@@ -45,6 +154,10 @@ pub struct AccountsDbPluginService {
     slot_status_observer: Option<SlotStatusObserver>,
     plugin_manager: Arc<RwLock<AccountsDbPluginManager>>,
     accounts_update_notifier: Option<AccountsUpdateNotifier>,
+    transaction_notifier: Option<TransactionNotifier>,
+    block_metadata_notifier: Option<BlockMetadataNotifier>,
+    exit: Arc<AtomicBool>,
+    config_watcher: JoinHandle<()>,
 }
 
 impl AccountsDbPluginService {
@@ -58,8 +171,20 @@ impl AccountsDbPluginService {
     ///    (.so file) to be loaded. The shared library must implement the `AccountsDbPlugin`
     ///    trait. And the shared library shall export a `C` function `_create_plugin` which
     ///    shall create the implementation of `AccountsDbPlugin` and returns to the caller.
+    ///    Instead of `libpath`, the `builtin` field can name a plugin compiled into the validator,
+    ///    e.g. "unix_socket" to stream the notifications to other processes over a Unix socket.
     ///    The rest of the JSON fields' definition is up to to the concrete plugin implementation
     ///    It is usually used to configure the connection information for the external data store.
+    ///    The optional `watchdog` field sets a latency budget for the callbacks of the plugin,
+    ///    e.g. `"watchdog": {"latency_budget_us": 5000, "max_consecutive_slow_callbacks": 100,
+    ///    "action": "detach"}`. When that many callbacks in a row exceed the budget, the plugin
+    ///    is reported as slow, and with the "detach" action, unloaded. The default action is
+    ///    "log". The callback timings and errors of every plugin are reported regardless.
+    ///    Every plugin is called from its own worker thread. A plugin running a callback, or
+    ///    leaving its queue full, for longer than the `call_deadline_ms` of its watchdog,
+    ///    5000 by default, is disabled whatever the action.
+    ///    The plugins are reloaded whenever their config file is modified, a plugin failing to
+    ///    load from the modified config file is kept as it was.
 
     pub fn new(
         confirmed_bank_receiver: Receiver<BankNotification>,
@@ -75,6 +200,8 @@ impl AccountsDbPluginService {
             Self::load_plugin(&mut plugin_manager, accountsdb_plugin_config_file)?;
         }
         let to_notify_account_data = plugin_manager.to_notify_account_data();
+        let transaction_notifications_enabled = plugin_manager.transaction_notifications_enabled();
+        let block_metadata_notifications_enabled = !plugin_manager.is_empty();
 
         let plugin_manager = Arc::new(RwLock::new(plugin_manager));
 
@@ -85,7 +212,24 @@ impl AccountsDbPluginService {
             None
         };
 
-        let slot_status_observer = if to_notify_account_data {
+        let transaction_notifier: Option<TransactionNotifier> = if transaction_notifications_enabled
+        {
+            let transaction_notifier = TransactionNotifierImpl::new(plugin_manager.clone());
+            Some(Arc::new(RwLock::new(transaction_notifier)))
+        } else {
+            None
+        };
+
+        let block_metadata_notifier: Option<BlockMetadataNotifier> =
+            if block_metadata_notifications_enabled {
+                let block_metadata_notifier =
+                    BlockMetadataNotifierImpl::new(plugin_manager.clone());
+                Some(Arc::new(RwLock::new(block_metadata_notifier)))
+            } else {
+                None
+            };
+
+        let slot_status_observer = if to_notify_account_data || transaction_notifications_enabled {
             let slot_status_notifier = SlotStatusNotifierImpl::new(plugin_manager.clone());
             let slot_status_notifier = Arc::new(RwLock::new(slot_status_notifier));
             Some(SlotStatusObserver::new(
@@ -96,18 +240,101 @@ impl AccountsDbPluginService {
             None
         };
 
+        let exit = Arc::new(AtomicBool::new(false));
+        let config_watcher = {
+            let control = AccountsDbPluginControl {
+                plugin_manager: plugin_manager.clone(),
+            };
+            let exit = exit.clone();
+            Builder::new()
+                .name("solana-accountsdb-plugin-config-watcher".to_string())
+                .spawn(move || Self::watch_plugin_configs(control, CONFIG_WATCH_INTERVAL, exit))
+                .unwrap()
+        };
+
         info!("Started AccountsDbPluginService");
         Ok(AccountsDbPluginService {
             slot_status_observer,
             plugin_manager,
             accounts_update_notifier,
+            transaction_notifier,
+            block_metadata_notifier,
+            exit,
+            config_watcher,
         })
     }
 
+    /// Reload the plugins whose config file is modified, until `exit` is set
+    fn watch_plugin_configs(
+        control: AccountsDbPluginControl,
+        interval: Duration,
+        exit: Arc<AtomicBool>,
+    ) {
+        let modified = |config_file: &str| {
+            fs::metadata(config_file)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        };
+        // The config file of each plugin and when it was last modified
+        let mut config_files: HashMap<String, (String, Option<SystemTime>)> = HashMap::new();
+        let mut last_check = Instant::now();
+        while !exit.load(Ordering::Relaxed) {
+            thread::sleep(CONFIG_WATCH_EXIT_POLL_INTERVAL.min(interval));
+            if last_check.elapsed() < interval {
+                continue;
+            }
+            last_check = Instant::now();
+
+            let mut new_config_files = HashMap::new();
+            for plugin in control.list_plugins() {
+                let last_modified = modified(&plugin.config_file);
+                if let Some((config_file, previous_modified)) = config_files.get(&plugin.name) {
+                    if *config_file == plugin.config_file
+                        && last_modified.is_some()
+                        && last_modified != *previous_modified
+                    {
+                        info!(
+                            "The config file {:?} of plugin {:?} was modified, reloading it",
+                            plugin.config_file, plugin.name
+                        );
+                        // Not retried before the config file is modified again
+                        if let Err(err) = control.reload_plugin(&plugin.name, None) {
+                            error!("Failed to reload plugin {:?}: {:?}", plugin.name, err);
+                        }
+                    }
+                }
+                new_config_files.insert(plugin.name, (plugin.config_file, last_modified));
+            }
+            config_files = new_config_files;
+        }
+    }
+
     fn load_plugin(
         plugin_manager: &mut AccountsDbPluginManager,
         accountsdb_plugin_config_file: &Path,
     ) -> Result<(), AccountsdbPluginServiceError> {
+        let (config, config_file, watchdog) =
+            Self::read_plugin_config(accountsdb_plugin_config_file)?;
+        let (plugin, lib) = Self::create_plugin(&config, config_file)?;
+        let name = plugin.name();
+        if let Err(err) = plugin_manager.add_plugin(plugin, config_file, lib) {
+            let msg = format!(
+                "Failed to load the plugin from config file {:?}, error: {:?}",
+                accountsdb_plugin_config_file, err
+            );
+            return Err(AccountsdbPluginServiceError::PluginLoadError(msg));
+        }
+        plugin_manager
+            .set_plugin_watchdog(name, watchdog)
+            .map_err(|err| AccountsdbPluginServiceError::PluginLoadError(err.to_string()))
+    }
+
+    /// Read the config file of a plugin, returning its content, its path as a string and the
+    /// watchdog it sets for the plugin.
+    fn read_plugin_config(
+        accountsdb_plugin_config_file: &Path,
+    ) -> Result<(serde_json::Value, &str, Option<PluginWatchdogConfig>), AccountsdbPluginServiceError>
+    {
         let mut file = match File::open(accountsdb_plugin_config_file) {
             Ok(file) => file,
             Err(err) => {
@@ -138,32 +365,83 @@ impl AccountsDbPluginService {
             }
         };
 
-        let libpath = result["libpath"]
-            .as_str()
-            .ok_or(AccountsdbPluginServiceError::LibPathNotSet)?;
         let config_file = accountsdb_plugin_config_file
             .as_os_str()
             .to_str()
             .ok_or(AccountsdbPluginServiceError::InvalidPluginPath)?;
 
-        unsafe {
-            let result = plugin_manager.load_plugin(libpath, config_file);
-            if let Err(err) = result {
+        let watchdog = if result["watchdog"].is_null() {
+            None
+        } else {
+            match serde_json::from_value::<PluginWatchdogConfig>(result["watchdog"].clone()) {
+                Ok(watchdog) => Some(watchdog),
+                Err(err) => {
+                    return Err(AccountsdbPluginServiceError::InvalidConfigFileFormat(
+                        format!(
+                            "The watchdog in the config file {:?} is not valid, error: {:?}",
+                            accountsdb_plugin_config_file, err
+                        ),
+                    ));
+                }
+            }
+        };
+        Ok((result, config_file, watchdog))
+    }
+
+    /// Create the plugin defined by the config, which is yet to be loaded.
+    fn create_plugin(
+        result: &serde_json::Value,
+        config_file: &str,
+    ) -> Result<(Box<dyn AccountsDbPlugin>, Option<Library>), AccountsdbPluginServiceError> {
+        if let Some(builtin) = result["builtin"].as_str() {
+            let plugin: Box<dyn AccountsDbPlugin> = match builtin {
+                UNIX_SOCKET_PLUGIN_NAME => Box::new(UnixSocketPlugin::new()),
+                _ => {
+                    return Err(AccountsdbPluginServiceError::UnknownBuiltinPlugin(
+                        builtin.to_string(),
+                    ))
+                }
+            };
+            return Ok((plugin, None));
+        }
+
+        let libpath = result["libpath"]
+            .as_str()
+            .ok_or(AccountsdbPluginServiceError::LibPathNotSet)?;
+
+        match unsafe { AccountsDbPluginManager::open_plugin_library(libpath) } {
+            Ok((plugin, lib)) => Ok((plugin, Some(lib))),
+            Err(err) => {
                 let msg = format!(
-                    "Failed to load the plugin library: {:?}, error: {:?}",
-                    libpath, err
+                    "Failed to load the plugin library: {:?} for config file {:?}, error: {:?}",
+                    libpath, config_file, err
                 );
-                return Err(AccountsdbPluginServiceError::PluginLoadError(msg));
+                Err(AccountsdbPluginServiceError::PluginLoadError(msg))
             }
         }
-        Ok(())
     }
 
     pub fn get_accounts_update_notifier(&self) -> Option<AccountsUpdateNotifier> {
         self.accounts_update_notifier.clone()
     }
 
+    pub fn get_transaction_notifier(&self) -> Option<TransactionNotifier> {
+        self.transaction_notifier.clone()
+    }
+
+    pub fn get_block_metadata_notifier(&self) -> Option<BlockMetadataNotifier> {
+        self.block_metadata_notifier.clone()
+    }
+
+    pub fn get_plugin_control(&self) -> AccountsDbPluginControl {
+        AccountsDbPluginControl {
+            plugin_manager: self.plugin_manager.clone(),
+        }
+    }
+
     pub fn join(self) -> thread::Result<()> {
+        self.exit.store(true, Ordering::Relaxed);
+        self.config_watcher.join()?;
         if let Some(mut slot_status_observer) = self.slot_status_observer {
             slot_status_observer.join()?;
         }
@@ -171,3 +449,169 @@ impl AccountsDbPluginService {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crossbeam_channel::unbounded, tempfile::TempDir};
+
+    fn write_socket_plugin_config(dir: &Path, name: &str) -> PathBuf {
+        let config_file = dir.join(format!("{}.json", name));
+        fs::write(
+            &config_file,
+            format!(
+                r#"{{"builtin": "unix_socket", "socket_path": {:?}}}"#,
+                dir.join(format!("{}.sock", name))
+            ),
+        )
+        .unwrap();
+        config_file
+    }
+
+    #[test]
+    fn test_plugin_watchdog_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("watchdog.json");
+        let (_confirmed_bank_sender, confirmed_bank_receiver) = unbounded();
+        let service = AccountsDbPluginService::new(confirmed_bank_receiver, &[]).unwrap();
+        let control = service.get_plugin_control();
+
+        fs::write(
+            &config_file,
+            format!(
+                r#"{{"builtin": "unix_socket", "socket_path": {:?}, "watchdog": {{"action": "detach"}}}}"#,
+                temp_dir.path().join("watchdog.sock")
+            ),
+        )
+        .unwrap();
+        assert!(matches!(
+            control.load_plugin(&config_file),
+            Err(AccountsdbPluginServiceError::InvalidConfigFileFormat(_))
+        ));
+        assert!(control.list_plugins().is_empty());
+
+        fs::write(
+            &config_file,
+            format!(
+                r#"{{"builtin": "unix_socket", "socket_path": {:?}, "watchdog": {{"latency_budget_us": 5000, "action": "detach"}}}}"#,
+                temp_dir.path().join("watchdog.sock")
+            ),
+        )
+        .unwrap();
+        control.load_plugin(&config_file).unwrap();
+        assert_eq!(control.list_plugins().len(), 1);
+        service.join().unwrap();
+    }
+
+    #[test]
+    fn test_plugin_control() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_a = write_socket_plugin_config(temp_dir.path(), "a");
+        let config_b = write_socket_plugin_config(temp_dir.path(), "b");
+        let (_confirmed_bank_sender, confirmed_bank_receiver) = unbounded();
+        let service = AccountsDbPluginService::new(confirmed_bank_receiver, &[]).unwrap();
+        let control = service.get_plugin_control();
+        assert!(control.list_plugins().is_empty());
+
+        let name = control.load_plugin(&config_a).unwrap();
+        assert!(temp_dir.path().join("a.sock").exists());
+        assert_eq!(
+            control.list_plugins(),
+            vec![LoadedPluginInfo {
+                name: name.clone(),
+                config_file: config_a.to_str().unwrap().to_string(),
+            }]
+        );
+        assert!(control.load_plugin(&config_b).is_err());
+        assert!(control
+            .load_plugin(&temp_dir.path().join("missing.json"))
+            .is_err());
+
+        // Reloading from the same config file
+        assert_eq!(control.reload_plugin(&name, None).unwrap(), name);
+        assert_eq!(
+            control.list_plugins()[0].config_file,
+            config_a.to_str().unwrap()
+        );
+        assert!(temp_dir.path().join("a.sock").exists());
+
+        // The plugin is kept when the new config file is bad
+        let bad_config = temp_dir.path().join("bad.json");
+        fs::write(&bad_config, r#"{"builtin": "unknown"}"#).unwrap();
+        assert!(matches!(
+            control.reload_plugin(&name, Some(&bad_config)),
+            Err(AccountsdbPluginServiceError::UnknownBuiltinPlugin(_))
+        ));
+        assert_eq!(
+            control.list_plugins()[0].config_file,
+            config_a.to_str().unwrap()
+        );
+        assert!(temp_dir.path().join("a.sock").exists());
+
+        // Reloading from a new config file
+        control.reload_plugin(&name, Some(&config_b)).unwrap();
+        assert!(!temp_dir.path().join("a.sock").exists());
+        assert!(temp_dir.path().join("b.sock").exists());
+        assert_eq!(
+            control.list_plugins()[0].config_file,
+            config_b.to_str().unwrap()
+        );
+
+        control.unload_plugin(&name).unwrap();
+        assert!(control.list_plugins().is_empty());
+        assert!(control.unload_plugin(&name).is_err());
+        assert!(control.reload_plugin(&name, None).is_err());
+        service.join().unwrap();
+    }
+
+    #[test]
+    fn test_watch_plugin_configs() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = write_socket_plugin_config(temp_dir.path(), "a");
+        let (_confirmed_bank_sender, confirmed_bank_receiver) = unbounded();
+        let service =
+            AccountsDbPluginService::new(confirmed_bank_receiver, &[config_file.clone()]).unwrap();
+        let control = service.get_plugin_control();
+        let exit = Arc::new(AtomicBool::new(false));
+        let watcher = {
+            let control = control.clone();
+            let exit = exit.clone();
+            thread::spawn(move || {
+                AccountsDbPluginService::watch_plugin_configs(
+                    control,
+                    Duration::from_millis(10),
+                    exit,
+                )
+            })
+        };
+
+        let wait_for_socket = |socket_path: &Path| {
+            let start = Instant::now();
+            while !socket_path.exists() {
+                assert!(start.elapsed() < Duration::from_secs(10));
+                thread::sleep(Duration::from_millis(10));
+            }
+        };
+        // Give the watcher time to see the config file as it is
+        thread::sleep(Duration::from_millis(100));
+        fs::write(
+            &config_file,
+            format!(
+                r#"{{"builtin": "unix_socket", "socket_path": {:?}}}"#,
+                temp_dir.path().join("b.sock")
+            ),
+        )
+        .unwrap();
+        wait_for_socket(&temp_dir.path().join("b.sock"));
+        assert!(!temp_dir.path().join("a.sock").exists());
+
+        // A bad config file leaves the plugin as it is
+        fs::write(&config_file, "{").unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(control.list_plugins().len(), 1);
+        assert!(temp_dir.path().join("b.sock").exists());
+
+        exit.store(true, Ordering::Relaxed);
+        watcher.join().unwrap();
+        service.join().unwrap();
+    }
+}
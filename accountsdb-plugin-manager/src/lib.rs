@@ -1,5 +1,9 @@
 pub mod accounts_update_notifier;
 pub mod accountsdb_plugin_manager;
 pub mod accountsdb_plugin_service;
+pub mod block_metadata_notifier;
+pub mod plugin_monitor;
 pub mod slot_status_notifier;
 pub mod slot_status_observer;
+pub mod transaction_notifier;
+pub mod unix_socket_plugin;
@@ -0,0 +1,447 @@
+/// Module responsible for calling every plugin from its own worker thread, measuring the
+/// callbacks and for the watchdog acting on the plugins whose callbacks exceed their latency
+/// budget or their deadline. The notifiers only queue the callbacks, so a slow plugin does not
+/// slow down replay, e.g. on the AccountsDb write path, until its queue is full, and a hung
+/// plugin is disabled once its deadline is blown.
+use {
+    crossbeam_channel::{unbounded, Receiver, Sender},
+    libloading::Library,
+    log::*,
+    serde_derive::{Deserialize, Serialize},
+    solana_accountsdb_plugin_interface::accountsdb_plugin_interface::{
+        AccountsDbPlugin, Result as PluginResult,
+    },
+    solana_measure::measure::Measure,
+    solana_metrics::*,
+    std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicBool, AtomicU64, Ordering},
+            Arc, Condvar, Mutex,
+        },
+        thread::{Builder, JoinHandle},
+        time::{Duration, Instant},
+    },
+};
+
+/// The interval at which the stats of every plugin are reported
+const PLUGIN_STATS_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_MAX_CONSECUTIVE_SLOW_CALLBACKS: u64 = 100;
+const DEFAULT_CALL_DEADLINE_MS: u64 = 5_000;
+/// The size of the callbacks queued for a plugin, in bytes, before the notifiers wait for it
+pub(crate) const PLUGIN_QUEUE_CAPACITY_BYTES: usize = 256 * 1024 * 1024;
+/// `PluginWorkerState::busy_since_us` when the worker waits for a callback
+const IDLE: u64 = 0;
+/// `PluginWorkerState::busy_since_us` when the worker runs a callback without deadline
+const NO_DEADLINE: u64 = u64::MAX;
+
+/// A callback queued for a plugin, returning None when it skips the plugin
+pub type PluginTask =
+    Arc<dyn Fn(&mut Box<dyn AccountsDbPlugin>) -> Option<PluginResult<()>> + Send + Sync>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PluginCallback {
+    UpdateAccount,
+    NotifyEndOfStartup,
+    UpdateSlotStatus,
+    NotifyTransaction,
+    NotifyBlockMetadata,
+}
+
+impl PluginCallback {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PluginCallback::UpdateAccount => "update_account",
+            PluginCallback::NotifyEndOfStartup => "notify_end_of_startup",
+            PluginCallback::UpdateSlotStatus => "update_slot_status",
+            PluginCallback::NotifyTransaction => "notify_transaction",
+            PluginCallback::NotifyBlockMetadata => "notify_block_metadata",
+        }
+    }
+
+    /// `notify_end_of_startup` may take as long as the plugin needs to flush the accounts
+    /// restored from the snapshot, the notifiers wait for it as long as it runs.
+    fn has_deadline(&self) -> bool {
+        *self != PluginCallback::NotifyEndOfStartup
+    }
+}
+
+/// What the watchdog does with a plugin exceeding its latency budget
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SlowPluginAction {
+    /// Log an error and report a datapoint, the plugin keeps being notified
+    Log,
+    /// Unload the plugin, it is not notified any more
+    Detach,
+}
+
+impl Default for SlowPluginAction {
+    fn default() -> Self {
+        SlowPluginAction::Log
+    }
+}
+
+fn default_max_consecutive_slow_callbacks() -> u64 {
+    DEFAULT_MAX_CONSECUTIVE_SLOW_CALLBACKS
+}
+
+fn default_call_deadline_ms() -> u64 {
+    DEFAULT_CALL_DEADLINE_MS
+}
+
+/// The "watchdog" section of a plugin config file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PluginWatchdogConfig {
+    /// The callbacks taking longer than this are slow
+    pub latency_budget_us: u64,
+    /// The watchdog acts when this many callbacks in a row are slow
+    #[serde(default = "default_max_consecutive_slow_callbacks")]
+    pub max_consecutive_slow_callbacks: u64,
+    #[serde(default)]
+    pub action: SlowPluginAction,
+    /// The plugin is disabled when a callback runs, or its queue stays full, for longer than
+    /// this, whatever the action
+    #[serde(default = "default_call_deadline_ms")]
+    pub call_deadline_ms: u64,
+}
+
+#[derive(Debug, Default)]
+struct CallbackStats {
+    count: u64,
+    errors: u64,
+    slow: u64,
+    total_us: u64,
+    max_us: u64,
+}
+
+/// The stats and the watchdog of a loaded plugin
+#[derive(Debug)]
+pub(crate) struct PluginMonitor {
+    watchdog: Option<PluginWatchdogConfig>,
+    stats: HashMap<PluginCallback, CallbackStats>,
+    consecutive_slow_callbacks: u64,
+    last_report: Instant,
+}
+
+impl PluginMonitor {
+    pub fn new() -> Self {
+        Self {
+            watchdog: None,
+            stats: HashMap::default(),
+            consecutive_slow_callbacks: 0,
+            last_report: Instant::now(),
+        }
+    }
+
+    pub fn set_watchdog(&mut self, watchdog: Option<PluginWatchdogConfig>) {
+        self.watchdog = watchdog;
+        self.consecutive_slow_callbacks = 0;
+    }
+
+    /// Record a callback of the plugin. Returns true if the watchdog decided to detach the
+    /// plugin.
+    pub fn record(
+        &mut self,
+        name: &str,
+        callback: PluginCallback,
+        elapsed_us: u64,
+        is_err: bool,
+    ) -> bool {
+        let stats = self.stats.entry(callback).or_default();
+        stats.count += 1;
+        stats.total_us += elapsed_us;
+        stats.max_us = stats.max_us.max(elapsed_us);
+        if is_err {
+            stats.errors += 1;
+        }
+
+        let mut detach = false;
+        if let Some(watchdog) = &self.watchdog {
+            if elapsed_us > watchdog.latency_budget_us {
+                stats.slow += 1;
+                self.consecutive_slow_callbacks += 1;
+                if self.consecutive_slow_callbacks >= watchdog.max_consecutive_slow_callbacks {
+                    self.consecutive_slow_callbacks = 0;
+                    error!(
+                        "Plugin {} exceeded its latency budget of {}us in {} callbacks in a row, \
+                        the last {} took {}us",
+                        name,
+                        watchdog.latency_budget_us,
+                        watchdog.max_consecutive_slow_callbacks,
+                        callback.as_str(),
+                        elapsed_us
+                    );
+                    datapoint_error!(
+                        "accountsdb-plugin-slow",
+                        ("plugin", name.to_string(), String),
+                        ("callback", callback.as_str().to_string(), String),
+                        ("elapsed_us", elapsed_us, i64),
+                    );
+                    detach = watchdog.action == SlowPluginAction::Detach;
+                }
+            } else {
+                self.consecutive_slow_callbacks = 0;
+            }
+        }
+
+        if self.last_report.elapsed() >= PLUGIN_STATS_REPORT_INTERVAL {
+            self.report(name);
+        }
+        detach
+    }
+
+    /// Report the stats accumulated since the last report
+    pub fn report(&mut self, name: &str) {
+        self.last_report = Instant::now();
+        for (callback, stats) in self.stats.drain() {
+            datapoint_info!(
+                "accountsdb-plugin-stats",
+                ("plugin", name.to_string(), String),
+                ("callback", callback.as_str().to_string(), String),
+                ("count", stats.count, i64),
+                ("errors", stats.errors, i64),
+                ("slow", stats.slow, i64),
+                ("total_us", stats.total_us, i64),
+                ("max_us", stats.max_us, i64),
+            );
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PluginWorkerState {
+    monitor: Mutex<PluginMonitor>,
+    call_deadline_ms: AtomicU64,
+    /// When the running callback started, in microseconds since `start`, or IDLE or
+    /// NO_DEADLINE
+    busy_since_us: AtomicU64,
+    start: Instant,
+    /// Set when the plugin is detached by the watchdog or disabled, the worker then stops
+    /// calling it
+    disabled: AtomicBool,
+    /// The size of the queued callbacks, including the running one
+    queued_bytes: Mutex<usize>,
+    /// Signaled when a callback leaves the queue, or the worker stops
+    dequeued: Condvar,
+}
+
+impl PluginWorkerState {
+    fn call_deadline(&self) -> Duration {
+        Duration::from_millis(self.call_deadline_ms.load(Ordering::Relaxed))
+    }
+
+    fn now_us(&self) -> u64 {
+        // Never IDLE
+        self.start.elapsed().as_micros() as u64 + 1
+    }
+
+    fn disable(&self) {
+        self.disabled.store(true, Ordering::Relaxed);
+        let _queued_bytes = self.queued_bytes.lock().unwrap();
+        self.dequeued.notify_all();
+    }
+}
+
+/// The worker thread a loaded plugin is called from. The worker owns the plugin and the
+/// library holding its code, it unloads the plugin when it is shut down or disabled.
+#[derive(Debug)]
+pub(crate) struct PluginWorker {
+    sender: Sender<(PluginCallback, PluginTask, usize)>,
+    state: Arc<PluginWorkerState>,
+    thread: JoinHandle<()>,
+}
+
+impl PluginWorker {
+    pub fn new(plugin: Box<dyn AccountsDbPlugin>, lib: Option<Library>) -> Self {
+        let (sender, receiver) = unbounded();
+        let state = Arc::new(PluginWorkerState {
+            monitor: Mutex::new(PluginMonitor::new()),
+            call_deadline_ms: AtomicU64::new(DEFAULT_CALL_DEADLINE_MS),
+            busy_since_us: AtomicU64::new(IDLE),
+            start: Instant::now(),
+            disabled: AtomicBool::new(false),
+            queued_bytes: Mutex::new(0),
+            dequeued: Condvar::new(),
+        });
+        let thread = {
+            let state = state.clone();
+            Builder::new()
+                .name("solana-accountsdb-plugin-worker".to_string())
+                .spawn(move || Self::run(plugin, lib, receiver, state))
+                .unwrap()
+        };
+        Self {
+            sender,
+            state,
+            thread,
+        }
+    }
+
+    fn run(
+        mut plugin: Box<dyn AccountsDbPlugin>,
+        lib: Option<Library>,
+        receiver: Receiver<(PluginCallback, PluginTask, usize)>,
+        state: Arc<PluginWorkerState>,
+    ) {
+        // Ends when the worker is shut down, once the queued callbacks are run
+        for (callback, task, size) in receiver.iter() {
+            if state.disabled.load(Ordering::Relaxed) {
+                break;
+            }
+            let busy_since_us = if callback.has_deadline() {
+                state.now_us()
+            } else {
+                NO_DEADLINE
+            };
+            state.busy_since_us.store(busy_since_us, Ordering::Relaxed);
+            let mut measure = Measure::start("accountsdb-plugin-notify-plugin");
+            let result = task(&mut plugin);
+            measure.stop();
+            state.busy_since_us.store(IDLE, Ordering::Relaxed);
+            *state.queued_bytes.lock().unwrap() -= size;
+            state.dequeued.notify_all();
+            let result = match result {
+                Some(result) => result,
+                None => continue,
+            };
+            if state.monitor.lock().unwrap().record(
+                plugin.name(),
+                callback,
+                measure.as_us(),
+                result.is_err(),
+            ) {
+                error!(
+                    "Detaching plugin {:?} exceeding its latency budget",
+                    plugin.name()
+                );
+                state.disable();
+                break;
+            }
+        }
+
+        info!("Unloading plugin for {:?}", plugin.name());
+        plugin.on_unload();
+        // The plugin must be dropped before the library holding its code
+        drop(plugin);
+        drop(lib);
+    }
+
+    pub fn set_watchdog(&self, watchdog: Option<PluginWatchdogConfig>) {
+        let call_deadline_ms = watchdog
+            .as_ref()
+            .map(|watchdog| watchdog.call_deadline_ms)
+            .unwrap_or(DEFAULT_CALL_DEADLINE_MS);
+        self.state
+            .call_deadline_ms
+            .store(call_deadline_ms, Ordering::Relaxed);
+        self.state.monitor.lock().unwrap().set_watchdog(watchdog);
+    }
+
+    /// True when the plugin is running a callback for longer than its deadline
+    fn is_stalled(&self) -> bool {
+        let busy_since_us = self.state.busy_since_us.load(Ordering::Relaxed);
+        busy_since_us != IDLE
+            && busy_since_us != NO_DEADLINE
+            && self.state.now_us().saturating_sub(busy_since_us)
+                > self.state.call_deadline().as_micros() as u64
+    }
+
+    /// Queue the callback of `size` bytes for the plugin, waiting for room in the queue up to
+    /// the deadline of the plugin. A callback larger than the queue is queued once the queue is
+    /// empty. Returns false when the plugin is to be disabled: it was detached by the watchdog,
+    /// or it blew its deadline.
+    pub fn notify(&self, callback: PluginCallback, size: usize, task: PluginTask) -> bool {
+        if self.state.disabled.load(Ordering::Relaxed) || self.is_stalled() {
+            return false;
+        }
+        let deadline = Instant::now() + self.state.call_deadline();
+        let mut queued_bytes = self.state.queued_bytes.lock().unwrap();
+        while *queued_bytes > 0 && *queued_bytes + size > PLUGIN_QUEUE_CAPACITY_BYTES {
+            if self.state.disabled.load(Ordering::Relaxed) {
+                return false;
+            }
+            if self.state.busy_since_us.load(Ordering::Relaxed) == NO_DEADLINE {
+                queued_bytes = self.state.dequeued.wait(queued_bytes).unwrap();
+                continue;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            queued_bytes = self
+                .state
+                .dequeued
+                .wait_timeout(queued_bytes, deadline - now)
+                .unwrap()
+                .0;
+        }
+        *queued_bytes += size;
+        self.sender.send((callback, task, size)).is_ok()
+    }
+
+    /// Stop calling the plugin without waiting for its running callback to return, the
+    /// worker unloads it if it ever does.
+    pub fn disable(&self) {
+        self.state.disable();
+    }
+
+    /// Unload the plugin once the queued callbacks are run, the plugin is disabled instead
+    /// when it is stalled.
+    pub fn shutdown(self) {
+        if self.is_stalled() {
+            error!("Disabling a stalled plugin instead of unloading it");
+            self.disable();
+            return;
+        }
+        drop(self.sender);
+        if self.thread.join().is_err() {
+            error!("The worker of a plugin panicked");
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plugin_watchdog() {
+        let mut monitor = PluginMonitor::new();
+        // Without watchdog, the callbacks are only measured
+        assert!(!monitor.record("plugin", PluginCallback::UpdateAccount, 1_000_000, true));
+        let stats = &monitor.stats[&PluginCallback::UpdateAccount];
+        assert_eq!((stats.count, stats.errors, stats.slow), (1, 1, 0));
+
+        let watchdog: PluginWatchdogConfig = serde_json::from_str(
+            r#"{"latency_budget_us": 100, "max_consecutive_slow_callbacks": 2, "action": "detach"}"#,
+        )
+        .unwrap();
+        monitor.set_watchdog(Some(watchdog));
+        assert!(!monitor.record("plugin", PluginCallback::UpdateAccount, 101, false));
+        // A callback within the budget resets the count
+        assert!(!monitor.record("plugin", PluginCallback::UpdateSlotStatus, 100, false));
+        assert!(!monitor.record("plugin", PluginCallback::UpdateAccount, 101, false));
+        assert!(monitor.record("plugin", PluginCallback::UpdateAccount, 101, false));
+        let stats = &monitor.stats[&PluginCallback::UpdateAccount];
+        assert_eq!((stats.count, stats.errors, stats.slow), (4, 1, 3));
+        assert_eq!(stats.max_us, 1_000_000);
+
+        // Logging only
+        let watchdog: PluginWatchdogConfig =
+            serde_json::from_str(r#"{"latency_budget_us": 100}"#).unwrap();
+        assert_eq!(watchdog.action, SlowPluginAction::Log);
+        assert_eq!(
+            watchdog.max_consecutive_slow_callbacks,
+            DEFAULT_MAX_CONSECUTIVE_SLOW_CALLBACKS
+        );
+        assert_eq!(watchdog.call_deadline_ms, DEFAULT_CALL_DEADLINE_MS);
+        monitor.set_watchdog(Some(watchdog));
+        for _ in 0..2 * DEFAULT_MAX_CONSECUTIVE_SLOW_CALLBACKS {
+            assert!(!monitor.record("plugin", PluginCallback::UpdateAccount, 101, false));
+        }
+
+        monitor.report("plugin");
+        assert!(monitor.stats.is_empty());
+    }
+}
@@ -1,5 +1,5 @@
 use {
-    crate::accountsdb_plugin_manager::AccountsDbPluginManager,
+    crate::{accountsdb_plugin_manager::AccountsDbPluginManager, plugin_monitor::PluginCallback},
     log::*,
     solana_accountsdb_plugin_interface::accountsdb_plugin_interface::SlotStatus,
     solana_measure::measure::Measure,
@@ -45,14 +45,15 @@ impl SlotStatusNotifierImpl {
     }
 
     pub fn notify_slot_status(&self, slot: Slot, parent: Option<Slot>, slot_status: SlotStatus) {
-        let mut plugin_manager = self.plugin_manager.write().unwrap();
-        if plugin_manager.plugins.is_empty() {
+        let plugin_manager = self.plugin_manager.read().unwrap();
+        if plugin_manager.is_empty() {
             return;
         }
 
-        for plugin in plugin_manager.plugins.iter_mut() {
+        plugin_manager.notify_plugins(PluginCallback::UpdateSlotStatus, 0, move |plugin| {
             let mut measure = Measure::start("accountsdb-plugin-update-slot");
-            match plugin.update_slot_status(slot, parent, slot_status.clone()) {
+            let result = plugin.update_slot_status(slot, parent, slot_status.clone());
+            match &result {
                 Err(err) => {
                     error!(
                         "Failed to update slot status at slot {}, error: {} to plugin {}",
@@ -76,6 +77,7 @@ impl SlotStatusNotifierImpl {
                 1000,
                 1000
             );
-        }
+            Some(result)
+        });
     }
 }
@@ -0,0 +1,123 @@
+/// Module responsible for notifying plugins of transactions
+use {
+    crate::{accountsdb_plugin_manager::AccountsDbPluginManager, plugin_monitor::PluginCallback},
+    log::*,
+    solana_accountsdb_plugin_interface::accountsdb_plugin_interface::{
+        ReplicaTransactionInfo, ReplicaTransactionInfoVersions,
+    },
+    solana_measure::measure::Measure,
+    solana_metrics::*,
+    solana_sdk::{
+        clock::Slot, packet::PACKET_DATA_SIZE, signature::Signature,
+        transaction::SanitizedTransaction,
+    },
+    solana_transaction_status::TransactionStatusMeta,
+    std::sync::{Arc, RwLock},
+};
+
+pub trait TransactionNotifierInterface {
+    /// Notified when a transaction is processed in a slot.
+    fn notify_transaction(
+        &self,
+        slot: Slot,
+        signature: &Signature,
+        transaction_status_meta: &TransactionStatusMeta,
+        transaction: &SanitizedTransaction,
+    );
+}
+
+pub type TransactionNotifier = Arc<RwLock<dyn TransactionNotifierInterface + Sync + Send>>;
+
+/// This implementation of TransactionNotifierInterface passes the transaction
+/// information to every loaded plugin which is interested in transactions.
+pub(crate) struct TransactionNotifierImpl {
+    plugin_manager: Arc<RwLock<AccountsDbPluginManager>>,
+}
+
+impl TransactionNotifierInterface for TransactionNotifierImpl {
+    fn notify_transaction(
+        &self,
+        slot: Slot,
+        signature: &Signature,
+        transaction_status_meta: &TransactionStatusMeta,
+        transaction: &SanitizedTransaction,
+    ) {
+        let mut measure = Measure::start("accountsdb-plugin-notify_plugins_of_transaction_info");
+        let plugin_manager = self.plugin_manager.read().unwrap();
+
+        if plugin_manager.is_empty() {
+            return;
+        }
+
+        // The plugins are notified from their worker threads
+        let signature = *signature;
+        let transaction_status_meta = transaction_status_meta.clone();
+        let transaction = transaction.clone();
+        // The transaction fits in a packet, the log messages make up most of the rest
+        let size = PACKET_DATA_SIZE
+            + transaction_status_meta
+                .log_messages
+                .iter()
+                .flatten()
+                .map(String::len)
+                .sum::<usize>();
+        plugin_manager.notify_plugins(PluginCallback::NotifyTransaction, size, move |plugin| {
+            if !plugin.transaction_notifications_enabled() {
+                return None;
+            }
+            let transaction_log_info = Self::build_replica_transaction_info(
+                &signature,
+                &transaction_status_meta,
+                &transaction,
+            );
+            let result = plugin.notify_transaction(
+                ReplicaTransactionInfoVersions::V0_0_1(&transaction_log_info),
+                slot,
+            );
+            match &result {
+                Err(err) => {
+                    error!(
+                        "Failed to notify transaction, error: ({}) to plugin {}",
+                        err,
+                        plugin.name()
+                    )
+                }
+                Ok(_) => {
+                    trace!(
+                        "Successfully notified transaction {} at slot {} to plugin {}",
+                        signature,
+                        slot,
+                        plugin.name()
+                    );
+                }
+            }
+            Some(result)
+        });
+        measure.stop();
+        inc_new_counter_debug!(
+            "accountsdb-plugin-notify_plugins_of_transaction_info-us",
+            measure.as_us() as usize,
+            10000,
+            10000
+        );
+    }
+}
+
+impl TransactionNotifierImpl {
+    pub fn new(plugin_manager: Arc<RwLock<AccountsDbPluginManager>>) -> Self {
+        Self { plugin_manager }
+    }
+
+    fn build_replica_transaction_info<'a>(
+        signature: &'a Signature,
+        transaction_status_meta: &'a TransactionStatusMeta,
+        transaction: &'a SanitizedTransaction,
+    ) -> ReplicaTransactionInfo<'a> {
+        ReplicaTransactionInfo {
+            signature,
+            is_vote: transaction.is_simple_vote_transaction(),
+            transaction,
+            transaction_status_meta,
+        }
+    }
+}
@@ -0,0 +1,828 @@
+/// A built-in AccountsDb plugin which streams account updates and slot status
+/// changes to out-of-process consumers over a Unix domain socket.
+///
+/// Unlike the dynamically loaded plugins, this plugin is compiled into the validator,
+/// so a misbehaving consumer cannot crash the validator and consumers do not have to
+/// be built against the validator's rustc version. Consumers can be written in any
+/// language able to read from a Unix domain socket.
+///
+/// # Wire format
+///
+/// Every message is a frame made of a 4-byte little-endian length followed by the
+/// frame body of that many bytes. The body starts with a header:
+/// * `version`: u8, the protocol version, currently `1`.
+/// * `kind`: u8, the message kind, see below.
+/// * `sequence`: u64, incremented for every message produced by the plugin. A gap in
+///    the sequence tells the consumer that messages were dropped.
+///
+/// The rest of the body depends on the kind. All integers are little-endian.
+/// * `1`, account update: `slot` u64, `is_startup` u8, `pubkey` 32 bytes, `lamports` u64,
+///    `owner` 32 bytes, `executable` u8, `rent_epoch` u64, `write_version` u64,
+///    `data_len` u32, followed by `data_len` bytes of account data.
+/// * `2`, slot status: `slot` u64, `has_parent` u8, `parent` u64 (0 when there is no
+///    parent), `status` u8 (0 processed, 1 confirmed, 2 rooted).
+/// * `3`, end of startup: no payload.
+use {
+    crossbeam_channel::{
+        bounded, unbounded, Receiver, RecvTimeoutError, SendTimeoutError, Sender, TrySendError,
+    },
+    log::*,
+    serde_derive::Deserialize,
+    solana_accountsdb_plugin_interface::accountsdb_plugin_interface::{
+        AccountsDbPlugin, AccountsDbPluginError, ReplicaAccountInfo, ReplicaAccountInfoVersions,
+        Result, SlotStatus,
+    },
+    solana_metrics::*,
+    std::{
+        fs::{self, File},
+        io::{ErrorKind, Read, Write},
+        os::unix::{
+            fs::{FileTypeExt, MetadataExt},
+            net::{UnixListener, UnixStream},
+        },
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc,
+        },
+        thread::{self, Builder, JoinHandle},
+        time::{Duration, Instant},
+    },
+};
+
+/// The name used in the `builtin` field of the config file to select this plugin.
+pub const UNIX_SOCKET_PLUGIN_NAME: &str = "unix_socket";
+
+/// The version of the wire format written into every frame.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+const MESSAGE_KIND_ACCOUNT_UPDATE: u8 = 1;
+const MESSAGE_KIND_SLOT_STATUS: u8 = 2;
+const MESSAGE_KIND_END_OF_STARTUP: u8 = 3;
+
+const FRAME_HEADER_SIZE: usize = 4 + 1 + 1 + 8;
+const DEFAULT_BUFFER_SIZE: usize = 100_000;
+const LISTENER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const WRITER_RECV_TIMEOUT: Duration = Duration::from_millis(100);
+const CONSUMER_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What to do with a new message when the buffer to the consumers is full.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackpressurePolicy {
+    /// Drop the message, the validator is never slowed down by the consumers.
+    Drop,
+    /// Wait for room in the buffer, the validator is slowed down to the pace of
+    /// the slowest consumer. A consumer that stalls for longer than the write
+    /// timeout is disconnected.
+    Block,
+}
+
+impl Default for BackpressurePolicy {
+    fn default() -> Self {
+        Self::Drop
+    }
+}
+
+fn default_buffer_size() -> usize {
+    DEFAULT_BUFFER_SIZE
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct UnixSocketPluginConfig {
+    /// The path of the Unix domain socket consumers connect to.
+    pub socket_path: PathBuf,
+    /// The maximum number of messages buffered for the consumers, and for each
+    /// one of them.
+    #[serde(default = "default_buffer_size")]
+    pub buffer_size: usize,
+    /// The policy applied when the buffer is full.
+    #[serde(default)]
+    pub backpressure: BackpressurePolicy,
+}
+
+fn new_frame(kind: u8, sequence: u64, payload_len: usize) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + payload_len);
+    // The length is filled in by finish_frame
+    frame.extend_from_slice(&[0u8; 4]);
+    frame.push(PROTOCOL_VERSION);
+    frame.push(kind);
+    frame.extend_from_slice(&sequence.to_le_bytes());
+    frame
+}
+
+fn finish_frame(mut frame: Vec<u8>) -> Vec<u8> {
+    let body_len = (frame.len() - 4) as u32;
+    frame[..4].copy_from_slice(&body_len.to_le_bytes());
+    frame
+}
+
+pub fn encode_account_update(
+    sequence: u64,
+    account: &ReplicaAccountInfo,
+    slot: u64,
+    is_startup: bool,
+) -> Vec<u8> {
+    let payload_len = 8 + 1 + 32 + 8 + 32 + 1 + 8 + 8 + 4 + account.data.len();
+    let mut frame = new_frame(MESSAGE_KIND_ACCOUNT_UPDATE, sequence, payload_len);
+    frame.extend_from_slice(&slot.to_le_bytes());
+    frame.push(is_startup as u8);
+    frame.extend_from_slice(account.pubkey);
+    frame.extend_from_slice(&account.lamports.to_le_bytes());
+    frame.extend_from_slice(account.owner);
+    frame.push(account.executable as u8);
+    frame.extend_from_slice(&account.rent_epoch.to_le_bytes());
+    frame.extend_from_slice(&account.write_version.to_le_bytes());
+    frame.extend_from_slice(&(account.data.len() as u32).to_le_bytes());
+    frame.extend_from_slice(account.data);
+    finish_frame(frame)
+}
+
+pub fn encode_slot_status(
+    sequence: u64,
+    slot: u64,
+    parent: Option<u64>,
+    status: &SlotStatus,
+) -> Vec<u8> {
+    let mut frame = new_frame(MESSAGE_KIND_SLOT_STATUS, sequence, 8 + 1 + 8 + 1);
+    frame.extend_from_slice(&slot.to_le_bytes());
+    frame.push(parent.is_some() as u8);
+    frame.extend_from_slice(&parent.unwrap_or_default().to_le_bytes());
+    frame.push(match status {
+        SlotStatus::Processed => 0,
+        SlotStatus::Confirmed => 1,
+        SlotStatus::Rooted => 2,
+    });
+    finish_frame(frame)
+}
+
+pub fn encode_end_of_startup(sequence: u64) -> Vec<u8> {
+    finish_frame(new_frame(MESSAGE_KIND_END_OF_STARTUP, sequence, 0))
+}
+
+/// Queue the frame for the consumers according to the backpressure policy.
+/// Returns false if the frame is dropped because the buffer is full.
+fn enqueue_frame(
+    sender: &Sender<Vec<u8>>,
+    backpressure: BackpressurePolicy,
+    frame: Vec<u8>,
+) -> std::result::Result<bool, String> {
+    match backpressure {
+        BackpressurePolicy::Block => sender
+            .send(frame)
+            .map(|_| true)
+            .map_err(|err| err.to_string()),
+        BackpressurePolicy::Drop => match sender.try_send(frame) {
+            Ok(_) => Ok(true),
+            Err(TrySendError::Full(_)) => Ok(false),
+            Err(err @ TrySendError::Disconnected(_)) => Err(err.to_string()),
+        },
+    }
+}
+
+/// A connected consumer, written to by its own thread from a bounded queue so that a stalled
+/// reader never blocks the validator or the other consumers.
+struct Consumer {
+    sender: Sender<Arc<Vec<u8>>>,
+    writer_thread: JoinHandle<()>,
+}
+
+impl Consumer {
+    fn new(stream: UnixStream, buffer_size: usize, exit: Arc<AtomicBool>) -> std::io::Result<Self> {
+        stream.set_nonblocking(false)?;
+        stream.set_write_timeout(Some(CONSUMER_WRITE_TIMEOUT))?;
+        let (sender, receiver) = bounded(buffer_size);
+        let writer_thread = Builder::new()
+            .name("solana-accountsdb-socket-consumer".to_string())
+            .spawn(move || Self::write_frames(stream, receiver, exit))?;
+        Ok(Self {
+            sender,
+            writer_thread,
+        })
+    }
+
+    fn write_frames(
+        mut stream: UnixStream,
+        receiver: Receiver<Arc<Vec<u8>>>,
+        exit: Arc<AtomicBool>,
+    ) {
+        loop {
+            match receiver.recv_timeout(WRITER_RECV_TIMEOUT) {
+                Ok(frame) => {
+                    // Also fails once the write timeout expires, the consumer is then dropped
+                    if let Err(err) = stream.write_all(&frame) {
+                        info!("AccountsDb socket consumer disconnected: {}", err);
+                        break;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if exit.load(Ordering::Relaxed) {
+                        break;
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    }
+
+    /// Queue the frame for the consumer according to the backpressure policy.
+    /// Returns false once the consumer is gone, or too far behind to keep up with the
+    /// blocking policy by the deadline.
+    fn send(
+        &self,
+        frame: Arc<Vec<u8>>,
+        backpressure: BackpressurePolicy,
+        deadline: Instant,
+    ) -> bool {
+        match backpressure {
+            BackpressurePolicy::Block => match self.sender.send_deadline(frame, deadline) {
+                Ok(_) => true,
+                Err(SendTimeoutError::Timeout(_)) => {
+                    warn!("AccountsDb socket consumer is stalled, disconnecting it");
+                    false
+                }
+                Err(SendTimeoutError::Disconnected(_)) => false,
+            },
+            BackpressurePolicy::Drop => match self.sender.try_send(frame) {
+                Ok(_) => true,
+                // The consumer sees a gap in the sequence when its queue is full
+                Err(TrySendError::Full(_)) => {
+                    inc_new_counter_info!("accountsdb-plugin-unix-socket-consumer-dropped", 1);
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            },
+        }
+    }
+
+    fn join(self) {
+        drop(self.sender);
+        if self.writer_thread.join().is_err() {
+            error!("AccountsDb socket consumer thread panicked");
+        }
+    }
+}
+
+struct SocketStreamer {
+    sender: Sender<Vec<u8>>,
+    backpressure: BackpressurePolicy,
+    sequence: u64,
+    dropped: u64,
+    num_consumers: Arc<AtomicUsize>,
+    socket_path: PathBuf,
+    // The inode of the bound socket, the path may be taken over by a reloaded plugin
+    socket_ino: u64,
+    exit: Arc<AtomicBool>,
+    listener_thread: Option<JoinHandle<()>>,
+    dispatcher_thread: Option<JoinHandle<()>>,
+}
+
+impl SocketStreamer {
+    fn new(config: &UnixSocketPluginConfig) -> std::io::Result<Self> {
+        // Remove the socket left behind by a previous run, binding fails otherwise. Anything
+        // else at the path is left alone.
+        match fs::symlink_metadata(&config.socket_path) {
+            Ok(metadata) if metadata.file_type().is_socket() => {
+                fs::remove_file(&config.socket_path)?;
+            }
+            Ok(_) => {
+                return Err(std::io::Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("{:?} exists and is not a socket", config.socket_path),
+                ));
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        let listener = UnixListener::bind(&config.socket_path)?;
+        listener.set_nonblocking(true)?;
+        let socket_ino = fs::metadata(&config.socket_path)?.ino();
+
+        let buffer_size = config.buffer_size.max(1);
+        let (sender, receiver) = bounded(buffer_size);
+        let (consumer_sender, consumer_receiver) = unbounded();
+        let num_consumers = Arc::new(AtomicUsize::new(0));
+        let exit = Arc::new(AtomicBool::new(false));
+
+        let listener_thread = {
+            let exit = exit.clone();
+            Builder::new()
+                .name("solana-accountsdb-socket-listener".to_string())
+                .spawn(move || {
+                    Self::accept_consumers(listener, consumer_sender, buffer_size, exit)
+                })?
+        };
+        let dispatcher_thread = {
+            let num_consumers = num_consumers.clone();
+            let exit = exit.clone();
+            let backpressure = config.backpressure;
+            Builder::new()
+                .name("solana-accountsdb-socket-dispatcher".to_string())
+                .spawn(move || {
+                    Self::dispatch_frames(
+                        receiver,
+                        consumer_receiver,
+                        backpressure,
+                        num_consumers,
+                        exit,
+                    )
+                })?
+        };
+
+        Ok(Self {
+            sender,
+            backpressure: config.backpressure,
+            sequence: 0,
+            dropped: 0,
+            num_consumers,
+            socket_path: config.socket_path.clone(),
+            socket_ino,
+            exit,
+            listener_thread: Some(listener_thread),
+            dispatcher_thread: Some(dispatcher_thread),
+        })
+    }
+
+    fn accept_consumers(
+        listener: UnixListener,
+        consumer_sender: Sender<Consumer>,
+        buffer_size: usize,
+        exit: Arc<AtomicBool>,
+    ) {
+        while !exit.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, _)) => match Consumer::new(stream, buffer_size, exit.clone()) {
+                    Ok(consumer) => {
+                        info!("AccountsDb socket consumer connected");
+                        if consumer_sender.send(consumer).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        error!("Failed to configure the consumer connection: {}", err);
+                    }
+                },
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(LISTENER_POLL_INTERVAL);
+                }
+                Err(err) => {
+                    error!("Failed to accept AccountsDb socket consumer: {}", err);
+                    thread::sleep(LISTENER_POLL_INTERVAL);
+                }
+            }
+        }
+    }
+
+    /// Pass every frame on to the queue of each connected consumer
+    fn dispatch_frames(
+        receiver: Receiver<Vec<u8>>,
+        consumer_receiver: Receiver<Consumer>,
+        backpressure: BackpressurePolicy,
+        num_consumers: Arc<AtomicUsize>,
+        exit: Arc<AtomicBool>,
+    ) {
+        let mut consumers: Vec<Consumer> = Vec::new();
+        loop {
+            consumers.extend(consumer_receiver.try_iter());
+            num_consumers.store(consumers.len(), Ordering::Relaxed);
+            match receiver.recv_timeout(WRITER_RECV_TIMEOUT) {
+                Ok(frame) => {
+                    // Frames produced while no consumer is connected are discarded
+                    let frame = Arc::new(frame);
+                    // The thread of a dropped consumer exits on its own once its write times
+                    // out, without holding up the other consumers. The stalled consumers share
+                    // one deadline, so a frame is never held up longer than the write timeout.
+                    let deadline = Instant::now() + CONSUMER_WRITE_TIMEOUT;
+                    consumers
+                        .retain(|consumer| consumer.send(frame.clone(), backpressure, deadline));
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if exit.load(Ordering::Relaxed) {
+                        break;
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        consumers.extend(consumer_receiver.try_iter());
+        consumers.into_iter().for_each(Consumer::join);
+        num_consumers.store(0, Ordering::Relaxed);
+    }
+
+    fn send(&mut self, frame: Vec<u8>) -> std::result::Result<(), String> {
+        self.sequence = self.sequence.wrapping_add(1);
+        if !enqueue_frame(&self.sender, self.backpressure, frame)? {
+            self.dropped += 1;
+            inc_new_counter_info!("accountsdb-plugin-unix-socket-dropped", 1);
+            if self.dropped % 10_000 == 1 {
+                warn!(
+                    "AccountsDb socket buffer is full, {} messages dropped so far",
+                    self.dropped
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn next_sequence(&self) -> u64 {
+        self.sequence.wrapping_add(1)
+    }
+
+    fn num_consumers(&self) -> usize {
+        self.num_consumers.load(Ordering::Relaxed)
+    }
+
+    fn shutdown(&mut self) {
+        self.exit.store(true, Ordering::Relaxed);
+        let threads = vec![self.listener_thread.take(), self.dispatcher_thread.take()];
+        for thread in threads.into_iter().flatten() {
+            if thread.join().is_err() {
+                error!("AccountsDb socket thread panicked");
+            }
+        }
+        let is_own_socket = fs::metadata(&self.socket_path)
+            .map(|metadata| metadata.ino() == self.socket_ino)
+            .unwrap_or(false);
+        if !is_own_socket {
+            return;
+        }
+        if let Err(err) = fs::remove_file(&self.socket_path) {
+            warn!(
+                "Failed to remove AccountsDb socket {:?}: {}",
+                self.socket_path, err
+            );
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct UnixSocketPlugin {
+    streamer: Option<SocketStreamer>,
+}
+
+impl std::fmt::Debug for UnixSocketPlugin {
+    fn fmt(&self, _: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Ok(())
+    }
+}
+
+impl UnixSocketPlugin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn streamer(&mut self) -> std::result::Result<&mut SocketStreamer, String> {
+        self.streamer
+            .as_mut()
+            .ok_or_else(|| "The Unix socket plugin is not loaded".to_string())
+    }
+}
+
+impl AccountsDbPlugin for UnixSocketPlugin {
+    fn name(&self) -> &'static str {
+        "AccountsDbUnixSocketPlugin"
+    }
+
+    /// Bind the Unix domain socket described by the config file.
+    ///
+    /// # Format of the config file:
+    /// * "builtin", must be "unix_socket" to select this plugin.
+    /// * "socket_path", the path of the Unix domain socket consumers connect to.
+    /// * "buffer_size", optional, the maximum number of messages buffered for the
+    /// consumers. The default is '100000'.
+    /// * "backpressure", optional, either "drop" to drop messages or "block" to stall
+    /// the validator when the buffer is full. The default is 'drop'.
+    /// # Examples
+    ///
+    /// {
+    ///    "builtin": "unix_socket",
+    ///    "socket_path": "/home/solana/accountsdb.sock",
+    ///    "buffer_size": 100000,
+    ///    "backpressure": "drop"
+    /// }
+    fn on_load(&mut self, config_file: &str) -> Result<()> {
+        info!(
+            "Loading plugin {:?} from config_file {:?}",
+            self.name(),
+            config_file
+        );
+        let mut file = File::open(config_file)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+
+        let config: UnixSocketPluginConfig = serde_json::from_str(&contents).map_err(|err| {
+            AccountsDbPluginError::ConfigFileReadError {
+                msg: format!(
+                    "The config file is not in the JSON format expected: {:?}",
+                    err
+                ),
+            }
+        })?;
+        let streamer = SocketStreamer::new(&config).map_err(|err| {
+            AccountsDbPluginError::Custom(Box::new(std::io::Error::new(
+                err.kind(),
+                format!(
+                    "Failed to bind the Unix socket {:?}: {}",
+                    config.socket_path, err
+                ),
+            )))
+        })?;
+        self.streamer = Some(streamer);
+        Ok(())
+    }
+
+    fn on_unload(&mut self) {
+        info!("Unloading plugin: {:?}", self.name());
+        if let Some(mut streamer) = self.streamer.take() {
+            streamer.shutdown();
+        }
+    }
+
+    fn update_account(
+        &mut self,
+        account: ReplicaAccountInfoVersions,
+        slot: u64,
+        is_startup: bool,
+    ) -> Result<()> {
+        let streamer = self
+            .streamer()
+            .map_err(|msg| AccountsDbPluginError::AccountsUpdateError { msg })?;
+        let frame = match account {
+            ReplicaAccountInfoVersions::V0_0_1(account) => {
+                encode_account_update(streamer.next_sequence(), account, slot, is_startup)
+            }
+        };
+        streamer
+            .send(frame)
+            .map_err(|msg| AccountsDbPluginError::AccountsUpdateError { msg })
+    }
+
+    fn notify_end_of_startup(&mut self) -> Result<()> {
+        let streamer = self
+            .streamer()
+            .map_err(|msg| AccountsDbPluginError::AccountsUpdateError { msg })?;
+        let frame = encode_end_of_startup(streamer.next_sequence());
+        streamer
+            .send(frame)
+            .map_err(|msg| AccountsDbPluginError::AccountsUpdateError { msg })
+    }
+
+    fn update_slot_status(
+        &mut self,
+        slot: u64,
+        parent: Option<u64>,
+        status: SlotStatus,
+    ) -> Result<()> {
+        let streamer = self
+            .streamer()
+            .map_err(|msg| AccountsDbPluginError::SlotStatusUpdateError { msg })?;
+        let frame = encode_slot_status(streamer.next_sequence(), slot, parent, &status);
+        streamer
+            .send(frame)
+            .map_err(|msg| AccountsDbPluginError::SlotStatusUpdateError { msg })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use {super::*, solana_sdk::pubkey::Pubkey, std::convert::TryInto, tempfile::TempDir};
+
+    fn read_frame(stream: &mut UnixStream) -> Vec<u8> {
+        let mut len = [0u8; 4];
+        stream.read_exact(&mut len).unwrap();
+        let mut body = vec![0u8; u32::from_le_bytes(len) as usize];
+        stream.read_exact(&mut body).unwrap();
+        body
+    }
+
+    fn read_u64(bytes: &[u8]) -> u64 {
+        u64::from_le_bytes(bytes[..8].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_encode_account_update() {
+        let pubkey = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let account = ReplicaAccountInfo {
+            pubkey: pubkey.as_ref(),
+            lamports: 42,
+            owner: owner.as_ref(),
+            executable: true,
+            rent_epoch: 3,
+            data: &[7, 8, 9],
+            write_version: 11,
+        };
+        let frame = encode_account_update(5, &account, 100, true);
+        assert_eq!(
+            u32::from_le_bytes(frame[..4].try_into().unwrap()) as usize,
+            frame.len() - 4
+        );
+
+        let body = &frame[4..];
+        assert_eq!(body[0], PROTOCOL_VERSION);
+        assert_eq!(body[1], MESSAGE_KIND_ACCOUNT_UPDATE);
+        assert_eq!(read_u64(&body[2..]), 5);
+        let payload = &body[10..];
+        assert_eq!(read_u64(payload), 100);
+        assert_eq!(payload[8], 1);
+        assert_eq!(&payload[9..41], pubkey.as_ref());
+        assert_eq!(read_u64(&payload[41..]), 42);
+        assert_eq!(&payload[49..81], owner.as_ref());
+        assert_eq!(payload[81], 1);
+        assert_eq!(read_u64(&payload[82..]), 3);
+        assert_eq!(read_u64(&payload[90..]), 11);
+        assert_eq!(u32::from_le_bytes(payload[98..102].try_into().unwrap()), 3);
+        assert_eq!(&payload[102..], &[7, 8, 9]);
+    }
+
+    #[test]
+    fn test_encode_slot_status() {
+        let frame = encode_slot_status(1, 10, Some(9), &SlotStatus::Rooted);
+        let payload = &frame[FRAME_HEADER_SIZE..];
+        assert_eq!(frame[5], MESSAGE_KIND_SLOT_STATUS);
+        assert_eq!(read_u64(payload), 10);
+        assert_eq!(payload[8], 1);
+        assert_eq!(read_u64(&payload[9..]), 9);
+        assert_eq!(payload[17], 2);
+
+        let frame = encode_slot_status(2, 10, None, &SlotStatus::Processed);
+        let payload = &frame[FRAME_HEADER_SIZE..];
+        assert_eq!(payload[8], 0);
+        assert_eq!(read_u64(&payload[9..]), 0);
+        assert_eq!(payload[17], 0);
+    }
+
+    #[test]
+    fn test_enqueue_frame_backpressure() {
+        let (sender, receiver) = bounded(1);
+        assert_eq!(
+            enqueue_frame(&sender, BackpressurePolicy::Drop, vec![1]),
+            Ok(true)
+        );
+        assert_eq!(
+            enqueue_frame(&sender, BackpressurePolicy::Drop, vec![2]),
+            Ok(false)
+        );
+        assert_eq!(receiver.recv().unwrap(), vec![1]);
+
+        let blocked_sender = sender.clone();
+        enqueue_frame(&sender, BackpressurePolicy::Drop, vec![3]).unwrap();
+        let blocked = thread::spawn(move || {
+            enqueue_frame(&blocked_sender, BackpressurePolicy::Block, vec![4])
+        });
+        assert_eq!(receiver.recv().unwrap(), vec![3]);
+        assert_eq!(blocked.join().unwrap(), Ok(true));
+        assert_eq!(receiver.recv().unwrap(), vec![4]);
+
+        drop(receiver);
+        assert!(enqueue_frame(&sender, BackpressurePolicy::Drop, vec![5]).is_err());
+        assert!(enqueue_frame(&sender, BackpressurePolicy::Block, vec![6]).is_err());
+    }
+
+    #[test]
+    fn test_config_defaults() {
+        let config: UnixSocketPluginConfig =
+            serde_json::from_str(r#"{"builtin": "unix_socket", "socket_path": "/tmp/a.sock"}"#)
+                .unwrap();
+        assert_eq!(config.socket_path, PathBuf::from("/tmp/a.sock"));
+        assert_eq!(config.buffer_size, DEFAULT_BUFFER_SIZE);
+        assert_eq!(config.backpressure, BackpressurePolicy::Drop);
+
+        let config: UnixSocketPluginConfig = serde_json::from_str(
+            r#"{"socket_path": "/tmp/a.sock", "buffer_size": 10, "backpressure": "block"}"#,
+        )
+        .unwrap();
+        assert_eq!(config.buffer_size, 10);
+        assert_eq!(config.backpressure, BackpressurePolicy::Block);
+    }
+
+    #[test]
+    fn test_stream_to_consumer() {
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("accountsdb.sock");
+        let config_path = temp_dir.path().join("config.json");
+        fs::write(
+            &config_path,
+            format!(
+                r#"{{"builtin": "unix_socket", "socket_path": {:?}, "backpressure": "block"}}"#,
+                socket_path
+            ),
+        )
+        .unwrap();
+
+        let mut plugin = UnixSocketPlugin::new();
+        plugin.on_load(config_path.to_str().unwrap()).unwrap();
+        let mut stream = UnixStream::connect(&socket_path).unwrap();
+        let start = Instant::now();
+        while plugin.streamer.as_ref().unwrap().num_consumers() == 0 {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        plugin
+            .update_slot_status(5, Some(4), SlotStatus::Confirmed)
+            .unwrap();
+        plugin.notify_end_of_startup().unwrap();
+
+        let body = read_frame(&mut stream);
+        assert_eq!(body[1], MESSAGE_KIND_SLOT_STATUS);
+        assert_eq!(read_u64(&body[2..]), 1);
+        assert_eq!(read_u64(&body[10..]), 5);
+        let body = read_frame(&mut stream);
+        assert_eq!(body[1], MESSAGE_KIND_END_OF_STARTUP);
+        assert_eq!(read_u64(&body[2..]), 2);
+        assert_eq!(body.len(), FRAME_HEADER_SIZE - 4);
+
+        plugin.on_unload();
+        assert!(!socket_path.exists());
+        assert!(plugin
+            .update_slot_status(6, None, SlotStatus::Processed)
+            .is_err());
+    }
+
+    #[test]
+    fn test_socket_path_not_a_socket() {
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("accountsdb.sock");
+        fs::write(&socket_path, b"not a socket").unwrap();
+        let config = UnixSocketPluginConfig {
+            socket_path: socket_path.clone(),
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            backpressure: BackpressurePolicy::Drop,
+        };
+        assert_eq!(
+            SocketStreamer::new(&config).err().unwrap().kind(),
+            ErrorKind::AlreadyExists
+        );
+        assert_eq!(fs::read(&socket_path).unwrap(), b"not a socket");
+    }
+
+    #[test]
+    fn test_stalled_consumer() {
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("accountsdb.sock");
+        let config_path = temp_dir.path().join("config.json");
+        fs::write(
+            &config_path,
+            format!(
+                r#"{{"builtin": "unix_socket", "socket_path": {:?}, "buffer_size": 4}}"#,
+                socket_path
+            ),
+        )
+        .unwrap();
+
+        let mut plugin = UnixSocketPlugin::new();
+        plugin.on_load(config_path.to_str().unwrap()).unwrap();
+        // The first consumer never reads, the second one does
+        let _stalled_stream = UnixStream::connect(&socket_path).unwrap();
+        let mut stream = UnixStream::connect(&socket_path).unwrap();
+        let start = Instant::now();
+        while plugin.streamer.as_ref().unwrap().num_consumers() < 2 {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let (num_frames_sender, num_frames_receiver) = bounded(1);
+        thread::spawn(move || {
+            let mut num_frames = 0;
+            loop {
+                let body = read_frame(&mut stream);
+                if body[1] == MESSAGE_KIND_END_OF_STARTUP {
+                    num_frames_sender.send(num_frames).unwrap();
+                    return;
+                }
+                num_frames += 1;
+            }
+        });
+        let pubkey = Pubkey::new_unique();
+        let data = vec![0u8; 64 * 1024];
+        for slot in 0..100 {
+            let account = ReplicaAccountInfo {
+                pubkey: pubkey.as_ref(),
+                lamports: 1,
+                owner: pubkey.as_ref(),
+                executable: false,
+                rent_epoch: 0,
+                data: &data,
+                write_version: slot,
+            };
+            plugin
+                .update_account(ReplicaAccountInfoVersions::V0_0_1(&account), slot, false)
+                .unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+        // Keep sending until the reading consumer gets the end of startup message
+        let num_frames = loop {
+            plugin.notify_end_of_startup().unwrap();
+            if let Ok(num_frames) = num_frames_receiver.recv_timeout(Duration::from_millis(10)) {
+                break num_frames;
+            }
+            assert!(start.elapsed() < Duration::from_secs(30));
+        };
+        assert!(num_frames > 0);
+
+        // Unloading doesn't wait on the stalled consumer for longer than the write timeout
+        plugin.on_unload();
+        assert!(start.elapsed() < Duration::from_secs(30));
+    }
+}
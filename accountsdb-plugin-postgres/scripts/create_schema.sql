@@ -24,8 +24,120 @@ CREATE TABLE slot (
     updated_on TIMESTAMP NOT NULL
 );
 
+-- The table storing transactions selected by the transaction_selector
+CREATE TABLE transaction (
+    signature BYTEA NOT NULL,
+    slot BIGINT NOT NULL,
+    is_vote BOOL NOT NULL,
+    message_hash BYTEA NOT NULL,
+    signatures BYTEA[] NOT NULL,
+    account_keys BYTEA[] NOT NULL,
+    fee BIGINT NOT NULL,
+    error TEXT,
+    pre_balances BIGINT[] NOT NULL,
+    post_balances BIGINT[] NOT NULL,
+    log_messages TEXT[],
+    updated_on TIMESTAMP NOT NULL,
+    CONSTRAINT transaction_pk PRIMARY KEY (slot, signature)
+);
+
+CREATE TYPE "RewardType" AS ENUM (
+    'Fee',
+    'Rent',
+    'Staking',
+    'Voting'
+);
+
+CREATE TYPE "Reward" AS (
+    pubkey VARCHAR(44),
+    lamports BIGINT,
+    post_balance BIGINT,
+    reward_type "RewardType",
+    commission SMALLINT
+);
+
+-- The table storing block metadata
+CREATE TABLE block (
+    slot BIGINT PRIMARY KEY,
+    parent_slot BIGINT,
+    blockhash VARCHAR(44),
+    rewards "Reward"[],
+    block_time BIGINT,
+    block_height BIGINT,
+    updated_on TIMESTAMP NOT NULL
+);
+
+-- The table storing the accounts parsed by the account-decoder when "store_parsed_accounts" is enabled
+CREATE TABLE account_parsed (
+    pubkey BYTEA PRIMARY KEY,
+    -- The parsing program, e.g. 'vote', 'stake' or 'spl-token'
+    program VARCHAR(32) NOT NULL,
+    parsed JSONB NOT NULL,
+    slot BIGINT NOT NULL,
+    write_version BIGINT NOT NULL,
+    updated_on TIMESTAMP NOT NULL
+);
+
+CREATE INDEX account_parsed_program ON account_parsed (program);
+
+/**
+ * The following is for the account history kept by the plugin when "account_history" is enabled
+ * in the plugin config and is not required for plugin to work otherwise.
+ */
+-- The table storing every update of the selected accounts
+CREATE TABLE account_history (
+    pubkey BYTEA NOT NULL,
+    slot BIGINT NOT NULL,
+    write_version BIGINT NOT NULL,
+    owner BYTEA,
+    lamports BIGINT NOT NULL,
+    executable BOOL NOT NULL,
+    rent_epoch BIGINT NOT NULL,
+    -- NULL when the data is deduplicated into account_data
+    data BYTEA,
+    -- Set when the data is deduplicated into account_data
+    data_hash BYTEA,
+    updated_on TIMESTAMP NOT NULL,
+    CONSTRAINT account_history_pk PRIMARY KEY (pubkey, slot, write_version)
+);
+
+CREATE INDEX account_history_slot ON account_history (slot);
+CREATE INDEX account_history_data_hash ON account_history (data_hash);
+
+-- The table storing the distinct account data when "account_history_dedup_data" is enabled
+CREATE TABLE account_data (
+    data_hash BYTEA PRIMARY KEY,
+    data BYTEA NOT NULL
+);
+
+-- Get the state of an account at a slot, i.e. its latest update at or before the slot.
+-- Updates made in slots not rooted yet, including the ones on abandoned forks, are only
+-- considered when rooted_only is false.
+CREATE FUNCTION get_account_at_slot(target_pubkey BYTEA, target_slot BIGINT, rooted_only BOOL DEFAULT false)
+RETURNS TABLE (
+    pubkey BYTEA,
+    slot BIGINT,
+    write_version BIGINT,
+    owner BYTEA,
+    lamports BIGINT,
+    executable BOOL,
+    rent_epoch BIGINT,
+    data BYTEA
+) AS $get_account_at_slot$
+    SELECT hist.pubkey, hist.slot, hist.write_version, hist.owner, hist.lamports, hist.executable,
+        hist.rent_epoch, COALESCE(hist.data, dedup.data)
+    FROM account_history AS hist
+        LEFT JOIN account_data AS dedup ON hist.data_hash = dedup.data_hash
+    WHERE hist.pubkey = target_pubkey AND hist.slot <= target_slot
+        AND (NOT rooted_only OR EXISTS (
+            SELECT 1 FROM slot WHERE slot.slot = hist.slot AND slot.status = 'rooted'))
+    ORDER BY hist.slot DESC, hist.write_version DESC
+    LIMIT 1;
+$get_account_at_slot$ LANGUAGE SQL STABLE;
+
 /**
- * The following is for keeping historical data for accounts and is not required for plugin to work.
+ * The following is for keeping historical data for accounts using a trigger and is not required for plugin to work.
+ * The account_history table above is more compact and can be queried by slot.
  */
 -- The table storing historical data for accounts
 CREATE TABLE account_audit (
@@ -5,5 +5,14 @@
 DROP TRIGGER account_update_trigger ON account;
 DROP FUNCTION audit_account_update;
 DROP TABLE account_audit;
+DROP FUNCTION get_account_at_slot;
+DROP TABLE account_history;
+DROP TABLE account_data;
+DROP TABLE account_parsed;
 DROP TABLE account;
 DROP TABLE slot;
+DROP TABLE transaction;
+DROP TABLE block;
+
+DROP TYPE "Reward";
+DROP TYPE "RewardType";
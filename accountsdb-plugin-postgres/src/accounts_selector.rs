@@ -1,10 +1,85 @@
-use {log::*, std::collections::HashSet};
+use {
+    log::*,
+    serde_derive::Deserialize,
+    solana_account_decoder::parse_token::{get_token_account_mint, spl_token_id_v2_0},
+    solana_accountsdb_plugin_interface::accountsdb_plugin_interface::ReplicaAccountInfo,
+    solana_client::rpc_filter::{Memcmp, RpcFilterType},
+    std::collections::HashSet,
+};
+
+/// The filters narrowing down the accounts selected by pubkey, owner or token mint.
+/// `DataSize` and `Memcmp` have the same format and meaning as the RPC `RpcFilterType`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum AccountsFilter {
+    DataSize(u64),
+    Memcmp(Memcmp),
+    Lamports { min: Option<u64>, max: Option<u64> },
+}
+
+/// The filters in the form they are applied, with the memcmp bytes decoded once.
+#[derive(Debug, PartialEq)]
+enum DecodedAccountsFilter {
+    DataSize(u64),
+    Memcmp { offset: usize, bytes: Vec<u8> },
+    Lamports { min: Option<u64>, max: Option<u64> },
+}
+
+impl DecodedAccountsFilter {
+    fn new(filter: &AccountsFilter) -> Result<Self, String> {
+        Ok(match filter {
+            AccountsFilter::DataSize(size) => Self::DataSize(*size),
+            AccountsFilter::Memcmp(memcmp) => {
+                RpcFilterType::Memcmp(memcmp.clone())
+                    .verify()
+                    .map_err(|err| format!("Invalid memcmp filter {:?}: {}", memcmp, err))?;
+                let bytes = memcmp
+                    .bytes()
+                    .ok_or_else(|| format!("Invalid memcmp filter bytes {:?}", memcmp))?;
+                Self::Memcmp {
+                    offset: memcmp.offset,
+                    bytes: bytes.into_owned(),
+                }
+            }
+            AccountsFilter::Lamports { min, max } => Self::Lamports {
+                min: *min,
+                max: *max,
+            },
+        })
+    }
+
+    fn matches(&self, account: &ReplicaAccountInfo) -> bool {
+        match self {
+            Self::DataSize(size) => account.data.len() as u64 == *size,
+            Self::Memcmp { offset, bytes } => account
+                .data
+                .get(*offset..)
+                .map_or(false, |data| data.starts_with(bytes)),
+            Self::Lamports { min, max } => {
+                min.map_or(true, |min| account.lamports >= min)
+                    && max.map_or(true, |max| account.lamports <= max)
+            }
+        }
+    }
+}
 
 #[derive(Debug)]
 pub(crate) struct AccountsSelector {
     pub accounts: HashSet<Vec<u8>>,
     pub owners: HashSet<Vec<u8>>,
+    pub token_mints: HashSet<Vec<u8>>,
     pub select_all_accounts: bool,
+    filters: Vec<DecodedAccountsFilter>,
+}
+
+fn decode_pubkeys(keys: &[String]) -> Result<HashSet<Vec<u8>>, String> {
+    keys.iter()
+        .map(|key| {
+            bs58::decode(key)
+                .into_vec()
+                .map_err(|err| format!("Invalid pubkey {:?}: {}", key, err))
+        })
+        .collect()
 }
 
 impl AccountsSelector {
@@ -12,52 +87,110 @@ impl AccountsSelector {
         AccountsSelector {
             accounts: HashSet::default(),
             owners: HashSet::default(),
+            token_mints: HashSet::default(),
             select_all_accounts: true,
+            filters: Vec::default(),
         }
     }
 
     pub fn new(accounts: &[String], owners: &[String]) -> Self {
+        Self::new_with_filters(accounts, owners, &[], &[]).unwrap()
+    }
+
+    pub fn new_with_filters(
+        accounts: &[String],
+        owners: &[String],
+        token_mints: &[String],
+        filters: &[AccountsFilter],
+    ) -> Result<Self, String> {
         info!(
-            "Creating AccountsSelector from accounts: {:?}, owners: {:?}",
-            accounts, owners
+            "Creating AccountsSelector from accounts: {:?}, owners: {:?}, token_mints: {:?}, filters: {:?}",
+            accounts, owners, token_mints, filters
         );
 
+        let filters = filters
+            .iter()
+            .map(DecodedAccountsFilter::new)
+            .collect::<Result<_, _>>()?;
         let select_all_accounts = accounts.iter().any(|key| key == "*");
         if select_all_accounts {
-            return AccountsSelector {
+            return Ok(AccountsSelector {
                 accounts: HashSet::default(),
                 owners: HashSet::default(),
+                token_mints: HashSet::default(),
                 select_all_accounts,
-            };
+                filters,
+            });
         }
-        let accounts = accounts
-            .iter()
-            .map(|key| bs58::decode(key).into_vec().unwrap())
-            .collect();
-        let owners = owners
-            .iter()
-            .map(|key| bs58::decode(key).into_vec().unwrap())
-            .collect();
-        AccountsSelector {
-            accounts,
-            owners,
+        Ok(AccountsSelector {
+            accounts: decode_pubkeys(accounts)?,
+            owners: decode_pubkeys(owners)?,
+            token_mints: decode_pubkeys(token_mints)?,
             select_all_accounts,
-        }
+            filters,
+        })
+    }
+
+    /// An account is selected when it is matched by its pubkey, its owner or, for SPL token
+    /// accounts and the mint accounts themselves, its mint, and it passes all the filters.
+    pub fn is_account_selected(&self, account: &ReplicaAccountInfo) -> bool {
+        (self.select_all_accounts
+            || self.accounts.contains(account.pubkey)
+            || self.owners.contains(account.owner)
+            || self.token_mints.contains(account.pubkey)
+            || self.is_token_mint_selected(account))
+            && self.filters.iter().all(|filter| filter.matches(account))
+    }
+
+    /// Whether the selection of an account can change with its state, the accounts selected
+    /// by pubkey alone always are. An account which is no longer selected, e.g. as it was
+    /// assigned to another owner, is to be removed from the database.
+    pub fn is_selected_by_state(&self) -> bool {
+        !self.owners.is_empty() || !self.token_mints.is_empty() || !self.filters.is_empty()
     }
 
-    pub fn is_account_selected(&self, account: &[u8], owner: &[u8]) -> bool {
-        self.select_all_accounts || self.accounts.contains(account) || self.owners.contains(owner)
+    fn is_token_mint_selected(&self, account: &ReplicaAccountInfo) -> bool {
+        if self.token_mints.is_empty() || account.owner != spl_token_id_v2_0().as_ref() {
+            return false;
+        }
+        get_token_account_mint(account.data)
+            .map_or(false, |mint| self.token_mints.contains(mint.as_ref()))
     }
 
     /// Check if any account is of interested at all
     pub fn is_enabled(&self) -> bool {
-        self.select_all_accounts || !self.accounts.is_empty() || !self.owners.is_empty()
+        self.select_all_accounts
+            || !self.accounts.is_empty()
+            || !self.owners.is_empty()
+            || !self.token_mints.is_empty()
     }
 }
 
 #[cfg(test)]
 pub(crate) mod tests {
-    use super::*;
+    use {
+        super::*,
+        solana_client::rpc_filter::MemcmpEncodedBytes,
+        solana_sdk::pubkey::Pubkey,
+        spl_token_v2_0::{solana_program::program_pack::Pack, state::Account as TokenAccount},
+    };
+
+    fn new_account<'a>(
+        pubkey: &'a Pubkey,
+        owner: &'a Pubkey,
+        lamports: u64,
+        data: &'a [u8],
+    ) -> ReplicaAccountInfo<'a> {
+        ReplicaAccountInfo {
+            pubkey: pubkey.as_ref(),
+            lamports,
+            owner: owner.as_ref(),
+            executable: false,
+            rent_epoch: 0,
+            data,
+            write_version: 0,
+        }
+    }
 
     #[test]
     fn test_create_accounts_selector() {
@@ -70,5 +203,105 @@ pub(crate) mod tests {
             &[],
             &["9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin".to_string()],
         );
+
+        assert!(
+            AccountsSelector::new_with_filters(&["invalid-pubkey".to_string()], &[], &[], &[])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_select_accounts_with_filters() {
+        let owner = Pubkey::new_unique();
+        let pubkey = Pubkey::new_unique();
+        let selector = AccountsSelector::new_with_filters(
+            &[],
+            &[owner.to_string()],
+            &[],
+            &[
+                AccountsFilter::DataSize(4),
+                AccountsFilter::Memcmp(Memcmp {
+                    offset: 1,
+                    bytes: MemcmpEncodedBytes::Base58(bs58::encode([2u8, 3]).into_string()),
+                    encoding: None,
+                }),
+                AccountsFilter::Lamports {
+                    min: Some(10),
+                    max: None,
+                },
+            ],
+        )
+        .unwrap();
+        assert!(selector.is_enabled());
+
+        assert!(selector.is_account_selected(&new_account(&pubkey, &owner, 10, &[1, 2, 3, 4])));
+        // Not matching the owner
+        assert!(!selector.is_account_selected(&new_account(
+            &pubkey,
+            &Pubkey::new_unique(),
+            10,
+            &[1, 2, 3, 4]
+        )));
+        // Not matching the data size
+        assert!(!selector.is_account_selected(&new_account(&pubkey, &owner, 10, &[1, 2, 3])));
+        // Not matching the memcmp
+        assert!(!selector.is_account_selected(&new_account(&pubkey, &owner, 10, &[1, 2, 4, 4])));
+        // Not matching the lamports
+        assert!(!selector.is_account_selected(&new_account(&pubkey, &owner, 9, &[1, 2, 3, 4])));
+    }
+
+    #[test]
+    fn test_select_token_accounts_by_mint() {
+        let mint = Pubkey::new_unique();
+        let selector =
+            AccountsSelector::new_with_filters(&[], &[], &[mint.to_string()], &[]).unwrap();
+        assert!(selector.is_enabled());
+
+        let mut data = vec![0; TokenAccount::get_packed_len()];
+        data[..32].copy_from_slice(mint.as_ref());
+        let pubkey = Pubkey::new_unique();
+        let token_program = spl_token_id_v2_0();
+        assert!(selector.is_account_selected(&new_account(&pubkey, &token_program, 1, &data)));
+        // The mint account, which gives the decimals of the token accounts
+        assert!(selector.is_account_selected(&new_account(&mint, &token_program, 1, &[0; 82])));
+
+        // Owned by another program
+        assert!(!selector.is_account_selected(&new_account(
+            &pubkey,
+            &Pubkey::new_unique(),
+            1,
+            &data
+        )));
+        // Another mint
+        data[..32].copy_from_slice(Pubkey::new_unique().as_ref());
+        assert!(!selector.is_account_selected(&new_account(&pubkey, &token_program, 1, &data)));
+        // Not a token account
+        assert!(!selector.is_account_selected(&new_account(
+            &pubkey,
+            &token_program,
+            1,
+            mint.as_ref()
+        )));
+    }
+
+    #[test]
+    fn test_is_selected_by_state() {
+        let pubkey = Pubkey::new_unique();
+        assert!(!AccountsSelector::new(&[pubkey.to_string()], &[]).is_selected_by_state());
+        assert!(!AccountsSelector::new(&["*".to_string()], &[]).is_selected_by_state());
+        assert!(AccountsSelector::new(&[], &[pubkey.to_string()]).is_selected_by_state());
+        assert!(
+            AccountsSelector::new_with_filters(&[], &[], &[pubkey.to_string()], &[])
+                .unwrap()
+                .is_selected_by_state()
+        );
+        assert!(AccountsSelector::new_with_filters(
+            &["*".to_string()],
+            &[],
+            &[],
+            &[AccountsFilter::DataSize(4)]
+        )
+        .unwrap()
+        .is_selected_by_state());
     }
 }
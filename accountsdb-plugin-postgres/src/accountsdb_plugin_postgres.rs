@@ -3,15 +3,17 @@ use solana_measure::measure::Measure;
 /// Main entry for the PostgreSQL plugin
 use {
     crate::{
-        accounts_selector::AccountsSelector,
+        accounts_selector::{AccountsFilter, AccountsSelector},
         postgres_client::{ParallelPostgresClient, PostgresClientBuilder},
+        transaction_selector::TransactionSelector,
     },
     bs58,
     log::*,
     serde_derive::{Deserialize, Serialize},
     serde_json,
     solana_accountsdb_plugin_interface::accountsdb_plugin_interface::{
-        AccountsDbPlugin, AccountsDbPluginError, ReplicaAccountInfoVersions, Result, SlotStatus,
+        AccountsDbPlugin, AccountsDbPluginError, ReplicaAccountInfoVersions,
+        ReplicaBlockInfoVersions, ReplicaTransactionInfoVersions, Result, SlotStatus,
     },
     solana_metrics::*,
     std::{fs::File, io::Read},
@@ -22,6 +24,7 @@ use {
 pub struct AccountsDbPluginPostgres {
     client: Option<ParallelPostgresClient>,
     accounts_selector: Option<AccountsSelector>,
+    transaction_selector: Option<TransactionSelector>,
 }
 
 impl std::fmt::Debug for AccountsDbPluginPostgres {
@@ -39,6 +42,20 @@ pub struct AccountsDbPluginPostgresConfig {
     pub threads: Option<usize>,
     pub batch_size: Option<usize>,
     pub panic_on_db_errors: Option<bool>,
+    pub account_history: Option<bool>,
+    pub account_history_dedup_data: Option<bool>,
+    pub account_history_retention_slots: Option<u64>,
+    pub commitment_level: Option<CommitmentLevel>,
+    pub store_parsed_accounts: Option<bool>,
+}
+
+/// The commitment level a slot must reach before its account updates are written
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommitmentLevel {
+    Processed,
+    Confirmed,
+    Rooted,
 }
 
 #[derive(Error, Debug)]
@@ -69,13 +86,37 @@ impl AccountsDbPlugin for AccountsDbPluginPostgres {
     /// "accounts_selector" = {
     ///     "owners" : \["pubkey-1", "pubkey-2", ..., "pubkey-m"\]
     /// }
-    /// Accounts either satisyfing the accounts condition or owners condition will be selected.
-    /// When only owners is specified,
+    /// or:
+    /// "accounts_selector" = {
+    ///     "token_mints" : \["mint-1", "mint-2", ..., "mint-k"\]
+    /// }
+    /// Accounts either satisyfing the accounts condition, owners condition or token_mints
+    /// condition will be selected. When only owners is specified,
     /// all accounts belonging to the owners will be streamed.
+    /// The token_mints condition selects the SPL token accounts of the given mints.
     /// The accounts field support wildcard to select all accounts:
     /// "accounts_selector" : {
     ///     "accounts" : \["*"\],
     /// }
+    /// The selected accounts can be narrowed down further with filters, all of which must match.
+    /// "dataSize" and "memcmp" are the same as the filters of the `getProgramAccounts` RPC method:
+    /// "accounts_selector" : {
+    ///     "owners" : \["pubkey-1"\],
+    ///     "filters" : \[
+    ///         {"dataSize": 165},
+    ///         {"memcmp": {"offset": 32, "bytes": "base58-encoded-bytes"}},
+    ///         {"lamports": {"min": 1000000, "max": 2000000}}
+    ///     \]
+    /// }
+    /// An account selected by its owner, mint or filters is removed from the database once it
+    /// is no longer selected, e.g. when it is assigned to another owner.
+    /// * The `transaction_selector` section allows the user to control transaction selections.
+    /// "transaction_selector" : {
+    ///     "mentions" : \["pubkey-1", "pubkey-2", ..., "pubkey-n"\],
+    /// }
+    /// Transactions mentioning any of the addresses are selected and saved into the
+    /// `transaction` table. Use \["*"\] to select all transactions and \["all_votes"\] to
+    /// select all vote transactions. When the section is not given, no transaction is selected.
     /// * "host", optional, specifies the PostgreSQL server.
     /// * "user", optional, specifies the PostgreSQL user.
     /// * "port", optional, specifies the PostgreSQL server's port.
@@ -89,6 +130,25 @@ impl AccountsDbPlugin for AccountsDbPluginPostgres {
     /// from restoring a snapshot. The default is '10'.
     /// * "panic_on_db_errors", optional, contols if to panic when there are errors replicating data to the
     /// PostgreSQL database. The default is 'false'.
+    /// * "account_history", optional, controls if every update of the selected accounts is kept
+    /// in the `account_history` table, keyed by (pubkey, slot, write_version). The state of an
+    /// account at a slot can be looked up using the `get_account_at_slot` SQL function.
+    /// The default is 'false'.
+    /// * "account_history_dedup_data", optional, controls if the account data in the history is
+    /// stored once per distinct content in the `account_data` table, keyed by its hash.
+    /// The default is 'false'.
+    /// * "account_history_retention_slots", optional, the number of slots of account history
+    /// to keep behind the latest root. The latest version of an account before the window is
+    /// always kept. The default is to keep the whole history.
+    /// * "commitment_level", optional, one of "processed", "confirmed" or "rooted". The account
+    /// updates of a slot are held back in memory until the slot reaches this commitment level,
+    /// and discarded if the slot is pruned off the fork, so only the canonical state is written.
+    /// The default is 'processed', writing the updates as soon as they are notified.
+    /// * "store_parsed_accounts", optional, controls if the selected stake, vote, nonce, sysvar,
+    /// config, upgradeable loader and SPL token accounts are parsed by the account-decoder and
+    /// stored as JSON in the `account_parsed` table, in the same format as the `jsonParsed`
+    /// encoding of the RPC. SPL token accounts are only parsed when their mint account is
+    /// selected as well, as its decimals are needed. The default is 'false'.
     /// # Examples
     ///
     /// {
@@ -113,7 +173,8 @@ impl AccountsDbPlugin for AccountsDbPluginPostgres {
         file.read_to_string(&mut contents)?;
 
         let result: serde_json::Value = serde_json::from_str(&contents).unwrap();
-        self.accounts_selector = Some(Self::create_accounts_selector_from_config(&result));
+        self.accounts_selector = Some(Self::create_accounts_selector_from_config(&result)?);
+        self.transaction_selector = Some(Self::create_transaction_selector_from_config(&result));
 
         let result: serde_json::Result<AccountsDbPluginPostgresConfig> =
             serde_json::from_str(&contents);
@@ -157,13 +218,19 @@ impl AccountsDbPlugin for AccountsDbPluginPostgres {
             ReplicaAccountInfoVersions::V0_0_1(account) => {
                 let mut measure_select =
                     Measure::start("accountsdb-plugin-postgres-update-account-select");
-                if let Some(accounts_selector) = &self.accounts_selector {
-                    if !accounts_selector.is_account_selected(account.pubkey, account.owner) {
-                        return Ok(());
+                // An account which is not selected is removed from the database in case it was
+                // selected before, except at startup where nothing is written for it yet.
+                let is_selected = match &self.accounts_selector {
+                    Some(accounts_selector) => {
+                        let is_selected = accounts_selector.is_account_selected(account);
+                        if !is_selected && (is_startup || !accounts_selector.is_selected_by_state())
+                        {
+                            return Ok(());
+                        }
+                        is_selected
                     }
-                } else {
-                    return Ok(());
-                }
+                    None => return Ok(()),
+                };
                 measure_select.stop();
                 inc_new_counter_debug!(
                     "accountsdb-plugin-postgres-update-account-select-us",
@@ -192,7 +259,8 @@ impl AccountsDbPlugin for AccountsDbPluginPostgres {
                     Some(client) => {
                         let mut measure_update =
                             Measure::start("accountsdb-plugin-postgres-update-account-client");
-                        let result = { client.update_account(account, slot, is_startup) };
+                        let result =
+                            { client.update_account(account, slot, is_startup, is_selected) };
                         measure_update.stop();
 
                         inc_new_counter_debug!(
@@ -277,6 +345,71 @@ impl AccountsDbPlugin for AccountsDbPluginPostgres {
         Ok(())
     }
 
+    fn notify_transaction(
+        &mut self,
+        transaction_info: ReplicaTransactionInfoVersions,
+        slot: u64,
+    ) -> Result<()> {
+        match &mut self.client {
+            None => {
+                return Err(AccountsDbPluginError::Custom(Box::new(
+                    AccountsDbPluginPostgresError::DataStoreConnectionError {
+                        msg: "There is no connection to the PostgreSQL database.".to_string(),
+                    },
+                )));
+            }
+            Some(client) => match transaction_info {
+                ReplicaTransactionInfoVersions::V0_0_1(transaction_info) => {
+                    if let Some(transaction_selector) = &self.transaction_selector {
+                        if !transaction_selector.is_transaction_selected(
+                            transaction_info.is_vote,
+                            Box::new(transaction_info.transaction.message().account_keys_iter()),
+                        ) {
+                            return Ok(());
+                        }
+                    } else {
+                        return Ok(());
+                    }
+
+                    let result = client.log_transaction_info(transaction_info, slot);
+
+                    if let Err(err) = result {
+                        return Err(AccountsDbPluginError::TransactionUpdateError {
+                            msg: format!("Failed to persist the transaction info to the PostgreSQL database. Error: {:?}", err)
+                        });
+                    }
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    fn notify_block_metadata(&mut self, block_info: ReplicaBlockInfoVersions) -> Result<()> {
+        match &mut self.client {
+            None => {
+                return Err(AccountsDbPluginError::Custom(Box::new(
+                    AccountsDbPluginPostgresError::DataStoreConnectionError {
+                        msg: "There is no connection to the PostgreSQL database.".to_string(),
+                    },
+                )));
+            }
+            Some(client) => match block_info {
+                ReplicaBlockInfoVersions::V0_0_1(block_info) => {
+                    let result = client.update_block_metadata(block_info);
+
+                    if let Err(err) = result {
+                        return Err(AccountsDbPluginError::SlotStatusUpdateError{
+                                msg: format!("Failed to persist the update of block metadata to the PostgreSQL database. Error: {:?}", err)
+                            });
+                    }
+                }
+            },
+        }
+
+        Ok(())
+    }
+
     /// Check if the plugin is interested in account data
     /// Default is true -- if the plugin is not interested in
     /// account data, please return false.
@@ -285,14 +418,23 @@ impl AccountsDbPlugin for AccountsDbPluginPostgres {
             .as_ref()
             .map_or_else(|| false, |selector| selector.is_enabled())
     }
+
+    /// Check if the plugin is interested in transaction data
+    fn transaction_notifications_enabled(&self) -> bool {
+        self.transaction_selector
+            .as_ref()
+            .map_or_else(|| false, |selector| selector.is_enabled())
+    }
 }
 
 impl AccountsDbPluginPostgres {
-    fn create_accounts_selector_from_config(config: &serde_json::Value) -> AccountsSelector {
+    fn create_accounts_selector_from_config(
+        config: &serde_json::Value,
+    ) -> Result<AccountsSelector> {
         let accounts_selector = &config["accounts_selector"];
 
         if accounts_selector.is_null() {
-            AccountsSelector::default()
+            Ok(AccountsSelector::default())
         } else {
             let accounts = &accounts_selector["accounts"];
             let accounts: Vec<String> = if accounts.is_array() {
@@ -316,7 +458,50 @@ impl AccountsDbPluginPostgres {
             } else {
                 Vec::default()
             };
-            AccountsSelector::new(&accounts, &owners)
+            let token_mints = &accounts_selector["token_mints"];
+            let token_mints: Vec<String> = if token_mints.is_array() {
+                token_mints
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|val| val.as_str().unwrap().to_string())
+                    .collect()
+            } else {
+                Vec::default()
+            };
+            let filters = &accounts_selector["filters"];
+            let filters: Vec<AccountsFilter> = if filters.is_null() {
+                Vec::default()
+            } else {
+                serde_json::from_value(filters.clone()).map_err(|err| {
+                    AccountsDbPluginError::ConfigFileReadError {
+                        msg: format!("Invalid accounts_selector filters: {:?}", err),
+                    }
+                })?
+            };
+            AccountsSelector::new_with_filters(&accounts, &owners, &token_mints, &filters)
+                .map_err(|msg| AccountsDbPluginError::ConfigFileReadError { msg })
+        }
+    }
+
+    fn create_transaction_selector_from_config(config: &serde_json::Value) -> TransactionSelector {
+        let transaction_selector = &config["transaction_selector"];
+
+        if transaction_selector.is_null() {
+            TransactionSelector::default()
+        } else {
+            let mentions = &transaction_selector["mentions"];
+            let mentions: Vec<String> = if mentions.is_array() {
+                mentions
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|val| val.as_str().unwrap().to_string())
+                    .collect()
+            } else {
+                Vec::default()
+            };
+            TransactionSelector::new(&mentions)
         }
     }
 
@@ -324,6 +509,7 @@ impl AccountsDbPluginPostgres {
         AccountsDbPluginPostgres {
             client: None,
             accounts_selector: None,
+            transaction_selector: None,
         }
     }
 }
@@ -350,6 +536,41 @@ pub(crate) mod tests {
         }}";
 
         let config: serde_json::Value = serde_json::from_str(config).unwrap();
-        AccountsDbPluginPostgres::create_accounts_selector_from_config(&config);
+        AccountsDbPluginPostgres::create_accounts_selector_from_config(&config).unwrap();
+
+        let config = "{\"accounts_selector\" : { \
+           \"token_mints\" : [\"9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin\"], \
+           \"filters\" : [{\"dataSize\": 165}, {\"lamports\": {\"min\": 1}}, \
+               {\"memcmp\": {\"offset\": 32, \"bytes\": \"9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin\"}}] \
+        }}";
+        let config: serde_json::Value = serde_json::from_str(config).unwrap();
+        let selector =
+            AccountsDbPluginPostgres::create_accounts_selector_from_config(&config).unwrap();
+        assert!(selector.is_enabled());
+        assert_eq!(selector.token_mints.len(), 1);
+
+        let config = "{\"accounts_selector\" : { \
+           \"accounts\" : [\"*\"], \
+           \"filters\" : [{\"unknown\": 1}] \
+        }}";
+        let config: serde_json::Value = serde_json::from_str(config).unwrap();
+        assert!(AccountsDbPluginPostgres::create_accounts_selector_from_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_transaction_selector_from_config() {
+        let config = "{\"transaction_selector\" : { \
+           \"mentions\" : [\"all_votes\"] \
+        }}";
+
+        let config: serde_json::Value = serde_json::from_str(config).unwrap();
+        let selector = AccountsDbPluginPostgres::create_transaction_selector_from_config(&config);
+        assert!(selector.is_enabled());
+        assert!(selector.select_all_vote_transactions);
+        assert!(!selector.select_all_transactions);
+
+        let config: serde_json::Value = serde_json::from_str("{}").unwrap();
+        let selector = AccountsDbPluginPostgres::create_transaction_selector_from_config(&config);
+        assert!(!selector.is_enabled());
     }
 }
@@ -1,9 +1,24 @@
 #![allow(clippy::integer_arithmetic)]
 
+mod postgres_client_account_history;
+mod postgres_client_block_metadata;
+mod postgres_client_commitment;
+mod postgres_client_parsed_account;
+mod postgres_client_transaction;
+
 /// A concurrent implementation for writing accounts into the PostgreSQL in parallel.
 use {
-    crate::accountsdb_plugin_postgres::{
-        AccountsDbPluginPostgresConfig, AccountsDbPluginPostgresError,
+    crate::{
+        accountsdb_plugin_postgres::{
+            AccountsDbPluginPostgresConfig, AccountsDbPluginPostgresError, CommitmentLevel,
+        },
+        postgres_client::{
+            postgres_client_account_history::AccountHistoryStatements,
+            postgres_client_block_metadata::UpdateBlockMetadataRequest,
+            postgres_client_commitment::PendingAccountUpdates,
+            postgres_client_parsed_account::ParsedAccountStatements,
+            postgres_client_transaction::LogTransactionRequest,
+        },
     },
     chrono::Utc,
     crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender},
@@ -39,8 +54,13 @@ struct PostgresSqlClientWrapper {
     client: Client,
     update_account_stmt: Statement,
     bulk_account_insert_stmt: Statement,
+    delete_account_stmt: Statement,
     update_slot_with_parent_stmt: Statement,
     update_slot_without_parent_stmt: Statement,
+    update_transaction_log_stmt: Statement,
+    update_block_metadata_stmt: Statement,
+    account_history_stmts: Option<AccountHistoryStatements>,
+    parsed_account_stmts: Option<ParsedAccountStatements>,
 }
 
 pub struct SimplePostgresClient {
@@ -96,6 +116,20 @@ impl DbAccountInfo {
             write_version: account.write_version(),
         }
     }
+
+    /// The account info of an account to remove, its data is not needed
+    fn new_removed<T: ReadableAccountInfo>(account: &T, slot: u64) -> DbAccountInfo {
+        Self {
+            pubkey: account.pubkey().to_vec(),
+            lamports: account.lamports() as i64,
+            owner: account.owner().to_vec(),
+            executable: account.executable(),
+            rent_epoch: account.rent_epoch() as i64,
+            data: Vec::default(),
+            slot: slot as i64,
+            write_version: account.write_version(),
+        }
+    }
 }
 
 pub trait ReadableAccountInfo: Sized {
@@ -179,6 +213,9 @@ pub trait PostgresClient {
         is_startup: bool,
     ) -> Result<(), AccountsDbPluginError>;
 
+    /// Remove the account which is no longer selected, unless a later update was written
+    fn remove_account(&mut self, account: DbAccountInfo) -> Result<(), AccountsDbPluginError>;
+
     fn update_slot_status(
         &mut self,
         slot: u64,
@@ -187,6 +224,18 @@ pub trait PostgresClient {
     ) -> Result<(), AccountsDbPluginError>;
 
     fn notify_end_of_startup(&mut self) -> Result<(), AccountsDbPluginError>;
+
+    fn log_transaction(
+        &mut self,
+        transaction_log_info: LogTransactionRequest,
+    ) -> Result<(), AccountsDbPluginError>;
+
+    fn update_block_metadata(
+        &mut self,
+        block_info: UpdateBlockMetadataRequest,
+    ) -> Result<(), AccountsDbPluginError>;
+
+    fn prune_account_history(&mut self, prune_slot: u64) -> Result<(), AccountsDbPluginError>;
 }
 
 impl SimplePostgresClient {
@@ -307,6 +356,28 @@ impl SimplePostgresClient {
         }
     }
 
+    fn build_account_delete_statement(
+        client: &mut Client,
+        config: &AccountsDbPluginPostgresConfig,
+    ) -> Result<Statement, AccountsDbPluginError> {
+        let stmt = "DELETE FROM account AS acct WHERE acct.pubkey = $1 AND (acct.slot < $2 OR \
+        (acct.slot = $2 AND acct.write_version < $3))";
+
+        let stmt = client.prepare(stmt);
+
+        match stmt {
+            Err(err) => {
+                return Err(AccountsDbPluginError::Custom(Box::new(AccountsDbPluginPostgresError::DataSchemaError {
+                    msg: format!(
+                        "Error in preparing for the accounts removal PostgreSQL database: {} host: {:?} user: {:?} config: {:?}",
+                        err, config.host, config.user, config
+                    ),
+                })));
+            }
+            Ok(delete_account_stmt) => Ok(delete_account_stmt),
+        }
+    }
+
     fn build_slot_upsert_statement_with_parent(
         client: &mut Client,
         config: &AccountsDbPluginPostgresConfig,
@@ -392,9 +463,9 @@ impl SimplePostgresClient {
     /// Update or insert a single account
     fn upsert_account(&mut self, account: &DbAccountInfo) -> Result<(), AccountsDbPluginError> {
         let client = self.client.get_mut().unwrap();
-        let statement = &client.update_account_stmt;
-        let client = &mut client.client;
-        Self::upsert_account_internal(account, statement, client)
+        Self::upsert_account_internal(account, &client.update_account_stmt, &mut client.client)?;
+        Self::insert_account_history_internal(client, std::slice::from_ref(account))?;
+        Self::insert_parsed_accounts_internal(client, std::slice::from_ref(account))
     }
 
     /// Insert accounts in batch to reduce network overhead
@@ -436,6 +507,14 @@ impl SimplePostgresClient {
             let result = client
                 .client
                 .query(&client.bulk_account_insert_stmt, &values);
+            let pending_account_updates = &self.pending_account_updates;
+            let history_result = if result.is_ok() {
+                Self::insert_account_history_internal(client, pending_account_updates).and_then(
+                    |_| Self::insert_parsed_accounts_internal(client, pending_account_updates),
+                )
+            } else {
+                Ok(())
+            };
 
             self.pending_account_updates.clear();
             if let Err(err) = result {
@@ -446,6 +525,7 @@ impl SimplePostgresClient {
                 error!("{}", msg);
                 return Err(AccountsDbPluginError::AccountsUpdateError { msg });
             }
+            history_result?;
             measure.stop();
             inc_new_counter_debug!(
                 "accountsdb-plugin-postgres-update-account-us",
@@ -470,6 +550,8 @@ impl SimplePostgresClient {
         }
 
         let client = self.client.get_mut().unwrap();
+        Self::insert_account_history_internal(client, &self.pending_account_updates)?;
+        Self::insert_parsed_accounts_internal(client, &self.pending_account_updates)?;
         let statement = &client.update_account_stmt;
         let client = &mut client.client;
 
@@ -486,11 +568,18 @@ impl SimplePostgresClient {
         let bulk_account_insert_stmt =
             Self::build_bulk_account_insert_statement(&mut client, config)?;
         let update_account_stmt = Self::build_single_account_upsert_statement(&mut client, config)?;
+        let delete_account_stmt = Self::build_account_delete_statement(&mut client, config)?;
 
         let update_slot_with_parent_stmt =
             Self::build_slot_upsert_statement_with_parent(&mut client, config)?;
         let update_slot_without_parent_stmt =
             Self::build_slot_upsert_statement_without_parent(&mut client, config)?;
+        let update_transaction_log_stmt =
+            Self::build_transaction_info_upsert_statement(&mut client, config)?;
+        let update_block_metadata_stmt =
+            Self::build_block_metadata_upsert_statement(&mut client, config)?;
+        let account_history_stmts = Self::build_account_history_statements(&mut client, config)?;
+        let parsed_account_stmts = Self::build_parsed_account_statements(&mut client, config)?;
 
         let batch_size = config
             .batch_size
@@ -503,8 +592,13 @@ impl SimplePostgresClient {
                 client,
                 update_account_stmt,
                 bulk_account_insert_stmt,
+                delete_account_stmt,
                 update_slot_with_parent_stmt,
                 update_slot_without_parent_stmt,
+                update_transaction_log_stmt,
+                update_block_metadata_stmt,
+                account_history_stmts,
+                parsed_account_stmts,
             }),
         })
     }
@@ -528,6 +622,28 @@ impl PostgresClient for SimplePostgresClient {
         self.insert_accounts_in_batch(account)
     }
 
+    fn remove_account(&mut self, account: DbAccountInfo) -> Result<(), AccountsDbPluginError> {
+        trace!(
+            "Removing account {} at slot {}",
+            bs58::encode(account.pubkey()).into_string(),
+            account.slot,
+        );
+        let client = self.client.get_mut().unwrap();
+        let result = client.client.execute(
+            &client.delete_account_stmt,
+            &[&account.pubkey(), &account.slot, &account.write_version()],
+        );
+        if let Err(err) = result {
+            let msg = format!(
+                "Failed to remove the account from the PostgreSQL database. Error: {:?}",
+                err
+            );
+            error!("{}", msg);
+            return Err(AccountsDbPluginError::AccountsUpdateError { msg });
+        }
+        Self::remove_parsed_accounts_internal(client, std::slice::from_ref(&account))
+    }
+
     fn update_slot_status(
         &mut self,
         slot: u64,
@@ -573,11 +689,31 @@ impl PostgresClient for SimplePostgresClient {
     fn notify_end_of_startup(&mut self) -> Result<(), AccountsDbPluginError> {
         self.flush_buffered_writes()
     }
+
+    fn log_transaction(
+        &mut self,
+        transaction_log_info: LogTransactionRequest,
+    ) -> Result<(), AccountsDbPluginError> {
+        self.log_transaction_impl(transaction_log_info)
+    }
+
+    fn update_block_metadata(
+        &mut self,
+        block_info: UpdateBlockMetadataRequest,
+    ) -> Result<(), AccountsDbPluginError> {
+        self.update_block_metadata_impl(block_info)
+    }
+
+    fn prune_account_history(&mut self, prune_slot: u64) -> Result<(), AccountsDbPluginError> {
+        self.prune_account_history_impl(prune_slot)
+    }
 }
 
 struct UpdateAccountRequest {
     account: DbAccountInfo,
     is_startup: bool,
+    /// The account is removed when it is not selected
+    is_selected: bool,
 }
 
 struct UpdateSlotRequest {
@@ -589,6 +725,9 @@ struct UpdateSlotRequest {
 enum DbWorkItem {
     UpdateAccount(UpdateAccountRequest),
     UpdateSlot(UpdateSlotRequest),
+    LogTransaction(Box<LogTransactionRequest>),
+    UpdateBlockMetadata(Box<UpdateBlockMetadataRequest>),
+    PruneAccountHistory(u64),
 }
 
 impl PostgresClientWorker {
@@ -627,10 +766,13 @@ impl PostgresClientWorker {
             match work {
                 Ok(work) => match work {
                     DbWorkItem::UpdateAccount(request) => {
-                        if let Err(err) = self
-                            .client
-                            .update_account(request.account, request.is_startup)
-                        {
+                        let result = if request.is_selected {
+                            self.client
+                                .update_account(request.account, request.is_startup)
+                        } else {
+                            self.client.remove_account(request.account)
+                        };
+                        if let Err(err) = result {
                             error!("Failed to update account: ({})", err);
                             if panic_on_db_errors {
                                 abort();
@@ -649,6 +791,30 @@ impl PostgresClientWorker {
                             }
                         }
                     }
+                    DbWorkItem::LogTransaction(transaction_log_info) => {
+                        if let Err(err) = self.client.log_transaction(*transaction_log_info) {
+                            error!("Failed to update transaction: ({})", err);
+                            if panic_on_db_errors {
+                                abort();
+                            }
+                        }
+                    }
+                    DbWorkItem::UpdateBlockMetadata(block_info) => {
+                        if let Err(err) = self.client.update_block_metadata(*block_info) {
+                            error!("Failed to update block metadata: ({})", err);
+                            if panic_on_db_errors {
+                                abort();
+                            }
+                        }
+                    }
+                    DbWorkItem::PruneAccountHistory(prune_slot) => {
+                        if let Err(err) = self.client.prune_account_history(prune_slot) {
+                            error!("Failed to prune account history: ({})", err);
+                            if panic_on_db_errors {
+                                abort();
+                            }
+                        }
+                    }
                 },
                 Err(err) => match err {
                     RecvTimeoutError::Timeout => {
@@ -686,6 +852,11 @@ pub struct ParallelPostgresClient {
     initialized_worker_count: Arc<AtomicUsize>,
     sender: Sender<DbWorkItem>,
     last_report: AtomicInterval,
+    account_history_retention_slots: Option<u64>,
+    last_account_history_prune_slot: u64,
+    /// The account updates held back until their slots reach the commitment level,
+    /// `None` when the updates are written at the processed commitment level.
+    pending_account_updates: Option<PendingAccountUpdates>,
 }
 
 impl ParallelPostgresClient {
@@ -749,6 +920,19 @@ impl ParallelPostgresClient {
             startup_done_count,
             initialized_worker_count,
             sender,
+            account_history_retention_slots: config
+                .account_history
+                .unwrap_or_default()
+                .then(|| config.account_history_retention_slots)
+                .flatten(),
+            last_account_history_prune_slot: 0,
+            pending_account_updates: match config
+                .commitment_level
+                .unwrap_or(CommitmentLevel::Processed)
+            {
+                CommitmentLevel::Processed => None,
+                commitment_level => Some(PendingAccountUpdates::new(commitment_level)),
+            },
         })
     }
 
@@ -774,6 +958,7 @@ impl ParallelPostgresClient {
         account: &ReplicaAccountInfo,
         slot: u64,
         is_startup: bool,
+        is_selected: bool,
     ) -> Result<(), AccountsDbPluginError> {
         if self.last_report.should_update(30000) {
             datapoint_debug!(
@@ -782,10 +967,19 @@ impl ParallelPostgresClient {
             );
         }
         let mut measure = Measure::start("accountsdb-plugin-posgres-create-work-item");
-        let wrk_item = DbWorkItem::UpdateAccount(UpdateAccountRequest {
-            account: DbAccountInfo::new(account, slot),
+        let request = UpdateAccountRequest {
+            account: if is_selected {
+                DbAccountInfo::new(account, slot)
+            } else {
+                DbAccountInfo::new_removed(account, slot)
+            },
             is_startup,
-        });
+            is_selected,
+        };
+        let request = match &mut self.pending_account_updates {
+            Some(pending_account_updates) => pending_account_updates.buffer(slot, request),
+            None => Some(request),
+        };
 
         measure.stop();
 
@@ -796,6 +990,12 @@ impl ParallelPostgresClient {
             100000
         );
 
+        let wrk_item = match request {
+            Some(request) => DbWorkItem::UpdateAccount(request),
+            // Held back until the slot reaches the commitment level
+            None => return Ok(()),
+        };
+
         let mut measure = Measure::start("accountsdb-plugin-posgres-send-msg");
 
         if let Err(err) = self.sender.send(wrk_item) {
@@ -825,6 +1025,8 @@ impl ParallelPostgresClient {
         parent: Option<u64>,
         status: SlotStatus,
     ) -> Result<(), AccountsDbPluginError> {
+        let is_rooted = matches!(status, SlotStatus::Rooted);
+        self.commit_pending_account_updates(slot, parent, &status)?;
         if let Err(err) = self.sender.send(DbWorkItem::UpdateSlot(UpdateSlotRequest {
             slot,
             parent,
@@ -834,6 +1036,9 @@ impl ParallelPostgresClient {
                 msg: format!("Failed to update the slot {:?}, error: {:?}", slot, err),
             });
         }
+        if is_rooted {
+            self.maybe_prune_account_history(slot)?;
+        }
         Ok(())
     }
 
@@ -877,3 +1082,27 @@ impl PostgresClientBuilder {
         SimplePostgresClient::new(config)
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use {super::*, solana_sdk::pubkey::Pubkey};
+
+    /// An update of the account with `data`, written at `slot * 10`
+    pub(crate) fn new_db_account_info(
+        pubkey: &Pubkey,
+        owner: &Pubkey,
+        slot: i64,
+        data: Vec<u8>,
+    ) -> DbAccountInfo {
+        DbAccountInfo {
+            pubkey: pubkey.to_bytes().to_vec(),
+            lamports: 10,
+            owner: owner.to_bytes().to_vec(),
+            executable: false,
+            rent_epoch: 1,
+            data,
+            slot,
+            write_version: slot * 10,
+        }
+    }
+}
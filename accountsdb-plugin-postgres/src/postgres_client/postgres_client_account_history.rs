@@ -0,0 +1,414 @@
+/// Module responsible for keeping the history of the accounts in the PostgreSQL database.
+/// Every account update is written into the `account_history` table keyed by
+/// `(pubkey, slot, write_version)`. When data deduplication is enabled, the account data
+/// is stored once per distinct content in the `account_data` table, keyed by its hash.
+use {
+    crate::{
+        accountsdb_plugin_postgres::{
+            AccountsDbPluginPostgresConfig, AccountsDbPluginPostgresError,
+        },
+        postgres_client::{
+            DbAccountInfo, DbWorkItem, ParallelPostgresClient, PostgresSqlClientWrapper,
+            SimplePostgresClient,
+        },
+    },
+    chrono::Utc,
+    log::*,
+    postgres::{Client, Statement},
+    solana_accountsdb_plugin_interface::accountsdb_plugin_interface::AccountsDbPluginError,
+    solana_measure::measure::Measure,
+    solana_metrics::*,
+    solana_sdk::hash::hash,
+};
+
+/// The history is pruned at most once in this many rooted slots.
+const ACCOUNT_HISTORY_PRUNE_INTERVAL_SLOTS: u64 = 1000;
+
+/// The key of the PostgreSQL advisory lock keeping the deduplicated data from being pruned
+/// while history rows referencing it are being inserted. The inserts hold it shared and the
+/// pruning exclusively, until their transaction ends.
+const ACCOUNT_DATA_LOCK_KEY: i64 = 0x6163_636f_756e_7464;
+
+/// The statements to deduplicate the account data into `account_data`
+struct AccountDataStatements {
+    insert_data_stmt: Statement,
+    prune_data_stmt: Statement,
+    lock_data_shared_stmt: Statement,
+    lock_data_stmt: Statement,
+}
+
+pub(crate) struct AccountHistoryStatements {
+    insert_history_stmt: Statement,
+    prune_history_stmt: Statement,
+    data_stmts: Option<AccountDataStatements>,
+}
+
+/// The columns of the `account_history` and `account_data` rows for a set of accounts
+#[derive(Debug, Default, PartialEq)]
+struct AccountHistoryColumns {
+    pubkeys: Vec<Vec<u8>>,
+    slots: Vec<i64>,
+    write_versions: Vec<i64>,
+    owners: Vec<Vec<u8>>,
+    lamports: Vec<i64>,
+    executables: Vec<bool>,
+    rent_epochs: Vec<i64>,
+    data: Vec<Option<Vec<u8>>>,
+    data_hashes: Vec<Option<Vec<u8>>>,
+    /// The distinct data to be inserted into `account_data` when deduplicating.
+    dedup_data_hashes: Vec<Vec<u8>>,
+    dedup_data: Vec<Vec<u8>>,
+}
+
+impl AccountHistoryColumns {
+    fn new(accounts: &[DbAccountInfo], dedup_data: bool) -> Self {
+        let mut columns = Self::default();
+        for account in accounts {
+            columns.pubkeys.push(account.pubkey.clone());
+            columns.slots.push(account.slot);
+            columns.write_versions.push(account.write_version);
+            columns.owners.push(account.owner.clone());
+            columns.lamports.push(account.lamports);
+            columns.executables.push(account.executable);
+            columns.rent_epochs.push(account.rent_epoch);
+            if dedup_data {
+                let data_hash = hash(&account.data).as_ref().to_vec();
+                if !columns.dedup_data_hashes.contains(&data_hash) {
+                    columns.dedup_data_hashes.push(data_hash.clone());
+                    columns.dedup_data.push(account.data.clone());
+                }
+                columns.data.push(None);
+                columns.data_hashes.push(Some(data_hash));
+            } else {
+                columns.data.push(Some(account.data.clone()));
+                columns.data_hashes.push(None);
+            }
+        }
+        columns
+    }
+}
+
+/// Returns the slot before which the history is to be pruned when `root` is rooted,
+/// if any.
+pub(crate) fn account_history_prune_slot(
+    root: u64,
+    retention_slots: Option<u64>,
+    last_prune_slot: u64,
+) -> Option<u64> {
+    let prune_slot = root.saturating_sub(retention_slots?);
+    if prune_slot >= last_prune_slot.saturating_add(ACCOUNT_HISTORY_PRUNE_INTERVAL_SLOTS) {
+        Some(prune_slot)
+    } else {
+        None
+    }
+}
+
+fn prepare_statement(
+    client: &mut Client,
+    config: &AccountsDbPluginPostgresConfig,
+    stmt: &str,
+) -> Result<Statement, AccountsDbPluginError> {
+    client.prepare(stmt).map_err(|err| {
+        AccountsDbPluginError::Custom(Box::new(AccountsDbPluginPostgresError::DataSchemaError {
+            msg: format!(
+                "Error in preparing for the account history PostgreSQL database: ({}) host: {:?} user: {:?} config: {:?}",
+                err, config.host, config.user, config
+            ),
+        }))
+    })
+}
+
+impl SimplePostgresClient {
+    pub(crate) fn build_account_history_statements(
+        client: &mut Client,
+        config: &AccountsDbPluginPostgresConfig,
+    ) -> Result<Option<AccountHistoryStatements>, AccountsDbPluginError> {
+        if !config.account_history.unwrap_or_default() {
+            return Ok(None);
+        }
+        let dedup_data = config.account_history_dedup_data.unwrap_or_default();
+
+        let insert_history_stmt = prepare_statement(
+            client,
+            config,
+            "INSERT INTO account_history (pubkey, slot, write_version, owner, lamports, executable, rent_epoch, \
+            data, data_hash, updated_on) \
+            SELECT *, $10::TIMESTAMP FROM UNNEST($1::BYTEA[], $2::BIGINT[], $3::BIGINT[], $4::BYTEA[], $5::BIGINT[], \
+            $6::BOOL[], $7::BIGINT[], $8::BYTEA[], $9::BYTEA[]) \
+            ON CONFLICT (pubkey, slot, write_version) DO NOTHING",
+        )?;
+        // Only the latest rooted version at or before the prune slot is kept for every account,
+        // so lookups at the prune slot or later return the same results as before pruning.
+        // A version is only ever pruned in favor of a newer one in a rooted slot, the versions
+        // of slots on abandoned forks never replace a rooted one.
+        let prune_history_stmt = prepare_statement(
+            client,
+            config,
+            "DELETE FROM account_history AS old WHERE old.slot < $1 AND EXISTS (\
+            SELECT 1 FROM account_history AS new \
+            JOIN slot ON slot.slot = new.slot AND slot.status = 'rooted' \
+            WHERE new.pubkey = old.pubkey AND new.slot <= $1 \
+            AND (new.slot, new.write_version) > (old.slot, old.write_version))",
+        )?;
+
+        let data_stmts = if dedup_data {
+            Some(AccountDataStatements {
+                insert_data_stmt: prepare_statement(
+                    client,
+                    config,
+                    "INSERT INTO account_data (data_hash, data) \
+                    SELECT * FROM UNNEST($1::BYTEA[], $2::BYTEA[]) \
+                    ON CONFLICT (data_hash) DO NOTHING",
+                )?,
+                prune_data_stmt: prepare_statement(
+                    client,
+                    config,
+                    "DELETE FROM account_data AS d WHERE NOT EXISTS (\
+                    SELECT 1 FROM account_history AS h WHERE h.data_hash = d.data_hash)",
+                )?,
+                lock_data_shared_stmt: prepare_statement(
+                    client,
+                    config,
+                    "SELECT pg_advisory_xact_lock_shared($1)",
+                )?,
+                lock_data_stmt: prepare_statement(
+                    client,
+                    config,
+                    "SELECT pg_advisory_xact_lock($1)",
+                )?,
+            })
+        } else {
+            None
+        };
+
+        Ok(Some(AccountHistoryStatements {
+            insert_history_stmt,
+            prune_history_stmt,
+            data_stmts,
+        }))
+    }
+
+    /// Append the accounts to the history. Does nothing if the history is not enabled.
+    pub(crate) fn insert_account_history_internal(
+        client: &mut PostgresSqlClientWrapper,
+        accounts: &[DbAccountInfo],
+    ) -> Result<(), AccountsDbPluginError> {
+        let statements = match &client.account_history_stmts {
+            Some(statements) => statements,
+            None => return Ok(()),
+        };
+        if accounts.is_empty() {
+            return Ok(());
+        }
+
+        let mut measure = Measure::start("accountsdb-plugin-postgres-insert-account-history");
+        let columns = AccountHistoryColumns::new(accounts, statements.data_stmts.is_some());
+        let updated_on = Utc::now().naive_utc();
+
+        // The data and the history rows referencing it are committed together, and the data
+        // cannot be pruned as unreferenced before they are.
+        let result = client.client.transaction().and_then(|mut transaction| {
+            if let Some(data_stmts) = &statements.data_stmts {
+                transaction
+                    .execute(&data_stmts.lock_data_shared_stmt, &[&ACCOUNT_DATA_LOCK_KEY])?;
+                transaction.execute(
+                    &data_stmts.insert_data_stmt,
+                    &[&columns.dedup_data_hashes, &columns.dedup_data],
+                )?;
+            }
+            transaction.execute(
+                &statements.insert_history_stmt,
+                &[
+                    &columns.pubkeys,
+                    &columns.slots,
+                    &columns.write_versions,
+                    &columns.owners,
+                    &columns.lamports,
+                    &columns.executables,
+                    &columns.rent_epochs,
+                    &columns.data,
+                    &columns.data_hashes,
+                    &updated_on,
+                ],
+            )?;
+            transaction.commit()
+        });
+
+        if let Err(err) = result {
+            let msg = format!(
+                "Failed to persist the account history to the PostgreSQL database. Error: {:?}",
+                err
+            );
+            error!("{}", msg);
+            return Err(AccountsDbPluginError::AccountsUpdateError { msg });
+        }
+        measure.stop();
+        inc_new_counter_debug!(
+            "accountsdb-plugin-postgres-insert-account-history-us",
+            measure.as_us() as usize,
+            10000,
+            10000
+        );
+        Ok(())
+    }
+
+    /// Remove the history which is not needed for lookups at `prune_slot` or later.
+    pub(crate) fn prune_account_history_impl(
+        &mut self,
+        prune_slot: u64,
+    ) -> Result<(), AccountsDbPluginError> {
+        let PostgresSqlClientWrapper {
+            client,
+            account_history_stmts,
+            ..
+        } = self.client.get_mut().unwrap();
+        let statements = match account_history_stmts {
+            Some(statements) => statements,
+            None => return Ok(()),
+        };
+
+        let mut measure = Measure::start("accountsdb-plugin-postgres-prune-account-history");
+        let result = client
+            .execute(&statements.prune_history_stmt, &[&(prune_slot as i64)])
+            .and_then(|pruned| {
+                info!(
+                    "Pruned {} rows of account history before slot {}",
+                    pruned, prune_slot
+                );
+                match &statements.data_stmts {
+                    // Once the lock is taken, the history inserted along with the data is
+                    // committed and seen by the pruning
+                    Some(data_stmts) => client.transaction().and_then(|mut transaction| {
+                        transaction
+                            .execute(&data_stmts.lock_data_stmt, &[&ACCOUNT_DATA_LOCK_KEY])?;
+                        let pruned = transaction.execute(&data_stmts.prune_data_stmt, &[])?;
+                        transaction.commit()?;
+                        Ok(pruned)
+                    }),
+                    None => Ok(0),
+                }
+            });
+        if let Err(err) = result {
+            let msg = format!(
+                "Failed to prune the account history in the PostgreSQL database. Error: {:?}",
+                err
+            );
+            error!("{}", msg);
+            return Err(AccountsDbPluginError::SlotStatusUpdateError { msg });
+        }
+        measure.stop();
+        inc_new_counter_info!(
+            "accountsdb-plugin-postgres-prune-account-history-ms",
+            measure.as_ms() as usize
+        );
+        Ok(())
+    }
+}
+
+impl ParallelPostgresClient {
+    /// Ask a worker to prune the account history if enough slots were rooted since the
+    /// last time it was pruned.
+    pub(crate) fn maybe_prune_account_history(
+        &mut self,
+        root: u64,
+    ) -> Result<(), AccountsDbPluginError> {
+        if let Some(prune_slot) = account_history_prune_slot(
+            root,
+            self.account_history_retention_slots,
+            self.last_account_history_prune_slot,
+        ) {
+            self.last_account_history_prune_slot = prune_slot;
+            if let Err(err) = self
+                .sender
+                .send(DbWorkItem::PruneAccountHistory(prune_slot))
+            {
+                return Err(AccountsDbPluginError::SlotStatusUpdateError {
+                    msg: format!(
+                        "Failed to prune the account history before slot {:?}, error: {:?}",
+                        prune_slot, err
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use {
+        super::*, crate::postgres_client::tests::new_db_account_info, solana_sdk::pubkey::Pubkey,
+    };
+
+    fn new_account(pubkey: u8, slot: i64, data: Vec<u8>) -> DbAccountInfo {
+        new_db_account_info(
+            &Pubkey::new_from_array([pubkey; 32]),
+            &Pubkey::default(),
+            slot,
+            data,
+        )
+    }
+
+    #[test]
+    fn test_account_history_columns() {
+        let accounts = vec![
+            new_account(1, 5, vec![1, 2, 3]),
+            new_account(2, 5, vec![1, 2, 3]),
+            new_account(1, 6, vec![4]),
+        ];
+
+        let columns = AccountHistoryColumns::new(&accounts, false);
+        assert_eq!(columns.slots, vec![5, 5, 6]);
+        assert_eq!(columns.write_versions, vec![50, 50, 60]);
+        assert_eq!(
+            columns.data,
+            vec![Some(vec![1, 2, 3]), Some(vec![1, 2, 3]), Some(vec![4])]
+        );
+        assert_eq!(columns.data_hashes, vec![None, None, None]);
+        assert!(columns.dedup_data.is_empty());
+
+        let columns = AccountHistoryColumns::new(&accounts, true);
+        let hash_0 = hash(&[1, 2, 3]).as_ref().to_vec();
+        let hash_1 = hash(&[4]).as_ref().to_vec();
+        assert_eq!(columns.data, vec![None, None, None]);
+        assert_eq!(
+            columns.data_hashes,
+            vec![
+                Some(hash_0.clone()),
+                Some(hash_0.clone()),
+                Some(hash_1.clone())
+            ]
+        );
+        assert_eq!(columns.dedup_data_hashes, vec![hash_0, hash_1]);
+        assert_eq!(columns.dedup_data, vec![vec![1, 2, 3], vec![4]]);
+    }
+
+    #[test]
+    fn test_account_history_prune_slot() {
+        // No retention configured
+        assert_eq!(account_history_prune_slot(100_000, None, 0), None);
+        // Within the retention window
+        assert_eq!(account_history_prune_slot(500, Some(1000), 0), None);
+        // Not enough slots since the last pruning
+        assert_eq!(
+            account_history_prune_slot(
+                1000 + ACCOUNT_HISTORY_PRUNE_INTERVAL_SLOTS - 1,
+                Some(1000),
+                0
+            ),
+            None
+        );
+        assert_eq!(
+            account_history_prune_slot(1000 + ACCOUNT_HISTORY_PRUNE_INTERVAL_SLOTS, Some(1000), 0),
+            Some(ACCOUNT_HISTORY_PRUNE_INTERVAL_SLOTS)
+        );
+        assert_eq!(
+            account_history_prune_slot(
+                5000,
+                Some(1000),
+                4000 - ACCOUNT_HISTORY_PRUNE_INTERVAL_SLOTS
+            ),
+            Some(4000)
+        );
+    }
+}
@@ -0,0 +1,217 @@
+/// Module responsible for handling persisting block metadata to the PostgreSQL
+/// database.
+use {
+    crate::{
+        accountsdb_plugin_postgres::{
+            AccountsDbPluginPostgresConfig, AccountsDbPluginPostgresError,
+        },
+        postgres_client::{DbWorkItem, ParallelPostgresClient, SimplePostgresClient},
+    },
+    chrono::Utc,
+    log::*,
+    postgres::{Client, Statement},
+    postgres_types::ToSql,
+    solana_accountsdb_plugin_interface::accountsdb_plugin_interface::{
+        AccountsDbPluginError, ReplicaBlockInfo,
+    },
+    solana_sdk::reward_type::RewardType,
+    solana_transaction_status::Reward,
+};
+
+#[derive(Clone, Debug, PartialEq, ToSql)]
+#[postgres(name = "RewardType")]
+pub enum DbRewardType {
+    Fee,
+    Rent,
+    Staking,
+    Voting,
+}
+
+#[derive(Clone, Debug, PartialEq, ToSql)]
+#[postgres(name = "Reward")]
+pub struct DbReward {
+    pub pubkey: String,
+    pub lamports: i64,
+    pub post_balance: i64,
+    pub reward_type: Option<DbRewardType>,
+    pub commission: Option<i16>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DbBlockInfo {
+    pub slot: i64,
+    pub parent_slot: i64,
+    pub blockhash: String,
+    pub rewards: Vec<DbReward>,
+    pub block_time: Option<i64>,
+    pub block_height: Option<i64>,
+}
+
+pub struct UpdateBlockMetadataRequest {
+    pub block_info: DbBlockInfo,
+}
+
+impl From<&RewardType> for DbRewardType {
+    fn from(reward_type: &RewardType) -> Self {
+        match reward_type {
+            RewardType::Fee => Self::Fee,
+            RewardType::Rent => Self::Rent,
+            RewardType::Staking => Self::Staking,
+            RewardType::Voting => Self::Voting,
+        }
+    }
+}
+
+impl From<&Reward> for DbReward {
+    fn from(reward: &Reward) -> Self {
+        Self {
+            pubkey: reward.pubkey.clone(),
+            lamports: reward.lamports as i64,
+            post_balance: reward.post_balance as i64,
+            reward_type: reward.reward_type.as_ref().map(DbRewardType::from),
+            commission: reward
+                .commission
+                .as_ref()
+                .map(|commission| *commission as i16),
+        }
+    }
+}
+
+impl<'a> From<&ReplicaBlockInfo<'a>> for DbBlockInfo {
+    fn from(block_info: &ReplicaBlockInfo) -> Self {
+        Self {
+            slot: block_info.slot as i64,
+            parent_slot: block_info.parent_slot as i64,
+            blockhash: block_info.blockhash.to_string(),
+            rewards: block_info.rewards.iter().map(DbReward::from).collect(),
+            block_time: block_info.block_time,
+            block_height: block_info
+                .block_height
+                .map(|block_height| block_height as i64),
+        }
+    }
+}
+
+impl SimplePostgresClient {
+    pub(crate) fn build_block_metadata_upsert_statement(
+        client: &mut Client,
+        config: &AccountsDbPluginPostgresConfig,
+    ) -> Result<Statement, AccountsDbPluginError> {
+        let stmt = "INSERT INTO block (slot, parent_slot, blockhash, rewards, block_time, block_height, updated_on) \
+        VALUES ($1, $2, $3, $4, $5, $6, $7) \
+        ON CONFLICT (slot) DO UPDATE SET parent_slot=excluded.parent_slot, blockhash=excluded.blockhash, \
+        rewards=excluded.rewards, block_time=excluded.block_time, block_height=excluded.block_height, \
+        updated_on=excluded.updated_on";
+
+        let stmt = client.prepare(stmt);
+
+        match stmt {
+            Err(err) => {
+                return Err(AccountsDbPluginError::Custom(Box::new(AccountsDbPluginPostgresError::DataSchemaError {
+                    msg: format!(
+                        "Error in preparing for the block metadata update PostgreSQL database: ({}) host: {:?} user: {:?} config: {:?}",
+                        err, config.host, config.user, config
+                    ),
+                })));
+            }
+            Ok(stmt) => Ok(stmt),
+        }
+    }
+
+    pub(crate) fn update_block_metadata_impl(
+        &mut self,
+        block_info: UpdateBlockMetadataRequest,
+    ) -> Result<(), AccountsDbPluginError> {
+        let client = self.client.get_mut().unwrap();
+        let statement = &client.update_block_metadata_stmt;
+        let client = &mut client.client;
+        let updated_on = Utc::now().naive_utc();
+
+        let block_info = block_info.block_info;
+        let result = client.query(
+            statement,
+            &[
+                &block_info.slot,
+                &block_info.parent_slot,
+                &block_info.blockhash,
+                &block_info.rewards,
+                &block_info.block_time,
+                &block_info.block_height,
+                &updated_on,
+            ],
+        );
+
+        if let Err(err) = result {
+            let msg = format!(
+                "Failed to persist the update of block metadata to the PostgreSQL database. Error: {:?}",
+                err
+            );
+            error!("{}", msg);
+            return Err(AccountsDbPluginError::SlotStatusUpdateError { msg });
+        }
+
+        Ok(())
+    }
+}
+
+impl ParallelPostgresClient {
+    pub fn update_block_metadata(
+        &mut self,
+        block_info: &ReplicaBlockInfo,
+    ) -> Result<(), AccountsDbPluginError> {
+        if let Err(err) = self.sender.send(DbWorkItem::UpdateBlockMetadata(Box::new(
+            UpdateBlockMetadataRequest {
+                block_info: DbBlockInfo::from(block_info),
+            },
+        ))) {
+            return Err(AccountsDbPluginError::SlotStatusUpdateError {
+                msg: format!(
+                    "Failed to update the block metadata at slot {:?}, error: {:?}",
+                    block_info.slot, err
+                ),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use {super::*, solana_sdk::pubkey::Pubkey};
+
+    #[test]
+    fn test_transform_block_info() {
+        let rewards = vec![Reward {
+            pubkey: Pubkey::new_unique().to_string(),
+            lamports: -5000,
+            post_balance: 1_000_000,
+            reward_type: Some(RewardType::Fee),
+            commission: Some(10),
+        }];
+        let block_info = ReplicaBlockInfo {
+            slot: 10,
+            parent_slot: 8,
+            blockhash: "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin",
+            rewards: &rewards,
+            block_time: Some(1_639_000_000),
+            block_height: Some(9),
+        };
+
+        let db_block_info = DbBlockInfo::from(&block_info);
+        assert_eq!(db_block_info.slot, 10);
+        assert_eq!(db_block_info.parent_slot, 8);
+        assert_eq!(db_block_info.blockhash, block_info.blockhash);
+        assert_eq!(db_block_info.block_time, Some(1_639_000_000));
+        assert_eq!(db_block_info.block_height, Some(9));
+        assert_eq!(
+            db_block_info.rewards,
+            vec![DbReward {
+                pubkey: rewards[0].pubkey.clone(),
+                lamports: -5000,
+                post_balance: 1_000_000,
+                reward_type: Some(DbRewardType::Fee),
+                commission: Some(10),
+            }]
+        );
+    }
+}
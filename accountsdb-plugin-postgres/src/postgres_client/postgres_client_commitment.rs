@@ -0,0 +1,288 @@
+/// Module responsible for holding back the account updates of a slot until the slot
+/// reaches the configured commitment level, so that the database only reflects the
+/// canonical fork. The updates of the slots which are pruned off the fork are discarded.
+use {
+    crate::{
+        accountsdb_plugin_postgres::CommitmentLevel,
+        postgres_client::{DbWorkItem, ParallelPostgresClient, UpdateAccountRequest},
+    },
+    log::*,
+    solana_accountsdb_plugin_interface::accountsdb_plugin_interface::{
+        AccountsDbPluginError, SlotStatus,
+    },
+    solana_metrics::*,
+    solana_sdk::clock::Slot,
+    std::collections::{HashMap, HashSet},
+};
+
+impl CommitmentLevel {
+    fn is_reached_by(&self, status: &SlotStatus) -> bool {
+        match self {
+            CommitmentLevel::Processed => true,
+            CommitmentLevel::Confirmed => {
+                matches!(status, SlotStatus::Confirmed | SlotStatus::Rooted)
+            }
+            CommitmentLevel::Rooted => matches!(status, SlotStatus::Rooted),
+        }
+    }
+}
+
+pub(crate) struct PendingAccountUpdates {
+    commitment_level: CommitmentLevel,
+    /// The account updates held back, by slot
+    updates: HashMap<Slot, Vec<UpdateAccountRequest>>,
+    /// The parents of the slots above the root
+    parents: HashMap<Slot, Slot>,
+    /// The slots at or above the root which reached the commitment level
+    committed_slots: HashSet<Slot>,
+    root: Slot,
+}
+
+impl PendingAccountUpdates {
+    pub(crate) fn new(commitment_level: CommitmentLevel) -> Self {
+        Self {
+            commitment_level,
+            updates: HashMap::default(),
+            parents: HashMap::default(),
+            committed_slots: HashSet::default(),
+            root: 0,
+        }
+    }
+
+    /// Hold back the update until its slot reaches the commitment level. The update is
+    /// returned if it can be written right away.
+    pub(crate) fn buffer(
+        &mut self,
+        slot: Slot,
+        request: UpdateAccountRequest,
+    ) -> Option<UpdateAccountRequest> {
+        if request.is_startup || self.committed_slots.contains(&slot) {
+            return Some(request);
+        }
+        if slot < self.root {
+            // The slot is not an ancestor of the root or it would have been committed
+            return None;
+        }
+        self.updates.entry(slot).or_default().push(request);
+        None
+    }
+
+    /// Record the new status of the slot and return the held back updates which can be
+    /// written now, the ones of the ancestors first.
+    pub(crate) fn update_slot_status(
+        &mut self,
+        slot: Slot,
+        parent: Option<Slot>,
+        status: &SlotStatus,
+    ) -> Vec<UpdateAccountRequest> {
+        if let Some(parent) = parent {
+            if slot > self.root {
+                self.parents.insert(slot, parent);
+            }
+        }
+
+        let mut committed_updates = vec![];
+        if self.commitment_level.is_reached_by(status) {
+            // The ancestors of a committed slot are committed as well
+            let mut next = Some(slot);
+            while let Some(current) = next {
+                if current < self.root {
+                    break;
+                }
+                self.committed_slots.insert(current);
+                if let Some(updates) = self.updates.remove(&current) {
+                    committed_updates.push(updates);
+                }
+                next = self.parents.get(&current).copied();
+            }
+            committed_updates.reverse();
+        }
+
+        if matches!(status, SlotStatus::Rooted) && slot > self.root {
+            self.set_root(slot);
+        }
+        committed_updates.into_iter().flatten().collect()
+    }
+
+    /// Whether the slot is the root or descends from it. Slots whose ancestry is not fully
+    /// known yet are presumed to be on the fork.
+    fn is_on_rooted_fork(&self, slot: Slot) -> bool {
+        let mut slot = slot;
+        while slot > self.root {
+            match self.parents.get(&slot) {
+                Some(parent) => slot = *parent,
+                None => return true,
+            }
+        }
+        slot == self.root
+    }
+
+    /// Discard the updates of the slots which can no longer be committed
+    fn set_root(&mut self, root: Slot) {
+        self.root = root;
+        let abandoned_slots: HashSet<Slot> = self
+            .parents
+            .keys()
+            .chain(self.updates.keys())
+            .filter(|slot| !self.is_on_rooted_fork(**slot))
+            .copied()
+            .collect();
+
+        let mut discarded_slots = 0;
+        let mut discarded_updates = 0;
+        self.updates.retain(|slot, updates| {
+            if abandoned_slots.contains(slot) {
+                discarded_slots += 1;
+                discarded_updates += updates.len();
+                false
+            } else {
+                true
+            }
+        });
+        self.parents
+            .retain(|slot, _| *slot > root && !abandoned_slots.contains(slot));
+        self.committed_slots.retain(|slot| *slot >= root);
+
+        if discarded_slots > 0 {
+            info!(
+                "Discarded {} account updates of {} slots pruned off the fork at root {}",
+                discarded_updates, discarded_slots, root
+            );
+            inc_new_counter_info!(
+                "accountsdb-plugin-postgres-discarded-account-updates",
+                discarded_updates
+            );
+        }
+    }
+
+    pub(crate) fn num_pending_slots(&self) -> usize {
+        self.updates.len()
+    }
+}
+
+impl ParallelPostgresClient {
+    /// Send the account updates which reached the commitment level to the workers.
+    pub(crate) fn commit_pending_account_updates(
+        &mut self,
+        slot: Slot,
+        parent: Option<Slot>,
+        status: &SlotStatus,
+    ) -> Result<(), AccountsDbPluginError> {
+        let pending_account_updates = match &mut self.pending_account_updates {
+            Some(pending_account_updates) => pending_account_updates,
+            None => return Ok(()),
+        };
+        for request in pending_account_updates.update_slot_status(slot, parent, status) {
+            if let Err(err) = self.sender.send(DbWorkItem::UpdateAccount(request)) {
+                return Err(AccountsDbPluginError::SlotStatusUpdateError {
+                    msg: format!(
+                        "Failed to commit the account updates at slot {:?}, error: {:?}",
+                        slot, err
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use {
+        super::*, crate::postgres_client::tests::new_db_account_info, solana_sdk::pubkey::Pubkey,
+    };
+
+    fn new_request(slot: Slot, is_startup: bool) -> UpdateAccountRequest {
+        UpdateAccountRequest {
+            account: new_db_account_info(
+                &Pubkey::new_from_array([1; 32]),
+                &Pubkey::new_from_array([2; 32]),
+                slot as i64,
+                vec![],
+            ),
+            is_startup,
+            is_selected: true,
+        }
+    }
+
+    fn slots(requests: &[UpdateAccountRequest]) -> Vec<i64> {
+        requests
+            .iter()
+            .map(|request| request.account.slot)
+            .collect()
+    }
+
+    #[test]
+    fn test_commit_on_confirmed() {
+        let mut pending = PendingAccountUpdates::new(CommitmentLevel::Confirmed);
+        assert!(pending.buffer(0, new_request(0, true)).is_some());
+
+        // Fork: 1 -> 2 -> 4 and 1 -> 3
+        for (slot, parent) in [(1, 0), (2, 1), (3, 1), (4, 2)] {
+            assert!(pending.buffer(slot, new_request(slot, false)).is_none());
+            assert!(pending
+                .update_slot_status(slot, Some(parent), &SlotStatus::Processed)
+                .is_empty());
+        }
+        assert_eq!(pending.num_pending_slots(), 4);
+
+        // Confirming 4 commits its ancestors too
+        let committed = pending.update_slot_status(4, None, &SlotStatus::Confirmed);
+        assert_eq!(slots(&committed), vec![1, 2, 4]);
+        assert_eq!(pending.num_pending_slots(), 1);
+
+        // Late updates of a committed slot are written right away
+        assert!(pending.buffer(4, new_request(4, false)).is_some());
+
+        // Rooting 2 discards the abandoned slot 3
+        assert!(pending
+            .update_slot_status(2, Some(1), &SlotStatus::Rooted)
+            .is_empty());
+        assert_eq!(pending.num_pending_slots(), 0);
+        // Updates of the slots below the root are never committed
+        assert!(pending.buffer(1, new_request(1, false)).is_none());
+        assert_eq!(pending.num_pending_slots(), 0);
+    }
+
+    #[test]
+    fn test_commit_on_rooted() {
+        let mut pending = PendingAccountUpdates::new(CommitmentLevel::Rooted);
+        for (slot, parent) in [(1, 0), (2, 1), (3, 1), (4, 3)] {
+            pending.buffer(slot, new_request(slot, false));
+            pending.update_slot_status(slot, Some(parent), &SlotStatus::Processed);
+        }
+        assert!(pending
+            .update_slot_status(4, None, &SlotStatus::Confirmed)
+            .is_empty());
+
+        // Only the root notification of the latest root may be sent
+        let committed = pending.update_slot_status(3, Some(1), &SlotStatus::Rooted);
+        assert_eq!(slots(&committed), vec![1, 3]);
+        // Slot 2 is discarded, slot 4 is still pending
+        assert_eq!(pending.num_pending_slots(), 1);
+
+        let committed = pending.update_slot_status(4, Some(3), &SlotStatus::Rooted);
+        assert_eq!(slots(&committed), vec![4]);
+        assert_eq!(pending.num_pending_slots(), 0);
+    }
+
+    #[test]
+    fn test_confirmed_before_frozen() {
+        let mut pending = PendingAccountUpdates::new(CommitmentLevel::Confirmed);
+        pending.buffer(1, new_request(1, false));
+        pending.update_slot_status(1, Some(0), &SlotStatus::Processed);
+        pending.buffer(2, new_request(2, false));
+
+        // The parent of 2 is not known yet when it is confirmed
+        let committed = pending.update_slot_status(2, None, &SlotStatus::Confirmed);
+        assert_eq!(slots(&committed), vec![2]);
+        assert!(pending
+            .update_slot_status(2, Some(1), &SlotStatus::Processed)
+            .is_empty());
+
+        // The root commits the ancestors left behind
+        let committed = pending.update_slot_status(2, Some(1), &SlotStatus::Rooted);
+        assert_eq!(slots(&committed), vec![1]);
+        assert_eq!(pending.num_pending_slots(), 0);
+    }
+}
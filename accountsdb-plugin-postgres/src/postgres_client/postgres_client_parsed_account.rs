@@ -0,0 +1,541 @@
+/// Module responsible for storing the accounts parsed by the account-decoder as JSON in
+/// the `account_parsed` table, so the stake, vote, nonce, sysvar, config, upgradeable
+/// loader and SPL token accounts can be queried with SQL.
+use {
+    crate::{
+        accountsdb_plugin_postgres::{
+            AccountsDbPluginPostgresConfig, AccountsDbPluginPostgresError,
+        },
+        postgres_client::{DbAccountInfo, PostgresSqlClientWrapper, SimplePostgresClient},
+    },
+    chrono::Utc,
+    log::*,
+    postgres::{Client, Statement},
+    solana_account_decoder::{
+        parse_account_data::{
+            parse_account_data, AccountAdditionalData, ParsedAccount, PARSABLE_PROGRAM_IDS,
+        },
+        parse_token::{get_token_account_mint, spl_token_id_v2_0},
+    },
+    solana_accountsdb_plugin_interface::accountsdb_plugin_interface::AccountsDbPluginError,
+    solana_measure::measure::Measure,
+    solana_metrics::*,
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        collections::HashMap,
+        time::{Duration, Instant},
+    },
+};
+
+/// How long a mint whose decimals could not be looked up is not looked up again
+const UNKNOWN_MINT_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+/// The number of unknown mints above which the expired ones are forgotten
+const MAX_UNKNOWN_MINTS: usize = 10_000;
+
+pub(crate) struct ParsedAccountStatements {
+    upsert_parsed_stmt: Statement,
+    delete_parsed_stmt: Statement,
+    get_mint_decimals_stmt: Statement,
+    mint_decimals: MintDecimals,
+}
+
+/// The decimals of the SPL token mints, which are needed to parse the token accounts
+#[derive(Debug, Default)]
+struct MintDecimals {
+    decimals: HashMap<Pubkey, u8>,
+    /// The mints whose decimals could not be looked up, with the time of the lookup
+    unknown_mints: HashMap<Pubkey, Instant>,
+}
+
+impl MintDecimals {
+    fn insert(&mut self, mint: Pubkey, decimals: u8) {
+        self.unknown_mints.remove(&mint);
+        self.decimals.insert(mint, decimals);
+    }
+
+    /// Returns the decimals of the mint, looked up with `load_mint_decimals` when they are
+    /// not known. A mint which could not be looked up is not looked up again before
+    /// `UNKNOWN_MINT_RETRY_INTERVAL`.
+    fn get_or_load(
+        &mut self,
+        mint: &Pubkey,
+        load_mint_decimals: impl FnOnce(&Pubkey) -> Option<u8>,
+    ) -> Option<u8> {
+        if let Some(decimals) = self.decimals.get(mint) {
+            return Some(*decimals);
+        }
+        if let Some(looked_up) = self.unknown_mints.get(mint) {
+            if looked_up.elapsed() < UNKNOWN_MINT_RETRY_INTERVAL {
+                return None;
+            }
+        }
+        match load_mint_decimals(mint) {
+            Some(decimals) => {
+                self.insert(*mint, decimals);
+                Some(decimals)
+            }
+            None => {
+                if self.unknown_mints.len() >= MAX_UNKNOWN_MINTS {
+                    self.unknown_mints
+                        .retain(|_, looked_up| looked_up.elapsed() < UNKNOWN_MINT_RETRY_INTERVAL);
+                }
+                self.unknown_mints.insert(*mint, Instant::now());
+                None
+            }
+        }
+    }
+}
+
+/// The columns of the `account_parsed` rows to upsert and the ones to remove for a set of
+/// accounts
+#[derive(Debug, Default, PartialEq)]
+struct ParsedAccountColumns {
+    pubkeys: Vec<Vec<u8>>,
+    slots: Vec<i64>,
+    write_versions: Vec<i64>,
+    programs: Vec<String>,
+    parsed: Vec<serde_json::Value>,
+    /// The accounts which are closed or no longer owned by a parsable program
+    removed_pubkeys: Vec<Vec<u8>>,
+    removed_slots: Vec<i64>,
+    removed_write_versions: Vec<i64>,
+}
+
+/// Returns the decimals of the mint if the account is parsed as an SPL token mint
+fn get_parsed_mint_decimals(parsed_account: &ParsedAccount) -> Option<u8> {
+    if parsed_account.program != "spl-token" || parsed_account.parsed["type"] != "mint" {
+        return None;
+    }
+    parsed_account.parsed["info"]["decimals"]
+        .as_u64()
+        .map(|decimals| decimals as u8)
+}
+
+impl ParsedAccountColumns {
+    /// `load_mint_decimals` is called for the mints of the token accounts whose decimals
+    /// are not in `mint_decimals` yet.
+    fn new(
+        accounts: &[DbAccountInfo],
+        mint_decimals: &mut MintDecimals,
+        mut load_mint_decimals: impl FnMut(&Pubkey) -> Option<u8>,
+    ) -> Self {
+        // Only the latest version of every account is kept as the upsert cannot update the
+        // same row twice
+        let mut latest_accounts: HashMap<&[u8], &DbAccountInfo> = HashMap::default();
+        for account in accounts {
+            let latest = latest_accounts.entry(&account.pubkey).or_insert(account);
+            if (latest.slot, latest.write_version) < (account.slot, account.write_version) {
+                *latest = account;
+            }
+        }
+        let mut latest_accounts: Vec<&DbAccountInfo> = latest_accounts.into_values().collect();
+        latest_accounts.sort_by(|a, b| a.pubkey.cmp(&b.pubkey));
+
+        let token_program_id = spl_token_id_v2_0();
+        let mut parsed_accounts = Vec::with_capacity(latest_accounts.len());
+        let mut token_accounts = vec![];
+        for account in latest_accounts {
+            let pubkey = Pubkey::new(&account.pubkey);
+            let owner = Pubkey::new(&account.owner);
+            if owner == token_program_id {
+                if let Some(mint) = get_token_account_mint(&account.data) {
+                    token_accounts.push((account, pubkey, owner, mint));
+                    continue;
+                }
+            }
+            let parsed_account = parse_account_data(&pubkey, &owner, &account.data, None);
+            if let Some(decimals) = parsed_account
+                .as_ref()
+                .ok()
+                .and_then(get_parsed_mint_decimals)
+            {
+                mint_decimals.insert(pubkey, decimals);
+            }
+            parsed_accounts.push((account, owner, parsed_account));
+        }
+
+        // The token accounts are parsed after the mints of the same batch, which give them
+        // their decimals
+        for (account, pubkey, owner, mint) in token_accounts {
+            let spl_token_decimals = mint_decimals.get_or_load(&mint, &mut load_mint_decimals);
+            let parsed_account = parse_account_data(
+                &pubkey,
+                &owner,
+                &account.data,
+                Some(AccountAdditionalData { spl_token_decimals }),
+            );
+            parsed_accounts.push((account, owner, parsed_account));
+        }
+
+        let mut columns = Self::default();
+        for (account, owner, parsed_account) in parsed_accounts {
+            match parsed_account {
+                Ok(parsed_account) => {
+                    columns.pubkeys.push(account.pubkey.clone());
+                    columns.slots.push(account.slot);
+                    columns.write_versions.push(account.write_version);
+                    columns.programs.push(parsed_account.program);
+                    columns.parsed.push(parsed_account.parsed);
+                }
+                Err(err) => {
+                    trace!(
+                        "Account {} is not parsable: {:?}",
+                        bs58::encode(&account.pubkey).into_string(),
+                        err
+                    );
+                    // The parsed account is left as it is when the account is still owned by
+                    // a parsable program, e.g. a token account whose mint decimals are unknown
+                    if account.lamports == 0 || !PARSABLE_PROGRAM_IDS.contains_key(&owner) {
+                        columns.removed_pubkeys.push(account.pubkey.clone());
+                        columns.removed_slots.push(account.slot);
+                        columns.removed_write_versions.push(account.write_version);
+                    }
+                }
+            }
+        }
+        columns
+    }
+}
+
+fn prepare_statement(
+    client: &mut Client,
+    config: &AccountsDbPluginPostgresConfig,
+    stmt: &str,
+) -> Result<Statement, AccountsDbPluginError> {
+    client.prepare(stmt).map_err(|err| {
+        AccountsDbPluginError::Custom(Box::new(AccountsDbPluginPostgresError::DataSchemaError {
+            msg: format!(
+                "Error in preparing for the parsed accounts PostgreSQL database: ({}) host: {:?} user: {:?} config: {:?}",
+                err, config.host, config.user, config
+            ),
+        }))
+    })
+}
+
+impl SimplePostgresClient {
+    pub(crate) fn build_parsed_account_statements(
+        client: &mut Client,
+        config: &AccountsDbPluginPostgresConfig,
+    ) -> Result<Option<ParsedAccountStatements>, AccountsDbPluginError> {
+        if !config.store_parsed_accounts.unwrap_or_default() {
+            return Ok(None);
+        }
+
+        let upsert_parsed_stmt = prepare_statement(
+            client,
+            config,
+            "INSERT INTO account_parsed AS acct (pubkey, slot, write_version, program, parsed, updated_on) \
+            SELECT *, $6::TIMESTAMP FROM UNNEST($1::BYTEA[], $2::BIGINT[], $3::BIGINT[], $4::VARCHAR[], $5::JSONB[]) \
+            ON CONFLICT (pubkey) DO UPDATE SET slot=excluded.slot, write_version=excluded.write_version, \
+            program=excluded.program, parsed=excluded.parsed, updated_on=excluded.updated_on \
+            WHERE acct.slot < excluded.slot OR (acct.slot = excluded.slot AND acct.write_version < excluded.write_version)",
+        )?;
+        let delete_parsed_stmt = prepare_statement(
+            client,
+            config,
+            "DELETE FROM account_parsed AS acct \
+            USING UNNEST($1::BYTEA[], $2::BIGINT[], $3::BIGINT[]) AS removed (pubkey, slot, write_version) \
+            WHERE acct.pubkey = removed.pubkey AND (acct.slot < removed.slot OR \
+            (acct.slot = removed.slot AND acct.write_version < removed.write_version))",
+        )?;
+        let get_mint_decimals_stmt = prepare_statement(
+            client,
+            config,
+            "SELECT (parsed->'info'->>'decimals')::SMALLINT FROM account_parsed \
+            WHERE pubkey = $1 AND program = 'spl-token' AND parsed->>'type' = 'mint'",
+        )?;
+
+        Ok(Some(ParsedAccountStatements {
+            upsert_parsed_stmt,
+            delete_parsed_stmt,
+            get_mint_decimals_stmt,
+            mint_decimals: MintDecimals::default(),
+        }))
+    }
+
+    /// Store the parsed accounts. Does nothing if storing the parsed accounts is not enabled.
+    /// The mints of the SPL token accounts are looked up in the `account_parsed` table
+    /// when they are not seen by this client yet, token accounts whose mint is unknown are
+    /// not updated. The parsed accounts are removed once the accounts are closed or assigned
+    /// to a program whose accounts are not parsed.
+    pub(crate) fn insert_parsed_accounts_internal(
+        client: &mut PostgresSqlClientWrapper,
+        accounts: &[DbAccountInfo],
+    ) -> Result<(), AccountsDbPluginError> {
+        let PostgresSqlClientWrapper {
+            client,
+            parsed_account_stmts,
+            ..
+        } = client;
+        let statements = match parsed_account_stmts {
+            Some(statements) => statements,
+            None => return Ok(()),
+        };
+        if accounts.is_empty() {
+            return Ok(());
+        }
+
+        let mut measure = Measure::start("accountsdb-plugin-postgres-parse-accounts");
+        let get_mint_decimals_stmt = &statements.get_mint_decimals_stmt;
+        let columns =
+            ParsedAccountColumns::new(accounts, &mut statements.mint_decimals, |mint| match client
+                .query_opt(get_mint_decimals_stmt, &[&mint.as_ref()])
+            {
+                Ok(row) => row
+                    .and_then(|row| row.get::<_, Option<i16>>(0))
+                    .map(|decimals| decimals as u8),
+                Err(err) => {
+                    warn!("Failed to look up the decimals of mint {}: {:?}", mint, err);
+                    None
+                }
+            });
+        measure.stop();
+        inc_new_counter_debug!(
+            "accountsdb-plugin-postgres-parse-accounts-us",
+            measure.as_us() as usize,
+            10000,
+            10000
+        );
+
+        let mut measure = Measure::start("accountsdb-plugin-postgres-insert-parsed-accounts");
+        let updated_on = Utc::now().naive_utc();
+        let mut result = Ok(0);
+        if !columns.pubkeys.is_empty() {
+            result = client.execute(
+                &statements.upsert_parsed_stmt,
+                &[
+                    &columns.pubkeys,
+                    &columns.slots,
+                    &columns.write_versions,
+                    &columns.programs,
+                    &columns.parsed,
+                    &updated_on,
+                ],
+            );
+        }
+        if result.is_ok() && !columns.removed_pubkeys.is_empty() {
+            result = client.execute(
+                &statements.delete_parsed_stmt,
+                &[
+                    &columns.removed_pubkeys,
+                    &columns.removed_slots,
+                    &columns.removed_write_versions,
+                ],
+            );
+        }
+
+        if let Err(err) = result {
+            let msg = format!(
+                "Failed to persist the parsed accounts to the PostgreSQL database. Error: {:?}",
+                err
+            );
+            error!("{}", msg);
+            return Err(AccountsDbPluginError::AccountsUpdateError { msg });
+        }
+        measure.stop();
+        inc_new_counter_debug!(
+            "accountsdb-plugin-postgres-insert-parsed-accounts-us",
+            measure.as_us() as usize,
+            10000,
+            10000
+        );
+        Ok(())
+    }
+
+    /// Remove the parsed accounts of the accounts which are no longer selected. Does nothing
+    /// if storing the parsed accounts is not enabled.
+    pub(crate) fn remove_parsed_accounts_internal(
+        client: &mut PostgresSqlClientWrapper,
+        accounts: &[DbAccountInfo],
+    ) -> Result<(), AccountsDbPluginError> {
+        let PostgresSqlClientWrapper {
+            client,
+            parsed_account_stmts,
+            ..
+        } = client;
+        let statements = match parsed_account_stmts {
+            Some(statements) => statements,
+            None => return Ok(()),
+        };
+        if accounts.is_empty() {
+            return Ok(());
+        }
+
+        let pubkeys: Vec<Vec<u8>> = accounts
+            .iter()
+            .map(|account| account.pubkey.clone())
+            .collect();
+        let slots: Vec<i64> = accounts.iter().map(|account| account.slot).collect();
+        let write_versions: Vec<i64> = accounts
+            .iter()
+            .map(|account| account.write_version)
+            .collect();
+        if let Err(err) = client.execute(
+            &statements.delete_parsed_stmt,
+            &[&pubkeys, &slots, &write_versions],
+        ) {
+            let msg = format!(
+                "Failed to remove the parsed accounts from the PostgreSQL database. Error: {:?}",
+                err
+            );
+            error!("{}", msg);
+            return Err(AccountsDbPluginError::AccountsUpdateError { msg });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use {
+        super::*,
+        crate::postgres_client::tests::new_db_account_info as new_account,
+        solana_account_decoder::parse_token::spl_token_v2_0_native_mint,
+        spl_token_v2_0::{
+            solana_program::{program_option::COption, program_pack::Pack},
+            state::{Account as TokenAccount, AccountState, Mint},
+        },
+    };
+
+    fn new_mint_data(decimals: u8) -> Vec<u8> {
+        let mut data = vec![0; Mint::get_packed_len()];
+        Mint::pack(
+            Mint {
+                mint_authority: COption::None,
+                supply: 1000,
+                decimals,
+                is_initialized: true,
+                freeze_authority: COption::None,
+            },
+            &mut data,
+        )
+        .unwrap();
+        data
+    }
+
+    fn new_token_account_data(mint: &Pubkey, amount: u64) -> Vec<u8> {
+        let mut data = vec![0; TokenAccount::get_packed_len()];
+        TokenAccount::pack(
+            TokenAccount {
+                mint: spl_token_v2_0::solana_program::pubkey::Pubkey::new_from_array(
+                    mint.to_bytes(),
+                ),
+                amount,
+                state: AccountState::Initialized,
+                ..TokenAccount::default()
+            },
+            &mut data,
+        )
+        .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_parsed_account_columns() {
+        let token_program_id = spl_token_id_v2_0();
+        let mint = Pubkey::new_unique();
+        let token_account = Pubkey::new_unique();
+        let other_program_account = Pubkey::new_unique();
+        let mut accounts = vec![
+            new_account(
+                &token_account,
+                &token_program_id,
+                5,
+                new_token_account_data(&mint, 1),
+            ),
+            new_account(
+                &token_account,
+                &token_program_id,
+                6,
+                new_token_account_data(&mint, 2),
+            ),
+            new_account(&mint, &token_program_id, 5, new_mint_data(2)),
+            new_account(&other_program_account, &Pubkey::new_unique(), 5, vec![1, 2]),
+        ];
+        accounts.sort_by_key(|account| account.pubkey.clone());
+
+        // The mint in the same batch gives the decimals of the token account
+        let mut mint_decimals = MintDecimals::default();
+        let columns = ParsedAccountColumns::new(&accounts, &mut mint_decimals, |_| {
+            panic!("The mint decimals are known")
+        });
+        assert_eq!(mint_decimals.decimals.get(&mint), Some(&2));
+        assert_eq!(columns.programs, vec!["spl-token", "spl-token"]);
+        let token_account_index = columns
+            .pubkeys
+            .iter()
+            .position(|pubkey| pubkey == token_account.as_ref())
+            .unwrap();
+        assert_eq!(columns.slots[token_account_index], 6);
+        assert_eq!(
+            columns.parsed[token_account_index]["info"]["tokenAmount"]["uiAmountString"],
+            "0.02"
+        );
+        assert!(columns.removed_pubkeys.is_empty());
+
+        // The decimals of an unknown mint are loaded, the token accounts of the mints
+        // which cannot be loaded are left as they are
+        let other_mint = spl_token_v2_0_native_mint();
+        let accounts = vec![
+            new_account(
+                &token_account,
+                &token_program_id,
+                7,
+                new_token_account_data(&other_mint, 3),
+            ),
+            new_account(
+                &Pubkey::new_unique(),
+                &token_program_id,
+                7,
+                new_token_account_data(&Pubkey::new_unique(), 3),
+            ),
+        ];
+        let columns = ParsedAccountColumns::new(&accounts, &mut mint_decimals, |mint| {
+            (*mint == other_mint).then(|| 9)
+        });
+        assert_eq!(mint_decimals.decimals.get(&other_mint), Some(&9));
+        assert_eq!(columns.pubkeys, vec![token_account.to_bytes().to_vec()]);
+        assert!(columns.removed_pubkeys.is_empty());
+        assert_eq!(mint_decimals.unknown_mints.len(), 1);
+
+        // The mints which cannot be loaded are not looked up again for a while
+        let unknown_mint = *mint_decimals.unknown_mints.keys().next().unwrap();
+        let unknown_mint_account = new_account(
+            &Pubkey::new_unique(),
+            &token_program_id,
+            7,
+            new_token_account_data(&unknown_mint, 3),
+        );
+        let columns = ParsedAccountColumns::new(
+            std::slice::from_ref(&unknown_mint_account),
+            &mut mint_decimals,
+            |_| panic!("The mint was looked up recently"),
+        );
+        assert!(columns.pubkeys.is_empty());
+        *mint_decimals.unknown_mints.get_mut(&unknown_mint).unwrap() -= UNKNOWN_MINT_RETRY_INTERVAL;
+        let columns =
+            ParsedAccountColumns::new(&[unknown_mint_account], &mut mint_decimals, |_| Some(4));
+        assert_eq!(columns.pubkeys.len(), 1);
+        assert!(mint_decimals.unknown_mints.is_empty());
+
+        // Accounts assigned to a program whose accounts are not parsed are removed
+        let columns = ParsedAccountColumns::new(
+            &[new_account(&mint, &Pubkey::new_unique(), 8, vec![])],
+            &mut mint_decimals,
+            |_| None,
+        );
+        assert!(columns.pubkeys.is_empty());
+        assert_eq!(columns.removed_pubkeys, vec![mint.to_bytes().to_vec()]);
+
+        // Closed accounts are removed
+        let mut closed_account = new_account(&token_account, &Pubkey::default(), 8, vec![]);
+        closed_account.lamports = 0;
+        let columns = ParsedAccountColumns::new(&[closed_account], &mut mint_decimals, |_| None);
+        assert!(columns.pubkeys.is_empty());
+        assert_eq!(
+            columns.removed_pubkeys,
+            vec![token_account.to_bytes().to_vec()]
+        );
+        assert_eq!(columns.removed_slots, vec![8]);
+    }
+}
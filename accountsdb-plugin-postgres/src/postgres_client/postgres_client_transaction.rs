@@ -0,0 +1,232 @@
+/// Module responsible for handling persisting transaction data to the PostgreSQL
+/// database.
+use {
+    crate::{
+        accountsdb_plugin_postgres::{
+            AccountsDbPluginPostgresConfig, AccountsDbPluginPostgresError,
+        },
+        postgres_client::{DbWorkItem, ParallelPostgresClient, SimplePostgresClient},
+    },
+    chrono::Utc,
+    log::*,
+    postgres::{Client, Statement},
+    solana_accountsdb_plugin_interface::accountsdb_plugin_interface::{
+        AccountsDbPluginError, ReplicaTransactionInfo,
+    },
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DbTransaction {
+    pub signature: Vec<u8>,
+    pub slot: i64,
+    pub is_vote: bool,
+    pub message_hash: Vec<u8>,
+    pub signatures: Vec<Vec<u8>>,
+    pub account_keys: Vec<Vec<u8>>,
+    pub fee: i64,
+    pub error: Option<String>,
+    pub pre_balances: Vec<i64>,
+    pub post_balances: Vec<i64>,
+    pub log_messages: Option<Vec<String>>,
+}
+
+pub struct LogTransactionRequest {
+    pub transaction_info: DbTransaction,
+}
+
+fn build_db_transaction(slot: u64, transaction_info: &ReplicaTransactionInfo) -> DbTransaction {
+    let transaction = transaction_info.transaction;
+    let meta = transaction_info.transaction_status_meta;
+    DbTransaction {
+        signature: transaction_info.signature.as_ref().to_vec(),
+        slot: slot as i64,
+        is_vote: transaction_info.is_vote,
+        message_hash: transaction.message_hash().as_ref().to_vec(),
+        signatures: transaction
+            .signatures()
+            .iter()
+            .map(|signature| signature.as_ref().to_vec())
+            .collect(),
+        account_keys: transaction
+            .message()
+            .account_keys_iter()
+            .map(|key| key.as_ref().to_vec())
+            .collect(),
+        fee: meta.fee as i64,
+        error: meta.status.as_ref().err().map(|err| err.to_string()),
+        pre_balances: meta
+            .pre_balances
+            .iter()
+            .map(|balance| *balance as i64)
+            .collect(),
+        post_balances: meta
+            .post_balances
+            .iter()
+            .map(|balance| *balance as i64)
+            .collect(),
+        log_messages: meta.log_messages.clone(),
+    }
+}
+
+impl SimplePostgresClient {
+    pub(crate) fn build_transaction_info_upsert_statement(
+        client: &mut Client,
+        config: &AccountsDbPluginPostgresConfig,
+    ) -> Result<Statement, AccountsDbPluginError> {
+        let stmt = "INSERT INTO transaction AS txn (signature, slot, is_vote, message_hash, signatures, account_keys, \
+        fee, error, pre_balances, post_balances, log_messages, updated_on) \
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) \
+        ON CONFLICT (slot, signature) DO UPDATE SET is_vote=excluded.is_vote, message_hash=excluded.message_hash, \
+        signatures=excluded.signatures, account_keys=excluded.account_keys, fee=excluded.fee, error=excluded.error, \
+        pre_balances=excluded.pre_balances, post_balances=excluded.post_balances, log_messages=excluded.log_messages, \
+        updated_on=excluded.updated_on";
+
+        let stmt = client.prepare(stmt);
+
+        match stmt {
+            Err(err) => {
+                return Err(AccountsDbPluginError::Custom(Box::new(AccountsDbPluginPostgresError::DataSchemaError {
+                    msg: format!(
+                        "Error in preparing for the transaction update PostgreSQL database: ({}) host: {:?} user: {:?} config: {:?}",
+                        err, config.host, config.user, config
+                    ),
+                })));
+            }
+            Ok(stmt) => Ok(stmt),
+        }
+    }
+
+    pub(crate) fn log_transaction_impl(
+        &mut self,
+        transaction_log_info: LogTransactionRequest,
+    ) -> Result<(), AccountsDbPluginError> {
+        let client = self.client.get_mut().unwrap();
+        let statement = &client.update_transaction_log_stmt;
+        let client = &mut client.client;
+        let updated_on = Utc::now().naive_utc();
+
+        let transaction_info = transaction_log_info.transaction_info;
+        let result = client.query(
+            statement,
+            &[
+                &transaction_info.signature,
+                &transaction_info.slot,
+                &transaction_info.is_vote,
+                &transaction_info.message_hash,
+                &transaction_info.signatures,
+                &transaction_info.account_keys,
+                &transaction_info.fee,
+                &transaction_info.error,
+                &transaction_info.pre_balances,
+                &transaction_info.post_balances,
+                &transaction_info.log_messages,
+                &updated_on,
+            ],
+        );
+
+        if let Err(err) = result {
+            let msg = format!(
+                "Failed to persist the update of transaction info to the PostgreSQL database. Error: {:?}",
+                err
+            );
+            error!("{}", msg);
+            return Err(AccountsDbPluginError::TransactionUpdateError { msg });
+        }
+
+        Ok(())
+    }
+}
+
+impl ParallelPostgresClient {
+    fn build_transaction_request(
+        slot: u64,
+        transaction_info: &ReplicaTransactionInfo,
+    ) -> LogTransactionRequest {
+        LogTransactionRequest {
+            transaction_info: build_db_transaction(slot, transaction_info),
+        }
+    }
+
+    pub fn log_transaction_info(
+        &mut self,
+        transaction_info: &ReplicaTransactionInfo,
+        slot: u64,
+    ) -> Result<(), AccountsDbPluginError> {
+        let wrk_item = DbWorkItem::LogTransaction(Box::new(Self::build_transaction_request(
+            slot,
+            transaction_info,
+        )));
+
+        if let Err(err) = self.sender.send(wrk_item) {
+            return Err(AccountsDbPluginError::TransactionUpdateError {
+                msg: format!(
+                    "Failed to update the transaction {}, error: {:?}",
+                    transaction_info.signature, err
+                ),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use {
+        super::*,
+        solana_sdk::{
+            hash::Hash,
+            message::Message,
+            pubkey::Pubkey,
+            signature::{Keypair, Signer},
+            system_instruction,
+            transaction::{SanitizedTransaction, Transaction},
+        },
+        solana_transaction_status::TransactionStatusMeta,
+    };
+
+    #[test]
+    fn test_build_db_transaction() {
+        let from = Keypair::new();
+        let to = Pubkey::new_unique();
+        let instruction = system_instruction::transfer(&from.pubkey(), &to, 42);
+        let message = Message::new(&[instruction], Some(&from.pubkey()));
+        let transaction = Transaction::new(&[&from], message, Hash::new_unique());
+        let transaction = SanitizedTransaction::try_from_legacy_transaction(transaction).unwrap();
+
+        let transaction_status_meta = TransactionStatusMeta {
+            status: Ok(()),
+            fee: 5000,
+            pre_balances: vec![100_000, 0, 1],
+            post_balances: vec![94_958, 42, 1],
+            log_messages: Some(vec!["Program log: transfer".to_string()]),
+            ..TransactionStatusMeta::default()
+        };
+
+        let transaction_info = ReplicaTransactionInfo {
+            signature: transaction.signature(),
+            is_vote: false,
+            transaction: &transaction,
+            transaction_status_meta: &transaction_status_meta,
+        };
+
+        let db_transaction = build_db_transaction(7, &transaction_info);
+        assert_eq!(db_transaction.slot, 7);
+        assert_eq!(
+            db_transaction.signature,
+            transaction.signature().as_ref().to_vec()
+        );
+        assert!(!db_transaction.is_vote);
+        assert_eq!(db_transaction.account_keys.len(), 3);
+        assert_eq!(
+            db_transaction.account_keys[0],
+            from.pubkey().to_bytes().to_vec()
+        );
+        assert_eq!(db_transaction.fee, 5000);
+        assert_eq!(db_transaction.error, None);
+        assert_eq!(db_transaction.post_balances, vec![94_958, 42, 1]);
+        assert_eq!(
+            db_transaction.log_messages,
+            Some(vec!["Program log: transfer".to_string()])
+        );
+    }
+}
@@ -8,7 +8,6 @@ pub(crate) struct TransactionSelector {
     pub select_all_vote_transactions: bool,
 }
 
-#[allow(dead_code)]
 impl TransactionSelector {
     pub fn default() -> Self {
         Self {
@@ -4,7 +4,7 @@ use crossbeam_channel::unbounded;
 use log::*;
 use rand::{thread_rng, Rng};
 use rayon::prelude::*;
-use solana_core::banking_stage::BankingStage;
+use solana_core::banking_stage::{BankingStage, TransactionScheduling};
 use solana_gossip::{cluster_info::ClusterInfo, cluster_info::Node};
 use solana_ledger::{
     blockstore::Blockstore,
@@ -20,6 +20,7 @@ use solana_runtime::{
 };
 use solana_sdk::{
     hash::Hash,
+    pubkey::Pubkey,
     signature::Keypair,
     signature::Signature,
     system_transaction,
@@ -28,6 +29,7 @@ use solana_sdk::{
 };
 use solana_streamer::socket::SocketAddrSpace;
 use std::{
+    str::FromStr,
     sync::{atomic::Ordering, mpsc::Receiver, Arc, Mutex, RwLock},
     thread::sleep,
     time::{Duration, Instant},
@@ -64,23 +66,61 @@ fn check_txs(
     no_bank
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum WriteLockContention {
+    /// No transactions lock the same account for write
+    None,
+    /// The transactions of a packet batch lock the same account for write
+    SameBatchOnly,
+    /// All the transactions lock the same account for write
+    Full,
+}
+
+impl WriteLockContention {
+    fn possible_values<'a>() -> &'a [&'a str] {
+        &["none", "same-batch-only", "full"]
+    }
+}
+
+impl FromStr for WriteLockContention {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "same-batch-only" => Ok(Self::SameBatchOnly),
+            "full" => Ok(Self::Full),
+            _ => Err(format!("{} is not a valid write lock contention", s)),
+        }
+    }
+}
+
 fn make_accounts_txs(
     total_num_transactions: usize,
+    packets_per_batch: usize,
     hash: Hash,
+    contention: WriteLockContention,
     same_payer: bool,
 ) -> Vec<Transaction> {
     let to_pubkey = solana_sdk::pubkey::new_rand();
+    let batch_pubkeys: Vec<Pubkey> = (0..total_num_transactions / packets_per_batch + 1)
+        .map(|_| solana_sdk::pubkey::new_rand())
+        .collect();
     let payer_key = Keypair::new();
     let dummy = system_transaction::transfer(&payer_key, &to_pubkey, 1, hash);
     (0..total_num_transactions)
         .into_par_iter()
-        .map(|_| {
+        .map(|i| {
             let mut new = dummy.clone();
             let sig: Vec<u8> = (0..64).map(|_| thread_rng().gen::<u8>()).collect();
             if !same_payer {
                 new.message.account_keys[0] = solana_sdk::pubkey::new_rand();
             }
-            new.message.account_keys[1] = solana_sdk::pubkey::new_rand();
+            new.message.account_keys[1] = match contention {
+                WriteLockContention::None => solana_sdk::pubkey::new_rand(),
+                WriteLockContention::SameBatchOnly => batch_pubkeys[i / packets_per_batch],
+                WriteLockContention::Full => to_pubkey,
+            };
             new.signatures = vec![Signature::new(&sig[0..64])];
             new
         })
@@ -148,6 +188,25 @@ fn main() {
                 .takes_value(true)
                 .help("Number of iterations"),
         )
+        .arg(
+            Arg::with_name("write_lock_contention")
+                .long("write-lock-contention")
+                .takes_value(true)
+                .possible_values(WriteLockContention::possible_values())
+                .help(
+                    "Accounts that the transfers contend for: none, the recipient of all the \
+                       transfers of a packet batch or the recipient of all the transfers",
+                ),
+        )
+        .arg(
+            Arg::with_name("central_scheduling")
+                .long("central-scheduling")
+                .takes_value(false)
+                .help(
+                    "Schedule conflict-free batches centrally instead of letting every banking \
+                       thread process the packets it receives",
+                ),
+        )
         .get_matches();
 
     let num_threads =
@@ -156,6 +215,13 @@ fn main() {
     let num_chunks = value_t!(matches, "num_chunks", usize).unwrap_or(16);
     let packets_per_chunk = value_t!(matches, "packets_per_chunk", usize).unwrap_or(192);
     let iterations = value_t!(matches, "iterations", usize).unwrap_or(1000);
+    let write_lock_contention = value_t!(matches, "write_lock_contention", WriteLockContention)
+        .unwrap_or(WriteLockContention::None);
+    let transaction_scheduling = if matches.is_present("central_scheduling") {
+        TransactionScheduling::Central
+    } else {
+        TransactionScheduling::ThreadLocal
+    };
 
     let total_num_transactions = num_chunks * num_threads * packets_per_chunk;
     let mint_total = 1_000_000_000_000;
@@ -173,11 +239,19 @@ fn main() {
     let mut bank_forks = BankForks::new(bank0);
     let mut bank = bank_forks.working_bank();
 
-    info!("threads: {} txs: {}", num_threads, total_num_transactions);
+    info!(
+        "threads: {} txs: {} contention: {:?} scheduling: {:?}",
+        num_threads, total_num_transactions, write_lock_contention, transaction_scheduling
+    );
 
     let same_payer = matches.is_present("same_payer");
-    let mut transactions =
-        make_accounts_txs(total_num_transactions, genesis_config.hash(), same_payer);
+    let mut transactions = make_accounts_txs(
+        total_num_transactions,
+        packets_per_chunk,
+        genesis_config.hash(),
+        write_lock_contention,
+        same_payer,
+    );
 
     // fund all the accounts
     transactions.iter().for_each(|tx| {
@@ -203,12 +277,14 @@ fn main() {
         });
         bank.clear_signatures();
         //sanity check, make sure all the transactions can execute in parallel
-
-        let res = bank.process_transactions(transactions.iter());
-        for r in res {
-            assert!(r.is_ok(), "sanity parallel execution error: {:?}", r);
+        //unless they are meant to contend for the same accounts
+        if write_lock_contention == WriteLockContention::None {
+            let res = bank.process_transactions(transactions.iter());
+            for r in res {
+                assert!(r.is_ok(), "sanity parallel execution error: {:?}", r);
+            }
+            bank.clear_signatures();
         }
-        bank.clear_signatures();
     }
 
     let mut verified: Vec<_> = to_packets_chunked(&transactions, packets_per_chunk);
@@ -225,7 +301,7 @@ fn main() {
             SocketAddrSpace::Unspecified,
         );
         let cluster_info = Arc::new(cluster_info);
-        let banking_stage = BankingStage::new(
+        let banking_stage = BankingStage::new_with_scheduling(
             &cluster_info,
             &poh_recorder,
             verified_receiver,
@@ -234,6 +310,7 @@ fn main() {
             None,
             replay_vote_sender,
             Arc::new(RwLock::new(CostModel::default())),
+            transaction_scheduling,
         );
         poh_recorder.lock().unwrap().set_bank(&bank);
 
@@ -78,7 +78,7 @@ fn bench_consume_buffered(bencher: &mut Bencher) {
         let mut packets = VecDeque::new();
         for batch in batches {
             let batch_len = batch.packets.len();
-            packets.push_back((batch, vec![0usize; batch_len], false));
+            packets.push_back((batch, vec![0usize; batch_len], false, vec![0; batch_len]));
         }
         let (s, _r) = unbounded();
         // This tests the performance of buffering packets.
@@ -94,7 +94,10 @@ fn bench_consume_buffered(bencher: &mut Bencher) {
                 None::<Box<dyn Fn()>>,
                 &BankingStageStats::default(),
                 &recorder,
-                &Arc::new(QosService::new(Arc::new(RwLock::new(CostModel::default())))),
+                &Arc::new(QosService::new(
+                    Arc::new(RwLock::new(CostModel::default())),
+                    "bench",
+                )),
             );
         });
 
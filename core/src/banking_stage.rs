@@ -1,8 +1,16 @@
 //! The `banking_stage` processes Transaction messages. It is intended to be used
 //! to contruct a software pipeline. The stage uses all available CPU cores and
 //! can do its processing in parallel with signature verification on the GPU.
-use crate::{packet_hasher::PacketHasher, qos_service::QosService};
-use crossbeam_channel::{Receiver as CrossbeamReceiver, RecvTimeoutError};
+use crate::{
+    packet_hasher::PacketHasher,
+    qos_service::QosService,
+    transaction_scheduler::{
+        CompletedBatch, PendingTransaction, ScheduledBatch, TransactionScheduler,
+    },
+};
+use crossbeam_channel::{
+    select, unbounded, Receiver as CrossbeamReceiver, RecvTimeoutError, Sender as CrossbeamSender,
+};
 use itertools::Itertools;
 use lru::LruCache;
 use retain_mut::RetainMut;
@@ -30,11 +38,14 @@ use solana_runtime::{
     vote_sender_types::ReplayVoteSender,
 };
 use solana_sdk::{
+    borsh::try_from_slice_unchecked,
     clock::{
         Slot, DEFAULT_TICKS_PER_SLOT, MAX_PROCESSING_AGE, MAX_TRANSACTION_FORWARDING_DELAY,
         MAX_TRANSACTION_FORWARDING_DELAY_GPU,
     },
+    compute_budget::{self, ComputeBudget, ComputeBudgetInstruction},
     feature_set,
+    hash::Hash,
     message::Message,
     pubkey::Pubkey,
     short_vec::decode_shortu16_len,
@@ -47,7 +58,7 @@ use solana_transaction_status::token_balances::{
     collect_token_balances, TransactionTokenBalancesSet,
 };
 use std::{
-    cmp,
+    cmp::{self, Reverse},
     collections::{HashMap, VecDeque},
     env,
     mem::size_of,
@@ -60,9 +71,11 @@ use std::{
     time::Instant,
 };
 
-/// (packets, valid_indexes, forwarded)
-/// Set of packets with a list of which are valid and if this batch has been forwarded.
-type PacketsAndOffsets = (Packets, Vec<usize>, bool);
+/// (packets, valid_indexes, forwarded, priorities)
+/// Set of packets with a list of which are valid, if this batch has been forwarded and the
+/// priority of each packet. `valid_indexes` are ordered from the highest to the lowest priority
+/// and `priorities` is indexed like `packets`.
+type PacketsAndOffsets = (Packets, Vec<usize>, bool, Vec<u64>);
 
 pub type UnprocessedPackets = VecDeque<PacketsAndOffsets>;
 
@@ -77,6 +90,12 @@ const TOTAL_BUFFERED_PACKETS: usize = 500_000;
 
 const MAX_NUM_TRANSACTIONS_PER_BATCH: usize = 128;
 
+// Batches the scheduler keeps queued or in process per worker thread
+const MAX_IN_FLIGHT_BATCHES_PER_WORKER: usize = 2;
+
+// Transaction priorities are expressed in lamports per this many compute units
+const PRIORITY_COMPUTE_UNITS_SCALE: u64 = 1_000_000;
+
 const DEFAULT_LRU_SIZE: usize = 200_000;
 
 const NUM_VOTE_PROCESSING_THREADS: u32 = 2;
@@ -96,6 +115,11 @@ pub struct BankingStageStats {
     current_buffered_packet_batches_count: AtomicUsize,
     rebuffered_packets_count: AtomicUsize,
     consumed_buffered_packets_count: AtomicUsize,
+    scheduled_batches_count: AtomicUsize,
+    scheduled_transactions_count: AtomicUsize,
+    scheduler_conflicts_count: AtomicUsize,
+    retried_transactions_count: AtomicUsize,
+    in_flight_batches_count: AtomicUsize,
 
     // Timing
     consume_buffered_packets_elapsed: AtomicU64,
@@ -106,6 +130,7 @@ pub struct BankingStageStats {
     packet_conversion_elapsed: AtomicU64,
     unprocessed_packet_conversion_elapsed: AtomicU64,
     transaction_processing_elapsed: AtomicU64,
+    schedule_elapsed: AtomicU64,
 }
 
 impl BankingStageStats {
@@ -175,6 +200,31 @@ impl BankingStageStats {
                         .swap(0, Ordering::Relaxed) as i64,
                     i64
                 ),
+                (
+                    "scheduled_batches_count",
+                    self.scheduled_batches_count.swap(0, Ordering::Relaxed) as i64,
+                    i64
+                ),
+                (
+                    "scheduled_transactions_count",
+                    self.scheduled_transactions_count.swap(0, Ordering::Relaxed) as i64,
+                    i64
+                ),
+                (
+                    "scheduler_conflicts_count",
+                    self.scheduler_conflicts_count.swap(0, Ordering::Relaxed) as i64,
+                    i64
+                ),
+                (
+                    "retried_transactions_count",
+                    self.retried_transactions_count.swap(0, Ordering::Relaxed) as i64,
+                    i64
+                ),
+                (
+                    "in_flight_batches_count",
+                    self.in_flight_batches_count.swap(0, Ordering::Relaxed) as i64,
+                    i64
+                ),
                 (
                     "consume_buffered_packets_elapsed",
                     self.consume_buffered_packets_elapsed
@@ -221,6 +271,11 @@ impl BankingStageStats {
                         .swap(0, Ordering::Relaxed) as i64,
                     i64
                 ),
+                (
+                    "schedule_elapsed",
+                    self.schedule_elapsed.swap(0, Ordering::Relaxed) as i64,
+                    i64
+                ),
             );
         }
     }
@@ -246,6 +301,17 @@ pub enum ForwardOption {
     ForwardTransaction,
 }
 
+/// How the non-vote transactions are spread over the banking threads
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransactionScheduling {
+    /// Every banking thread buffers and processes the packets it receives on its own, retrying
+    /// the transactions that hit account lock conflicts with the other threads
+    ThreadLocal,
+    /// A scheduler thread buffers the packets and hands the banking threads batches that don't
+    /// conflict with the batches they are already processing
+    Central,
+}
+
 impl BankingStage {
     /// Create the stage using `bank`. Exit when `verified_receiver` is dropped.
     #[allow(clippy::new_ret_no_self)]
@@ -258,6 +324,31 @@ impl BankingStage {
         transaction_status_sender: Option<TransactionStatusSender>,
         gossip_vote_sender: ReplayVoteSender,
         cost_model: Arc<RwLock<CostModel>>,
+    ) -> Self {
+        Self::new_with_scheduling(
+            cluster_info,
+            poh_recorder,
+            verified_receiver,
+            tpu_verified_vote_receiver,
+            verified_vote_receiver,
+            transaction_status_sender,
+            gossip_vote_sender,
+            cost_model,
+            TransactionScheduling::ThreadLocal,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_with_scheduling(
+        cluster_info: &Arc<ClusterInfo>,
+        poh_recorder: &Arc<Mutex<PohRecorder>>,
+        verified_receiver: CrossbeamReceiver<Vec<Packets>>,
+        tpu_verified_vote_receiver: CrossbeamReceiver<Vec<Packets>>,
+        verified_vote_receiver: CrossbeamReceiver<Vec<Packets>>,
+        transaction_status_sender: Option<TransactionStatusSender>,
+        gossip_vote_sender: ReplayVoteSender,
+        cost_model: Arc<RwLock<CostModel>>,
+        transaction_scheduling: TransactionScheduling,
     ) -> Self {
         Self::new_num_threads(
             cluster_info,
@@ -269,9 +360,11 @@ impl BankingStage {
             transaction_status_sender,
             gossip_vote_sender,
             cost_model,
+            transaction_scheduling,
         )
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn new_num_threads(
         cluster_info: &Arc<ClusterInfo>,
         poh_recorder: &Arc<Mutex<PohRecorder>>,
@@ -282,6 +375,7 @@ impl BankingStage {
         transaction_status_sender: Option<TransactionStatusSender>,
         gossip_vote_sender: ReplayVoteSender,
         cost_model: Arc<RwLock<CostModel>>,
+        transaction_scheduling: TransactionScheduling,
     ) -> Self {
         let batch_limit = TOTAL_BUFFERED_PACKETS / ((num_threads - 1) as usize * PACKETS_PER_BATCH);
         // Single thread to generate entries from many banks.
@@ -292,10 +386,14 @@ impl BankingStage {
             PacketHasher::default(),
         )));
         let data_budget = Arc::new(DataBudget::default());
-        let qos_service = Arc::new(QosService::new(cost_model));
+        let qos_service = Arc::new(QosService::new(cost_model, "banking_stage"));
+        // Batches built by the scheduler and the results of processing them, only used with
+        // `TransactionScheduling::Central`
+        let (batch_sender, batch_receiver) = unbounded();
+        let (completed_sender, completed_receiver) = unbounded();
         // Many banks that process transactions in parallel.
         assert!(num_threads >= NUM_VOTE_PROCESSING_THREADS + MIN_THREADS_BANKING);
-        let bank_thread_hdls: Vec<JoinHandle<()>> = (0..num_threads)
+        let mut bank_thread_hdls: Vec<JoinHandle<()>> = (0..num_threads)
             .map(|i| {
                 let (verified_receiver, forward_option) = match i {
                     0 => {
@@ -318,6 +416,26 @@ impl BankingStage {
                 let duplicates = duplicates.clone();
                 let data_budget = data_budget.clone();
                 let qos_service = qos_service.clone();
+                if transaction_scheduling == TransactionScheduling::Central
+                    && matches!(forward_option, ForwardOption::ForwardTransaction)
+                {
+                    let batch_receiver = batch_receiver.clone();
+                    let completed_sender = completed_sender.clone();
+                    return Builder::new()
+                        .name("solana-banking-stage-tx".to_string())
+                        .spawn(move || {
+                            Self::process_scheduled_batches(
+                                &batch_receiver,
+                                &completed_sender,
+                                &poh_recorder,
+                                i,
+                                transaction_status_sender,
+                                gossip_vote_sender,
+                                qos_service,
+                            );
+                        })
+                        .unwrap();
+                }
                 Builder::new()
                     .name("solana-banking-stage-tx".to_string())
                     .spawn(move || {
@@ -339,6 +457,30 @@ impl BankingStage {
                     .unwrap()
             })
             .collect();
+        if transaction_scheduling == TransactionScheduling::Central {
+            let num_workers = (num_threads - NUM_VOTE_PROCESSING_THREADS) as usize;
+            let poh_recorder = poh_recorder.clone();
+            let cluster_info = cluster_info.clone();
+            bank_thread_hdls.push(
+                Builder::new()
+                    .name("solana-banking-stage-scheduler".to_string())
+                    .spawn(move || {
+                        Self::schedule_loop(
+                            &verified_receiver,
+                            &poh_recorder,
+                            &cluster_info,
+                            num_threads,
+                            batch_limit * num_workers * PACKETS_PER_BATCH,
+                            MAX_IN_FLIGHT_BATCHES_PER_WORKER * num_workers,
+                            &duplicates,
+                            &data_budget,
+                            batch_sender,
+                            completed_receiver,
+                        );
+                    })
+                    .unwrap(),
+            );
+        }
         Self { bank_thread_hdls }
     }
 
@@ -346,8 +488,8 @@ impl BankingStage {
         all_packets: impl Iterator<Item = &'a PacketsAndOffsets>,
     ) -> Vec<&'a Packet> {
         all_packets
-            .filter(|(_p, _indexes, forwarded)| !forwarded)
-            .flat_map(|(p, valid_indexes, _forwarded)| {
+            .filter(|(_p, _indexes, forwarded, _priorities)| !forwarded)
+            .flat_map(|(p, valid_indexes, _forwarded, _priorities)| {
                 valid_indexes.iter().map(move |x| &p.packets[*x])
             })
             .collect()
@@ -360,6 +502,15 @@ impl BankingStage {
         data_budget: &DataBudget,
     ) -> std::io::Result<()> {
         let packets = Self::filter_valid_packets_for_forwarding(unprocessed_packets.iter());
+        Self::forward_packets(socket, tpu_forwards, packets, data_budget)
+    }
+
+    fn forward_packets(
+        socket: &std::net::UdpSocket,
+        tpu_forwards: &std::net::SocketAddr,
+        packets: Vec<&Packet>,
+        data_budget: &DataBudget,
+    ) -> std::io::Result<()> {
         inc_new_counter_info!("banking_stage-forwarded_packets", packets.len());
         const INTERVAL_MS: u64 = 100;
         const MAX_BYTES_PER_SECOND: usize = 10_000 * 1200;
@@ -415,7 +566,13 @@ impl BankingStage {
         let mut proc_start = Measure::start("consume_buffered_process");
         let mut reached_end_of_slot = None;
 
-        buffered_packets.retain_mut(|(msgs, ref mut original_unprocessed_indexes, _forwarded)| {
+        // Consume the batches holding the highest priority packets first, batches of equal
+        // priority keep their arrival order
+        buffered_packets
+            .make_contiguous()
+            .sort_by_key(|batch| Reverse(Self::batch_priority(batch)));
+
+        buffered_packets.retain_mut(|(msgs, ref mut original_unprocessed_indexes, ..)| {
             if let Some((next_leader, bank)) = &reached_end_of_slot {
                 // We've hit the end of this slot, no need to perform more processing,
                 // just filter the remaining packets for the invalid (e.g. too old) ones
@@ -556,32 +713,7 @@ impl BankingStage {
         data_budget: &DataBudget,
         qos_service: &Arc<QosService>,
     ) -> BufferedPacketsDecision {
-        let bank_start;
-        let (
-            leader_at_slot_offset,
-            bank_still_processing_txs,
-            would_be_leader,
-            would_be_leader_shortly,
-        ) = {
-            let poh = poh_recorder.lock().unwrap();
-            bank_start = poh.bank_start();
-            (
-                poh.leader_after_n_slots(FORWARD_TRANSACTIONS_TO_LEADER_AT_SLOT_OFFSET),
-                PohRecorder::get_working_bank_if_not_expired(&bank_start.as_ref()),
-                poh.would_be_leader(HOLD_TRANSACTIONS_SLOT_OFFSET * DEFAULT_TICKS_PER_SLOT),
-                poh.would_be_leader(
-                    (FORWARD_TRANSACTIONS_TO_LEADER_AT_SLOT_OFFSET - 1) * DEFAULT_TICKS_PER_SLOT,
-                ),
-            )
-        };
-
-        let decision = Self::consume_or_forward_packets(
-            my_pubkey,
-            leader_at_slot_offset,
-            bank_still_processing_txs,
-            would_be_leader,
-            would_be_leader_shortly,
-        );
+        let (decision, _bank_start) = Self::buffered_packets_decision(my_pubkey, poh_recorder);
 
         match decision {
             BufferedPacketsDecision::Consume(max_tx_ingestion_ns) => {
@@ -625,6 +757,40 @@ impl BankingStage {
         decision
     }
 
+    /// What to do with the buffered packets, along with the bank they would be consumed into
+    fn buffered_packets_decision(
+        my_pubkey: &Pubkey,
+        poh_recorder: &Arc<Mutex<PohRecorder>>,
+    ) -> (BufferedPacketsDecision, Option<BankStart>) {
+        let bank_start;
+        let (
+            leader_at_slot_offset,
+            bank_still_processing_txs,
+            would_be_leader,
+            would_be_leader_shortly,
+        ) = {
+            let poh = poh_recorder.lock().unwrap();
+            bank_start = poh.bank_start();
+            (
+                poh.leader_after_n_slots(FORWARD_TRANSACTIONS_TO_LEADER_AT_SLOT_OFFSET),
+                PohRecorder::get_working_bank_if_not_expired(&bank_start.as_ref()),
+                poh.would_be_leader(HOLD_TRANSACTIONS_SLOT_OFFSET * DEFAULT_TICKS_PER_SLOT),
+                poh.would_be_leader(
+                    (FORWARD_TRANSACTIONS_TO_LEADER_AT_SLOT_OFFSET - 1) * DEFAULT_TICKS_PER_SLOT,
+                ),
+            )
+        };
+
+        let decision = Self::consume_or_forward_packets(
+            my_pubkey,
+            leader_at_slot_offset,
+            bank_still_processing_txs,
+            would_be_leader,
+            would_be_leader_shortly,
+        );
+        (decision, bank_start)
+    }
+
     fn handle_forwarding(
         forward_option: &ForwardOption,
         cluster_info: &ClusterInfo,
@@ -652,8 +818,8 @@ impl BankingStage {
         };
         let _ = Self::forward_buffered_packets(socket, &addr, buffered_packets, data_budget);
         if hold {
-            buffered_packets.retain(|(_, index, _)| !index.is_empty());
-            for (_, _, forwarded) in buffered_packets.iter_mut() {
+            buffered_packets.retain(|(_, index, _, _)| !index.is_empty());
+            for (_, _, forwarded, _) in buffered_packets.iter_mut() {
                 *forwarded = true;
             }
         } else {
@@ -740,6 +906,356 @@ impl BankingStage {
         }
     }
 
+    /// Buffers the non-vote transactions and hands batches of them that don't contend for
+    /// account locks to the `process_scheduled_batches` threads
+    #[allow(clippy::too_many_arguments)]
+    fn schedule_loop(
+        verified_receiver: &CrossbeamReceiver<Vec<Packets>>,
+        poh_recorder: &Arc<Mutex<PohRecorder>>,
+        cluster_info: &ClusterInfo,
+        id: u32,
+        capacity: usize,
+        max_in_flight_batches: usize,
+        duplicates: &Arc<Mutex<(LruCache<u64, ()>, PacketHasher)>>,
+        data_budget: &DataBudget,
+        batch_sender: CrossbeamSender<ScheduledBatch>,
+        completed_receiver: CrossbeamReceiver<CompletedBatch>,
+    ) {
+        let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let mut scheduler = TransactionScheduler::default();
+        let banking_stage_stats = BankingStageStats::new(id);
+        loop {
+            let my_pubkey = cluster_info.id();
+            if scheduler.pending_len() > 0 {
+                match Self::buffered_packets_decision(&my_pubkey, poh_recorder) {
+                    (BufferedPacketsDecision::Consume(_), Some(bank_start)) => {
+                        Self::schedule_pending_transactions(
+                            &bank_start.working_bank,
+                            &mut scheduler,
+                            max_in_flight_batches,
+                            &batch_sender,
+                            &banking_stage_stats,
+                        );
+                    }
+                    (BufferedPacketsDecision::Forward, _) => {
+                        Self::forward_pending_transactions(
+                            cluster_info,
+                            &mut scheduler,
+                            poh_recorder,
+                            &socket,
+                            false,
+                            data_budget,
+                        );
+                    }
+                    (BufferedPacketsDecision::ForwardAndHold, _) => {
+                        Self::forward_pending_transactions(
+                            cluster_info,
+                            &mut scheduler,
+                            poh_recorder,
+                            &socket,
+                            true,
+                            data_budget,
+                        );
+                    }
+                    _ => (),
+                }
+            }
+
+            let recv_timeout = if scheduler.pending_len() > 0 || scheduler.in_flight_batches() > 0 {
+                // Transactions waiting for a bank or for their accounts to be unlocked
+                Duration::from_millis(10)
+            } else {
+                // Default wait time
+                Duration::from_millis(100)
+            };
+
+            select! {
+                recv(completed_receiver) -> completed => match completed {
+                    Ok(completed) => Self::complete_scheduled_batch(
+                        completed,
+                        &mut scheduler,
+                        capacity,
+                        &banking_stage_stats,
+                    ),
+                    Err(_) => break,
+                },
+                recv(verified_receiver) -> mms => match mms {
+                    Ok(mms) => {
+                        let poh_recorder_bank =
+                            poh_recorder.lock().unwrap().get_poh_recorder_bank();
+                        Self::buffer_received_packets(
+                            mms,
+                            poh_recorder_bank.bank(),
+                            &mut scheduler,
+                            capacity,
+                            duplicates,
+                            &banking_stage_stats,
+                        );
+                    }
+                    Err(_) => break,
+                },
+                default(recv_timeout) => (),
+            }
+            // Release the locks of every completed batch before scheduling again
+            for completed in completed_receiver.try_iter() {
+                Self::complete_scheduled_batch(
+                    completed,
+                    &mut scheduler,
+                    capacity,
+                    &banking_stage_stats,
+                );
+            }
+
+            banking_stage_stats
+                .in_flight_batches_count
+                .swap(scheduler.in_flight_batches(), Ordering::Relaxed);
+            banking_stage_stats
+                .current_buffered_packets_count
+                .swap(scheduler.pending_len(), Ordering::Relaxed);
+            banking_stage_stats.report(1000);
+        }
+    }
+
+    /// Moves the pending transactions that don't conflict with the batches in flight into new
+    /// batches, highest priority first, and sends them to the workers. Transactions with
+    /// conflicts stay pending until the batches holding their accounts complete.
+    fn schedule_pending_transactions(
+        bank: &Bank,
+        scheduler: &mut TransactionScheduler,
+        max_in_flight_batches: usize,
+        batch_sender: &CrossbeamSender<ScheduledBatch>,
+        banking_stage_stats: &BankingStageStats,
+    ) {
+        // Only votes are processed by a vote only bank and they don't go through the scheduler
+        if bank.vote_only_bank() {
+            return;
+        }
+        let mut schedule_time = Measure::start("schedule_pending_transactions");
+        let (batches, schedule_stats) =
+            scheduler.schedule(max_in_flight_batches, MAX_NUM_TRANSACTIONS_PER_BATCH);
+        let scheduled_batches_count = batches.len();
+        for batch in batches {
+            Self::send_scheduled_batch(batch, scheduler, batch_sender);
+        }
+        schedule_time.stop();
+
+        banking_stage_stats
+            .schedule_elapsed
+            .fetch_add(schedule_time.as_us(), Ordering::Relaxed);
+        banking_stage_stats
+            .scheduled_batches_count
+            .fetch_add(scheduled_batches_count, Ordering::Relaxed);
+        banking_stage_stats.scheduled_transactions_count.fetch_add(
+            schedule_stats.scheduled_transactions_count,
+            Ordering::Relaxed,
+        );
+        banking_stage_stats
+            .scheduler_conflicts_count
+            .fetch_add(schedule_stats.conflicts_count, Ordering::Relaxed);
+    }
+
+    fn send_scheduled_batch(
+        batch: ScheduledBatch,
+        scheduler: &mut TransactionScheduler,
+        batch_sender: &CrossbeamSender<ScheduledBatch>,
+    ) {
+        let batch_id = batch.id;
+        if batch_sender.send(batch).is_err() {
+            // No worker is left to complete the batch
+            scheduler.complete(batch_id, vec![], 0);
+        }
+    }
+
+    /// Forwards the pending transactions that weren't forwarded yet to the next leader, they are
+    /// dropped unless `hold` is set
+    fn forward_pending_transactions(
+        cluster_info: &ClusterInfo,
+        scheduler: &mut TransactionScheduler,
+        poh_recorder: &Arc<Mutex<PohRecorder>>,
+        socket: &UdpSocket,
+        hold: bool,
+        data_budget: &DataBudget,
+    ) {
+        let addr = match next_leader_tpu_forwards(cluster_info, poh_recorder) {
+            Some(addr) => addr,
+            None => return,
+        };
+        let packets = scheduler
+            .pending()
+            .filter(|transaction| !transaction.forwarded)
+            .map(|transaction| &transaction.packet)
+            .collect();
+        let _ = Self::forward_packets(socket, &addr, packets, data_budget);
+        if hold {
+            for transaction in scheduler.pending_mut() {
+                transaction.forwarded = true;
+            }
+        } else {
+            scheduler.clear_pending();
+        }
+    }
+
+    /// Releases the account locks of a batch and buffers its transactions to retry
+    fn complete_scheduled_batch(
+        completed: CompletedBatch,
+        scheduler: &mut TransactionScheduler,
+        capacity: usize,
+        banking_stage_stats: &BankingStageStats,
+    ) {
+        let retryable_transactions_count = completed.retryable_transactions.len();
+        let dropped_transactions =
+            scheduler.complete(completed.id, completed.retryable_transactions, capacity);
+        banking_stage_stats
+            .retried_transactions_count
+            .fetch_add(retryable_transactions_count, Ordering::Relaxed);
+        banking_stage_stats
+            .dropped_packets_count
+            .fetch_add(dropped_transactions.len(), Ordering::Relaxed);
+    }
+
+    /// Sanitizes the packets received from sigverify and buffers their transactions for the
+    /// scheduler, prioritized with the fees of `bank`
+    fn buffer_received_packets(
+        mms: Vec<Packets>,
+        bank: &Bank,
+        scheduler: &mut TransactionScheduler,
+        capacity: usize,
+        duplicates: &Arc<Mutex<(LruCache<u64, ()>, PacketHasher)>>,
+        banking_stage_stats: &BankingStageStats,
+    ) {
+        let count: usize = mms.iter().map(|x| x.packets.len()).sum();
+        inc_new_counter_debug!("banking_stage-transactions_received", count);
+        let lamports_per_signature = bank.get_lamports_per_signature();
+        let demote_program_write_locks = bank.demote_program_write_locks();
+        let mut packet_conversion_time = Measure::start("packet_conversion");
+        let mut dropped_packets_count = 0;
+        let mut newly_buffered_packets_count = 0;
+        for msgs in mms {
+            let mut packet_indexes = Self::generate_packet_indexes(&msgs.packets);
+            Self::filter_duplicate_packets(
+                &msgs,
+                &mut packet_indexes,
+                duplicates,
+                banking_stage_stats,
+            );
+            // Only non-vote packets are sent to the scheduler
+            let (transactions, transaction_to_packet_indexes) =
+                Self::transactions_from_packets(&msgs, &packet_indexes, &bank.feature_set, false);
+            for (transaction, packet_index) in
+                transactions.into_iter().zip(transaction_to_packet_indexes)
+            {
+                let priority = Self::transaction_priority(&transaction, lamports_per_signature);
+                let transaction = PendingTransaction::new(
+                    transaction,
+                    msgs.packets[packet_index].clone(),
+                    priority,
+                    demote_program_write_locks,
+                );
+                match scheduler.buffer(transaction, capacity) {
+                    Ok(evicted) => {
+                        newly_buffered_packets_count += 1;
+                        dropped_packets_count += usize::from(evicted.is_some());
+                    }
+                    Err(_rejected) => dropped_packets_count += 1,
+                }
+            }
+        }
+        packet_conversion_time.stop();
+
+        banking_stage_stats
+            .process_packets_count
+            .fetch_add(count, Ordering::Relaxed);
+        banking_stage_stats
+            .packet_conversion_elapsed
+            .fetch_add(packet_conversion_time.as_us(), Ordering::Relaxed);
+        banking_stage_stats
+            .dropped_packets_count
+            .fetch_add(dropped_packets_count, Ordering::Relaxed);
+        banking_stage_stats
+            .newly_buffered_packets_count
+            .fetch_add(newly_buffered_packets_count, Ordering::Relaxed);
+    }
+
+    /// Processes the batches built by `schedule_loop` and sends back the transactions to retry
+    /// along with the completion of each batch
+    fn process_scheduled_batches(
+        batch_receiver: &CrossbeamReceiver<ScheduledBatch>,
+        completed_sender: &CrossbeamSender<CompletedBatch>,
+        poh_recorder: &Arc<Mutex<PohRecorder>>,
+        id: u32,
+        transaction_status_sender: Option<TransactionStatusSender>,
+        gossip_vote_sender: ReplayVoteSender,
+        qos_service: Arc<QosService>,
+    ) {
+        let recorder = poh_recorder.lock().unwrap().recorder();
+        let banking_stage_stats = BankingStageStats::new(id);
+        loop {
+            let batch = match batch_receiver.recv_timeout(Duration::from_millis(100)) {
+                Ok(batch) => batch,
+                Err(RecvTimeoutError::Timeout) => {
+                    banking_stage_stats.report(1000);
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+
+            let bank_start = poh_recorder.lock().unwrap().bank_start();
+            let retryable_indexes = if let Some(BankStart {
+                working_bank,
+                bank_creation_time,
+            }) = bank_start
+            {
+                let mut process_tx_time = Measure::start("process_tx_time");
+                let (processed, unprocessed_tx_indexes) = Self::process_transactions(
+                    &working_bank,
+                    &bank_creation_time,
+                    &batch.transactions,
+                    &recorder,
+                    transaction_status_sender.clone(),
+                    &gossip_vote_sender,
+                    &qos_service,
+                );
+                process_tx_time.stop();
+                banking_stage_stats
+                    .new_tx_count
+                    .fetch_add(processed, Ordering::Relaxed);
+                banking_stage_stats
+                    .transaction_processing_elapsed
+                    .fetch_add(process_tx_time.as_us(), Ordering::Relaxed);
+
+                let transaction_indexes: Vec<usize> = (0..batch.transactions.len()).collect();
+                Self::filter_pending_packets_from_pending_txs(
+                    &working_bank,
+                    &batch.transactions,
+                    &transaction_indexes,
+                    &unprocessed_tx_indexes,
+                )
+            } else {
+                // The slot ended while the batch was queued
+                (0..batch.transactions.len()).collect()
+            };
+
+            // The retryable indexes are in ascending order
+            let mut retryable_indexes = retryable_indexes.into_iter().peekable();
+            let retryable_transactions = batch
+                .transactions
+                .into_iter()
+                .enumerate()
+                .filter(|(index, _transaction)| retryable_indexes.next_if_eq(index).is_some())
+                .collect();
+            if completed_sender
+                .send(CompletedBatch {
+                    id: batch.id,
+                    retryable_transactions,
+                })
+                .is_err()
+            {
+                break;
+            }
+            banking_stage_stats.report(1000);
+        }
+    }
+
     pub fn num_threads() -> u32 {
         cmp::max(
             env::var("SOLANA_BANKING_THREADS")
@@ -1032,6 +1548,74 @@ impl BankingStage {
         (chunk_start, unprocessed_txs)
     }
 
+    /// Priority of a transaction: the fee it is charged per `PRIORITY_COMPUTE_UNITS_SCALE`
+    /// compute units it requests from the compute budget program, or per the default budget if
+    /// it doesn't request any
+    pub fn transaction_priority(
+        transaction: &SanitizedTransaction,
+        lamports_per_signature: u64,
+    ) -> u64 {
+        let mut requested_units = ComputeBudget::default().max_units;
+        for (program_id, instruction) in transaction.message().program_instructions_iter() {
+            if compute_budget::check_id(program_id) {
+                if let Ok(ComputeBudgetInstruction::RequestUnits(units)) =
+                    try_from_slice_unchecked(&instruction.data)
+                {
+                    requested_units = units as u64;
+                }
+            }
+        }
+        let fee = Bank::get_fee_for_message_with_lamports_per_signature(
+            transaction.message(),
+            lamports_per_signature,
+        );
+        fee.saturating_mul(PRIORITY_COMPUTE_UNITS_SCALE) / requested_units.max(1)
+    }
+
+    /// Compute the priority of the packets at `packet_indexes` with the fees of `bank` and order
+    /// the indexes from the highest to the lowest priority. The priorities are computed once,
+    /// when the packets are received, and buffered along with them.
+    ///
+    /// Returns the priorities indexed like `msgs.packets`. Packets that don't hold a sanitizable
+    /// transaction get the lowest priority, they will be discarded when converted to
+    /// transactions anyway.
+    fn prioritize_packets(msgs: &Packets, packet_indexes: &mut [usize], bank: &Bank) -> Vec<u64> {
+        let lamports_per_signature = bank.get_lamports_per_signature();
+        let mut priorities = vec![0; msgs.packets.len()];
+        for index in packet_indexes.iter() {
+            let packet = &msgs.packets[*index];
+            priorities[*index] =
+                limited_deserialize::<VersionedTransaction>(&packet.data[0..packet.meta.size])
+                    .ok()
+                    .and_then(|tx| {
+                        SanitizedTransaction::try_create(tx, Hash::default(), None, |_| {
+                            Err(TransactionError::UnsupportedVersion)
+                        })
+                        .ok()
+                    })
+                    .map(|tx| Self::transaction_priority(&tx, lamports_per_signature))
+                    .unwrap_or_default();
+        }
+        Self::sort_by_priority(packet_indexes, &priorities);
+        priorities
+    }
+
+    /// Order `packet_indexes` from the highest to the lowest priority, packets of equal priority
+    /// keep their order
+    fn sort_by_priority(packet_indexes: &mut [usize], priorities: &[u64]) {
+        packet_indexes.sort_by_key(|index| Reverse(priorities[*index]));
+    }
+
+    // The priority of a batch is the priority of its worst unprocessed packet, so that a single
+    // high priority packet neither keeps a batch of low priority ones buffered nor gets them
+    // consumed first
+    fn batch_priority((_, packet_indexes, _, priorities): &PacketsAndOffsets) -> u64 {
+        packet_indexes
+            .last()
+            .map(|index| priorities[*index])
+            .unwrap_or_default()
+    }
+
     // This function creates a filter of transaction results with Ok() for every pending
     // transaction. The non-pending transactions are marked with TransactionError
     fn prepare_filter_for_pending_transactions(
@@ -1314,14 +1898,18 @@ impl BankingStage {
         let mut dropped_packet_batches_count = 0;
         let mut newly_buffered_packets_count = 0;
         while let Some(msgs) = mms_iter.next() {
-            let packet_indexes = Self::generate_packet_indexes(&msgs.packets);
+            let mut packet_indexes = Self::generate_packet_indexes(&msgs.packets);
             let poh_recorder_bank = poh.lock().unwrap().get_poh_recorder_bank();
+            // The highest priority transactions of the batch are processed first
+            let priorities =
+                Self::prioritize_packets(&msgs, &mut packet_indexes, poh_recorder_bank.bank());
             let working_bank_start = poh_recorder_bank.working_bank_start();
             if PohRecorder::get_working_bank_if_not_expired(&working_bank_start).is_none() {
                 Self::push_unprocessed(
                     buffered_packets,
                     msgs,
                     packet_indexes,
+                    priorities,
                     &mut dropped_packet_batches_count,
                     &mut dropped_packets_count,
                     &mut newly_buffered_packets_count,
@@ -1358,6 +1946,7 @@ impl BankingStage {
                 buffered_packets,
                 msgs,
                 unprocessed_indexes,
+                priorities,
                 &mut dropped_packet_batches_count,
                 &mut dropped_packets_count,
                 &mut newly_buffered_packets_count,
@@ -1373,7 +1962,9 @@ impl BankingStage {
                 // Walk thru rest of the transactions and filter out the invalid (e.g. too old) ones
                 #[allow(clippy::while_let_on_iterator)]
                 while let Some(msgs) = mms_iter.next() {
-                    let packet_indexes = Self::generate_packet_indexes(&msgs.packets);
+                    let mut packet_indexes = Self::generate_packet_indexes(&msgs.packets);
+                    let priorities =
+                        Self::prioritize_packets(&msgs, &mut packet_indexes, working_bank);
                     let unprocessed_indexes = Self::filter_unprocessed_packets(
                         working_bank,
                         &msgs,
@@ -1386,6 +1977,7 @@ impl BankingStage {
                         buffered_packets,
                         msgs,
                         unprocessed_indexes,
+                        priorities,
                         &mut dropped_packet_batches_count,
                         &mut dropped_packets_count,
                         &mut newly_buffered_packets_count,
@@ -1441,10 +2033,13 @@ impl BankingStage {
         Ok(())
     }
 
+    /// Buffers the packets at `packet_indexes` that are not duplicates, `priorities` are indexed
+    /// like `packets`
     fn push_unprocessed(
         unprocessed_packets: &mut UnprocessedPackets,
         packets: Packets,
         mut packet_indexes: Vec<usize>,
+        priorities: Vec<u64>,
         dropped_packet_batches_count: &mut usize,
         dropped_packets_count: &mut usize,
         newly_buffered_packets_count: &mut usize,
@@ -1452,41 +2047,93 @@ impl BankingStage {
         duplicates: &Arc<Mutex<(LruCache<u64, ()>, PacketHasher)>>,
         banking_stage_stats: &BankingStageStats,
     ) {
-        {
-            let original_packets_count = packet_indexes.len();
-            let mut packet_duplicate_check_time = Measure::start("packet_duplicate_check");
-            let mut duplicates = duplicates.lock().unwrap();
-            let (cache, hasher) = duplicates.deref_mut();
-            packet_indexes.retain(|i| {
-                let packet_hash = hasher.hash_packet(&packets.packets[*i]);
-                match cache.get_mut(&packet_hash) {
-                    Some(_hash) => false,
-                    None => {
-                        cache.put(packet_hash, ());
-                        true
-                    }
+        Self::filter_duplicate_packets(
+            &packets,
+            &mut packet_indexes,
+            duplicates,
+            banking_stage_stats,
+        );
+        Self::buffer_packets(
+            unprocessed_packets,
+            packets,
+            packet_indexes,
+            priorities,
+            dropped_packet_batches_count,
+            dropped_packets_count,
+            newly_buffered_packets_count,
+            batch_limit,
+        );
+    }
+
+    /// Removes the packets already seen from `packet_indexes`
+    fn filter_duplicate_packets(
+        packets: &Packets,
+        packet_indexes: &mut Vec<usize>,
+        duplicates: &Arc<Mutex<(LruCache<u64, ()>, PacketHasher)>>,
+        banking_stage_stats: &BankingStageStats,
+    ) {
+        let original_packets_count = packet_indexes.len();
+        let mut packet_duplicate_check_time = Measure::start("packet_duplicate_check");
+        let mut duplicates = duplicates.lock().unwrap();
+        let (cache, hasher) = duplicates.deref_mut();
+        packet_indexes.retain(|i| {
+            let packet_hash = hasher.hash_packet(&packets.packets[*i]);
+            match cache.get_mut(&packet_hash) {
+                Some(_hash) => false,
+                None => {
+                    cache.put(packet_hash, ());
+                    true
                 }
-            });
-            packet_duplicate_check_time.stop();
-            banking_stage_stats
-                .packet_duplicate_check_elapsed
-                .fetch_add(packet_duplicate_check_time.as_us(), Ordering::Relaxed);
-            banking_stage_stats
-                .dropped_duplicated_packets_count
-                .fetch_add(
-                    original_packets_count.saturating_sub(packet_indexes.len()),
-                    Ordering::Relaxed,
-                );
-        }
+            }
+        });
+        packet_duplicate_check_time.stop();
+        banking_stage_stats
+            .packet_duplicate_check_elapsed
+            .fetch_add(packet_duplicate_check_time.as_us(), Ordering::Relaxed);
+        banking_stage_stats
+            .dropped_duplicated_packets_count
+            .fetch_add(
+                original_packets_count.saturating_sub(packet_indexes.len()),
+                Ordering::Relaxed,
+            );
+    }
+
+    fn buffer_packets(
+        unprocessed_packets: &mut UnprocessedPackets,
+        packets: Packets,
+        mut packet_indexes: Vec<usize>,
+        priorities: Vec<u64>,
+        dropped_packet_batches_count: &mut usize,
+        dropped_packets_count: &mut usize,
+        newly_buffered_packets_count: &mut usize,
+        batch_limit: usize,
+    ) {
         if Self::packet_has_more_unprocessed_transactions(&packet_indexes) {
+            Self::sort_by_priority(&mut packet_indexes, &priorities);
+            let new_batch = (packets, packet_indexes, false, priorities);
             if unprocessed_packets.len() >= batch_limit {
                 *dropped_packet_batches_count += 1;
-                if let Some(dropped_batch) = unprocessed_packets.pop_front() {
-                    *dropped_packets_count += dropped_batch.1.len();
+                // Evict the batch with the lowest priority, the oldest one among equals. If
+                // that is the new batch, it is not buffered at all.
+                let lowest = unprocessed_packets
+                    .iter()
+                    .map(Self::batch_priority)
+                    .enumerate()
+                    .min_by_key(|(_index, priority)| *priority);
+                match lowest {
+                    Some((index, priority)) if priority <= Self::batch_priority(&new_batch) => {
+                        if let Some(dropped_batch) = unprocessed_packets.remove(index) {
+                            *dropped_packets_count += dropped_batch.1.len();
+                        }
+                    }
+                    _ => {
+                        *dropped_packets_count += new_batch.1.len();
+                        return;
+                    }
                 }
             }
-            *newly_buffered_packets_count += packet_indexes.len();
-            unprocessed_packets.push_back((packets, packet_indexes, false));
+            *newly_buffered_packets_count += new_batch.1.len();
+            unprocessed_packets.push_back(new_batch);
         }
     }
 
@@ -1551,8 +2198,9 @@ mod tests {
     use solana_gossip::{cluster_info::Node, contact_info::ContactInfo};
     use solana_ledger::{
         blockstore::{entries_to_test_shreds, Blockstore},
+        blockstore_db::BlockstoreOptions,
         genesis_utils::{create_genesis_config, GenesisConfigInfo},
-        get_tmp_ledger_path,
+        get_tmp_ledger_path, get_tmp_ledger_path_auto_delete,
         leader_schedule_cache::LeaderScheduleCache,
     };
     use solana_perf::packet::to_packets_chunked;
@@ -1562,12 +2210,13 @@ mod tests {
     };
     use solana_rpc::transaction_status_service::TransactionStatusService;
     use solana_sdk::{
+        fee_calculator::FeeRateGovernor,
         hash::Hash,
-        instruction::InstructionError,
+        instruction::{AccountMeta, InstructionError},
         poh_config::PohConfig,
         signature::{Keypair, Signer},
-        system_instruction::SystemError,
-        system_transaction,
+        system_instruction::{self, SystemError},
+        system_program, system_transaction,
         transaction::{Transaction, TransactionError},
     };
     use solana_streamer::socket::SocketAddrSpace;
@@ -1892,6 +2541,7 @@ mod tests {
                     None,
                     gossip_vote_sender,
                     Arc::new(RwLock::new(CostModel::default())),
+                    TransactionScheduling::Central,
                 );
 
                 // wait for banking_stage to eat the packets
@@ -2225,7 +2875,10 @@ mod tests {
                 0,
                 None,
                 &gossip_vote_sender,
-                &Arc::new(QosService::new(Arc::new(RwLock::new(CostModel::default())))),
+                &Arc::new(QosService::new(
+                    Arc::new(RwLock::new(CostModel::default())),
+                    "test",
+                )),
             )
             .0
             .unwrap();
@@ -2267,7 +2920,10 @@ mod tests {
                     0,
                     None,
                     &gossip_vote_sender,
-                    &Arc::new(QosService::new(Arc::new(RwLock::new(CostModel::default())))),
+                    &Arc::new(QosService::new(
+                        Arc::new(RwLock::new(CostModel::default())),
+                        "test",
+                    )),
                 )
                 .0,
                 Err(PohRecorderError::MaxHeightReached)
@@ -2355,7 +3011,10 @@ mod tests {
                 0,
                 None,
                 &gossip_vote_sender,
-                &Arc::new(QosService::new(Arc::new(RwLock::new(CostModel::default())))),
+                &Arc::new(QosService::new(
+                    Arc::new(RwLock::new(CostModel::default())),
+                    "test",
+                )),
             );
 
             poh_recorder
@@ -2389,7 +3048,7 @@ mod tests {
                 let valid_indexes = (0..32)
                     .filter_map(|x| if x % 2 != 0 { Some(x as usize) } else { None })
                     .collect_vec();
-                (packets, valid_indexes, false)
+                (packets, valid_indexes, false, vec![0; 32])
             })
             .collect_vec();
 
@@ -2464,7 +3123,10 @@ mod tests {
                     &recorder,
                     None,
                     &gossip_vote_sender,
-                    &Arc::new(QosService::new(Arc::new(RwLock::new(CostModel::default())))),
+                    &Arc::new(QosService::new(
+                        Arc::new(RwLock::new(CostModel::default())),
+                        "test",
+                    )),
                 );
 
             assert_eq!(processed_transactions_count, 0,);
@@ -2557,7 +3219,10 @@ mod tests {
                     enable_cpi_and_log_storage: false,
                 }),
                 &gossip_vote_sender,
-                &Arc::new(QosService::new(Arc::new(RwLock::new(CostModel::default())))),
+                &Arc::new(QosService::new(
+                    Arc::new(RwLock::new(CostModel::default())),
+                    "test",
+                )),
             );
 
             transaction_status_service.join().unwrap();
@@ -2595,6 +3260,110 @@ mod tests {
         Blockstore::destroy(&ledger_path).unwrap();
     }
 
+    #[test]
+    fn test_write_persist_program_signatures() {
+        solana_logger::setup();
+        let GenesisConfigInfo {
+            genesis_config,
+            mint_keypair,
+            ..
+        } = create_slow_genesis_config(10_000);
+        let bank = Arc::new(Bank::new_no_wallclock_throttle_for_tests(&genesis_config));
+        let pubkey = solana_sdk::pubkey::new_rand();
+
+        let success_tx =
+            system_transaction::transfer(&mint_keypair, &pubkey, 1, genesis_config.hash());
+        let success_signature = success_tx.signatures[0];
+        let entries = vec![next_entry(
+            &genesis_config.hash(),
+            1,
+            vec![success_tx.clone()],
+        )];
+        let transactions = sanitize_transactions(vec![success_tx]);
+
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let blockstore = Arc::new(
+            Blockstore::open_with_options(
+                ledger_path.path(),
+                BlockstoreOptions {
+                    enable_program_signatures: true,
+                    ..BlockstoreOptions::default()
+                },
+            )
+            .unwrap(),
+        );
+        let (poh_recorder, _entry_receiver, record_receiver) = PohRecorder::new(
+            bank.tick_height(),
+            bank.last_blockhash(),
+            bank.clone(),
+            Some((4, 4)),
+            bank.ticks_per_slot(),
+            &pubkey,
+            &blockstore,
+            &Arc::new(LeaderScheduleCache::new_from_bank(&bank)),
+            &Arc::new(PohConfig::default()),
+            Arc::new(AtomicBool::default()),
+        );
+        let recorder = poh_recorder.recorder();
+        let poh_recorder = Arc::new(Mutex::new(poh_recorder));
+        let poh_simulator = simulate_poh(record_receiver, &poh_recorder);
+        poh_recorder.lock().unwrap().set_bank(&bank);
+
+        let shreds = entries_to_test_shreds(entries, bank.slot(), 0, true, 0);
+        blockstore.insert_shreds(shreds, None, false).unwrap();
+        blockstore.set_roots(std::iter::once(&bank.slot())).unwrap();
+
+        let (transaction_status_sender, transaction_status_receiver) = unbounded();
+        let transaction_status_service = TransactionStatusService::new(
+            transaction_status_receiver,
+            Arc::new(AtomicU64::default()),
+            blockstore.clone(),
+            &Arc::new(AtomicBool::new(false)),
+        );
+        let (gossip_vote_sender, _gossip_vote_receiver) = unbounded();
+        let _ = BankingStage::process_and_record_transactions(
+            &bank,
+            &transactions,
+            &recorder,
+            0,
+            Some(TransactionStatusSender {
+                sender: transaction_status_sender,
+                enable_cpi_and_log_storage: true,
+            }),
+            &gossip_vote_sender,
+            &Arc::new(QosService::new(
+                Arc::new(RwLock::new(CostModel::default())),
+                "test",
+            )),
+        );
+        transaction_status_service.join().unwrap();
+
+        // The transaction is indexed under the program its logs show it invoked along with its
+        // status
+        let signatures = blockstore
+            .get_confirmed_signatures_for_program(
+                system_program::id(),
+                bank.slot(),
+                None,
+                None,
+                usize::MAX,
+            )
+            .unwrap();
+        assert_eq!(signatures.len(), 1);
+        assert_eq!(signatures[0].signature, success_signature);
+        assert!(blockstore
+            .get_rooted_transaction_status(success_signature)
+            .unwrap()
+            .is_some());
+
+        poh_recorder
+            .lock()
+            .unwrap()
+            .is_exited
+            .store(true, Ordering::Relaxed);
+        let _ = poh_simulator.join();
+    }
+
     #[allow(clippy::type_complexity)]
     fn setup_conflicting_transactions(
         ledger_path: &Path,
@@ -2666,6 +3435,7 @@ mod tests {
                 all_packets,
                 (0..num_conflicting_transactions).into_iter().collect(),
                 false,
+                vec![0; num_conflicting_transactions],
             )]
             .into_iter()
             .collect();
@@ -2685,7 +3455,10 @@ mod tests {
                 None::<Box<dyn Fn()>>,
                 &BankingStageStats::default(),
                 &recorder,
-                &Arc::new(QosService::new(Arc::new(RwLock::new(CostModel::default())))),
+                &Arc::new(QosService::new(
+                    Arc::new(RwLock::new(CostModel::default())),
+                    "test",
+                )),
             );
             assert_eq!(buffered_packets[0].1.len(), num_conflicting_transactions);
             // When the poh recorder has a bank, should process all non conflicting buffered packets.
@@ -2702,7 +3475,10 @@ mod tests {
                     None::<Box<dyn Fn()>>,
                     &BankingStageStats::default(),
                     &recorder,
-                    &Arc::new(QosService::new(Arc::new(RwLock::new(CostModel::default())))),
+                    &Arc::new(QosService::new(
+                        Arc::new(RwLock::new(CostModel::default())),
+                        "test",
+                    )),
                 );
                 if num_expected_unprocessed == 0 {
                     assert!(buffered_packets.is_empty())
@@ -2735,7 +3511,7 @@ mod tests {
             let mut buffered_packets: UnprocessedPackets = packets_vec
                 .clone()
                 .into_iter()
-                .map(|single_packets| (single_packets, vec![0], false))
+                .map(|single_packets| (single_packets, vec![0], false, vec![0]))
                 .collect();
 
             let (continue_sender, continue_receiver) = unbounded();
@@ -2768,7 +3544,10 @@ mod tests {
                         test_fn,
                         &BankingStageStats::default(),
                         &recorder,
-                        &Arc::new(QosService::new(Arc::new(RwLock::new(CostModel::default())))),
+                        &Arc::new(QosService::new(
+                            Arc::new(RwLock::new(CostModel::default())),
+                            "test",
+                        )),
                     );
 
                     // Check everything is correct. All indexes after `interrupted_iteration`
@@ -2818,7 +3597,7 @@ mod tests {
         // Create `Packets` with 1 unprocessed element
         let single_element_packets = Packets::new(vec![Packet::default()]);
         let mut unprocessed_packets: UnprocessedPackets =
-            vec![(single_element_packets, vec![0], false)]
+            vec![(single_element_packets, vec![0], false, vec![0])]
                 .into_iter()
                 .collect();
 
@@ -2867,7 +3646,9 @@ mod tests {
         // Create `Packets` with 2 unprocessed elements
         let new_packets = Packets::new(vec![Packet::default(); 2]);
         let mut unprocessed_packets: UnprocessedPackets =
-            vec![(new_packets, vec![0, 1], false)].into_iter().collect();
+            vec![(new_packets, vec![0, 1], false, vec![0, 0])]
+                .into_iter()
+                .collect();
         // Set the limit to 2
         let batch_limit = 2;
         // Create some new unprocessed packets
@@ -2888,6 +3669,7 @@ mod tests {
             &mut unprocessed_packets,
             new_packets.clone(),
             packet_indexes,
+            vec![0],
             &mut dropped_packet_batches_count,
             &mut dropped_packets_count,
             &mut newly_buffered_packets_count,
@@ -2907,6 +3689,7 @@ mod tests {
             &mut unprocessed_packets,
             new_packets,
             packet_indexes.clone(),
+            vec![0],
             &mut dropped_packet_batches_count,
             &mut dropped_packets_count,
             &mut newly_buffered_packets_count,
@@ -2931,6 +3714,7 @@ mod tests {
             &mut unprocessed_packets,
             new_packets.clone(),
             packet_indexes.clone(),
+            vec![0],
             &mut dropped_packet_batches_count,
             &mut dropped_packets_count,
             &mut newly_buffered_packets_count,
@@ -2949,6 +3733,7 @@ mod tests {
             &mut unprocessed_packets,
             new_packets.clone(),
             packet_indexes,
+            vec![0],
             &mut dropped_packet_batches_count,
             &mut dropped_packets_count,
             &mut newly_buffered_packets_count,
@@ -2963,6 +3748,316 @@ mod tests {
         assert_eq!(newly_buffered_packets_count, 2);
     }
 
+    fn make_prioritized_transaction(
+        num_signers: usize,
+        requested_units: Option<u32>,
+    ) -> Transaction {
+        let from = Keypair::new();
+        let other_signers: Vec<Keypair> = (1..num_signers).map(|_| Keypair::new()).collect();
+        let mut instruction =
+            system_instruction::transfer(&from.pubkey(), &solana_sdk::pubkey::new_rand(), 1);
+        instruction.accounts.extend(
+            other_signers
+                .iter()
+                .map(|signer| AccountMeta::new_readonly(signer.pubkey(), true)),
+        );
+        let mut instructions = vec![instruction];
+        if let Some(units) = requested_units {
+            instructions.insert(0, ComputeBudgetInstruction::request_units(units));
+        }
+        let signers: Vec<&Keypair> = std::iter::once(&from).chain(&other_signers).collect();
+        Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&from.pubkey()),
+            &signers,
+            Hash::new_unique(),
+        )
+    }
+
+    #[test]
+    fn test_transaction_priority() {
+        let lamports_per_signature = 5_000;
+        let default_units = ComputeBudget::default().max_units;
+        let priority = |num_signers, requested_units| {
+            let tx = SanitizedTransaction::from_transaction_for_tests(
+                make_prioritized_transaction(num_signers, requested_units),
+            );
+            BankingStage::transaction_priority(&tx, lamports_per_signature)
+        };
+        assert_eq!(
+            priority(1, None),
+            lamports_per_signature * PRIORITY_COMPUTE_UNITS_SCALE / default_units
+        );
+        assert_eq!(
+            priority(1, Some(1_000)),
+            lamports_per_signature * PRIORITY_COMPUTE_UNITS_SCALE / 1_000
+        );
+        assert!(priority(1, Some(1_000)) > priority(1, Some(10_000)));
+        // Paying for more signatures buys a higher priority for the same compute units
+        assert!(priority(2, None) > priority(1, None));
+        // A zero unit request doesn't divide by zero
+        assert_eq!(
+            priority(1, Some(0)),
+            lamports_per_signature * PRIORITY_COMPUTE_UNITS_SCALE
+        );
+
+        // Transactions charged no fee have the lowest priority
+        let tx = SanitizedTransaction::from_transaction_for_tests(make_prioritized_transaction(
+            1,
+            Some(1_000),
+        ));
+        assert_eq!(BankingStage::transaction_priority(&tx, 0), 0);
+    }
+
+    #[test]
+    fn test_prioritize_packets() {
+        let GenesisConfigInfo {
+            mut genesis_config, ..
+        } = create_genesis_config(10_000);
+        genesis_config.fee_rate_governor = FeeRateGovernor::new(5_000, 0);
+        let bank = Bank::new_for_tests(&genesis_config);
+        assert_eq!(bank.get_lamports_per_signature(), 5_000);
+        let transactions = vec![
+            make_prioritized_transaction(1, None),
+            make_prioritized_transaction(3, None),
+            make_prioritized_transaction(2, None),
+        ];
+        let mut packets = to_packets_chunked(&transactions, transactions.len())
+            .pop()
+            .unwrap();
+        // Garbage gets the lowest priority
+        packets.packets.push(Packet::default());
+        packets.packets[3].meta.size = 1;
+
+        let mut packet_indexes = vec![0, 1, 2, 3];
+        let priorities = BankingStage::prioritize_packets(&packets, &mut packet_indexes, &bank);
+        assert_eq!(packet_indexes, vec![1, 2, 0, 3]);
+        assert_eq!(priorities.len(), 4);
+        assert_eq!(priorities[3], 0);
+        let expected_priority = BankingStage::transaction_priority(
+            &SanitizedTransaction::from_transaction_for_tests(transactions[0].clone()),
+            bank.get_lamports_per_signature(),
+        );
+        assert_eq!(priorities[0], expected_priority);
+    }
+
+    #[test]
+    fn test_push_unprocessed_prioritizes_packets() {
+        solana_logger::setup();
+        let duplicates = Arc::new(Mutex::new((
+            LruCache::new(DEFAULT_LRU_SIZE),
+            PacketHasher::default(),
+        )));
+        let mut dropped_packet_batches_count = 0;
+        let mut dropped_packets_count = 0;
+        let mut newly_buffered_packets_count = 0;
+        let banking_stage_stats = BankingStageStats::default();
+        let batch_limit = 2;
+        let mut unprocessed_packets = UnprocessedPackets::default();
+        let mut push =
+            |unprocessed_packets: &mut UnprocessedPackets, txs: &[Transaction], priorities| {
+                let packets = to_packets_chunked(txs, txs.len()).pop().unwrap();
+                BankingStage::push_unprocessed(
+                    unprocessed_packets,
+                    packets,
+                    (0..txs.len()).collect(),
+                    priorities,
+                    &mut dropped_packet_batches_count,
+                    &mut dropped_packets_count,
+                    &mut newly_buffered_packets_count,
+                    batch_limit,
+                    &duplicates,
+                    &banking_stage_stats,
+                );
+            };
+
+        // Packets within a batch are ordered from the highest to the lowest priority
+        let low = make_prioritized_transaction(1, None);
+        let high = make_prioritized_transaction(1, None);
+        push(&mut unprocessed_packets, &[low, high], vec![10, 20]);
+        assert_eq!(unprocessed_packets[0].1, vec![1, 0]);
+        assert_eq!(unprocessed_packets[0].3, vec![10, 20]);
+
+        let lowest = make_prioritized_transaction(1, None);
+        push(&mut unprocessed_packets, &[lowest], vec![1]);
+        assert_eq!(unprocessed_packets.len(), 2);
+
+        // The buffer is full, the lowest priority batch is evicted for a better one
+        let higher = make_prioritized_transaction(1, None);
+        push(&mut unprocessed_packets, &[higher.clone()], vec![30]);
+        assert_eq!(unprocessed_packets.len(), 2);
+        assert_eq!(unprocessed_packets[0].1, vec![1, 0]);
+        assert_eq!(
+            unprocessed_packets[1].0.packets[0],
+            to_packets_chunked(&[higher], 1)[0].packets[0]
+        );
+
+        // A batch worse than everything buffered is dropped on arrival
+        let worse = make_prioritized_transaction(1, None);
+        push(&mut unprocessed_packets, &[worse], vec![1]);
+        assert_eq!(unprocessed_packets.len(), 2);
+        assert_eq!(unprocessed_packets[0].1, vec![1, 0]);
+
+        // A batch is only as good as its worst packet, one high priority packet doesn't keep it
+        let top = make_prioritized_transaction(1, None);
+        let bottom = make_prioritized_transaction(1, None);
+        push(&mut unprocessed_packets, &[top, bottom], vec![100, 2]);
+        assert_eq!(unprocessed_packets.len(), 2);
+        assert_eq!(unprocessed_packets[0].1, vec![1, 0]);
+        assert_eq!(unprocessed_packets[0].3, vec![10, 20]);
+        assert_eq!(dropped_packet_batches_count, 3);
+        assert_eq!(dropped_packets_count, 4);
+        assert_eq!(newly_buffered_packets_count, 4);
+
+        // Batches holding the best packets are consumed first
+        unprocessed_packets
+            .make_contiguous()
+            .sort_by_key(|batch| Reverse(BankingStage::batch_priority(batch)));
+        assert_eq!(unprocessed_packets[0].1, vec![0]);
+    }
+
+    #[test]
+    fn test_schedule_pending_transactions() {
+        solana_logger::setup();
+        let GenesisConfigInfo {
+            genesis_config,
+            mint_keypair,
+            ..
+        } = create_slow_genesis_config(10_000);
+        let bank = Arc::new(Bank::new_no_wallclock_throttle_for_tests(&genesis_config));
+
+        // The two transfers from the mint conflict, the third one doesn't
+        let transactions = vec![
+            system_transaction::transfer(
+                &mint_keypair,
+                &solana_sdk::pubkey::new_rand(),
+                1,
+                genesis_config.hash(),
+            ),
+            system_transaction::transfer(
+                &mint_keypair,
+                &solana_sdk::pubkey::new_rand(),
+                1,
+                genesis_config.hash(),
+            ),
+            system_transaction::transfer(
+                &Keypair::new(),
+                &solana_sdk::pubkey::new_rand(),
+                1,
+                genesis_config.hash(),
+            ),
+        ];
+        let signatures = |batch: &ScheduledBatch| {
+            batch
+                .transactions
+                .iter()
+                .map(|tx| *tx.signature())
+                .collect::<Vec<_>>()
+        };
+        let packets = to_packets_chunked(&transactions, 3);
+        let duplicates = Arc::new(Mutex::new((
+            LruCache::new(DEFAULT_LRU_SIZE),
+            PacketHasher::default(),
+        )));
+        let mut scheduler = TransactionScheduler::default();
+        let (batch_sender, batch_receiver) = unbounded();
+        let banking_stage_stats = BankingStageStats::default();
+
+        // The transactions are sanitized once, when they are buffered
+        BankingStage::buffer_received_packets(
+            packets.clone(),
+            &bank,
+            &mut scheduler,
+            10,
+            &duplicates,
+            &banking_stage_stats,
+        );
+        assert_eq!(scheduler.pending_len(), 3);
+        BankingStage::buffer_received_packets(
+            packets,
+            &bank,
+            &mut scheduler,
+            10,
+            &duplicates,
+            &banking_stage_stats,
+        );
+        assert_eq!(scheduler.pending_len(), 3);
+        assert_eq!(
+            banking_stage_stats
+                .dropped_duplicated_packets_count
+                .load(Ordering::Relaxed),
+            3
+        );
+
+        BankingStage::schedule_pending_transactions(
+            &bank,
+            &mut scheduler,
+            1,
+            &batch_sender,
+            &banking_stage_stats,
+        );
+        let batch = batch_receiver.try_recv().unwrap();
+        assert_eq!(
+            signatures(&batch),
+            vec![transactions[0].signatures[0], transactions[2].signatures[0]]
+        );
+        assert_eq!(scheduler.pending_len(), 1);
+        assert_eq!(
+            banking_stage_stats
+                .scheduler_conflicts_count
+                .load(Ordering::Relaxed),
+            1
+        );
+
+        // Nothing is scheduled, nor scanned, while the batch is in flight
+        BankingStage::schedule_pending_transactions(
+            &bank,
+            &mut scheduler,
+            1,
+            &batch_sender,
+            &banking_stage_stats,
+        );
+        assert!(batch_receiver.try_recv().is_err());
+        assert_eq!(
+            banking_stage_stats
+                .scheduler_conflicts_count
+                .load(Ordering::Relaxed),
+            1
+        );
+
+        // The conflicting transfer is scheduled once the batch completes, along with the
+        // transaction of the batch to retry
+        BankingStage::complete_scheduled_batch(
+            CompletedBatch {
+                id: batch.id,
+                retryable_transactions: vec![(1, batch.transactions[1].clone())],
+            },
+            &mut scheduler,
+            10,
+            &banking_stage_stats,
+        );
+        assert_eq!(scheduler.pending_len(), 2);
+        assert_eq!(
+            banking_stage_stats
+                .retried_transactions_count
+                .load(Ordering::Relaxed),
+            1
+        );
+        BankingStage::schedule_pending_transactions(
+            &bank,
+            &mut scheduler,
+            1,
+            &batch_sender,
+            &banking_stage_stats,
+        );
+        let batch = batch_receiver.try_recv().unwrap();
+        assert_eq!(
+            signatures(&batch),
+            vec![transactions[1].signatures[0], transactions[2].signatures[0]]
+        );
+        assert_eq!(scheduler.pending_len(), 0);
+    }
+
     #[test]
     fn test_packet_message() {
         let keypair = Keypair::new();
@@ -155,14 +155,19 @@ impl Tower {
         root: Slot,
         bank: &Bank,
     ) -> Self {
-        let mut tower = Tower {
-            node_pubkey: *node_pubkey,
-            ..Tower::default()
-        };
+        let mut tower = Self::new_with_key(node_pubkey);
         tower.initialize_lockouts_from_bank(vote_account_pubkey, root, bank);
         tower
     }
 
+    /// An empty tower of `node_pubkey`
+    pub fn new_with_key(node_pubkey: &Pubkey) -> Self {
+        Self {
+            node_pubkey: *node_pubkey,
+            ..Tower::default()
+        }
+    }
+
     #[cfg(test)]
     pub fn new_for_tests(threshold_depth: usize, threshold_size: f64) -> Self {
         Self {
@@ -432,7 +437,7 @@ impl Tower {
         }
     }
 
-    #[cfg(test)]
+    /// Records a vote for `slot` on top of the last vote, without a bank to vote from
     pub fn record_vote(&mut self, slot: Slot, hash: Hash) -> Option<Slot> {
         self.record_bank_vote_and_update_lockouts(slot, hash, self.last_voted_slot())
     }
@@ -449,6 +454,14 @@ impl Tower {
         self.stray_restored_slot
     }
 
+    pub fn node_pubkey(&self) -> Pubkey {
+        self.node_pubkey
+    }
+
+    pub fn vote_state(&self) -> &VoteState {
+        &self.vote_state
+    }
+
     pub fn last_vote(&mut self) -> Vote {
         self.last_vote.clone()
     }
@@ -1221,6 +1234,48 @@ impl TowerError {
     }
 }
 
+/// What `reconcile_blockstore_roots_with_tower` does to the blockstore
+#[derive(Debug, PartialEq)]
+pub enum RootsReconciliation {
+    /// Nothing, the tower root isn't ahead of the last blockstore root
+    UpToDate,
+    /// Mark these slots as roots: the tower root and its ancestors down to the last blockstore
+    /// root
+    NewRoots(Vec<Slot>),
+    /// Nothing, the tower root isn't in the blockstore
+    MissingTowerRoot,
+    /// Panic, the tower root doesn't descend from the last blockstore root
+    NotDescending,
+}
+
+/// Returns what `reconcile_blockstore_roots_with_tower` would do, without writing to the
+/// blockstore
+pub fn reconcile_blockstore_roots_with_tower_dry_run(
+    tower: &Tower,
+    blockstore: &Blockstore,
+) -> RootsReconciliation {
+    let tower_root = tower.root();
+    let last_blockstore_root = blockstore.last_root();
+    if last_blockstore_root >= tower_root {
+        return RootsReconciliation::UpToDate;
+    }
+    // Ensure tower_root itself to exist and be marked as rooted in the blockstore
+    // in addition to its ancestors.
+    let mut new_roots = vec![];
+    for slot in AncestorIterator::new_inclusive(tower_root, blockstore) {
+        match slot.cmp(&last_blockstore_root) {
+            Ordering::Greater => new_roots.push(slot),
+            Ordering::Equal => break,
+            Ordering::Less => return RootsReconciliation::NotDescending,
+        }
+    }
+    if new_roots.is_empty() {
+        RootsReconciliation::MissingTowerRoot
+    } else {
+        RootsReconciliation::NewRoots(new_roots)
+    }
+}
+
 // Given an untimely crash, tower may have roots that are not reflected in blockstore,
 // or the reverse of this.
 // That's because we don't impose any ordering guarantee or any kind of write barriers
@@ -1232,26 +1287,16 @@ pub fn reconcile_blockstore_roots_with_tower(
 ) -> blockstore_db::Result<()> {
     let tower_root = tower.root();
     let last_blockstore_root = blockstore.last_root();
-    if last_blockstore_root < tower_root {
-        // Ensure tower_root itself to exist and be marked as rooted in the blockstore
-        // in addition to its ancestors.
-        let new_roots: Vec<_> = AncestorIterator::new_inclusive(tower_root, blockstore)
-            .take_while(|current| match current.cmp(&last_blockstore_root) {
-                Ordering::Greater => true,
-                Ordering::Equal => false,
-                Ordering::Less => panic!(
-                    "couldn't find a last_blockstore_root upwards from: {}!?",
-                    tower_root
-                ),
-            })
-            .collect();
-        if !new_roots.is_empty() {
+    match reconcile_blockstore_roots_with_tower_dry_run(tower, blockstore) {
+        RootsReconciliation::UpToDate => {}
+        RootsReconciliation::NewRoots(new_roots) => {
             info!(
                 "Reconciling slots as root based on tower root: {:?} ({}..{}) ",
                 new_roots, tower_root, last_blockstore_root
             );
             blockstore.set_roots(new_roots.iter())?;
-        } else {
+        }
+        RootsReconciliation::MissingTowerRoot => {
             // This indicates we're in bad state; but still don't panic here.
             // That's because we might have a chance of recovering properly with
             // newer snapshot.
@@ -1262,6 +1307,10 @@ pub fn reconcile_blockstore_roots_with_tower(
                 tower_root, last_blockstore_root,
             );
         }
+        RootsReconciliation::NotDescending => panic!(
+            "couldn't find a last_blockstore_root upwards from: {}!?",
+            tower_root
+        ),
     }
     Ok(())
 }
@@ -1271,8 +1320,10 @@ pub mod test {
     use {
         super::*,
         crate::{
-            fork_choice::ForkChoice, heaviest_subtree_fork_choice::SlotHashKey,
-            replay_stage::HeaviestForkFailures, tower_storage::FileTowerStorage,
+            fork_choice::ForkChoice,
+            heaviest_subtree_fork_choice::SlotHashKey,
+            replay_stage::HeaviestForkFailures,
+            tower_storage::{FileTowerStorage, TOWER_HISTORY_PRUNE_INTERVAL},
             vote_simulator::VoteSimulator,
         },
         itertools::Itertools,
@@ -2660,6 +2711,50 @@ pub mod test {
         assert!((loaded.threshold_size - 0.9_f64).abs() < f64::EPSILON);
     }
 
+    #[test]
+    fn test_tower_history() {
+        let tower_path = TempDir::new().unwrap();
+        let identity_keypair = Keypair::new();
+        let node_pubkey = identity_keypair.pubkey();
+        let mut tower = Tower::new_for_tests(10, 0.9);
+        tower.node_pubkey = node_pubkey;
+
+        let tower_storage = FileTowerStorage::new_with_history(tower_path.path().to_path_buf(), 2);
+        for slot in 1..=3 {
+            tower.record_vote(slot, Hash::default());
+            tower.save(&tower_storage, &identity_keypair).unwrap();
+        }
+
+        let history = tower_storage.load_history(&node_pubkey).unwrap();
+        assert_eq!(history.len(), 2);
+        assert!(history[0].timestamp < history[1].timestamp);
+        let last_voted_slots: Vec<_> = history
+            .into_iter()
+            .map(|entry| {
+                entry
+                    .saved_tower
+                    .try_into_tower(&node_pubkey)
+                    .unwrap()
+                    .last_voted_slot()
+            })
+            .collect();
+        assert_eq!(last_voted_slots, vec![Some(2), Some(3)]);
+        assert_eq!(Tower::restore(&tower_storage, &node_pubkey).unwrap(), tower);
+
+        // The history is pruned once every TOWER_HISTORY_PRUNE_INTERVAL stores
+        let num_history_files = || {
+            std::fs::read_dir(tower_storage.history_path(&node_pubkey))
+                .unwrap()
+                .count()
+        };
+        assert_eq!(num_history_files(), 3);
+        for slot in 4..=TOWER_HISTORY_PRUNE_INTERVAL as Slot + 1 {
+            tower.record_vote(slot, Hash::default());
+            tower.save(&tower_storage, &identity_keypair).unwrap();
+        }
+        assert_eq!(num_history_files(), 2);
+    }
+
     #[test]
     fn test_load_tower_wrong_identity() {
         let identity_keypair = Arc::new(Keypair::new());
@@ -2798,6 +2893,54 @@ pub mod test {
         Blockstore::destroy(&blockstore_path).expect("Expected successful database destruction");
     }
 
+    #[test]
+    fn test_reconcile_blockstore_roots_with_tower_dry_run() {
+        solana_logger::setup();
+        let blockstore_path = get_tmp_ledger_path!();
+        {
+            let blockstore = Blockstore::open(&blockstore_path).unwrap();
+
+            let (shreds, _) = make_slot_entries(1, 0, 42);
+            blockstore.insert_shreds(shreds, None, false).unwrap();
+            let (shreds, _) = make_slot_entries(3, 1, 42);
+            blockstore.insert_shreds(shreds, None, false).unwrap();
+            let (shreds, _) = make_slot_entries(4, 1, 42);
+            blockstore.insert_shreds(shreds, None, false).unwrap();
+
+            let mut tower = Tower::default();
+            tower.vote_state.root_slot = Some(4);
+            assert_eq!(
+                reconcile_blockstore_roots_with_tower_dry_run(&tower, &blockstore),
+                RootsReconciliation::NewRoots(vec![4, 1])
+            );
+            // The blockstore is left alone
+            assert!(!blockstore.is_root(1));
+            assert!(!blockstore.is_root(4));
+
+            // Slot 5 was pruned
+            tower.vote_state.root_slot = Some(5);
+            assert_eq!(
+                reconcile_blockstore_roots_with_tower_dry_run(&tower, &blockstore),
+                RootsReconciliation::MissingTowerRoot
+            );
+
+            // Slot 4 doesn't descend from slot 3
+            blockstore.set_roots(std::iter::once(&3)).unwrap();
+            tower.vote_state.root_slot = Some(4);
+            assert_eq!(
+                reconcile_blockstore_roots_with_tower_dry_run(&tower, &blockstore),
+                RootsReconciliation::NotDescending
+            );
+
+            tower.vote_state.root_slot = Some(3);
+            assert_eq!(
+                reconcile_blockstore_roots_with_tower_dry_run(&tower, &blockstore),
+                RootsReconciliation::UpToDate
+            );
+        }
+        Blockstore::destroy(&blockstore_path).expect("Expected successful database destruction");
+    }
+
     #[test]
     fn test_adjust_lockouts_after_replay_future_slots() {
         solana_logger::setup();
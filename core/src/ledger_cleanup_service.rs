@@ -1,10 +1,18 @@
 //! The `ledger_cleanup_service` drops older ledger data to limit disk space usage
 
 use rand::{thread_rng, Rng};
-use solana_ledger::blockstore::{Blockstore, PurgeType};
-use solana_ledger::blockstore_db::Result as BlockstoreResult;
+use solana_ledger::blockstore::{
+    blockstore_integrity::IntegrityCheckOptions, Blockstore, PurgeType,
+};
+use solana_ledger::blockstore_db::{ColumnGroup, Result as BlockstoreResult};
 use solana_measure::measure::Measure;
-use solana_sdk::clock::{Slot, DEFAULT_TICKS_PER_SLOT, TICKS_PER_DAY};
+use solana_sdk::{
+    clock::{Slot, UnixTimestamp, DEFAULT_TICKS_PER_SLOT, TICKS_PER_DAY},
+    epoch_schedule::EpochSchedule,
+    timing::timestamp,
+};
+use std::fmt;
+use std::str::FromStr;
 use std::string::ToString;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc::{Receiver, RecvTimeoutError};
@@ -34,19 +42,131 @@ pub const DEFAULT_PURGE_SLOT_INTERVAL: u64 = 512;
 // Once a day should be ample
 const DEFAULT_COMPACTION_SLOT_INTERVAL: u64 = TICKS_PER_DAY / DEFAULT_TICKS_PER_SLOT;
 
+// The newest roots are left out of the integrity check, their transaction statuses may
+// still be in flight to the blockstore.
+const INTEGRITY_CHECK_ROOT_LAG: u64 = 32;
+
+#[derive(Clone, Debug)]
+pub struct LedgerIntegrityCheckConfig {
+    /// Number of rooted slots between background integrity checks
+    pub interval: u64,
+    pub options: IntegrityCheckOptions,
+}
+
+/// What a retention policy bounds
+#[derive(Clone, Debug, PartialEq)]
+pub enum RetentionLimit {
+    /// Keep at most this many shreds, as counted by the `SlotMeta`s
+    Shreds(u64),
+    /// Keep the slots of the current epoch and of the epochs before it, this many in total
+    Epochs(u64),
+    /// Keep the slots whose `Blocktime` is within this age
+    Age(Duration),
+    /// Keep the SST files of the column group under this many bytes
+    DiskBytes(u64),
+}
+
+/// A retention limit on one column group, parsed from `<GROUP>:<LIMIT>=<VALUE>` where the group
+/// is `shreds` or `transaction-status` and the limit is `shreds`, `epochs`, `hours` or `gib`
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetentionPolicy {
+    pub group: ColumnGroup,
+    pub limit: RetentionLimit,
+}
+
+impl FromStr for RetentionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (group, limit) = s
+            .split_once(':')
+            .ok_or_else(|| format!("Invalid retention policy {:?}, expected GROUP:LIMIT", s))?;
+        let group = group.parse()?;
+        let (kind, value) = limit
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid retention limit {:?}, expected KIND=VALUE", limit))?;
+        let value = value
+            .parse::<u64>()
+            .ok()
+            .filter(|value| *value > 0)
+            .ok_or_else(|| format!("Invalid retention limit value: {}", value))?;
+        let limit = match kind {
+            "shreds" => RetentionLimit::Shreds(value),
+            "epochs" => RetentionLimit::Epochs(value),
+            "hours" => RetentionLimit::Age(Duration::from_secs(value * 3600)),
+            "gib" => RetentionLimit::DiskBytes(value * 1024 * 1024 * 1024),
+            bad_kind => return Err(format!("Invalid retention limit: {}", bad_kind)),
+        };
+        Ok(Self { group, limit })
+    }
+}
+
+impl fmt::Display for RetentionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.limit {
+            RetentionLimit::Shreds(shreds) => write!(f, "{}:shreds={}", self.group, shreds),
+            RetentionLimit::Epochs(epochs) => write!(f, "{}:epochs={}", self.group, epochs),
+            RetentionLimit::Age(age) => write!(f, "{}:hours={}", self.group, age.as_secs() / 3600),
+            RetentionLimit::DiskBytes(bytes) => {
+                write!(f, "{}:gib={}", self.group, bytes / (1024 * 1024 * 1024))
+            }
+        }
+    }
+}
+
+/// The retention policies enforced by `LedgerCleanupService`. A column group is purged up to
+/// the slot required by the most demanding of its policies, and the transaction status group
+/// follows the shreds unless it has policies of its own.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LedgerRetentionConfig {
+    pub policies: Vec<RetentionPolicy>,
+}
+
+impl LedgerRetentionConfig {
+    /// The policies, along with the shred limit of `--limit-ledger-size`
+    pub fn new(max_ledger_shreds: Option<u64>, mut policies: Vec<RetentionPolicy>) -> Self {
+        if let Some(max_ledger_shreds) = max_ledger_shreds {
+            policies.insert(
+                0,
+                RetentionPolicy {
+                    group: ColumnGroup::Shreds,
+                    limit: RetentionLimit::Shreds(max_ledger_shreds),
+                },
+            );
+        }
+        Self { policies }
+    }
+
+    pub fn max_ledger_shreds(max_ledger_shreds: u64) -> Self {
+        Self::new(Some(max_ledger_shreds), vec![])
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.policies.is_empty()
+    }
+
+    fn has_policies(&self, group: ColumnGroup) -> bool {
+        self.policies.iter().any(|policy| policy.group == group)
+    }
+}
+
 pub struct LedgerCleanupService {
     t_cleanup: JoinHandle<()>,
     t_compact: JoinHandle<()>,
+    t_integrity: Option<JoinHandle<()>>,
 }
 
 impl LedgerCleanupService {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         new_root_receiver: Receiver<Slot>,
         blockstore: Arc<Blockstore>,
-        max_ledger_shreds: u64,
+        retention_config: LedgerRetentionConfig,
+        epoch_schedule: EpochSchedule,
         exit: &Arc<AtomicBool>,
         compaction_interval: Option<u64>,
         max_compaction_jitter: Option<u64>,
+        integrity_check: Option<LedgerIntegrityCheckConfig>,
     ) -> Self {
         let exit = exit.clone();
         let mut last_purge_slot = 0;
@@ -57,13 +177,44 @@ impl LedgerCleanupService {
         let last_compact_slot2 = last_compact_slot.clone();
 
         info!(
-            "LedgerCleanupService active. max ledger shreds={}, compaction interval={}",
-            max_ledger_shreds, compaction_interval,
+            "LedgerCleanupService active. retention policies={}, compaction interval={}",
+            retention_config
+                .policies
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(","),
+            compaction_interval,
         );
 
         let exit_compact = exit.clone();
         let blockstore_compact = blockstore.clone();
 
+        let t_integrity = integrity_check.map(|integrity_check| {
+            info!(
+                "LedgerCleanupService integrity check active. interval={}, repair={}",
+                integrity_check.interval, integrity_check.options.repair,
+            );
+            let exit = exit.clone();
+            let blockstore = blockstore.clone();
+            let mut last_checked_slot = blockstore.last_root();
+            Builder::new()
+                .name("sol-led-verify".to_string())
+                .spawn(move || loop {
+                    if exit.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    Self::check_ledger_integrity(
+                        &blockstore,
+                        &mut last_checked_slot,
+                        &integrity_check,
+                        &exit,
+                    );
+                    sleep(Duration::from_secs(1));
+                })
+                .unwrap()
+        });
+
         let t_cleanup = Builder::new()
             .name("sol-led-cleanup".to_string())
             .spawn(move || loop {
@@ -73,7 +224,8 @@ impl LedgerCleanupService {
                 if let Err(e) = Self::cleanup_ledger(
                     &new_root_receiver,
                     &blockstore,
-                    max_ledger_shreds,
+                    &retention_config,
+                    &epoch_schedule,
                     &mut last_purge_slot,
                     DEFAULT_PURGE_SLOT_INTERVAL,
                     &last_compact_slot,
@@ -107,6 +259,7 @@ impl LedgerCleanupService {
         Self {
             t_cleanup,
             t_compact,
+            t_integrity,
         }
     }
 
@@ -165,10 +318,12 @@ impl LedgerCleanupService {
         Ok(root)
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn cleanup_ledger(
         new_root_receiver: &Receiver<Slot>,
         blockstore: &Arc<Blockstore>,
-        max_ledger_shreds: u64,
+        retention_config: &LedgerRetentionConfig,
+        epoch_schedule: &EpochSchedule,
         last_purge_slot: &mut u64,
         purge_interval: u64,
         last_compact_slot: &Arc<AtomicU64>,
@@ -186,19 +341,42 @@ impl LedgerCleanupService {
 
         *last_purge_slot = root;
 
-        let (slots_to_clean, purge_first_slot, lowest_cleanup_slot, total_shreds) =
-            Self::find_slots_to_clean(blockstore, root, max_ledger_shreds);
-
-        if slots_to_clean {
+        let mut total_shreds = 0;
+        let separate_transaction_status =
+            retention_config.has_policies(ColumnGroup::TransactionStatus);
+
+        let purge_first_slot = Self::first_slot(blockstore, ColumnGroup::Shreds);
+        if let Some((lowest_cleanup_slot, policy)) = Self::find_retention_cutoff(
+            blockstore,
+            retention_config,
+            ColumnGroup::Shreds,
+            root,
+            purge_first_slot,
+            epoch_schedule,
+            &mut total_shreds,
+        ) {
+            Self::report_retention_purge(policy, purge_first_slot, lowest_cleanup_slot, root);
             let purge_complete = Arc::new(AtomicBool::new(false));
             let blockstore = blockstore.clone();
             let purge_complete1 = purge_complete.clone();
             let last_compact_slot1 = last_compact_slot.clone();
+            let compact_now = matches!(policy.limit, RetentionLimit::DiskBytes(_));
             let _t_purge = Builder::new()
                 .name("solana-ledger-purge".to_string())
                 .spawn(move || {
                     let mut slot_update_time = Measure::start("slot_update");
-                    *blockstore.lowest_cleanup_slot.write().unwrap() = lowest_cleanup_slot;
+                    blockstore.set_column_group_lowest_cleanup_slot(
+                        ColumnGroup::Shreds,
+                        lowest_cleanup_slot,
+                    );
+                    // The transaction status reads are gated separately, as their columns may be
+                    // retained for longer than the shreds
+                    if !separate_transaction_status {
+                        blockstore.set_column_group_lowest_cleanup_slot(
+                            ColumnGroup::TransactionStatus,
+                            lowest_cleanup_slot,
+                        );
+                    }
                     slot_update_time.stop();
 
                     info!(
@@ -222,9 +400,25 @@ impl LedgerCleanupService {
                     // files are immutable, even running iterators aren't affected; the database grabs
                     // a snapshot of the live set of sst files at iterator's creation.
                     // Also, we passed the PurgeType::CompactionFilter, meaning no delete_range for
-                    // transaction_status and address_signatures CFs. These are fine because they
-                    // don't require strong consistent view for their operation.
-                    blockstore.set_max_expired_slot(lowest_cleanup_slot);
+                    // transaction_status, address_signatures and blocktime CFs. These are fine
+                    // because they don't require strong consistent view for their operation.
+                    if separate_transaction_status {
+                        blockstore.set_column_group_max_expired_slot(
+                            ColumnGroup::Shreds,
+                            lowest_cleanup_slot,
+                        );
+                    } else {
+                        blockstore.set_max_expired_slot(lowest_cleanup_slot);
+                    }
+                    // The size of the SST files only drops once the purged range is compacted,
+                    // which is needed before the disk usage is measured again
+                    if compact_now {
+                        Self::compact_purged_range(
+                            &blockstore,
+                            purge_first_slot,
+                            lowest_cleanup_slot,
+                        );
+                    }
 
                     purge_time.stop();
                     info!("{}", purge_time);
@@ -244,12 +438,149 @@ impl LedgerCleanupService {
             }
         }
 
+        // The transaction status columns are only ever purged by their compaction filters, so
+        // moving their oldest slot is enough
+        if separate_transaction_status {
+            let first_slot = Self::first_slot(blockstore, ColumnGroup::TransactionStatus);
+            if let Some((max_expired_slot, policy)) = Self::find_retention_cutoff(
+                blockstore,
+                retention_config,
+                ColumnGroup::TransactionStatus,
+                root,
+                first_slot,
+                epoch_schedule,
+                &mut total_shreds,
+            ) {
+                Self::report_retention_purge(policy, first_slot, max_expired_slot, root);
+                blockstore.set_column_group_lowest_cleanup_slot(
+                    ColumnGroup::TransactionStatus,
+                    max_expired_slot,
+                );
+                blockstore.set_column_group_max_expired_slot(
+                    ColumnGroup::TransactionStatus,
+                    max_expired_slot,
+                );
+                if matches!(policy.limit, RetentionLimit::DiskBytes(_)) {
+                    Self::compact_purged_range(blockstore, first_slot, max_expired_slot);
+                }
+                last_compact_slot.fetch_max(max_expired_slot, Ordering::Relaxed);
+            }
+        }
+
         let disk_utilization_post = blockstore.storage_size();
         Self::report_disk_metrics(disk_utilization_pre, disk_utilization_post, total_shreds);
 
         Ok(())
     }
 
+    /// The oldest slot of `group` still in the blockstore, as far as is known without a scan
+    fn first_slot(blockstore: &Blockstore, group: ColumnGroup) -> Slot {
+        let oldest_slot = blockstore.column_group_oldest_slot(group);
+        if group == ColumnGroup::Shreds || oldest_slot == 0 {
+            blockstore
+                .slot_meta_iterator(0)
+                .ok()
+                .and_then(|mut slot_metas| slot_metas.next())
+                .map(|(slot, _)| slot)
+                .unwrap_or(oldest_slot)
+        } else {
+            oldest_slot
+        }
+    }
+
+    /// The highest slot of `group` to purge, along with the policy that requires it. The
+    /// most demanding of the group's policies wins.
+    fn find_retention_cutoff<'a>(
+        blockstore: &Arc<Blockstore>,
+        retention_config: &'a LedgerRetentionConfig,
+        group: ColumnGroup,
+        root: Slot,
+        first_slot: Slot,
+        epoch_schedule: &EpochSchedule,
+        total_shreds: &mut u64,
+    ) -> Option<(Slot, &'a RetentionPolicy)> {
+        // The slots below the oldest slot of the group are already expired
+        let lowest_cutoff_slot = match group {
+            ColumnGroup::Shreds => first_slot,
+            ColumnGroup::TransactionStatus => blockstore.column_group_oldest_slot(group),
+        };
+        retention_config
+            .policies
+            .iter()
+            .filter(|policy| policy.group == group)
+            .filter_map(|policy| {
+                let cutoff_slot = match policy.limit {
+                    RetentionLimit::Shreds(max_ledger_shreds) => {
+                        let (slots_to_clean, _, lowest_cleanup_slot, shreds) =
+                            Self::find_slots_to_clean(blockstore, root, max_ledger_shreds);
+                        *total_shreds = shreds;
+                        slots_to_clean.then(|| lowest_cleanup_slot)
+                    }
+                    RetentionLimit::Epochs(num_epochs) => {
+                        let oldest_epoch =
+                            (epoch_schedule.get_epoch(root) + 1).saturating_sub(num_epochs);
+                        epoch_schedule
+                            .get_first_slot_in_epoch(oldest_epoch)
+                            .checked_sub(1)
+                    }
+                    RetentionLimit::Age(max_age) => {
+                        let oldest_block_time =
+                            (timestamp() / 1000).saturating_sub(max_age.as_secs()) as UnixTimestamp;
+                        blockstore
+                            .latest_slot_with_block_time_before(
+                                lowest_cutoff_slot,
+                                oldest_block_time,
+                            )
+                            .unwrap_or_else(|err| {
+                                warn!("Couldn't find the slot to retain by block time: {:?}", err);
+                                None
+                            })
+                    }
+                    RetentionLimit::DiskBytes(max_bytes) => {
+                        let size = blockstore.column_group_storage_size(group).ok()?;
+                        if size <= max_bytes {
+                            return None;
+                        }
+                        // Assume the data is evenly spread over the slots
+                        let num_slots = u128::from(root.saturating_sub(first_slot));
+                        let excess_bytes = u128::from(size - max_bytes);
+                        Some(first_slot + (num_slots * excess_bytes / u128::from(size)) as Slot)
+                    }
+                };
+                cutoff_slot
+                    .filter(|cutoff_slot| {
+                        *cutoff_slot >= lowest_cutoff_slot && *cutoff_slot <= root
+                    })
+                    .map(|cutoff_slot| (cutoff_slot, policy))
+            })
+            .max_by_key(|(cutoff_slot, _)| *cutoff_slot)
+    }
+
+    fn compact_purged_range(blockstore: &Blockstore, from_slot: Slot, to_slot: Slot) {
+        if let Err(err) = blockstore.compact_storage(from_slot, to_slot) {
+            error!(
+                "Error: {:?}; Couldn't compact storage from {:?} to {:?}",
+                err, from_slot, to_slot,
+            );
+        }
+    }
+
+    fn report_retention_purge(
+        policy: &RetentionPolicy,
+        first_slot: Slot,
+        max_expired_slot: Slot,
+        root: Slot,
+    ) {
+        datapoint_info!(
+            "ledger_retention_purge",
+            ("column_group", policy.group.to_string(), String),
+            ("policy", policy.to_string(), String),
+            ("first_slot", first_slot as i64, i64),
+            ("max_expired_slot", max_expired_slot as i64, i64),
+            ("root", root as i64, i64),
+        );
+    }
+
     pub fn compact_ledger(
         blockstore: &Arc<Blockstore>,
         last_compaction_slot: &mut u64,
@@ -283,6 +614,44 @@ impl LedgerCleanupService {
         }
     }
 
+    pub fn check_ledger_integrity(
+        blockstore: &Arc<Blockstore>,
+        last_checked_slot: &mut Slot,
+        integrity_check: &LedgerIntegrityCheckConfig,
+        exit: &Arc<AtomicBool>,
+    ) {
+        let end_slot = blockstore
+            .last_root()
+            .saturating_sub(INTEGRITY_CHECK_ROOT_LAG);
+        if end_slot.saturating_sub(*last_checked_slot) < integrity_check.interval {
+            return;
+        }
+        let start_slot = *last_checked_slot + 1;
+        match blockstore.check_integrity(start_slot, end_slot, &integrity_check.options, exit) {
+            Ok(report) => {
+                for issue in &report.issues {
+                    warn!("blockstore integrity: {}", issue);
+                }
+                info!(
+                    "integrity check of slots {} to {}: {} slots, {} issues, {} repaired",
+                    start_slot,
+                    end_slot,
+                    report.slots_checked,
+                    report.issues.len(),
+                    report.num_repaired(),
+                );
+                *last_checked_slot = end_slot;
+            }
+            Err(err) => {
+                // The same slots are checked again after the next root
+                error!(
+                    "Error: {:?}; Couldn't check blockstore integrity from {:?} to {:?}",
+                    err, start_slot, end_slot,
+                );
+            }
+        }
+    }
+
     fn report_disk_metrics(
         pre: BlockstoreResult<u64>,
         post: BlockstoreResult<u64>,
@@ -301,6 +670,9 @@ impl LedgerCleanupService {
 
     pub fn join(self) -> thread::Result<()> {
         self.t_cleanup.join()?;
+        if let Some(t_integrity) = self.t_integrity {
+            t_integrity.join()?;
+        }
         self.t_compact.join()
     }
 }
@@ -309,6 +681,8 @@ mod tests {
     use super::*;
     use solana_ledger::blockstore::make_many_slot_entries;
     use solana_ledger::get_tmp_ledger_path;
+    use solana_sdk::signature::Signature;
+    use solana_transaction_status::TransactionStatusMeta;
     use std::sync::mpsc::channel;
 
     #[test]
@@ -328,7 +702,8 @@ mod tests {
         LedgerCleanupService::cleanup_ledger(
             &receiver,
             &blockstore,
-            5,
+            &LedgerRetentionConfig::max_ledger_shreds(5),
+            &EpochSchedule::default(),
             &mut last_purge_slot,
             10,
             &highest_compaction_slot,
@@ -398,7 +773,8 @@ mod tests {
             LedgerCleanupService::cleanup_ledger(
                 &receiver,
                 &blockstore,
-                initial_slots,
+                &LedgerRetentionConfig::max_ledger_shreds(initial_slots),
+                &EpochSchedule::default(),
                 &mut last_purge_slot,
                 10,
                 &last_compaction_slot,
@@ -416,4 +792,153 @@ mod tests {
         drop(blockstore);
         Blockstore::destroy(&blockstore_path).expect("Expected successful database destruction");
     }
+
+    #[test]
+    fn test_retention_policy_from_str() {
+        assert_eq!(
+            "shreds:epochs=2".parse::<RetentionPolicy>(),
+            Ok(RetentionPolicy {
+                group: ColumnGroup::Shreds,
+                limit: RetentionLimit::Epochs(2),
+            })
+        );
+        assert_eq!(
+            "transaction-status:hours=72".parse::<RetentionPolicy>(),
+            Ok(RetentionPolicy {
+                group: ColumnGroup::TransactionStatus,
+                limit: RetentionLimit::Age(Duration::from_secs(72 * 3600)),
+            })
+        );
+        let policy = "shreds:gib=500".parse::<RetentionPolicy>().unwrap();
+        assert_eq!(policy.limit, RetentionLimit::DiskBytes(500 << 30));
+        assert_eq!(policy.to_string(), "shreds:gib=500");
+        assert!("shreds".parse::<RetentionPolicy>().is_err());
+        assert!("shreds:epochs".parse::<RetentionPolicy>().is_err());
+        assert!("shreds:epochs=0".parse::<RetentionPolicy>().is_err());
+        assert!("shreds:weeks=2".parse::<RetentionPolicy>().is_err());
+        assert!("rewards:epochs=2".parse::<RetentionPolicy>().is_err());
+
+        let retention_config = LedgerRetentionConfig::new(Some(1000), vec![policy]);
+        assert_eq!(
+            retention_config.policies[0].limit,
+            RetentionLimit::Shreds(1000)
+        );
+        assert!(retention_config.has_policies(ColumnGroup::Shreds));
+        assert!(!retention_config.has_policies(ColumnGroup::TransactionStatus));
+    }
+
+    #[test]
+    fn test_cleanup_retention_policies() {
+        solana_logger::setup();
+        let blockstore_path = get_tmp_ledger_path!();
+        let blockstore = Blockstore::open(&blockstore_path).unwrap();
+        let (shreds, _) = make_many_slot_entries(0, 100, 5);
+        blockstore.insert_shreds(shreds, None, false).unwrap();
+        // A transaction of slot 40, whose shreds are purged but whose status is kept
+        let signature = Signature::new(&[1; 64]);
+        blockstore
+            .write_transaction_status(
+                40,
+                signature,
+                vec![],
+                vec![],
+                TransactionStatusMeta::default(),
+            )
+            .unwrap();
+        blockstore.cache_block_time(40, 1_000).unwrap();
+        let blockstore = Arc::new(blockstore);
+        let (sender, receiver) = channel();
+        let epoch_schedule = EpochSchedule::custom(32, 32, false);
+
+        // Root 99 is in epoch 3, keeping 2 epochs drops the slots before 64
+        let retention_config = LedgerRetentionConfig::new(
+            None,
+            vec![
+                "shreds:epochs=2".parse().unwrap(),
+                "transaction-status:epochs=3".parse().unwrap(),
+            ],
+        );
+        let mut last_purge_slot = 0;
+        let highest_compaction_slot = Arc::new(AtomicU64::new(0));
+        sender.send(99).unwrap();
+        LedgerCleanupService::cleanup_ledger(
+            &receiver,
+            &blockstore,
+            &retention_config,
+            &epoch_schedule,
+            &mut last_purge_slot,
+            10,
+            &highest_compaction_slot,
+        )
+        .unwrap();
+        assert_eq!(last_purge_slot, 99);
+        blockstore
+            .slot_meta_iterator(0)
+            .unwrap()
+            .for_each(|(slot, _)| assert!(slot >= 64));
+        assert_eq!(blockstore.column_group_oldest_slot(ColumnGroup::Shreds), 64);
+        // The transaction statuses are kept for an epoch longer
+        assert_eq!(
+            blockstore.column_group_oldest_slot(ColumnGroup::TransactionStatus),
+            32
+        );
+        assert_eq!(highest_compaction_slot.load(Ordering::Relaxed), 63);
+        assert_eq!(blockstore.lowest_cleanup_slot(), 63);
+        assert_eq!(
+            blockstore.column_group_lowest_cleanup_slot(ColumnGroup::TransactionStatus),
+            31
+        );
+
+        // The status and the block time of slot 40 are still readable after its shreds are gone
+        assert!(blockstore.meta(40).unwrap().is_none());
+        assert_eq!(
+            blockstore
+                .get_transaction_status(signature, &[40])
+                .unwrap()
+                .map(|(slot, _)| slot),
+            Some(40)
+        );
+        assert_eq!(blockstore.get_block_time(40).unwrap(), Some(1_000));
+
+        drop(blockstore);
+        Blockstore::destroy(&blockstore_path).expect("Expected successful database destruction");
+    }
+
+    #[test]
+    fn test_check_ledger_integrity() {
+        solana_logger::setup();
+        let blockstore_path = get_tmp_ledger_path!();
+        let blockstore = Blockstore::open(&blockstore_path).unwrap();
+        let (shreds, _) = make_many_slot_entries(0, 50, 5);
+        blockstore.insert_shreds(shreds, None, false).unwrap();
+        let roots: Vec<Slot> = (0..50).collect();
+        blockstore.set_roots(roots.iter()).unwrap();
+        let blockstore = Arc::new(blockstore);
+        let exit = Arc::new(AtomicBool::new(false));
+        let integrity_check = LedgerIntegrityCheckConfig {
+            interval: 10,
+            options: IntegrityCheckOptions::default(),
+        };
+
+        let mut last_checked_slot = 0;
+        LedgerCleanupService::check_ledger_integrity(
+            &blockstore,
+            &mut last_checked_slot,
+            &integrity_check,
+            &exit,
+        );
+        assert_eq!(last_checked_slot, 49 - INTEGRITY_CHECK_ROOT_LAG);
+
+        // Not enough new roots for another check
+        LedgerCleanupService::check_ledger_integrity(
+            &blockstore,
+            &mut last_checked_slot,
+            &integrity_check,
+            &exit,
+        );
+        assert_eq!(last_checked_slot, 49 - INTEGRITY_CHECK_ROOT_LAG);
+
+        drop(blockstore);
+        Blockstore::destroy(&blockstore_path).expect("Expected successful database destruction");
+    }
 }
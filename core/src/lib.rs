@@ -55,6 +55,8 @@ pub mod snapshot_packager_service;
 pub mod system_monitor_service;
 pub mod tower_storage;
 pub mod tpu;
+pub mod transaction_notifier_service;
+pub mod transaction_scheduler;
 pub mod tree_diff;
 pub mod tvu;
 pub mod unfrozen_gossip_verified_vote_hashes;
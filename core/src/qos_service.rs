@@ -3,28 +3,119 @@
 //! how transactions are included in blocks, and optimize those blocks.
 //!
 use {
+    itertools::Itertools,
+    solana_gossip::{cluster_info::ClusterInfo, contact_info::ContactInfo},
     solana_measure::measure::Measure,
+    solana_perf::packet::Packets,
     solana_runtime::{
         bank::Bank,
         cost_model::{CostModel, TransactionCost},
         cost_tracker::CostTrackerError,
     },
     solana_sdk::{
+        pubkey::Pubkey,
         timing::AtomicInterval,
         transaction::{self, SanitizedTransaction, TransactionError},
     },
     std::{
+        cmp::{self, Reverse},
+        collections::{hash_map::Entry, HashMap},
+        net::IpAddr,
         sync::{
             atomic::{AtomicBool, AtomicU64, Ordering},
-            Arc, RwLock,
+            Arc, Mutex, RwLock,
         },
         thread::{self, Builder, JoinHandle},
         time::Duration,
     },
 };
 
+// Share of the packets of an oversized sigverify batch reserved for the unstaked sources
+const UNSTAKED_INGRESS_SHARE_PERCENT: usize = 10;
+
+// The sources that had the most packets dropped over their ingress quotas reported per interval
+const MAX_REPORTED_DROPPED_SOURCES: usize = 5;
+
+/// Splits the packets that sigverify takes from a batch among the sources of the batch: the
+/// unstaked sources share a small fixed part of them and the sources of the staked nodes get
+/// the rest in proportion to their epoch stakes
+#[derive(Debug, Default)]
+pub struct IngressQuotas {
+    stakes: HashMap<IpAddr, u64>,
+    total_stake: u64,
+}
+
+impl IngressQuotas {
+    /// The staked nodes are told apart from the unstaked ones by the gossip address of their
+    /// contact info, nodes sharing an address share their stakes
+    pub fn new<'a>(
+        staked_nodes: &HashMap<Pubkey, u64>,
+        contact_infos: impl IntoIterator<Item = &'a ContactInfo>,
+    ) -> Self {
+        let mut stakes = HashMap::new();
+        for contact_info in contact_infos {
+            match staked_nodes.get(&contact_info.id) {
+                Some(stake) if *stake > 0 => {
+                    *stakes.entry(contact_info.gossip.ip()).or_default() += stake;
+                }
+                _ => (),
+            }
+        }
+        let total_stake = stakes.values().sum();
+        Self {
+            stakes,
+            total_stake,
+        }
+    }
+
+    pub fn stake(&self, source: &IpAddr) -> u64 {
+        self.stakes.get(source).copied().unwrap_or_default()
+    }
+
+    /// Number of packets each source may keep, given the number of packets each sent and the
+    /// number of packets to keep from the whole batch
+    pub fn source_quotas(
+        &self,
+        packet_counts: &HashMap<IpAddr, usize>,
+        max_packets: usize,
+    ) -> HashMap<IpAddr, usize> {
+        let num_unstaked_sources = packet_counts
+            .keys()
+            .filter(|source| self.stake(source) == 0)
+            .count()
+            .max(1);
+        let unstaked_share = max_packets * UNSTAKED_INGRESS_SHARE_PERCENT / 100;
+        let staked_share = max_packets - unstaked_share;
+        // A staked source gets at least as much as an unstaked one
+        let min_quota = cmp::max(unstaked_share / num_unstaked_sources, 1);
+
+        let mut quotas = HashMap::with_capacity(packet_counts.len());
+        let mut staked_packets = 0;
+        for (source, count) in packet_counts {
+            let stake = self.stake(source);
+            if stake > 0 {
+                let quota =
+                    (staked_share as u128 * stake as u128 / self.total_stake as u128) as usize;
+                let quota = cmp::max(quota, min_quota);
+                staked_packets += cmp::min(quota, *count);
+                quotas.insert(*source, quota);
+            }
+        }
+        // The unstaked sources also share what the staked ones leave
+        let unstaked_quota = cmp::max(
+            max_packets.saturating_sub(staked_packets) / num_unstaked_sources,
+            min_quota,
+        );
+        for source in packet_counts.keys() {
+            quotas.entry(*source).or_insert(unstaked_quota);
+        }
+        quotas
+    }
+}
+
 pub struct QosService {
     cost_model: Arc<RwLock<CostModel>>,
+    ingress_quotas: RwLock<IngressQuotas>,
     metrics: Arc<QosServiceMetrics>,
     reporting_thread: Option<JoinHandle<()>>,
     running_flag: Arc<AtomicBool>,
@@ -42,9 +133,10 @@ impl Drop for QosService {
 }
 
 impl QosService {
-    pub fn new(cost_model: Arc<RwLock<CostModel>>) -> Self {
+    /// `name` tells apart the metrics of the services of the different stages
+    pub fn new(cost_model: Arc<RwLock<CostModel>>, name: &'static str) -> Self {
         let running_flag = Arc::new(AtomicBool::new(true));
-        let metrics = Arc::new(QosServiceMetrics::default());
+        let metrics = Arc::new(QosServiceMetrics::new(name));
 
         let running_flag_clone = running_flag.clone();
         let metrics_clone = metrics.clone();
@@ -58,12 +150,87 @@ impl QosService {
         );
         Self {
             cost_model,
+            ingress_quotas: RwLock::new(IngressQuotas::default()),
             metrics,
             reporting_thread,
             running_flag,
         }
     }
 
+    /// Refresh the stakes of the ingress sources from the epoch stakes of `bank` and the
+    /// contact infos of the staked nodes
+    pub fn update_ingress_quotas(&self, bank: &Bank, cluster_info: &ClusterInfo) {
+        let staked_nodes = bank.epoch_staked_nodes(bank.epoch()).unwrap_or_default();
+        let ingress_quotas = IngressQuotas::new(&staked_nodes, &cluster_info.tvu_peers());
+        self.metrics
+            .ingress_staked_sources_count
+            .store(ingress_quotas.stakes.len() as u64, Ordering::Relaxed);
+        *self.ingress_quotas.write().unwrap() = ingress_quotas;
+    }
+
+    /// Mark the packets that exceed the ingress quota of their source as discarded so that at
+    /// most about `max_packets` of them are left, returns the number of packets discarded
+    pub fn discard_packets_over_ingress_quotas(
+        &self,
+        batches: &mut [Packets],
+        max_packets: usize,
+    ) -> usize {
+        let mut packet_counts: HashMap<IpAddr, usize> = HashMap::new();
+        for packet in batches.iter().flat_map(|batch| batch.packets.iter()) {
+            if !packet.meta.discard {
+                *packet_counts.entry(packet.meta.addr().ip()).or_default() += 1;
+            }
+        }
+
+        let ingress_quotas = self.ingress_quotas.read().unwrap();
+        let mut quotas = ingress_quotas.source_quotas(&packet_counts, max_packets);
+        let sources_over_quota_count = packet_counts
+            .iter()
+            .filter(|(source, count)| quotas.get(source).map_or(true, |quota| *count > quota))
+            .count();
+        let mut dropped_staked_packets_count = 0;
+        let mut dropped_unstaked_packets_count = 0;
+        let mut dropped_packets_by_source: HashMap<IpAddr, u64> = HashMap::new();
+        for packet in batches
+            .iter_mut()
+            .flat_map(|batch| batch.packets.iter_mut())
+            .filter(|packet| !packet.meta.discard)
+        {
+            let source = packet.meta.addr().ip();
+            if let Entry::Occupied(mut quota) = quotas.entry(source) {
+                if *quota.get() > 0 {
+                    *quota.get_mut() -= 1;
+                    continue;
+                }
+            }
+            packet.meta.discard = true;
+            *dropped_packets_by_source.entry(source).or_default() += 1;
+            if ingress_quotas.stake(&source) > 0 {
+                dropped_staked_packets_count += 1;
+            } else {
+                dropped_unstaked_packets_count += 1;
+            }
+        }
+
+        self.metrics
+            .ingress_sources_over_quota_count
+            .fetch_add(sources_over_quota_count as u64, Ordering::Relaxed);
+        self.metrics
+            .dropped_staked_packets_count
+            .fetch_add(dropped_staked_packets_count as u64, Ordering::Relaxed);
+        self.metrics
+            .dropped_unstaked_packets_count
+            .fetch_add(dropped_unstaked_packets_count as u64, Ordering::Relaxed);
+        if !dropped_packets_by_source.is_empty() {
+            let mut metrics_dropped_packets_by_source =
+                self.metrics.dropped_packets_by_source.lock().unwrap();
+            for (source, count) in dropped_packets_by_source {
+                *metrics_dropped_packets_by_source.entry(source).or_default() += count;
+            }
+        }
+        dropped_staked_packets_count + dropped_unstaked_packets_count
+    }
+
     pub fn compute_transaction_costs<'a>(
         &self,
         transactions: impl Iterator<Item = &'a SanitizedTransaction>,
@@ -144,6 +311,7 @@ impl QosService {
 
 #[derive(Default)]
 struct QosServiceMetrics {
+    name: &'static str,
     last_report: AtomicInterval,
     compute_cost_time: AtomicU64,
     compute_cost_count: AtomicU64,
@@ -151,13 +319,39 @@ struct QosServiceMetrics {
     selected_txs_count: AtomicU64,
     retried_txs_per_block_limit_count: AtomicU64,
     retried_txs_per_account_limit_count: AtomicU64,
+    ingress_staked_sources_count: AtomicU64,
+    ingress_sources_over_quota_count: AtomicU64,
+    dropped_staked_packets_count: AtomicU64,
+    dropped_unstaked_packets_count: AtomicU64,
+    // Packets dropped over the ingress quotas by source, since the last report
+    dropped_packets_by_source: Mutex<HashMap<IpAddr, u64>>,
 }
 
 impl QosServiceMetrics {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            ..Self::default()
+        }
+    }
+
+    /// The `max_sources` sources with the most packets dropped since the last call, the most
+    /// dropped first
+    fn take_top_dropped_sources(&self, max_sources: usize) -> Vec<(IpAddr, u64)> {
+        let dropped_packets_by_source =
+            std::mem::take(&mut *self.dropped_packets_by_source.lock().unwrap());
+        dropped_packets_by_source
+            .into_iter()
+            .sorted_by_key(|(source, count)| (Reverse(*count), *source))
+            .take(max_sources)
+            .collect()
+    }
+
     pub fn report(&self, report_interval_ms: u64) {
         if self.last_report.should_update(report_interval_ms) {
             datapoint_info!(
                 "qos-service-stats",
+                ("name", self.name, String),
                 (
                     "compute_cost_time",
                     self.compute_cost_time.swap(0, Ordering::Relaxed) as i64,
@@ -190,7 +384,37 @@ impl QosServiceMetrics {
                         .swap(0, Ordering::Relaxed) as i64,
                     i64
                 ),
+                (
+                    "ingress_staked_sources_count",
+                    self.ingress_staked_sources_count.load(Ordering::Relaxed) as i64,
+                    i64
+                ),
+                (
+                    "ingress_sources_over_quota_count",
+                    self.ingress_sources_over_quota_count
+                        .swap(0, Ordering::Relaxed) as i64,
+                    i64
+                ),
+                (
+                    "dropped_staked_packets_count",
+                    self.dropped_staked_packets_count.swap(0, Ordering::Relaxed) as i64,
+                    i64
+                ),
+                (
+                    "dropped_unstaked_packets_count",
+                    self.dropped_unstaked_packets_count
+                        .swap(0, Ordering::Relaxed) as i64,
+                    i64
+                ),
             );
+            for (source, count) in self.take_top_dropped_sources(MAX_REPORTED_DROPPED_SOURCES) {
+                datapoint_info!(
+                    "qos-service-dropped-source",
+                    ("name", self.name, String),
+                    ("source", source.to_string(), String),
+                    ("dropped_packets_count", count as i64, i64),
+                );
+            }
         }
     }
 }
@@ -200,6 +424,7 @@ mod tests {
     use {
         super::*,
         itertools::Itertools,
+        solana_perf::packet::Packet,
         solana_runtime::{
             bank::Bank,
             genesis_utils::{create_genesis_config, GenesisConfigInfo},
@@ -210,8 +435,17 @@ mod tests {
             system_transaction,
         },
         solana_vote_program::vote_transaction,
+        std::net::SocketAddr,
     };
 
+    fn new_contact_info(ip: [u8; 4]) -> ContactInfo {
+        ContactInfo {
+            id: Pubkey::new_unique(),
+            gossip: SocketAddr::from((ip, 8001)),
+            ..ContactInfo::default()
+        }
+    }
+
     #[test]
     fn test_compute_transaction_costs() {
         solana_logger::setup();
@@ -235,7 +469,7 @@ mod tests {
         let txs = vec![transfer_tx.clone(), vote_tx.clone(), vote_tx, transfer_tx];
 
         let cost_model = Arc::new(RwLock::new(CostModel::default()));
-        let qos_service = QosService::new(cost_model.clone());
+        let qos_service = QosService::new(cost_model.clone(), "test");
         let txs_costs = qos_service.compute_transaction_costs(txs.iter(), false);
 
         // verify the size of txs_costs and its contents
@@ -287,7 +521,7 @@ mod tests {
         // make a vec of txs
         let txs = vec![transfer_tx.clone(), vote_tx.clone(), transfer_tx, vote_tx];
 
-        let qos_service = QosService::new(cost_model);
+        let qos_service = QosService::new(cost_model, "test");
         let txs_costs = qos_service.compute_transaction_costs(txs.iter(), false);
 
         // set cost tracker limit to fit 1 transfer tx, vote tx bypasses limit check
@@ -323,7 +557,7 @@ mod tests {
         }
 
         let cost_model = Arc::new(RwLock::new(CostModel::default()));
-        let qos_service = Arc::new(QosService::new(cost_model));
+        let qos_service = Arc::new(QosService::new(cost_model, "test"));
         let qos_service_1 = qos_service.clone();
         let qos_service_2 = qos_service.clone();
 
@@ -346,4 +580,115 @@ mod tests {
                 .load(Ordering::Relaxed)
         );
     }
+
+    #[test]
+    fn test_ingress_source_quotas() {
+        let contact_infos = vec![
+            new_contact_info([10, 0, 0, 1]),
+            new_contact_info([10, 0, 0, 2]),
+            new_contact_info([10, 0, 0, 3]),
+            // Nodes sharing an address share their stakes
+            new_contact_info([10, 0, 0, 1]),
+        ];
+        let staked_nodes: HashMap<Pubkey, u64> = vec![
+            (contact_infos[0].id, 2),
+            (contact_infos[1].id, 1),
+            (contact_infos[2].id, 0),
+            (contact_infos[3].id, 1),
+            // Staked nodes missing from gossip don't count
+            (Pubkey::new_unique(), 5),
+        ]
+        .into_iter()
+        .collect();
+        let ingress_quotas = IngressQuotas::new(&staked_nodes, &contact_infos);
+
+        let staked_source = IpAddr::from([10, 0, 0, 1]);
+        let small_staked_source = IpAddr::from([10, 0, 0, 2]);
+        let unstaked_sources = [IpAddr::from([10, 0, 0, 3]), IpAddr::from([10, 0, 0, 4])];
+        assert_eq!(ingress_quotas.total_stake, 4);
+        assert_eq!(ingress_quotas.stake(&staked_source), 3);
+        assert_eq!(ingress_quotas.stake(&unstaked_sources[0]), 0);
+
+        let packet_counts: HashMap<IpAddr, usize> = vec![
+            (staked_source, 2000),
+            (small_staked_source, 10),
+            (unstaked_sources[0], 1000),
+            (unstaked_sources[1], 1000),
+        ]
+        .into_iter()
+        .collect();
+        let quotas = ingress_quotas.source_quotas(&packet_counts, 1000);
+        assert_eq!(quotas[&staked_source], 675);
+        assert_eq!(quotas[&small_staked_source], 225);
+        // The unstaked sources share their 10% along with what the staked sources leave
+        assert_eq!(quotas[&unstaked_sources[0]], 157);
+        assert_eq!(quotas[&unstaked_sources[1]], 157);
+    }
+
+    #[test]
+    fn test_discard_packets_over_ingress_quotas() {
+        let staked_contact_info = new_contact_info([10, 0, 0, 1]);
+        let staked_nodes: HashMap<Pubkey, u64> =
+            vec![(staked_contact_info.id, 1)].into_iter().collect();
+        let qos_service = QosService::new(Arc::new(RwLock::new(CostModel::default())), "test");
+        *qos_service.ingress_quotas.write().unwrap() =
+            IngressQuotas::new(&staked_nodes, &[staked_contact_info]);
+
+        let mut batch = Packets::default();
+        batch.packets.resize(10, Packet::default());
+        for (index, packet) in batch.packets.iter_mut().enumerate() {
+            let ip = if index % 2 == 0 {
+                [10, 0, 0, 1]
+            } else {
+                [10, 0, 0, 2]
+            };
+            packet.meta.set_addr(&SocketAddr::from((ip, 8000)));
+        }
+        let mut batches = vec![batch];
+
+        assert_eq!(
+            qos_service.discard_packets_over_ingress_quotas(&mut batches, 4),
+            5
+        );
+        let kept: Vec<usize> = batches[0]
+            .packets
+            .iter()
+            .enumerate()
+            .filter(|(_, packet)| !packet.meta.discard)
+            .map(|(index, _)| index)
+            .collect();
+        // The staked source keeps its first 4 packets, the unstaked one only its first
+        assert_eq!(kept, vec![0, 1, 2, 4, 6]);
+        assert_eq!(
+            qos_service
+                .metrics
+                .dropped_staked_packets_count
+                .load(Ordering::Relaxed),
+            1
+        );
+        assert_eq!(
+            qos_service
+                .metrics
+                .dropped_unstaked_packets_count
+                .load(Ordering::Relaxed),
+            4
+        );
+        assert_eq!(
+            qos_service
+                .metrics
+                .ingress_sources_over_quota_count
+                .load(Ordering::Relaxed),
+            2
+        );
+
+        // The sources with the most dropped packets are reported, once
+        assert_eq!(
+            qos_service.metrics.take_top_dropped_sources(1),
+            vec![(IpAddr::from([10, 0, 0, 2]), 4)]
+        );
+        assert!(qos_service
+            .metrics
+            .take_top_dropped_sources(MAX_REPORTED_DROPPED_SOURCES)
+            .is_empty());
+    }
 }
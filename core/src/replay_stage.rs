@@ -26,6 +26,7 @@ use {
         voting_service::VoteOp,
         window_service::DuplicateSlotReceiver,
     },
+    solana_accountsdb_plugin_manager::block_metadata_notifier::BlockMetadataNotifier,
     solana_client::rpc_response::SlotUpdate,
     solana_entry::entry::VerifyRecyclers,
     solana_gossip::cluster_info::ClusterInfo,
@@ -38,6 +39,7 @@ use {
     solana_measure::measure::Measure,
     solana_metrics::inc_new_counter_info,
     solana_poh::poh_recorder::{PohRecorder, GRACE_TICKS_FACTOR, MAX_GRACE_SLOTS},
+    solana_remote_vote_signer::vote_signer::VoteAncestry,
     solana_rpc::{
         optimistically_confirmed_bank_tracker::{BankNotification, BankNotificationSender},
         rpc_subscriptions::RpcSubscriptions,
@@ -129,11 +131,14 @@ pub struct ReplayStageConfig {
     pub transaction_status_sender: Option<TransactionStatusSender>,
     pub rewards_recorder_sender: Option<RewardsRecorderSender>,
     pub cache_block_meta_sender: Option<CacheBlockMetaSender>,
+    pub block_metadata_notifier: Option<BlockMetadataNotifier>,
     pub bank_notification_sender: Option<BankNotificationSender>,
     pub wait_for_vote_to_start_leader: bool,
     pub ancestor_hashes_replay_update_sender: AncestorHashesReplayUpdateSender,
     pub tower_storage: Arc<dyn TowerStorage>,
     pub disable_epoch_boundary_optimization: bool,
+    /// Votes are signed by a remote vote signer when no authorized voter keypair is available
+    pub remote_vote_signing: bool,
 }
 
 #[derive(Default)]
@@ -340,11 +345,13 @@ impl ReplayStage {
             transaction_status_sender,
             rewards_recorder_sender,
             cache_block_meta_sender,
+            block_metadata_notifier,
             bank_notification_sender,
             wait_for_vote_to_start_leader,
             ancestor_hashes_replay_update_sender,
             tower_storage,
             disable_epoch_boundary_optimization,
+            remote_vote_signing,
         } = config;
 
         trace!("replay stage");
@@ -423,6 +430,7 @@ impl ReplayStage {
                         &replay_vote_sender,
                         &bank_notification_sender,
                         &rewards_recorder_sender,
+                        block_metadata_notifier.as_ref(),
                         &rpc_subscriptions,
                         &mut duplicate_slots_tracker,
                         &gossip_duplicate_confirmed_slots,
@@ -563,6 +571,7 @@ impl ReplayStage {
                                                     has_new_vote_been_rooted, &mut
                                                     last_vote_refresh_time,
                                                     &voting_sender,
+                                                    remote_vote_signing,
                                                     );
                         }
                     }
@@ -646,6 +655,7 @@ impl ReplayStage {
                             &voting_sender,
                             &mut epoch_slots_frozen_slots,
                             &drop_bank_sender,
+                            remote_vote_signing,
                         );
                     };
                     voting_time.stop();
@@ -1618,6 +1628,7 @@ impl ReplayStage {
         voting_sender: &Sender<VoteOp>,
         epoch_slots_frozen_slots: &mut EpochSlotsFrozenSlots,
         bank_drop_sender: &Sender<Vec<Arc<Bank>>>,
+        remote_vote_signing: bool,
     ) {
         if bank.is_empty() {
             inc_new_counter_info!("replay_stage-voted_empty_bank", 1);
@@ -1706,6 +1717,7 @@ impl ReplayStage {
             *has_new_vote_been_rooted,
             replay_timing,
             voting_sender,
+            remote_vote_signing,
         );
     }
 
@@ -1718,8 +1730,9 @@ impl ReplayStage {
         switch_fork_decision: &SwitchForkDecision,
         vote_signatures: &mut Vec<Signature>,
         has_new_vote_been_rooted: bool,
+        remote_vote_signing: bool,
     ) -> Option<Transaction> {
-        if authorized_voter_keypairs.is_empty() {
+        if authorized_voter_keypairs.is_empty() && !remote_vote_signing {
             return None;
         }
         let vote_account = match bank.get_vote_account(vote_account_pubkey) {
@@ -1765,32 +1778,35 @@ impl ReplayStage {
                 return None;
             };
 
-        let authorized_voter_keypair = match authorized_voter_keypairs
+        // The remote vote signer signs every vote, so that its protection database sees them all
+        let authorized_voter_keypair = if remote_vote_signing {
+            None
+        } else if let Some(authorized_voter_keypair) = authorized_voter_keypairs
             .iter()
             .find(|keypair| keypair.pubkey() == authorized_voter_pubkey)
         {
-            None => {
-                warn!("The authorized keypair {} for vote account {} is not available.  Unable to vote",
-                      authorized_voter_pubkey, vote_account_pubkey);
-                return None;
-            }
-            Some(authorized_voter_keypair) => authorized_voter_keypair,
+            Some(authorized_voter_keypair)
+        } else {
+            warn!(
+                "The authorized keypair {} for vote account {} is not available.  Unable to vote",
+                authorized_voter_pubkey, vote_account_pubkey
+            );
+            return None;
         };
 
         // Send our last few votes along with the new one
         let vote_ix = switch_fork_decision
-            .to_vote_instruction(
-                vote,
-                vote_account_pubkey,
-                &authorized_voter_keypair.pubkey(),
-            )
+            .to_vote_instruction(vote, vote_account_pubkey, &authorized_voter_pubkey)
             .expect("Switch threshold failure should not lead to voting");
 
         let mut vote_tx = Transaction::new_with_payer(&[vote_ix], Some(&node_keypair.pubkey()));
 
         let blockhash = bank.last_blockhash();
         vote_tx.partial_sign(&[node_keypair], blockhash);
-        vote_tx.partial_sign(&[authorized_voter_keypair.as_ref()], blockhash);
+        // Otherwise the voting service gets the vote signed by the remote vote signer
+        if let Some(authorized_voter_keypair) = authorized_voter_keypair {
+            vote_tx.partial_sign(&[authorized_voter_keypair.as_ref()], blockhash);
+        }
 
         if !has_new_vote_been_rooted {
             vote_signatures.push(vote_tx.signatures[0]);
@@ -1816,6 +1832,7 @@ impl ReplayStage {
         has_new_vote_been_rooted: bool,
         last_vote_refresh_time: &mut LastVoteRefreshTime,
         voting_sender: &Sender<VoteOp>,
+        remote_vote_signing: bool,
     ) {
         let last_voted_slot = tower.last_voted_slot();
         if last_voted_slot.is_none() {
@@ -1858,6 +1875,7 @@ impl ReplayStage {
             &SwitchForkDecision::SameFork,
             vote_signatures,
             has_new_vote_been_rooted,
+            remote_vote_signing,
         );
 
         if let Some(vote_tx) = vote_tx {
@@ -1874,6 +1892,7 @@ impl ReplayStage {
             );
             voting_sender
                 .send(VoteOp::RefreshVote {
+                    ancestry: Self::vote_ancestry(&vote_tx, heaviest_bank_on_same_fork),
                     tx: vote_tx,
                     last_voted_slot,
                 })
@@ -1895,6 +1914,7 @@ impl ReplayStage {
         has_new_vote_been_rooted: bool,
         replay_timing: &mut ReplayTiming,
         voting_sender: &Sender<VoteOp>,
+        remote_vote_signing: bool,
     ) {
         let mut generate_time = Measure::start("generate_vote");
         let vote_tx = Self::generate_vote_tx(
@@ -1906,6 +1926,7 @@ impl ReplayStage {
             switch_fork_decision,
             vote_signatures,
             has_new_vote_been_rooted,
+            remote_vote_signing,
         );
         generate_time.stop();
         replay_timing.generate_vote_us += generate_time.as_us();
@@ -1915,6 +1936,7 @@ impl ReplayStage {
             let tower_slots = tower.tower_slots();
             voting_sender
                 .send(VoteOp::PushVote {
+                    ancestry: Self::vote_ancestry(&vote_tx, bank),
                     tx: vote_tx,
                     tower_slots,
                     saved_tower,
@@ -1923,6 +1945,19 @@ impl ReplayStage {
         }
     }
 
+    // The remote vote signer checks the lockouts of a vote against the fork of the voted bank,
+    // whose slot history goes back past the root of a validator started from a newer snapshot
+    fn vote_ancestry(vote_tx: &Transaction, bank: &Bank) -> Option<VoteAncestry> {
+        if vote_tx.is_signed() {
+            None
+        } else {
+            Some(VoteAncestry {
+                ancestors: bank.ancestors.keys(),
+                slot_history: bank.get_slot_history(),
+            })
+        }
+    }
+
     fn update_commitment_cache(
         bank: Arc<Bank>,
         root: Slot,
@@ -1984,6 +2019,7 @@ impl ReplayStage {
         replay_vote_sender: &ReplayVoteSender,
         bank_notification_sender: &Option<BankNotificationSender>,
         rewards_recorder_sender: &Option<RewardsRecorderSender>,
+        block_metadata_notifier: Option<&BlockMetadataNotifier>,
         rpc_subscriptions: &Arc<RpcSubscriptions>,
         duplicate_slots_tracker: &mut DuplicateSlotsTracker,
         gossip_duplicate_confirmed_slots: &GossipDuplicateConfirmedSlots,
@@ -2149,6 +2185,17 @@ impl ReplayStage {
                     }
                 }
                 Self::record_rewards(&bank, rewards_recorder_sender);
+                if let Some(block_metadata_notifier) = block_metadata_notifier {
+                    let block_metadata_notifier = block_metadata_notifier.read().unwrap();
+                    block_metadata_notifier.notify_block_metadata(
+                        bank.slot(),
+                        bank.parent_slot(),
+                        &bank.last_blockhash().to_string(),
+                        &bank.rewards,
+                        Some(bank.clock().unix_timestamp),
+                        Some(bank.block_height()),
+                    )
+                }
             } else {
                 trace!(
                     "bank {} not completed tick_height: {}, max_tick_height: {}",
@@ -5633,6 +5680,7 @@ pub mod tests {
             has_new_vote_been_rooted,
             &mut ReplayTiming::default(),
             &voting_sender,
+            false,
         );
         let vote_info = voting_receiver
             .recv_timeout(Duration::from_secs(1))
@@ -5641,6 +5689,7 @@ pub mod tests {
             &cluster_info,
             &poh_recorder,
             &tower_storage,
+            None,
             vote_info,
             false,
         );
@@ -5672,6 +5721,7 @@ pub mod tests {
                 has_new_vote_been_rooted,
                 &mut last_vote_refresh_time,
                 &voting_sender,
+                false,
             );
 
             // No new votes have been submitted to gossip
@@ -5697,6 +5747,7 @@ pub mod tests {
             has_new_vote_been_rooted,
             &mut ReplayTiming::default(),
             &voting_sender,
+            false,
         );
         let vote_info = voting_receiver
             .recv_timeout(Duration::from_secs(1))
@@ -5705,6 +5756,7 @@ pub mod tests {
             &cluster_info,
             &poh_recorder,
             &tower_storage,
+            None,
             vote_info,
             false,
         );
@@ -5728,6 +5780,7 @@ pub mod tests {
             has_new_vote_been_rooted,
             &mut last_vote_refresh_time,
             &voting_sender,
+            false,
         );
 
         // No new votes have been submitted to gossip
@@ -5765,6 +5818,7 @@ pub mod tests {
             has_new_vote_been_rooted,
             &mut last_vote_refresh_time,
             &voting_sender,
+            false,
         );
         let vote_info = voting_receiver
             .recv_timeout(Duration::from_secs(1))
@@ -5773,6 +5827,7 @@ pub mod tests {
             &cluster_info,
             &poh_recorder,
             &tower_storage,
+            None,
             vote_info,
             false,
         );
@@ -5832,6 +5887,7 @@ pub mod tests {
             has_new_vote_been_rooted,
             &mut last_vote_refresh_time,
             &voting_sender,
+            false,
         );
 
         let votes = cluster_info.get_votes(&mut cursor);
@@ -5847,6 +5903,63 @@ pub mod tests {
         assert_eq!(tower.last_voted_slot().unwrap(), 1);
     }
 
+    #[test]
+    fn test_push_vote_remote_vote_signing() {
+        let ReplayBlockstoreComponents {
+            cluster_info,
+            mut tower,
+            my_pubkey,
+            vote_simulator,
+            ..
+        } = replay_blockstore_components(None, 10, None::<GenerateVotes>);
+        let VoteSimulator {
+            mut validator_keypairs,
+            bank_forks,
+            ..
+        } = vote_simulator;
+
+        let identity_keypair = cluster_info.keypair().clone();
+        let my_vote_keypair = vec![Arc::new(
+            validator_keypairs.remove(&my_pubkey).unwrap().vote_keypair,
+        )];
+        let my_vote_pubkey = my_vote_keypair[0].pubkey();
+        let bank0 = bank_forks.read().unwrap().get(0).unwrap().clone();
+        let (voting_sender, voting_receiver) = channel();
+
+        // The authorized voter keypair is never used with a remote vote signer
+        tower.record_bank_vote(&bank0, &my_vote_pubkey);
+        ReplayStage::push_vote(
+            &bank0,
+            &my_vote_pubkey,
+            &identity_keypair,
+            &my_vote_keypair,
+            &mut tower,
+            SavedTower::default(),
+            &SwitchForkDecision::SameFork,
+            &mut vec![],
+            false,
+            &mut ReplayTiming::default(),
+            &voting_sender,
+            true,
+        );
+        match voting_receiver
+            .recv_timeout(Duration::from_secs(1))
+            .unwrap()
+        {
+            VoteOp::PushVote { tx, ancestry, .. } => {
+                assert!(!tx.is_signed());
+                assert_eq!(
+                    tx.signatures[0],
+                    identity_keypair.sign_message(&tx.message_data())
+                );
+                let ancestry = ancestry.unwrap();
+                assert!(ancestry.contains(0));
+                assert!(!ancestry.contains(1));
+            }
+            VoteOp::RefreshVote { .. } => panic!("expected a new vote"),
+        }
+    }
+
     fn run_compute_and_select_forks(
         bank_forks: &RwLock<BankForks>,
         progress: &mut ProgressMap,
@@ -5,22 +5,30 @@
 //! transaction. All processing is done on the CPU by default and on a GPU
 //! if perf-libs are available
 
-use crate::sigverify;
+use crate::{qos_service::QosService, sigverify};
 use crossbeam_channel::{SendError, Sender as CrossbeamSender};
+use solana_gossip::cluster_info::ClusterInfo;
 use solana_measure::measure::Measure;
 use solana_perf::packet::Packets;
+use solana_runtime::bank_forks::BankForks;
 use solana_sdk::timing;
 use solana_streamer::streamer::{self, PacketReceiver, StreamerError};
 use std::{
     collections::HashMap,
-    sync::mpsc::{Receiver, RecvTimeoutError},
+    sync::{
+        mpsc::{Receiver, RecvTimeoutError},
+        Arc, RwLock,
+    },
     thread::{self, Builder, JoinHandle},
-    time::Instant,
+    time::{Duration, Instant},
 };
 use thiserror::Error;
 
 const MAX_SIGVERIFY_BATCH: usize = 10_000;
 
+// How often the stakes behind the ingress quotas are refreshed
+const INGRESS_QUOTAS_UPDATE_INTERVAL: Duration = Duration::from_secs(10);
+
 #[derive(Error, Debug)]
 pub enum SigVerifyServiceError {
     #[error("send packets batch error")]
@@ -43,6 +51,22 @@ pub trait SigVerifier {
 #[derive(Default, Clone)]
 pub struct DisabledSigVerifier {}
 
+/// Applies the stake-weighted ingress quotas of a `QosService` to the batches that exceed
+/// `MAX_SIGVERIFY_BATCH`, so that unstaked sources can't crowd out the staked ones
+pub struct IngressQos {
+    pub qos_service: Arc<QosService>,
+    pub bank_forks: Arc<RwLock<BankForks>>,
+    pub cluster_info: Arc<ClusterInfo>,
+}
+
+impl IngressQos {
+    fn update_ingress_quotas(&self) {
+        let root_bank = self.bank_forks.read().unwrap().root_bank();
+        self.qos_service
+            .update_ingress_quotas(&root_bank, &self.cluster_info);
+    }
+}
+
 #[derive(Default)]
 struct SigVerifierStats {
     recv_batches_us_hist: histogram::Histogram, // time to call recv_batch
@@ -133,7 +157,23 @@ impl SigVerifyStage {
         verified_sender: CrossbeamSender<Vec<Packets>>,
         verifier: T,
     ) -> Self {
-        let thread_hdl = Self::verifier_services(packet_receiver, verified_sender, verifier);
+        let thread_hdl = Self::verifier_services(packet_receiver, verified_sender, verifier, None);
+        Self { thread_hdl }
+    }
+
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new_with_ingress_qos<T: SigVerifier + 'static + Send + Clone>(
+        packet_receiver: Receiver<Packets>,
+        verified_sender: CrossbeamSender<Vec<Packets>>,
+        verifier: T,
+        ingress_qos: IngressQos,
+    ) -> Self {
+        let thread_hdl = Self::verifier_services(
+            packet_receiver,
+            verified_sender,
+            verifier,
+            Some(ingress_qos),
+        );
         Self { thread_hdl }
     }
 
@@ -141,6 +181,9 @@ impl SigVerifyStage {
         let mut received_ips = HashMap::new();
         for (batch_index, batch) in batches.iter().enumerate() {
             for (packet_index, packets) in batch.packets.iter().enumerate() {
+                if packets.meta.discard {
+                    continue;
+                }
                 let e = received_ips
                     .entry(packets.meta.addr().ip())
                     .or_insert_with(Vec::new);
@@ -170,6 +213,7 @@ impl SigVerifyStage {
         recvr: &PacketReceiver,
         sendr: &CrossbeamSender<Vec<Packets>>,
         verifier: &T,
+        ingress_qos: Option<&IngressQos>,
         stats: &mut SigVerifierStats,
     ) -> Result<()> {
         let (mut batches, num_packets, recv_duration) = streamer::recv_batch(recvr)?;
@@ -181,7 +225,16 @@ impl SigVerifyStage {
             num_packets,
         );
         if num_packets > MAX_SIGVERIFY_BATCH {
-            Self::discard_excess_packets(&mut batches, MAX_SIGVERIFY_BATCH);
+            let num_discarded = ingress_qos
+                .map(|ingress_qos| {
+                    ingress_qos
+                        .qos_service
+                        .discard_packets_over_ingress_quotas(&mut batches, MAX_SIGVERIFY_BATCH)
+                })
+                .unwrap_or_default();
+            if num_packets - num_discarded > MAX_SIGVERIFY_BATCH {
+                Self::discard_excess_packets(&mut batches, MAX_SIGVERIFY_BATCH);
+            }
         }
 
         let mut verify_batch_time = Measure::start("sigverify_batch_time");
@@ -217,16 +270,30 @@ impl SigVerifyStage {
         packet_receiver: PacketReceiver,
         verified_sender: CrossbeamSender<Vec<Packets>>,
         verifier: &T,
+        ingress_qos: Option<IngressQos>,
     ) -> JoinHandle<()> {
         let verifier = verifier.clone();
         let mut stats = SigVerifierStats::default();
         let mut last_print = Instant::now();
+        let mut last_ingress_quotas_update: Option<Instant> = None;
         Builder::new()
             .name("solana-verifier".to_string())
             .spawn(move || loop {
-                if let Err(e) =
-                    Self::verifier(&packet_receiver, &verified_sender, &verifier, &mut stats)
-                {
+                if let Some(ingress_qos) = &ingress_qos {
+                    if last_ingress_quotas_update
+                        .map_or(true, |last| last.elapsed() > INGRESS_QUOTAS_UPDATE_INTERVAL)
+                    {
+                        ingress_qos.update_ingress_quotas();
+                        last_ingress_quotas_update = Some(Instant::now());
+                    }
+                }
+                if let Err(e) = Self::verifier(
+                    &packet_receiver,
+                    &verified_sender,
+                    &verifier,
+                    ingress_qos.as_ref(),
+                    &mut stats,
+                ) {
                     match e {
                         SigVerifyServiceError::Streamer(StreamerError::RecvTimeout(
                             RecvTimeoutError::Disconnected,
@@ -253,8 +320,9 @@ impl SigVerifyStage {
         packet_receiver: PacketReceiver,
         verified_sender: CrossbeamSender<Vec<Packets>>,
         verifier: T,
+        ingress_qos: Option<IngressQos>,
     ) -> JoinHandle<()> {
-        Self::verifier_service(packet_receiver, verified_sender, &verifier)
+        Self::verifier_service(packet_receiver, verified_sender, &verifier, ingress_qos)
     }
 
     pub fn join(self) -> thread::Result<()> {
@@ -3,15 +3,23 @@ use {
     solana_sdk::{
         pubkey::Pubkey,
         signature::{Signature, Signer},
+        timing::timestamp,
     },
     std::{
         fs::{self, File},
         io::{self, BufReader},
-        path::PathBuf,
-        sync::RwLock,
+        path::{Path, PathBuf},
+        sync::{
+            atomic::{AtomicU64, AtomicUsize, Ordering},
+            RwLock,
+        },
     },
 };
 
+// Listing the tower history on every vote is too slow, it is pruned once every this many stores
+// and holds up to this many towers more than retained in between
+pub(crate) const TOWER_HISTORY_PRUNE_INTERVAL: usize = 64;
+
 #[frozen_abi(digest = "Gaxfwvx5MArn52mKZQgzHmDCyn5YfCuTHvp5Et3rFfpp")]
 #[derive(Default, Clone, Serialize, Deserialize, Debug, PartialEq, AbiExample)]
 pub struct SavedTower {
@@ -59,11 +67,30 @@ impl SavedTower {
                 Ok(tower)
             })
     }
+
+    /// Decodes the tower without verifying its signature, which is only meant for tools
+    /// inspecting saved towers
+    pub fn tower_unverified(&self) -> Result<Tower> {
+        bincode::deserialize(&self.data).map_err(|e| e.into())
+    }
+}
+
+/// A saved tower retained by a `TowerStorage` along with the time it was stored at
+#[derive(Clone, Debug, PartialEq)]
+pub struct TowerHistoryEntry {
+    /// Milliseconds since the UNIX epoch
+    pub timestamp: u64,
+    pub saved_tower: SavedTower,
 }
 
 pub trait TowerStorage: Sync + Send {
     fn load(&self, node_pubkey: &Pubkey) -> Result<SavedTower>;
     fn store(&self, saved_tower: &SavedTower) -> Result<()>;
+
+    /// The last saved towers, oldest first, if the storage is configured to retain them
+    fn load_history(&self, _node_pubkey: &Pubkey) -> Result<Vec<TowerHistoryEntry>> {
+        Ok(vec![])
+    }
 }
 
 #[derive(Debug, Default, Clone, PartialEq)]
@@ -82,14 +109,25 @@ impl TowerStorage for NullTowerStorage {
     }
 }
 
-#[derive(Debug, Default, Clone, PartialEq)]
+#[derive(Debug, Default)]
 pub struct FileTowerStorage {
     pub tower_path: PathBuf,
+    /// Number of the last saved towers to also keep in `history_path()`
+    pub history_len: usize,
+    history_stores: AtomicUsize,
 }
 
 impl FileTowerStorage {
     pub fn new(tower_path: PathBuf) -> Self {
-        Self { tower_path }
+        Self::new_with_history(tower_path, 0)
+    }
+
+    pub fn new_with_history(tower_path: PathBuf, history_len: usize) -> Self {
+        Self {
+            tower_path,
+            history_len,
+            history_stores: AtomicUsize::default(),
+        }
     }
 
     pub fn filename(&self, node_pubkey: &Pubkey) -> PathBuf {
@@ -97,6 +135,63 @@ impl FileTowerStorage {
             .join(format!("tower-{}", node_pubkey))
             .with_extension("bin")
     }
+
+    pub fn history_path(&self, node_pubkey: &Pubkey) -> PathBuf {
+        self.tower_path
+            .join(format!("tower-{}-history", node_pubkey))
+    }
+
+    // The files are named after their zero-padded timestamp so that they sort by age
+    fn history_filename(history_path: &Path, timestamp: u64) -> PathBuf {
+        history_path
+            .join(format!("{:020}", timestamp))
+            .with_extension("bin")
+    }
+
+    fn history_timestamps(history_path: &Path) -> io::Result<Vec<u64>> {
+        let mut timestamps = vec![];
+        for entry in fs::read_dir(history_path)? {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("bin") {
+                continue;
+            }
+            if let Some(timestamp) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse().ok())
+            {
+                timestamps.push(timestamp);
+            }
+        }
+        timestamps.sort_unstable();
+        Ok(timestamps)
+    }
+
+    fn store_history(&self, saved_tower: &SavedTower) -> Result<()> {
+        let history_path = self.history_path(&saved_tower.node_pubkey);
+        fs::create_dir_all(&history_path)?;
+
+        // Don't overwrite a tower stored within the same millisecond
+        let mut timestamp = timestamp();
+        while Self::history_filename(&history_path, timestamp).exists() {
+            timestamp += 1;
+        }
+        let filename = Self::history_filename(&history_path, timestamp);
+        let new_filename = filename.with_extension("bin.new");
+        {
+            let mut file = File::create(&new_filename)?;
+            bincode::serialize_into(&mut file, saved_tower)?;
+        }
+        fs::rename(&new_filename, &filename)?;
+
+        if self.history_stores.fetch_add(1, Ordering::Relaxed) % TOWER_HISTORY_PRUNE_INTERVAL == 0 {
+            let timestamps = Self::history_timestamps(&history_path)?;
+            for timestamp in timestamps.iter().rev().skip(self.history_len) {
+                fs::remove_file(Self::history_filename(&history_path, *timestamp))?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl TowerStorage for FileTowerStorage {
@@ -125,14 +220,48 @@ impl TowerStorage for FileTowerStorage {
         }
         fs::rename(&new_filename, &filename)?;
         // self.path.parent().sync_all() hurts performance same as the above sync
+
+        if self.history_len > 0 {
+            // The history is only there for diagnostics, a failure to keep it must not stop
+            // the validator from voting
+            if let Err(err) = self.store_history(saved_tower) {
+                warn!("Unable to save tower history: {:?}", err);
+            }
+        }
         Ok(())
     }
+
+    fn load_history(&self, node_pubkey: &Pubkey) -> Result<Vec<TowerHistoryEntry>> {
+        let history_path = self.history_path(node_pubkey);
+        if !history_path.exists() {
+            return Ok(vec![]);
+        }
+        let timestamps = Self::history_timestamps(&history_path)?;
+        // The towers not pruned yet are left out
+        let num_stale = timestamps.len().saturating_sub(self.history_len);
+        timestamps
+            .into_iter()
+            .skip(num_stale)
+            .map(|timestamp| -> Result<TowerHistoryEntry> {
+                let file = File::open(Self::history_filename(&history_path, timestamp))?;
+                let mut stream = BufReader::new(file);
+                let saved_tower = bincode::deserialize_from(&mut stream)?;
+                Ok(TowerHistoryEntry {
+                    timestamp,
+                    saved_tower,
+                })
+            })
+            .collect()
+    }
 }
 
 pub struct EtcdTowerStorage {
     client: RwLock<etcd_client::Client>,
     instance_id: [u8; 8],
     runtime: tokio::runtime::Runtime,
+    history_len: usize,
+    last_history_timestamp: AtomicU64,
+    history_stores: AtomicUsize,
 }
 
 pub struct EtcdTlsConfig {
@@ -146,6 +275,15 @@ impl EtcdTowerStorage {
     pub fn new<E: AsRef<str>, S: AsRef<[E]>>(
         endpoints: S,
         tls_config: Option<EtcdTlsConfig>,
+    ) -> Result<Self> {
+        Self::new_with_history(endpoints, tls_config, 0)
+    }
+
+    /// Also retain the last `history_len` saved towers, under `<node_pubkey>/tower-history/`
+    pub fn new_with_history<E: AsRef<str>, S: AsRef<[E]>>(
+        endpoints: S,
+        tls_config: Option<EtcdTlsConfig>,
+        history_len: usize,
     ) -> Result<Self> {
         let runtime = tokio::runtime::Builder::new_current_thread()
             .enable_io()
@@ -177,8 +315,11 @@ impl EtcdTowerStorage {
 
         Ok(Self {
             client: RwLock::new(client),
-            instance_id: solana_sdk::timing::timestamp().to_le_bytes(),
+            instance_id: timestamp().to_le_bytes(),
             runtime,
+            history_len,
+            last_history_timestamp: AtomicU64::default(),
+            history_stores: AtomicUsize::default(),
         })
     }
 
@@ -188,6 +329,45 @@ impl EtcdTowerStorage {
         (instance_key, tower_key)
     }
 
+    fn get_history_prefix(node_pubkey: &Pubkey) -> String {
+        format!("{}/tower-history/", node_pubkey)
+    }
+
+    fn prune_history(
+        &self,
+        client: &mut etcd_client::Client,
+        node_pubkey: &Pubkey,
+    ) -> std::result::Result<(), etcd_client::Error> {
+        let history_prefix = Self::get_history_prefix(node_pubkey);
+        let response = self.runtime.block_on(async {
+            client
+                .get(
+                    history_prefix.clone(),
+                    Some(
+                        etcd_client::GetOptions::new()
+                            .with_prefix()
+                            .with_keys_only(),
+                    ),
+                )
+                .await
+        })?;
+
+        // Keys are returned in ascending order, oldest first
+        let kvs = response.kvs();
+        if kvs.len() > self.history_len {
+            let oldest_retained_key = kvs[kvs.len() - self.history_len].key().to_vec();
+            self.runtime.block_on(async {
+                client
+                    .delete(
+                        history_prefix,
+                        Some(etcd_client::DeleteOptions::new().with_range(oldest_retained_key)),
+                    )
+                    .await
+            })?;
+        }
+        Ok(())
+    }
+
     fn etdc_to_tower_error(error: etcd_client::Error) -> TowerError {
         TowerError::IoError(io::Error::new(io::ErrorKind::Other, error.to_string()))
     }
@@ -252,17 +432,30 @@ impl TowerStorage for EtcdTowerStorage {
         let (instance_key, tower_key) = Self::get_keys(&saved_tower.node_pubkey);
         let mut client = self.client.write().unwrap();
 
+        let data = bincode::serialize(saved_tower)?;
+        let mut ops = vec![etcd_client::TxnOp::put(tower_key, data.clone(), None)];
+        if self.history_len > 0 {
+            // Don't overwrite a tower stored within the same millisecond, the stores are
+            // serialized by the client lock
+            let history_timestamp =
+                timestamp().max(self.last_history_timestamp.load(Ordering::Relaxed) + 1);
+            self.last_history_timestamp
+                .store(history_timestamp, Ordering::Relaxed);
+            // Zero-padded so that the keys sort by age
+            let history_key = format!(
+                "{}{:020}",
+                Self::get_history_prefix(&saved_tower.node_pubkey),
+                history_timestamp
+            );
+            ops.push(etcd_client::TxnOp::put(history_key, data, None));
+        }
         let txn = etcd_client::Txn::new()
             .when(vec![etcd_client::Compare::value(
                 instance_key,
                 etcd_client::CompareOp::Equal,
                 self.instance_id,
             )])
-            .and_then(vec![etcd_client::TxnOp::put(
-                tower_key,
-                bincode::serialize(saved_tower)?,
-                None,
-            )]);
+            .and_then(ops);
 
         let response = self
             .runtime
@@ -279,6 +472,60 @@ impl TowerStorage for EtcdTowerStorage {
                 format!("Lost etcd instance lock for {}", saved_tower.node_pubkey),
             )));
         }
+
+        if self.history_len > 0
+            && self.history_stores.fetch_add(1, Ordering::Relaxed) % TOWER_HISTORY_PRUNE_INTERVAL
+                == 0
+        {
+            // The history is only there for diagnostics, a failure to prune it must not stop
+            // the validator from voting
+            if let Err(err) = self.prune_history(&mut client, &saved_tower.node_pubkey) {
+                warn!("Failed to prune etcd tower history: {}", err);
+            }
+        }
         Ok(())
     }
+
+    fn load_history(&self, node_pubkey: &Pubkey) -> Result<Vec<TowerHistoryEntry>> {
+        let history_prefix = Self::get_history_prefix(node_pubkey);
+        let mut client = self.client.write().unwrap();
+
+        let response = self
+            .runtime
+            .block_on(async {
+                client
+                    .get(
+                        history_prefix.clone(),
+                        Some(etcd_client::GetOptions::new().with_prefix()),
+                    )
+                    .await
+            })
+            .map_err(|err| {
+                error!("Failed to read etcd tower history: {}", err);
+                Self::etdc_to_tower_error(err)
+            })?;
+
+        // Keys are returned in ascending order, the towers not pruned yet are left out
+        let kvs = response.kvs();
+        kvs.iter()
+            .skip(kvs.len().saturating_sub(self.history_len))
+            .map(|kv| -> Result<TowerHistoryEntry> {
+                let timestamp = kv
+                    .key_str()
+                    .ok()
+                    .and_then(|key| key.strip_prefix(&history_prefix))
+                    .and_then(|timestamp| timestamp.parse().ok())
+                    .ok_or_else(|| {
+                        TowerError::IoError(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "Invalid etcd tower history key",
+                        ))
+                    })?;
+                Ok(TowerHistoryEntry {
+                    timestamp,
+                    saved_tower: bincode::deserialize_from(kv.value())?,
+                })
+            })
+            .collect()
+    }
 }
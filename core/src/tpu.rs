@@ -2,15 +2,16 @@
 //! multi-stage transaction processing pipeline in software.
 
 use crate::{
-    banking_stage::BankingStage,
+    banking_stage::{BankingStage, TransactionScheduling},
     broadcast_stage::{BroadcastStage, BroadcastStageType, RetransmitSlotsReceiver},
     cluster_info_vote_listener::{
         ClusterInfoVoteListener, GossipDuplicateConfirmedSlotsSender, GossipVerifiedVoteHashSender,
         VerifiedVoteSender, VoteTracker,
     },
     fetch_stage::FetchStage,
+    qos_service::QosService,
     sigverify::TransactionSigVerifier,
-    sigverify_stage::SigVerifyStage,
+    sigverify_stage::{IngressQos, SigVerifyStage},
 };
 use crossbeam_channel::unbounded;
 use solana_gossip::cluster_info::ClusterInfo;
@@ -73,6 +74,7 @@ impl Tpu {
         tpu_coalesce_ms: u64,
         cluster_confirmed_slot_sender: GossipDuplicateConfirmedSlotsSender,
         cost_model: &Arc<RwLock<CostModel>>,
+        transaction_scheduling: TransactionScheduling,
     ) -> Self {
         let (packet_sender, packet_receiver) = channel();
         let (vote_packet_sender, vote_packet_receiver) = channel();
@@ -90,7 +92,17 @@ impl Tpu {
 
         let sigverify_stage = {
             let verifier = TransactionSigVerifier::default();
-            SigVerifyStage::new(packet_receiver, verified_sender, verifier)
+            let ingress_qos = IngressQos {
+                qos_service: Arc::new(QosService::new(cost_model.clone(), "sigverify_stage")),
+                bank_forks: bank_forks.clone(),
+                cluster_info: cluster_info.clone(),
+            };
+            SigVerifyStage::new_with_ingress_qos(
+                packet_receiver,
+                verified_sender,
+                verifier,
+                ingress_qos,
+            )
         };
 
         let (verified_tpu_vote_packets_sender, verified_tpu_vote_packets_receiver) = unbounded();
@@ -122,7 +134,7 @@ impl Tpu {
             cluster_confirmed_slot_sender,
         );
 
-        let banking_stage = BankingStage::new(
+        let banking_stage = BankingStage::new_with_scheduling(
             cluster_info,
             poh_recorder,
             verified_receiver,
@@ -131,6 +143,7 @@ impl Tpu {
             transaction_status_sender,
             replay_vote_sender,
             cost_model.clone(),
+            transaction_scheduling,
         );
 
         let broadcast_stage = broadcast_type.new_broadcast_stage(
@@ -0,0 +1,84 @@
+//! The `transaction_notifier_service` passes the transactions executed during replay to the
+//! AccountsDb plugins interested in them. Every message is then forwarded on to the
+//! `TransactionStatusService` when RPC transaction history is enabled as well.
+//!
+//! Replay always records the logs and inner instructions of the transactions for the plugins,
+//! they are dropped before forwarding unless they are to be stored for RPC as well.
+
+use {
+    crossbeam_channel::{Receiver, RecvTimeoutError, Sender},
+    solana_accountsdb_plugin_manager::transaction_notifier::TransactionNotifier,
+    solana_ledger::blockstore_processor::{TransactionStatusBatch, TransactionStatusMessage},
+    std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread::{self, Builder, JoinHandle},
+        time::Duration,
+    },
+};
+
+pub struct TransactionNotifierService {
+    thread_hdl: JoinHandle<()>,
+}
+
+impl TransactionNotifierService {
+    pub fn new(
+        transaction_status_receiver: Receiver<TransactionStatusMessage>,
+        transaction_status_forward_sender: Option<Sender<TransactionStatusMessage>>,
+        enable_cpi_and_log_storage: bool,
+        transaction_notifier: TransactionNotifier,
+        exit: &Arc<AtomicBool>,
+    ) -> Self {
+        let exit = exit.clone();
+        let thread_hdl = Builder::new()
+            .name("solana-transaction-notifier".to_string())
+            .spawn(move || loop {
+                if exit.load(Ordering::Relaxed) {
+                    break;
+                }
+                match transaction_status_receiver.recv_timeout(Duration::from_secs(1)) {
+                    Err(RecvTimeoutError::Disconnected) => {
+                        break;
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Ok(mut message) => {
+                        if let TransactionStatusMessage::Batch(batch) = &mut message {
+                            Self::notify_transactions(batch, &transaction_notifier);
+                            if !enable_cpi_and_log_storage {
+                                batch.inner_instructions = None;
+                                batch.transaction_logs = None;
+                            }
+                        }
+                        if let Some(sender) = &transaction_status_forward_sender {
+                            if let Err(err) = sender.send(message) {
+                                trace!("transaction status forward failed: {:?}", err);
+                            }
+                        }
+                    }
+                }
+            })
+            .unwrap();
+        Self { thread_hdl }
+    }
+
+    fn notify_transactions(
+        batch: &TransactionStatusBatch,
+        transaction_notifier: &TransactionNotifier,
+    ) {
+        let slot = batch.bank.slot();
+        for (transaction, transaction_status_meta) in batch.committed_transaction_status_metas() {
+            transaction_notifier.read().unwrap().notify_transaction(
+                slot,
+                transaction.signature(),
+                &transaction_status_meta,
+                transaction,
+            );
+        }
+    }
+
+    pub fn join(self) -> thread::Result<()> {
+        self.thread_hdl.join()
+    }
+}
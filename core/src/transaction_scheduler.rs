@@ -0,0 +1,464 @@
+//! The `transaction_scheduler` buffers the transactions waiting to be processed by the banking
+//! stage, highest priority first, and keeps track of the accounts locked by the transaction
+//! batches that the banking stage workers are processing, so that the batches it hands out never
+//! contend with each other for account locks.
+//!
+use {
+    solana_perf::packet::Packet,
+    solana_sdk::{pubkey::Pubkey, transaction::SanitizedTransaction},
+    std::{
+        cmp::Reverse,
+        collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
+    },
+};
+
+/// The read and write locks held on accounts by scheduled transactions
+#[derive(Debug, Default)]
+pub struct AccountLocks {
+    write_locks: HashSet<Pubkey>,
+    read_locks: HashMap<Pubkey, u64>,
+}
+
+impl AccountLocks {
+    pub fn can_lock(&self, writable: &[Pubkey], readonly: &[Pubkey]) -> bool {
+        writable
+            .iter()
+            .all(|key| !self.write_locks.contains(key) && !self.read_locks.contains_key(key))
+            && readonly.iter().all(|key| !self.write_locks.contains(key))
+    }
+
+    fn lock(&mut self, writable: &[Pubkey], readonly: &[Pubkey]) {
+        self.write_locks.extend(writable.iter().copied());
+        for key in readonly {
+            *self.read_locks.entry(*key).or_default() += 1;
+        }
+    }
+
+    fn unlock(&mut self, writable: &[Pubkey], readonly: &[Pubkey]) {
+        for key in writable {
+            self.write_locks.remove(key);
+        }
+        for key in readonly {
+            if let Entry::Occupied(mut entry) = self.read_locks.entry(*key) {
+                *entry.get_mut() -= 1;
+                if *entry.get() == 0 {
+                    entry.remove();
+                }
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.write_locks.is_empty() && self.read_locks.is_empty()
+    }
+}
+
+/// A buffered transaction along with the packet it was read from. It is sanitized and its
+/// account locks are resolved once, when it is buffered.
+#[derive(Debug)]
+pub struct PendingTransaction {
+    pub transaction: SanitizedTransaction,
+    pub packet: Packet,
+    pub priority: u64,
+    pub forwarded: bool,
+    writable: Vec<Pubkey>,
+    readonly: Vec<Pubkey>,
+}
+
+impl PendingTransaction {
+    pub fn new(
+        transaction: SanitizedTransaction,
+        packet: Packet,
+        priority: u64,
+        demote_program_write_locks: bool,
+    ) -> Self {
+        let account_locks = transaction.get_account_locks(demote_program_write_locks);
+        let writable = account_locks.writable.into_iter().copied().collect();
+        let readonly = account_locks.readonly.into_iter().copied().collect();
+        Self {
+            transaction,
+            packet,
+            priority,
+            forwarded: false,
+            writable,
+            readonly,
+        }
+    }
+}
+
+/// What the scheduler keeps of a transaction handed to a worker, to release its locks and to
+/// buffer it again if it has to be retried
+#[derive(Debug)]
+struct ScheduledTransaction {
+    packet: Packet,
+    priority: u64,
+    writable: Vec<Pubkey>,
+    readonly: Vec<Pubkey>,
+}
+
+/// A conflict-free batch of transactions for a banking stage worker
+#[derive(Debug)]
+pub struct ScheduledBatch {
+    pub id: u64,
+    pub transactions: Vec<SanitizedTransaction>,
+}
+
+/// Sent back by a worker once it is done with a `ScheduledBatch`, along with the transactions to
+/// retry and their index in the batch
+#[derive(Debug)]
+pub struct CompletedBatch {
+    pub id: u64,
+    pub retryable_transactions: Vec<(usize, SanitizedTransaction)>,
+}
+
+/// Counts of a `TransactionScheduler::schedule` pass
+#[derive(Debug, Default, PartialEq)]
+pub struct ScheduleStats {
+    pub scheduled_transactions_count: usize,
+    pub conflicts_count: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct TransactionScheduler {
+    locks: AccountLocks,
+    /// Buffered transactions from the highest to the lowest priority, the oldest first among
+    /// equals
+    pending: BTreeMap<(Reverse<u64>, u64), PendingTransaction>,
+    next_pending_id: u64,
+    in_flight: HashMap<u64, Vec<ScheduledTransaction>>,
+    next_batch_id: u64,
+}
+
+impl TransactionScheduler {
+    /// Buffer `transaction` until its accounts are free. If `capacity` transactions are already
+    /// buffered, the lowest priority one, the newest among equals, is evicted to make room and
+    /// returned. `transaction` itself is returned as an error if none has a lower priority.
+    pub fn buffer(
+        &mut self,
+        transaction: PendingTransaction,
+        capacity: usize,
+    ) -> Result<Option<PendingTransaction>, PendingTransaction> {
+        let mut evicted = None;
+        if self.pending.len() >= capacity {
+            match self.pending.keys().next_back().copied() {
+                Some(lowest @ (Reverse(priority), _)) if priority <= transaction.priority => {
+                    evicted = self.pending.remove(&lowest);
+                }
+                _ => return Err(transaction),
+            }
+        }
+        let id = self.next_pending_id;
+        self.next_pending_id = self.next_pending_id.wrapping_add(1);
+        self.pending
+            .insert((Reverse(transaction.priority), id), transaction);
+        Ok(evicted)
+    }
+
+    /// Move the buffered transactions whose accounts are free into batches of up to
+    /// `max_batch_size` transactions, highest priority first, and lock their accounts until the
+    /// batches complete. No new batch is started once `max_in_flight_batches` are in flight.
+    pub fn schedule(
+        &mut self,
+        max_in_flight_batches: usize,
+        max_batch_size: usize,
+    ) -> (Vec<ScheduledBatch>, ScheduleStats) {
+        let mut stats = ScheduleStats::default();
+        let mut batches: Vec<(u64, Vec<(Reverse<u64>, u64)>)> = vec![];
+        let mut filling_batch = false;
+        for (key, transaction) in self.pending.iter() {
+            if !filling_batch && self.in_flight.len() >= max_in_flight_batches {
+                break;
+            }
+            if !self
+                .locks
+                .can_lock(&transaction.writable, &transaction.readonly)
+            {
+                stats.conflicts_count += 1;
+                continue;
+            }
+            self.locks
+                .lock(&transaction.writable, &transaction.readonly);
+            if !filling_batch {
+                let batch_id = self.next_batch_id;
+                self.next_batch_id = self.next_batch_id.wrapping_add(1);
+                batches.push((batch_id, vec![]));
+                filling_batch = true;
+            }
+            let (batch_id, keys) = batches.last_mut().unwrap();
+            self.in_flight
+                .entry(*batch_id)
+                .or_default()
+                .push(ScheduledTransaction {
+                    packet: transaction.packet.clone(),
+                    priority: transaction.priority,
+                    writable: transaction.writable.clone(),
+                    readonly: transaction.readonly.clone(),
+                });
+            keys.push(*key);
+            filling_batch = keys.len() < max_batch_size;
+        }
+
+        let batches: Vec<ScheduledBatch> = batches
+            .into_iter()
+            .map(|(id, keys)| ScheduledBatch {
+                id,
+                transactions: keys
+                    .iter()
+                    .filter_map(|key| self.pending.remove(key))
+                    .map(|pending| pending.transaction)
+                    .collect(),
+            })
+            .collect();
+        stats.scheduled_transactions_count =
+            batches.iter().map(|batch| batch.transactions.len()).sum();
+        (batches, stats)
+    }
+
+    /// Release the account locks of a batch once its worker is done with it and buffer its
+    /// `retryable_transactions` again. Returns the retried transactions evicted to respect
+    /// `capacity`.
+    pub fn complete(
+        &mut self,
+        batch_id: u64,
+        retryable_transactions: Vec<(usize, SanitizedTransaction)>,
+        capacity: usize,
+    ) -> Vec<PendingTransaction> {
+        let scheduled = match self.in_flight.remove(&batch_id) {
+            Some(scheduled) => scheduled,
+            None => return vec![],
+        };
+        for transaction in &scheduled {
+            self.locks
+                .unlock(&transaction.writable, &transaction.readonly);
+        }
+
+        let mut scheduled: Vec<Option<ScheduledTransaction>> =
+            scheduled.into_iter().map(Some).collect();
+        retryable_transactions
+            .into_iter()
+            .filter_map(|(index, transaction)| {
+                let ScheduledTransaction {
+                    packet,
+                    priority,
+                    writable,
+                    readonly,
+                } = scheduled.get_mut(index)?.take()?;
+                self.buffer(
+                    PendingTransaction {
+                        transaction,
+                        packet,
+                        priority,
+                        forwarded: false,
+                        writable,
+                        readonly,
+                    },
+                    capacity,
+                )
+                .unwrap_or_else(Some)
+            })
+            .collect()
+    }
+
+    /// The buffered transactions, from the highest to the lowest priority
+    pub fn pending(&self) -> impl Iterator<Item = &PendingTransaction> {
+        self.pending.values()
+    }
+
+    pub fn pending_mut(&mut self) -> impl Iterator<Item = &mut PendingTransaction> {
+        self.pending.values_mut()
+    }
+
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn clear_pending(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Number of batches holding account locks
+    pub fn in_flight_batches(&self) -> usize {
+        self.in_flight.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_sdk::{
+            hash::Hash,
+            instruction::{AccountMeta, Instruction},
+            message::Message,
+            signature::{Keypair, Signer},
+            transaction::Transaction,
+        },
+    };
+
+    fn make_transaction(
+        payer: &Keypair,
+        writable: &[Pubkey],
+        readonly: &[Pubkey],
+        priority: u64,
+    ) -> PendingTransaction {
+        let accounts = writable
+            .iter()
+            .map(|key| AccountMeta::new(*key, false))
+            .chain(
+                readonly
+                    .iter()
+                    .map(|key| AccountMeta::new_readonly(*key, false)),
+            )
+            .collect();
+        let instruction = Instruction::new_with_bytes(Pubkey::new_unique(), &[], accounts);
+        let message = Message::new(&[instruction], Some(&payer.pubkey()));
+        let transaction = SanitizedTransaction::from_transaction_for_tests(Transaction::new(
+            &[payer],
+            message,
+            Hash::default(),
+        ));
+        PendingTransaction::new(transaction, Packet::default(), priority, true)
+    }
+
+    fn message_hashes(batch: &ScheduledBatch) -> Vec<Hash> {
+        batch
+            .transactions
+            .iter()
+            .map(|transaction| *transaction.message_hash())
+            .collect()
+    }
+
+    #[test]
+    fn test_account_locks() {
+        let mut locks = AccountLocks::default();
+        let a = Pubkey::new_unique();
+        let b = Pubkey::new_unique();
+
+        locks.lock(&[a], &[b]);
+        assert!(!locks.can_lock(&[a], &[]));
+        assert!(!locks.can_lock(&[], &[a]));
+        assert!(!locks.can_lock(&[b], &[]));
+        assert!(locks.can_lock(&[], &[b]));
+
+        locks.lock(&[], &[b]);
+        locks.unlock(&[a], &[b]);
+        assert!(locks.can_lock(&[a], &[]));
+        assert!(!locks.can_lock(&[b], &[]));
+
+        locks.unlock(&[], &[b]);
+        assert!(locks.is_empty());
+    }
+
+    #[test]
+    fn test_buffer_evicts_lowest_priority() {
+        let mut scheduler = TransactionScheduler::default();
+        for priority in [2, 1, 3] {
+            assert!(scheduler
+                .buffer(make_transaction(&Keypair::new(), &[], &[], priority), 3)
+                .unwrap()
+                .is_none());
+        }
+        assert_eq!(
+            scheduler
+                .pending()
+                .map(|transaction| transaction.priority)
+                .collect::<Vec<_>>(),
+            vec![3, 2, 1]
+        );
+
+        // The lowest priority transaction makes room for a better one
+        let evicted = scheduler
+            .buffer(make_transaction(&Keypair::new(), &[], &[], 4), 3)
+            .unwrap()
+            .unwrap();
+        assert_eq!(evicted.priority, 1);
+        // A transaction worse than everything buffered is not buffered at all
+        let rejected = scheduler
+            .buffer(make_transaction(&Keypair::new(), &[], &[], 0), 3)
+            .unwrap_err();
+        assert_eq!(rejected.priority, 0);
+        assert_eq!(
+            scheduler
+                .pending()
+                .map(|transaction| transaction.priority)
+                .collect::<Vec<_>>(),
+            vec![4, 3, 2]
+        );
+    }
+
+    #[test]
+    fn test_schedule_conflicting_transactions() {
+        let mut scheduler = TransactionScheduler::default();
+        let hot_account = Pubkey::new_unique();
+        let shared_account = Pubkey::new_unique();
+
+        let first = make_transaction(&Keypair::new(), &[hot_account], &[shared_account], 4);
+        let conflicting = make_transaction(&Keypair::new(), &[hot_account], &[], 3);
+        let reader = make_transaction(&Keypair::new(), &[], &[shared_account], 2);
+        let writer = make_transaction(&Keypair::new(), &[shared_account, hot_account], &[], 1);
+        let expected_first_batch = vec![
+            *first.transaction.message_hash(),
+            *reader.transaction.message_hash(),
+        ];
+        let conflicting_hash = *conflicting.transaction.message_hash();
+        let writer_hash = *writer.transaction.message_hash();
+        for transaction in [first, conflicting, reader, writer] {
+            assert!(scheduler.buffer(transaction, 10).unwrap().is_none());
+        }
+
+        // Conflicts within a batch are left buffered, readers of the same account don't conflict
+        let (batches, stats) = scheduler.schedule(1, 10);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(message_hashes(&batches[0]), expected_first_batch);
+        assert_eq!(
+            stats,
+            ScheduleStats {
+                scheduled_transactions_count: 2,
+                conflicts_count: 2,
+            }
+        );
+        assert_eq!(scheduler.in_flight_batches(), 1);
+        assert_eq!(scheduler.pending_len(), 2);
+        let first_batch_id = batches[0].id;
+
+        // Nothing is scanned while the in flight budget is used up
+        let (batches, stats) = scheduler.schedule(1, 10);
+        assert!(batches.is_empty());
+        assert_eq!(stats, ScheduleStats::default());
+
+        // Until the batch completes
+        assert!(scheduler.complete(first_batch_id, vec![], 10).is_empty());
+        assert_eq!(scheduler.in_flight_batches(), 0);
+        assert!(scheduler.locks.is_empty());
+        let (batches, _stats) = scheduler.schedule(1, 10);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(message_hashes(&batches[0]), vec![conflicting_hash]);
+        assert_eq!(scheduler.pending_len(), 1);
+
+        // Retried transactions are buffered again, by their priority
+        let retried = batches.into_iter().next().unwrap();
+        let retryable_transactions = retried.transactions.into_iter().enumerate().collect();
+        assert!(scheduler
+            .complete(retried.id, retryable_transactions, 10)
+            .is_empty());
+        assert_eq!(
+            scheduler
+                .pending()
+                .map(|transaction| *transaction.transaction.message_hash())
+                .collect::<Vec<_>>(),
+            vec![conflicting_hash, writer_hash]
+        );
+        assert!(scheduler.locks.is_empty());
+
+        // Batches are no larger than the maximum size
+        let independent = make_transaction(&Keypair::new(), &[Pubkey::new_unique()], &[], 2);
+        let independent_hash = *independent.transaction.message_hash();
+        assert!(scheduler.buffer(independent, 10).unwrap().is_none());
+        let (batches, _stats) = scheduler.schedule(2, 1);
+        assert_eq!(batches.len(), 2);
+        assert_eq!(message_hashes(&batches[0]), vec![conflicting_hash]);
+        assert_eq!(message_hashes(&batches[1]), vec![independent_hash]);
+        assert_eq!(scheduler.in_flight_batches(), 2);
+        assert_eq!(scheduler.pending_len(), 1);
+    }
+}
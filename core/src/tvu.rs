@@ -14,7 +14,9 @@ use crate::{
     consensus::Tower,
     cost_update_service::CostUpdateService,
     drop_bank_service::DropBankService,
-    ledger_cleanup_service::LedgerCleanupService,
+    ledger_cleanup_service::{
+        LedgerCleanupService, LedgerIntegrityCheckConfig, LedgerRetentionConfig, RetentionPolicy,
+    },
     replay_stage::{ReplayStage, ReplayStageConfig},
     retransmit_stage::RetransmitStage,
     rewards_recorder_service::RewardsRecorderSender,
@@ -25,12 +27,14 @@ use crate::{
     voting_service::VotingService,
 };
 use crossbeam_channel::unbounded;
+use solana_accountsdb_plugin_manager::block_metadata_notifier::BlockMetadataNotifier;
 use solana_gossip::cluster_info::ClusterInfo;
 use solana_ledger::{
     blockstore::Blockstore, blockstore_processor::TransactionStatusSender,
     leader_schedule_cache::LeaderScheduleCache,
 };
 use solana_poh::poh_recorder::PohRecorder;
+use solana_remote_vote_signer::vote_signer::{RemoteVoteSigner, VoteSignerAddr};
 use solana_rpc::{
     max_slots::MaxSlots, optimistically_confirmed_bank_tracker::BankNotificationSender,
     rpc_subscriptions::RpcSubscriptions,
@@ -83,6 +87,7 @@ pub struct Sockets {
 #[derive(Default)]
 pub struct TvuConfig {
     pub max_ledger_shreds: Option<u64>,
+    pub ledger_retention_policies: Vec<RetentionPolicy>,
     pub shred_version: u16,
     pub halt_on_known_validators_accounts_hash_mismatch: bool,
     pub known_validators: Option<HashSet<Pubkey>>,
@@ -93,9 +98,11 @@ pub struct TvuConfig {
     pub use_index_hash_calculation: bool,
     pub rocksdb_compaction_interval: Option<u64>,
     pub rocksdb_max_compaction_jitter: Option<u64>,
+    pub ledger_integrity_check: Option<LedgerIntegrityCheckConfig>,
     pub wait_for_vote_to_start_leader: bool,
     pub accounts_shrink_ratio: AccountShrinkThreshold,
     pub disable_epoch_boundary_optimization: bool,
+    pub vote_signer_addr: Option<VoteSignerAddr>,
 }
 
 impl Tvu {
@@ -125,6 +132,7 @@ impl Tvu {
         transaction_status_sender: Option<TransactionStatusSender>,
         rewards_recorder_sender: Option<RewardsRecorderSender>,
         cache_block_meta_sender: Option<CacheBlockMetaSender>,
+        block_metadata_notifier: Option<BlockMetadataNotifier>,
         snapshot_config_and_pending_package: Option<(SnapshotConfig, PendingSnapshotPackage)>,
         vote_tracker: Arc<VoteTracker>,
         retransmit_slots_sender: RetransmitSlotsSender,
@@ -280,11 +288,13 @@ impl Tvu {
             transaction_status_sender,
             rewards_recorder_sender,
             cache_block_meta_sender,
+            block_metadata_notifier,
             bank_notification_sender,
             wait_for_vote_to_start_leader: tvu_config.wait_for_vote_to_start_leader,
             ancestor_hashes_replay_update_sender,
             tower_storage: tower_storage.clone(),
             disable_epoch_boundary_optimization: tvu_config.disable_epoch_boundary_optimization,
+            remote_vote_signing: tvu_config.vote_signer_addr.is_some(),
         };
 
         let (voting_sender, voting_receiver) = channel();
@@ -294,6 +304,7 @@ impl Tvu {
             poh_recorder.clone(),
             tower_storage,
             bank_forks.clone(),
+            tvu_config.vote_signer_addr.map(RemoteVoteSigner::new),
         );
 
         let (cost_update_sender, cost_update_receiver) = channel();
@@ -329,14 +340,20 @@ impl Tvu {
             drop_bank_sender,
         );
 
-        let ledger_cleanup_service = tvu_config.max_ledger_shreds.map(|max_ledger_shreds| {
+        let ledger_retention = LedgerRetentionConfig::new(
+            tvu_config.max_ledger_shreds,
+            tvu_config.ledger_retention_policies.clone(),
+        );
+        let ledger_cleanup_service = (!ledger_retention.is_empty()).then(|| {
             LedgerCleanupService::new(
                 ledger_cleanup_slot_receiver,
                 blockstore.clone(),
-                max_ledger_shreds,
+                ledger_retention,
+                *bank_forks.read().unwrap().root_bank().epoch_schedule(),
                 exit,
                 compaction_interval,
                 max_compaction_jitter,
+                tvu_config.ledger_integrity_check.clone(),
             )
         });
 
@@ -388,6 +405,7 @@ pub mod tests {
     use solana_gossip::cluster_info::{ClusterInfo, Node};
     use solana_ledger::{
         blockstore::BlockstoreSignals,
+        blockstore_db::BlockstoreOptions,
         create_new_tmp_ledger,
         genesis_utils::{create_genesis_config, GenesisConfigInfo},
     };
@@ -426,7 +444,7 @@ pub mod tests {
             blockstore,
             ledger_signal_receiver,
             ..
-        } = Blockstore::open_with_signal(&blockstore_path, None, true)
+        } = Blockstore::open_with_signal(&blockstore_path, BlockstoreOptions::default())
             .expect("Expected to successfully open ledger");
         let blockstore = Arc::new(blockstore);
         let bank = bank_forks.working_bank();
@@ -476,6 +494,7 @@ pub mod tests {
             None,
             None,
             None,
+            None,
             Arc::new(VoteTracker::new(&bank)),
             retransmit_slots_sender,
             gossip_verified_vote_hash_receiver,
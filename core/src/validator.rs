@@ -3,11 +3,13 @@
 pub use solana_perf::report_target_features;
 use {
     crate::{
+        banking_stage::TransactionScheduling,
         broadcast_stage::BroadcastStageType,
         cache_block_meta_service::{CacheBlockMetaSender, CacheBlockMetaService},
         cluster_info_vote_listener::VoteTracker,
         completed_data_sets_service::CompletedDataSetsService,
         consensus::{reconcile_blockstore_roots_with_tower, Tower},
+        ledger_cleanup_service::{LedgerIntegrityCheckConfig, RetentionPolicy},
         rewards_recorder_service::{RewardsRecorderSender, RewardsRecorderService},
         sample_performance_service::SamplePerformanceService,
         serve_repair::ServeRepair,
@@ -17,11 +19,15 @@ use {
         system_monitor_service::{verify_udp_stats_access, SystemMonitorService},
         tower_storage::TowerStorage,
         tpu::{Tpu, DEFAULT_TPU_COALESCE_MS},
+        transaction_notifier_service::TransactionNotifierService,
         tvu::{Sockets, Tvu, TvuConfig},
     },
     crossbeam_channel::{bounded, unbounded},
     rand::{thread_rng, Rng},
-    solana_accountsdb_plugin_manager::accountsdb_plugin_service::AccountsDbPluginService,
+    solana_accountsdb_plugin_manager::{
+        accountsdb_plugin_service::{AccountsDbPluginControl, AccountsDbPluginService},
+        transaction_notifier::TransactionNotifier,
+    },
     solana_entry::poh::compute_hash_time_ns,
     solana_gossip::{
         cluster_info::{
@@ -34,8 +40,11 @@ use {
     },
     solana_ledger::{
         bank_forks_utils,
-        blockstore::{Blockstore, BlockstoreSignals, CompletedSlotsReceiver, PurgeType},
-        blockstore_db::BlockstoreRecoveryMode,
+        blockstore::{
+            blockstore_integrity::IntegrityCheckOptions, Blockstore, BlockstoreSignals,
+            CompletedSlotsReceiver, PurgeType,
+        },
+        blockstore_db::{BlockstoreCompressionConfig, BlockstoreOptions, BlockstoreRecoveryMode},
         blockstore_processor::{self, TransactionStatusSender},
         leader_schedule::FixedSchedule,
         leader_schedule_cache::LeaderScheduleCache,
@@ -46,6 +55,7 @@ use {
         poh_recorder::{PohRecorder, GRACE_TICKS_FACTOR, MAX_GRACE_SLOTS},
         poh_service::{self, PohService},
     },
+    solana_remote_vote_signer::vote_signer::VoteSignerAddr,
     solana_replica_lib::{
         accountsdb_repl_server::{AccountsDbReplService, AccountsDbReplServiceConfig},
         accountsdb_repl_server_factory,
@@ -124,6 +134,8 @@ pub struct ValidatorConfig {
     pub pubsub_config: PubSubConfig,
     pub snapshot_config: Option<SnapshotConfig>,
     pub max_ledger_shreds: Option<u64>,
+    /// Retention policies of the `LedgerCleanupService`, on top of `max_ledger_shreds`
+    pub ledger_retention_policies: Vec<RetentionPolicy>,
     pub broadcast_stage_type: BroadcastStageType,
     pub enable_partition: Option<Arc<AtomicBool>>,
     pub enforce_ulimit_nofile: bool,
@@ -142,6 +154,12 @@ pub struct ValidatorConfig {
     pub accounts_hash_interval_slots: u64,
     pub max_genesis_archive_unpacked_size: u64,
     pub wal_recovery_mode: Option<BlockstoreRecoveryMode>,
+    pub blockstore_compression: BlockstoreCompressionConfig,
+    pub enable_program_signatures: bool,
+    /// Rooted slots between the background blockstore integrity checks of the
+    /// `LedgerCleanupService`, requires `max_ledger_shreds`
+    pub blockstore_integrity_check_interval: Option<u64>,
+    pub blockstore_integrity_check_repair: bool,
     pub poh_verify: bool, // Perform PoH verification during blockstore processing at boo
     pub require_tower: bool,
     pub tower_storage: Arc<dyn TowerStorage>,
@@ -162,10 +180,12 @@ pub struct ValidatorConfig {
     pub accounts_db_skip_shrink: bool,
     pub accounts_db_use_index_hash_calculation: bool,
     pub tpu_coalesce_ms: u64,
+    pub transaction_scheduling: TransactionScheduling,
     pub validator_exit: Arc<RwLock<Exit>>,
     pub no_wait_for_vote_to_start_leader: bool,
     pub accounts_shrink_ratio: AccountShrinkThreshold,
     pub disable_epoch_boundary_optimization: bool,
+    pub vote_signer_addr: Option<VoteSignerAddr>,
 }
 
 impl Default for ValidatorConfig {
@@ -177,6 +197,7 @@ impl Default for ValidatorConfig {
             expected_shred_version: None,
             voting_disabled: false,
             max_ledger_shreds: None,
+            ledger_retention_policies: Vec::new(),
             account_paths: Vec::new(),
             account_shrink_paths: None,
             rpc_config: JsonRpcConfig::default(),
@@ -203,6 +224,10 @@ impl Default for ValidatorConfig {
             accounts_hash_interval_slots: std::u64::MAX,
             max_genesis_archive_unpacked_size: MAX_GENESIS_ARCHIVE_UNPACKED_SIZE,
             wal_recovery_mode: None,
+            blockstore_compression: BlockstoreCompressionConfig::default(),
+            enable_program_signatures: false,
+            blockstore_integrity_check_interval: None,
+            blockstore_integrity_check_repair: false,
             poh_verify: true,
             require_tower: false,
             tower_storage: Arc::new(crate::tower_storage::NullTowerStorage::default()),
@@ -222,11 +247,13 @@ impl Default for ValidatorConfig {
             accounts_db_skip_shrink: false,
             accounts_db_use_index_hash_calculation: true,
             tpu_coalesce_ms: DEFAULT_TPU_COALESCE_MS,
+            transaction_scheduling: TransactionScheduling::ThreadLocal,
             validator_exit: Arc::new(RwLock::new(Exit::default())),
             no_wait_for_vote_to_start_leader: true,
             accounts_shrink_ratio: AccountShrinkThreshold::default(),
             accounts_db_config: None,
             disable_epoch_boundary_optimization: false,
+            vote_signer_addr: None,
         }
     }
 }
@@ -261,6 +288,7 @@ impl Default for ValidatorStartProgress {
 struct TransactionHistoryServices {
     transaction_status_sender: Option<TransactionStatusSender>,
     transaction_status_service: Option<TransactionStatusService>,
+    transaction_notifier_service: Option<TransactionNotifierService>,
     max_complete_transaction_status_slot: Arc<AtomicU64>,
     rewards_recorder_sender: Option<RewardsRecorderSender>,
     rewards_recorder_service: Option<RewardsRecorderService>,
@@ -275,6 +303,7 @@ pub struct Validator {
     rpc_completed_slots_service: JoinHandle<()>,
     optimistically_confirmed_bank_tracker: Option<OptimisticallyConfirmedBankTracker>,
     transaction_status_service: Option<TransactionStatusService>,
+    transaction_notifier_service: Option<TransactionNotifierService>,
     rewards_recorder_service: Option<RewardsRecorderService>,
     cache_block_meta_service: Option<CacheBlockMetaService>,
     system_monitor_service: Option<SystemMonitorService>,
@@ -351,6 +380,15 @@ impl Validator {
         if config.voting_disabled {
             warn!("voting disabled");
             authorized_voter_keypairs.write().unwrap().clear();
+        } else if let Some(vote_signer_addr) = &config.vote_signer_addr {
+            if !authorized_voter_keypairs.read().unwrap().is_empty() {
+                error!(
+                    "Authorized voter keypairs cannot be used with the remote vote signer {}",
+                    vote_signer_addr
+                );
+                abort();
+            }
+            warn!("vote signer: {}", vote_signer_addr);
         } else {
             for authorized_voter_keypair in authorized_voter_keypairs.read().unwrap().iter() {
                 warn!("authorized voter: {}", authorized_voter_keypair.pubkey());
@@ -420,9 +458,23 @@ impl Validator {
                 .and_then(|accountsdb_plugin_service| {
                     accountsdb_plugin_service.get_accounts_update_notifier()
                 });
+        let transaction_notifier =
+            accountsdb_plugin_service
+                .as_ref()
+                .and_then(|accountsdb_plugin_service| {
+                    accountsdb_plugin_service.get_transaction_notifier()
+                });
+        let block_metadata_notifier =
+            accountsdb_plugin_service
+                .as_ref()
+                .and_then(|accountsdb_plugin_service| {
+                    accountsdb_plugin_service.get_block_metadata_notifier()
+                });
         info!(
-            "AccountsDb plugin: accounts_update_notifier: {}",
-            accounts_update_notifier.is_some()
+            "AccountsDb plugin: accounts_update_notifier: {}, transaction_notifier: {}, block_metadata_notifier: {}",
+            accounts_update_notifier.is_some(),
+            transaction_notifier.is_some(),
+            block_metadata_notifier.is_some()
         );
 
         let (
@@ -437,6 +489,7 @@ impl Validator {
             TransactionHistoryServices {
                 transaction_status_sender,
                 transaction_status_service,
+                transaction_notifier_service,
                 max_complete_transaction_status_slot,
                 rewards_recorder_sender,
                 rewards_recorder_service,
@@ -456,6 +509,7 @@ impl Validator {
             config.no_poh_speed_test,
             accounts_package_channel.0.clone(),
             accounts_update_notifier,
+            transaction_notifier,
         );
 
         *start_progress.write().unwrap() = ValidatorStartProgress::StartingServices;
@@ -832,6 +886,7 @@ impl Validator {
             transaction_status_sender.clone(),
             rewards_recorder_sender,
             cache_block_meta_sender,
+            block_metadata_notifier,
             snapshot_config_and_pending_package,
             vote_tracker.clone(),
             retransmit_slots_sender,
@@ -843,6 +898,7 @@ impl Validator {
             cluster_confirmed_slot_receiver,
             TvuConfig {
                 max_ledger_shreds: config.max_ledger_shreds,
+                ledger_retention_policies: config.ledger_retention_policies.clone(),
                 halt_on_known_validators_accounts_hash_mismatch: config
                     .halt_on_known_validators_accounts_hash_mismatch,
                 shred_version: node.info.shred_version,
@@ -854,9 +910,21 @@ impl Validator {
                 use_index_hash_calculation: config.accounts_db_use_index_hash_calculation,
                 rocksdb_compaction_interval: config.rocksdb_compaction_interval,
                 rocksdb_max_compaction_jitter: config.rocksdb_compaction_interval,
+                ledger_integrity_check: config.blockstore_integrity_check_interval.map(
+                    |interval| LedgerIntegrityCheckConfig {
+                        interval,
+                        options: IntegrityCheckOptions {
+                            check_transaction_statuses: config
+                                .rpc_config
+                                .enable_rpc_transaction_history,
+                            repair: config.blockstore_integrity_check_repair,
+                        },
+                    },
+                ),
                 wait_for_vote_to_start_leader,
                 accounts_shrink_ratio: config.accounts_shrink_ratio,
                 disable_epoch_boundary_optimization: config.disable_epoch_boundary_optimization,
+                vote_signer_addr: config.vote_signer_addr.clone(),
             },
             &max_slots,
             &cost_model,
@@ -889,6 +957,7 @@ impl Validator {
             config.tpu_coalesce_ms,
             cluster_confirmed_slot_sender,
             &cost_model,
+            config.transaction_scheduling,
         );
 
         datapoint_info!("validator-new", ("id", id.to_string(), String));
@@ -902,6 +971,7 @@ impl Validator {
             rpc_completed_slots_service,
             optimistically_confirmed_bank_tracker,
             transaction_status_service,
+            transaction_notifier_service,
             rewards_recorder_service,
             cache_block_meta_service,
             system_monitor_service,
@@ -930,6 +1000,14 @@ impl Validator {
         self.join();
     }
 
+    /// The handle to list, load, unload and reload the AccountsDb plugins at runtime.
+    /// Only available when the validator was started with `--accountsdb-plugin-config`.
+    pub fn accountsdb_plugin_control(&self) -> Option<AccountsDbPluginControl> {
+        self.accountsdb_plugin_service
+            .as_ref()
+            .map(AccountsDbPluginService::get_plugin_control)
+    }
+
     fn print_node_info(node: &Node) {
         info!("{:?}", node.info);
         info!(
@@ -981,6 +1059,12 @@ impl Validator {
                 .expect("optimistically_confirmed_bank_tracker");
         }
 
+        if let Some(transaction_notifier_service) = self.transaction_notifier_service {
+            transaction_notifier_service
+                .join()
+                .expect("transaction_notifier_service");
+        }
+
         if let Some(transaction_status_service) = self.transaction_status_service {
             transaction_status_service
                 .join()
@@ -1182,6 +1266,7 @@ fn new_banks_from_ledger(
     no_poh_speed_test: bool,
     accounts_package_sender: AccountsPackageSender,
     accounts_update_notifier: Option<AccountsUpdateNotifier>,
+    transaction_notifier: Option<TransactionNotifier>,
 ) -> (
     GenesisConfig,
     BankForks,
@@ -1227,8 +1312,13 @@ fn new_banks_from_ledger(
         ..
     } = Blockstore::open_with_signal(
         ledger_path,
-        config.wal_recovery_mode.clone(),
-        enforce_ulimit_nofile,
+        BlockstoreOptions {
+            recovery_mode: config.wal_recovery_mode.clone(),
+            enforce_ulimit_nofile,
+            compression: config.blockstore_compression.clone(),
+            enable_program_signatures: config.enable_program_signatures,
+            ..BlockstoreOptions::default()
+        },
     )
     .expect("Failed to open ledger database");
     blockstore.set_no_compaction(config.no_rocksdb_compaction);
@@ -1274,12 +1364,16 @@ fn new_banks_from_ledger(
         ..blockstore_processor::ProcessOptions::default()
     };
 
+    let enable_rpc_transaction_history =
+        config.rpc_addrs.is_some() && config.rpc_config.enable_rpc_transaction_history;
     let transaction_history_services =
-        if config.rpc_addrs.is_some() && config.rpc_config.enable_rpc_transaction_history {
+        if enable_rpc_transaction_history || transaction_notifier.is_some() {
             initialize_rpc_transaction_history_services(
                 blockstore.clone(),
                 exit,
+                enable_rpc_transaction_history,
                 config.rpc_config.enable_cpi_and_log_storage,
+                transaction_notifier,
             )
         } else {
             TransactionHistoryServices::default()
@@ -1470,20 +1564,67 @@ fn backup_and_clear_blockstore(ledger_path: &Path, start_slot: Slot, shred_versi
 fn initialize_rpc_transaction_history_services(
     blockstore: Arc<Blockstore>,
     exit: &Arc<AtomicBool>,
+    enable_rpc_transaction_history: bool,
     enable_cpi_and_log_storage: bool,
+    transaction_notifier: Option<TransactionNotifier>,
 ) -> TransactionHistoryServices {
     let max_complete_transaction_status_slot = Arc::new(AtomicU64::new(blockstore.max_root()));
-    let (transaction_status_sender, transaction_status_receiver) = unbounded();
-    let transaction_status_sender = Some(TransactionStatusSender {
-        sender: transaction_status_sender,
-        enable_cpi_and_log_storage,
-    });
-    let transaction_status_service = Some(TransactionStatusService::new(
-        transaction_status_receiver,
-        max_complete_transaction_status_slot.clone(),
-        blockstore.clone(),
-        exit,
-    ));
+    let (rpc_transaction_status_sender, transaction_status_service) =
+        if enable_rpc_transaction_history {
+            let (transaction_status_sender, transaction_status_receiver) = unbounded();
+            let transaction_status_service = TransactionStatusService::new(
+                transaction_status_receiver,
+                max_complete_transaction_status_slot.clone(),
+                blockstore.clone(),
+                exit,
+            );
+            (
+                Some(transaction_status_sender),
+                Some(transaction_status_service),
+            )
+        } else {
+            (None, None)
+        };
+
+    // When plugins are interested in transactions, replay sends the transaction statuses to the
+    // TransactionNotifierService first, which forwards them on to the TransactionStatusService.
+    // The plugins always get the logs and inner instructions of the transactions.
+    let (transaction_status_sender, transaction_notifier_service) =
+        if let Some(transaction_notifier) = transaction_notifier {
+            let (transaction_status_sender, transaction_status_receiver) = unbounded();
+            let transaction_notifier_service = TransactionNotifierService::new(
+                transaction_status_receiver,
+                rpc_transaction_status_sender,
+                enable_cpi_and_log_storage,
+                transaction_notifier,
+                exit,
+            );
+            (
+                Some(TransactionStatusSender {
+                    sender: transaction_status_sender,
+                    enable_cpi_and_log_storage: true,
+                }),
+                Some(transaction_notifier_service),
+            )
+        } else {
+            (
+                rpc_transaction_status_sender.map(|sender| TransactionStatusSender {
+                    sender,
+                    enable_cpi_and_log_storage,
+                }),
+                None,
+            )
+        };
+
+    // The rewards and the block metadata are only written for the rpc transaction history
+    if !enable_rpc_transaction_history {
+        return TransactionHistoryServices {
+            transaction_status_sender,
+            transaction_notifier_service,
+            max_complete_transaction_status_slot,
+            ..TransactionHistoryServices::default()
+        };
+    }
 
     let (rewards_recorder_sender, rewards_receiver) = unbounded();
     let rewards_recorder_sender = Some(rewards_recorder_sender);
@@ -1503,6 +1644,7 @@ fn initialize_rpc_transaction_history_services(
     TransactionHistoryServices {
         transaction_status_sender,
         transaction_status_service,
+        transaction_notifier_service,
         max_complete_transaction_status_slot,
         rewards_recorder_sender,
         rewards_recorder_service,
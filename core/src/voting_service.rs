@@ -2,6 +2,7 @@ use crate::tower_storage::{SavedTower, TowerStorage};
 use solana_gossip::cluster_info::ClusterInfo;
 use solana_measure::measure::Measure;
 use solana_poh::poh_recorder::PohRecorder;
+use solana_remote_vote_signer::vote_signer::{RemoteVoteSigner, VoteAncestry};
 use solana_runtime::bank_forks::BankForks;
 use solana_sdk::{clock::Slot, transaction::Transaction};
 use std::{
@@ -9,15 +10,19 @@ use std::{
     thread::{self, Builder, JoinHandle},
 };
 
+/// `ancestry` is the fork of the voted bank, which is only needed to get a vote transaction
+/// signed by the remote vote signer
 pub enum VoteOp {
     PushVote {
         tx: Transaction,
         tower_slots: Vec<Slot>,
         saved_tower: SavedTower,
+        ancestry: Option<VoteAncestry>,
     },
     RefreshVote {
         tx: Transaction,
         last_voted_slot: Slot,
+        ancestry: Option<VoteAncestry>,
     },
 }
 
@@ -28,6 +33,13 @@ impl VoteOp {
             VoteOp::RefreshVote { tx, .. } => tx,
         }
     }
+
+    fn tx_mut_and_ancestry(&mut self) -> (&mut Transaction, Option<VoteAncestry>) {
+        match self {
+            VoteOp::PushVote { tx, ancestry, .. } => (tx, ancestry.take()),
+            VoteOp::RefreshVote { tx, ancestry, .. } => (tx, ancestry.take()),
+        }
+    }
 }
 
 pub struct VotingService {
@@ -41,6 +53,7 @@ impl VotingService {
         poh_recorder: Arc<Mutex<PohRecorder>>,
         tower_storage: Arc<dyn TowerStorage>,
         bank_forks: Arc<RwLock<BankForks>>,
+        remote_vote_signer: Option<RemoteVoteSigner>,
     ) -> Self {
         let thread_hdl = Builder::new()
             .name("sol-vote-service".to_string())
@@ -52,6 +65,7 @@ impl VotingService {
                         &cluster_info,
                         &poh_recorder,
                         tower_storage.as_ref(),
+                        remote_vote_signer.as_ref(),
                         vote_op,
                         send_to_tpu_vote_port,
                     );
@@ -65,7 +79,8 @@ impl VotingService {
         cluster_info: &ClusterInfo,
         poh_recorder: &Mutex<PohRecorder>,
         tower_storage: &dyn TowerStorage,
-        vote_op: VoteOp,
+        remote_vote_signer: Option<&RemoteVoteSigner>,
+        mut vote_op: VoteOp,
         send_to_tpu_vote_port: bool,
     ) {
         if let VoteOp::PushVote { saved_tower, .. } = &vote_op {
@@ -78,6 +93,26 @@ impl VotingService {
             inc_new_counter_info!("tower_save-ms", measure.as_ms() as usize);
         }
 
+        if let Some(remote_vote_signer) = remote_vote_signer {
+            let (tx, ancestry) = vote_op.tx_mut_and_ancestry();
+            if let Some(ancestry) = ancestry {
+                let mut measure = Measure::start("remote_vote_sign-ms");
+                let result = remote_vote_signer.sign_vote_transaction(tx, &ancestry);
+                measure.stop();
+                inc_new_counter_info!("remote_vote_sign-ms", measure.as_ms() as usize);
+                if let Err(err) = result {
+                    // The vote is dropped, the tower refreshes it if it doesn't land
+                    warn!(
+                        "Unable to sign vote with {}: {}",
+                        remote_vote_signer.addr(),
+                        err
+                    );
+                    inc_new_counter_info!("remote_vote_sign-failed", 1);
+                    return;
+                }
+            }
+        }
+
         let target_address = if send_to_tpu_vote_port {
             crate::banking_stage::next_leader_tpu_vote(cluster_info, poh_recorder)
         } else {
@@ -94,6 +129,7 @@ impl VotingService {
             VoteOp::RefreshVote {
                 tx,
                 last_voted_slot,
+                ..
             } => {
                 cluster_info.refresh_vote(tx, last_voted_slot);
             }
@@ -4,11 +4,12 @@
 #[cfg(test)]
 mod tests {
     use log::*;
-    use solana_core::ledger_cleanup_service::LedgerCleanupService;
+    use solana_core::ledger_cleanup_service::{LedgerCleanupService, LedgerRetentionConfig};
     use solana_ledger::blockstore::{make_many_slot_entries, Blockstore};
     use solana_ledger::get_tmp_ledger_path;
     use solana_ledger::shred::Shred;
     use solana_measure::measure::Measure;
+    use solana_sdk::epoch_schedule::EpochSchedule;
     use std::collections::VecDeque;
     use std::str::FromStr;
     use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
@@ -243,10 +244,12 @@ mod tests {
         let cleaner = LedgerCleanupService::new(
             receiver,
             blockstore.clone(),
-            max_ledger_shreds,
+            LedgerRetentionConfig::max_ledger_shreds(max_ledger_shreds),
+            EpochSchedule::default(),
             &exit,
             compaction_interval,
             None,
+            None,
         );
 
         let exit_cpu = Arc::new(AtomicBool::new(false));
@@ -521,7 +524,8 @@ mod tests {
         LedgerCleanupService::cleanup_ledger(
             &receiver,
             &blockstore,
-            max_ledger_shreds,
+            &LedgerRetentionConfig::max_ledger_shreds(max_ledger_shreds),
+            &EpochSchedule::default(),
             &mut last_purge_slot,
             10,
             &highest_compact_slot,
@@ -4,13 +4,18 @@ use clap::{
 };
 use solana_clap_utils::{
     input_parsers::pubkey_of,
-    input_validators::{is_slot, is_valid_pubkey},
+    input_validators::{is_parsable, is_slot, is_valid_pubkey},
 };
 use solana_cli_output::{
     display::println_transaction, CliBlock, CliTransaction, CliTransactionConfirmation,
     OutputFormat,
 };
-use solana_ledger::{blockstore::Blockstore, blockstore_db::AccessType};
+use solana_ledger::{
+    bigtable_upload::ConfirmedBlockUploadConfig,
+    blockstore::Blockstore,
+    blockstore_db::AccessType,
+    long_term_storage::{open_long_term_storage, LongTermStorageConfig},
+};
 use solana_sdk::{clock::Slot, pubkey::Pubkey, signature::Signature};
 use solana_transaction_status::{ConfirmedBlock, EncodedTransaction, UiTransactionEncoding};
 use std::{
@@ -21,13 +26,13 @@ use std::{
 };
 
 async fn upload(
+    storage_config: &LongTermStorageConfig,
     blockstore: Blockstore,
     starting_slot: Slot,
     ending_slot: Option<Slot>,
-    allow_missing_metadata: bool,
-    force_reupload: bool,
+    config: ConfirmedBlockUploadConfig,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let bigtable = solana_storage_bigtable::LedgerStorage::new(false, None)
+    let bigtable = open_long_term_storage(storage_config, false)
         .await
         .map_err(|err| format!("Failed to connect to storage: {:?}", err))?;
 
@@ -36,24 +41,29 @@ async fn upload(
         bigtable,
         starting_slot,
         ending_slot,
-        allow_missing_metadata,
-        force_reupload,
+        &config,
         Arc::new(AtomicBool::new(false)),
     )
     .await
 }
 
-async fn delete_slots(slots: Vec<Slot>, dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+async fn delete_slots(
+    storage_config: &LongTermStorageConfig,
+    slots: Vec<Slot>,
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
     let read_only = dry_run;
-    let bigtable = solana_storage_bigtable::LedgerStorage::new(read_only, None)
+    let bigtable = open_long_term_storage(storage_config, read_only)
         .await
         .map_err(|err| format!("Failed to connect to storage: {:?}", err))?;
 
     solana_ledger::bigtable_delete::delete_confirmed_blocks(bigtable, slots, dry_run).await
 }
 
-async fn first_available_block() -> Result<(), Box<dyn std::error::Error>> {
-    let bigtable = solana_storage_bigtable::LedgerStorage::new(true, None).await?;
+async fn first_available_block(
+    storage_config: &LongTermStorageConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let bigtable = open_long_term_storage(storage_config, true).await?;
     match bigtable.get_first_available_block().await? {
         Some(block) => println!("{}", block),
         None => println!("No blocks available"),
@@ -62,8 +72,12 @@ async fn first_available_block() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-async fn block(slot: Slot, output_format: OutputFormat) -> Result<(), Box<dyn std::error::Error>> {
-    let bigtable = solana_storage_bigtable::LedgerStorage::new(false, None)
+async fn block(
+    storage_config: &LongTermStorageConfig,
+    slot: Slot,
+    output_format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let bigtable = open_long_term_storage(storage_config, false)
         .await
         .map_err(|err| format!("Failed to connect to storage: {:?}", err))?;
 
@@ -77,8 +91,12 @@ async fn block(slot: Slot, output_format: OutputFormat) -> Result<(), Box<dyn st
     Ok(())
 }
 
-async fn blocks(starting_slot: Slot, limit: usize) -> Result<(), Box<dyn std::error::Error>> {
-    let bigtable = solana_storage_bigtable::LedgerStorage::new(false, None)
+async fn blocks(
+    storage_config: &LongTermStorageConfig,
+    starting_slot: Slot,
+    limit: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let bigtable = open_long_term_storage(storage_config, false)
         .await
         .map_err(|err| format!("Failed to connect to storage: {:?}", err))?;
 
@@ -90,11 +108,12 @@ async fn blocks(starting_slot: Slot, limit: usize) -> Result<(), Box<dyn std::er
 }
 
 async fn confirm(
+    storage_config: &LongTermStorageConfig,
     signature: &Signature,
     verbose: bool,
     output_format: OutputFormat,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let bigtable = solana_storage_bigtable::LedgerStorage::new(false, None)
+    let bigtable = open_long_term_storage(storage_config, false)
         .await
         .map_err(|err| format!("Failed to connect to storage: {:?}", err))?;
 
@@ -135,6 +154,7 @@ async fn confirm(
 }
 
 pub async fn transaction_history(
+    storage_config: &LongTermStorageConfig,
     address: &Pubkey,
     mut limit: usize,
     mut before: Option<Signature>,
@@ -143,7 +163,7 @@ pub async fn transaction_history(
     show_transactions: bool,
     query_chunk_size: usize,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let bigtable = solana_storage_bigtable::LedgerStorage::new(true, None).await?;
+    let bigtable = open_long_term_storage(storage_config, true).await?;
 
     let mut loaded_block: Option<(Slot, ConfirmedBlock)> = None;
     while limit > 0 {
@@ -234,6 +254,23 @@ impl BigTableSubCommand for App<'_, '_> {
                 .about("Ledger data on a BigTable instance")
                 .setting(AppSettings::InferSubcommands)
                 .setting(AppSettings::SubcommandRequiredElseHelp)
+                .arg(
+                    Arg::with_name("storage")
+                        .long("storage")
+                        .value_name("STORAGE")
+                        .takes_value(true)
+                        .global(true)
+                        .default_value("bigtable")
+                        .validator(|value| value.parse::<LongTermStorageConfig>().map(|_| ()))
+                        .help(
+                            "Where the ledger data is archived: bigtable, a local directory \
+                             with file://<DIRECTORY>, or an S3 compatible bucket with \
+                             s3://<BUCKET>[/<PREFIX>]. The S3 credentials are read from \
+                             AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY, the region from \
+                             AWS_REGION and a custom endpoint, e.g. MinIO, from \
+                             AWS_ENDPOINT_URL",
+                        ),
+                )
                 .subcommand(
                     SubCommand::with_name("upload")
                         .about("Upload the ledger to BigTable")
@@ -272,6 +309,34 @@ impl BigTableSubCommand for App<'_, '_> {
                                     Note: reupload will *not* delete any data from the tx-by-addr table;\
                                     Use with care.",
                                 ),
+                        )
+                        .arg(
+                            Arg::with_name("reconcile")
+                                .long("reconcile")
+                                .takes_value(false)
+                                .conflicts_with("force_reupload")
+                                .help(
+                                    "Compare every root of the ledger from the starting slot with \
+                                     the blocks already uploaded, and only upload the missing ones",
+                                ),
+                        )
+                        .arg(
+                            Arg::with_name("no_checkpoint")
+                                .long("no-checkpoint")
+                                .takes_value(false)
+                                .help(
+                                    "Don't resume from, or save, the upload checkpoint kept in \
+                                     the ledger for this storage",
+                                ),
+                        )
+                        .arg(
+                            Arg::with_name("num_upload_ranges")
+                                .long("parallel-ranges")
+                                .value_name("NUMBER")
+                                .takes_value(true)
+                                .default_value("1")
+                                .validator(is_parsable::<usize>)
+                                .help("Upload this many slot ranges concurrently"),
                         ),
                 )
                 .subcommand(
@@ -419,39 +484,48 @@ pub fn bigtable_process_command(ledger_path: &Path, matches: &ArgMatches<'_>) {
 
     let verbose = matches.is_present("verbose");
     let output_format = OutputFormat::from_matches(matches, "output_format", verbose);
+    let storage_config = value_t_or_exit!(matches, "storage", LongTermStorageConfig);
 
     let future = match matches.subcommand() {
         ("upload", Some(arg_matches)) => {
             let starting_slot = value_t!(arg_matches, "starting_slot", Slot).unwrap_or(0);
             let ending_slot = value_t!(arg_matches, "ending_slot", Slot).ok();
-            let allow_missing_metadata = arg_matches.is_present("allow_missing_metadata");
-            let force_reupload = arg_matches.is_present("force_reupload");
+            let config = ConfirmedBlockUploadConfig {
+                force_reupload: arg_matches.is_present("force_reupload"),
+                allow_missing_metadata: arg_matches.is_present("allow_missing_metadata"),
+                reconcile: arg_matches.is_present("reconcile"),
+                num_upload_ranges: value_t_or_exit!(arg_matches, "num_upload_ranges", usize),
+                checkpoint: (!arg_matches.is_present("no_checkpoint"))
+                    .then(|| storage_config.to_string()),
+            };
             let blockstore =
                 crate::open_blockstore(ledger_path, AccessType::TryPrimaryThenSecondary, None);
 
             runtime.block_on(upload(
+                &storage_config,
                 blockstore,
                 starting_slot,
                 ending_slot,
-                allow_missing_metadata,
-                force_reupload,
+                config,
             ))
         }
         ("delete-slots", Some(arg_matches)) => {
             let slots = values_t_or_exit!(arg_matches, "slots", Slot);
             let dry_run = !arg_matches.is_present("force");
-            runtime.block_on(delete_slots(slots, dry_run))
+            runtime.block_on(delete_slots(&storage_config, slots, dry_run))
+        }
+        ("first-available-block", Some(_arg_matches)) => {
+            runtime.block_on(first_available_block(&storage_config))
         }
-        ("first-available-block", Some(_arg_matches)) => runtime.block_on(first_available_block()),
         ("block", Some(arg_matches)) => {
             let slot = value_t_or_exit!(arg_matches, "slot", Slot);
-            runtime.block_on(block(slot, output_format))
+            runtime.block_on(block(&storage_config, slot, output_format))
         }
         ("blocks", Some(arg_matches)) => {
             let starting_slot = value_t_or_exit!(arg_matches, "starting_slot", Slot);
             let limit = value_t_or_exit!(arg_matches, "limit", usize);
 
-            runtime.block_on(blocks(starting_slot, limit))
+            runtime.block_on(blocks(&storage_config, starting_slot, limit))
         }
         ("confirm", Some(arg_matches)) => {
             let signature = arg_matches
@@ -460,7 +534,7 @@ pub fn bigtable_process_command(ledger_path: &Path, matches: &ArgMatches<'_>) {
                 .parse()
                 .expect("Invalid signature");
 
-            runtime.block_on(confirm(&signature, verbose, output_format))
+            runtime.block_on(confirm(&storage_config, &signature, verbose, output_format))
         }
         ("transaction-history", Some(arg_matches)) => {
             let address = pubkey_of(arg_matches, "address").unwrap();
@@ -475,6 +549,7 @@ pub fn bigtable_process_command(ledger_path: &Path, matches: &ArgMatches<'_>) {
             let show_transactions = arg_matches.is_present("show_transactions");
 
             runtime.block_on(transaction_history(
+                &storage_config,
                 &address,
                 limit,
                 before,
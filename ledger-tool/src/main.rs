@@ -20,9 +20,22 @@ use solana_entry::entry::Entry;
 use solana_ledger::{
     ancestor_iterator::AncestorIterator,
     bank_forks_utils,
-    blockstore::{create_new_ledger, Blockstore, PurgeType},
-    blockstore_db::{self, AccessType, BlockstoreRecoveryMode, Column, Database},
+    blockstore::{
+        blockstore_diff::LedgerDiffReport,
+        blockstore_integrity::{IntegrityCheckOptions, IntegrityReport},
+        create_new_ledger, Blockstore, BlockstoreError, PurgeType,
+    },
+    blockstore_db::{
+        self, AccessType, BlockstoreCompressionConfig, BlockstoreOptions, BlockstoreRecoveryMode,
+        Column, ColumnStorageStats, Database,
+    },
     blockstore_processor::ProcessOptions,
+    leader_schedule_utils,
+    ledger_archive::{
+        read_archive_slot, verify_archive_slot, write_archive_slot, ArchiveHeader,
+        LedgerArchiveError, LedgerArchiveFormat, LedgerArchiveReader, LedgerArchiveWriter,
+        ARCHIVE_VERSION,
+    },
     shred::Shred,
 };
 use solana_measure::measure::Measure;
@@ -45,6 +58,7 @@ use solana_sdk::{
     account::{AccountSharedData, ReadableAccount, WritableAccount},
     account_utils::StateMut,
     clock::{Epoch, Slot},
+    feature, feature_set,
     genesis_config::{ClusterType, GenesisConfig},
     hash::Hash,
     inflation::Inflation,
@@ -52,6 +66,7 @@ use solana_sdk::{
     pubkey::Pubkey,
     rent::Rent,
     shred_version::compute_shred_version,
+    signature::Signature,
     stake::{self, state::StakeState},
     system_program,
     transaction::{SanitizedTransaction, TransactionError},
@@ -65,7 +80,7 @@ use std::{
     collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     ffi::OsStr,
     fs::{self, File},
-    io::{self, stdout, BufRead, BufReader, Write},
+    io::{self, stdout, BufRead, BufReader, BufWriter, Write},
     path::{Path, PathBuf},
     process::{exit, Command, Stdio},
     str::FromStr,
@@ -77,7 +92,13 @@ use std::{
 };
 
 mod bigtable;
+mod replay_transaction;
+mod serve;
+mod tower;
 use bigtable::*;
+use replay_transaction::*;
+use serve::*;
+use tower::*;
 
 #[derive(PartialEq)]
 enum LedgerOutputMethod {
@@ -316,6 +337,131 @@ fn output_ledger(
     }
 }
 
+/// `demote_program_write_locks_slot` is the slot the program write lock demotion feature was
+/// activated at, which decides how the addresses of the transactions are split between
+/// writable and read-only.
+fn export_ledger(
+    blockstore: &Blockstore,
+    starting_slot: Slot,
+    ending_slot: Slot,
+    demote_program_write_locks_slot: Option<Slot>,
+    format: LedgerArchiveFormat,
+    header: ArchiveHeader,
+    archive_path: &Path,
+) -> Result<u64, String> {
+    let file = File::create(archive_path)
+        .map_err(|err| format!("Unable to create {:?}: {}", archive_path, err))?;
+    let mut writer = LedgerArchiveWriter::new(BufWriter::new(file), format, header)
+        .map_err(|err| format!("Failed to write {:?}: {}", archive_path, err))?;
+    let slot_iterator = blockstore
+        .rooted_slot_iterator(starting_slot)
+        .map_err(|err| {
+            format!(
+                "Failed to load roots starting from slot {}: {:?}",
+                starting_slot, err
+            )
+        })?;
+
+    let mut num_slots = 0;
+    for slot in slot_iterator.take_while(|slot| *slot <= ending_slot) {
+        let demote_program_write_locks = demote_program_write_locks_slot
+            .map(|activation_slot| slot >= activation_slot)
+            .unwrap_or(false);
+        let archive_slot = match read_archive_slot(blockstore, slot, demote_program_write_locks) {
+            Ok(archive_slot) => archive_slot,
+            Err(LedgerArchiveError::Blockstore(BlockstoreError::SlotUnavailable)) => {
+                warn!("Skipping root {}, its block is unavailable", slot);
+                continue;
+            }
+            Err(err) => return Err(format!("Failed to read slot {}: {}", slot, err)),
+        };
+        writer
+            .write_slot(archive_slot)
+            .map_err(|err| format!("Failed to write slot {}: {}", slot, err))?;
+        num_slots += 1;
+    }
+    writer
+        .finish()
+        .map_err(|err| format!("Failed to write {:?}: {}", archive_path, err))?;
+    Ok(num_slots)
+}
+
+fn import_ledger(
+    blockstore: &Blockstore,
+    ledger_path: &Path,
+    archive_path: &Path,
+) -> Result<u64, String> {
+    let file = File::open(archive_path)
+        .map_err(|err| format!("Unable to open {:?}: {}", archive_path, err))?;
+    let reader = LedgerArchiveReader::new(BufReader::new(file))
+        .map_err(|err| format!("Failed to read {:?}: {}", archive_path, err))?;
+    let header = reader.header();
+    println!(
+        "Importing slots {} to {} from a {:?} archive created by {}",
+        header.starting_slot,
+        header.ending_slot,
+        reader.format(),
+        header.created_by
+    );
+    match GenesisConfig::load(ledger_path) {
+        Ok(genesis_config) => {
+            if genesis_config.hash().to_string() != header.genesis_hash {
+                return Err(format!(
+                    "The archive genesis hash {} doesn't match the ledger genesis hash {}",
+                    header.genesis_hash,
+                    genesis_config.hash()
+                ));
+            }
+        }
+        Err(_) => warn!(
+            "No genesis config found in {:?}, copy the one with hash {}",
+            ledger_path, header.genesis_hash
+        ),
+    }
+
+    // The whole archive is verified before any slot is written, a truncated or corrupt archive
+    // leaves the ledger as it was
+    for archive_slot in reader {
+        let archive_slot =
+            archive_slot.map_err(|err| format!("Failed to read {:?}: {}", archive_path, err))?;
+        let slot = archive_slot.slot;
+        verify_archive_slot(archive_slot)
+            .map_err(|err| format!("Failed to verify slot {}: {}", slot, err))?;
+    }
+
+    let file = File::open(archive_path)
+        .map_err(|err| format!("Unable to open {:?}: {}", archive_path, err))?;
+    let reader = LedgerArchiveReader::new(BufReader::new(file))
+        .map_err(|err| format!("Failed to read {:?}: {}", archive_path, err))?;
+    let mut imported_slots = vec![];
+    // The slots missing from the ledger before the import, purged when the import fails
+    let mut new_slots = vec![];
+    for archive_slot in reader {
+        let result = archive_slot
+            .map_err(|err| format!("Failed to read {:?}: {}", archive_path, err))
+            .and_then(|archive_slot| {
+                let slot = archive_slot.slot;
+                imported_slots.push(slot);
+                if !matches!(blockstore.meta(slot), Ok(Some(_))) {
+                    new_slots.push(slot);
+                }
+                write_archive_slot(blockstore, archive_slot)
+                    .map_err(|err| format!("Failed to import slot {}: {}", slot, err))
+            });
+        if let Err(err) = result {
+            // The imported slots are only rooted once all of them are written
+            for slot in new_slots {
+                blockstore.purge_slots(slot, slot, PurgeType::Exact);
+            }
+            return Err(err);
+        }
+    }
+    blockstore
+        .set_roots(imported_slots.iter())
+        .map_err(|err| format!("Failed to root the imported slots: {}", err))?;
+    Ok(imported_slots.len() as u64)
+}
+
 fn render_dot(dot: String, output_file: &str, output_format: &str) -> io::Result<()> {
     let mut child = Command::new("dot")
         .arg(format!("-T{}", output_format))
@@ -639,6 +785,93 @@ fn analyze_column<
     println!("{}", serde_json::to_string_pretty(&json_result).unwrap());
 }
 
+fn output_column_storage_stats(column_storage_stats: &[ColumnStorageStats]) {
+    // The compression given by --blockstore-compression, not necessarily the one the existing
+    // SST files were written with
+    println!(
+        "{:<26} {:>22} {:>16} {:>16} {:>8}",
+        "Column", "Configured compression", "On-disk bytes", "Raw bytes", "Ratio"
+    );
+    for stats in column_storage_stats {
+        let configured_compression = stats
+            .configured_compression_type
+            .map(|compression_type| format!("{:?}", compression_type).to_lowercase())
+            .unwrap_or_else(|| "default".to_string());
+        let ratio = stats
+            .compression_ratio()
+            .map(|ratio| format!("{:.2}", ratio))
+            .unwrap_or_else(|| "-".to_string());
+        println!(
+            "{:<26} {:>22} {:>16} {:>16} {:>8}",
+            stats.name, configured_compression, stats.sst_files_bytes, stats.raw_bytes, ratio
+        );
+    }
+    println!(
+        "{:<26} {:>22} {:>16} {:>16}",
+        "Total",
+        "",
+        column_storage_stats
+            .iter()
+            .map(|stats| stats.sst_files_bytes)
+            .sum::<u64>(),
+        column_storage_stats
+            .iter()
+            .map(|stats| stats.raw_bytes)
+            .sum::<u64>(),
+    );
+}
+
+fn output_integrity_report(report: &IntegrityReport, output_format: Option<&str>) {
+    match output_format {
+        Some("json") => println!("{}", serde_json::to_string_pretty(report).unwrap()),
+        Some("json-compact") => println!("{}", serde_json::to_string(report).unwrap()),
+        _ => {
+            for issue in &report.issues {
+                println!("{}", issue);
+            }
+            println!(
+                "Checked slots {} to {}: {} slots, {} roots, {} data shreds, {} coding shreds",
+                report.start_slot,
+                report.end_slot,
+                report.slots_checked,
+                report.roots_checked,
+                report.data_shreds_checked,
+                report.coding_shreds_checked,
+            );
+            println!(
+                "{} issues found, {} repaired",
+                report.issues.len(),
+                report.num_repaired()
+            );
+        }
+    }
+}
+
+fn output_diff_report(report: &LedgerDiffReport, output_format: Option<&str>) {
+    match output_format {
+        Some("json") => println!("{}", serde_json::to_string_pretty(report).unwrap()),
+        Some("json-compact") => println!("{}", serde_json::to_string(report).unwrap()),
+        _ => {
+            match &report.first_divergence {
+                Some(divergence) => println!("{}", divergence),
+                None => println!("No divergence found"),
+            }
+            println!(
+                "Compared {} slots from {} to {}",
+                report.slots_compared, report.start_slot, report.end_slot
+            );
+        }
+    }
+}
+
+fn output_transaction_trace(trace: &TransactionTrace, output_format: Option<&str>) {
+    match output_format {
+        Some("json") => println!("{}", serde_json::to_string_pretty(trace).unwrap()),
+        Some("json-compact") => println!("{}", serde_json::to_string(trace).unwrap()),
+        _ => print!("{}", trace),
+    }
+}
+
 fn analyze_storage(database: &Database) {
     use blockstore_db::columns::*;
     analyze_column::<SlotMeta>(database, "SlotMeta", SlotMeta::key_size());
@@ -664,6 +897,11 @@ fn analyze_storage(database: &Database) {
         "AddressSignatures",
         AddressSignatures::key_size(),
     );
+    analyze_column::<ProgramSignatures>(
+        database,
+        "ProgramSignatures",
+        ProgramSignatures::key_size(),
+    );
     analyze_column::<Rewards>(database, "Rewards", Rewards::key_size());
 }
 
@@ -672,7 +910,18 @@ fn open_blockstore(
     access_type: AccessType,
     wal_recovery_mode: Option<BlockstoreRecoveryMode>,
 ) -> Blockstore {
-    match Blockstore::open_with_access_type(ledger_path, access_type, wal_recovery_mode, true) {
+    open_blockstore_with_options(
+        ledger_path,
+        BlockstoreOptions {
+            access_type,
+            recovery_mode: wal_recovery_mode,
+            ..BlockstoreOptions::default()
+        },
+    )
+}
+
+fn open_blockstore_with_options(ledger_path: &Path, options: BlockstoreOptions) -> Blockstore {
+    match Blockstore::open_with_options(ledger_path, options) {
         Ok(blockstore) => blockstore,
         Err(err) => {
             eprintln!("Failed to open ledger at {:?}: {:?}", ledger_path, err);
@@ -681,8 +930,17 @@ fn open_blockstore(
     }
 }
 
-fn open_database(ledger_path: &Path, access_type: AccessType) -> Database {
-    match Database::open(&ledger_path.join("rocksdb"), access_type, None) {
+fn open_database(
+    ledger_path: &Path,
+    access_type: AccessType,
+    compression: BlockstoreCompressionConfig,
+) -> Database {
+    let options = BlockstoreOptions {
+        access_type,
+        compression,
+        ..BlockstoreOptions::default()
+    };
+    match Database::open_with_options(&ledger_path.join("rocksdb"), options) {
         Ok(database) => database,
         Err(err) => {
             eprintln!("Unable to read the Ledger rocksdb: {:?}", err);
@@ -700,13 +958,11 @@ fn hardforks_of(matches: &ArgMatches<'_>, name: &str) -> Option<Vec<Slot>> {
     }
 }
 
-fn load_bank_forks(
+fn get_snapshot_config(
     arg_matches: &ArgMatches,
-    genesis_config: &GenesisConfig,
     blockstore: &Blockstore,
-    process_options: ProcessOptions,
     snapshot_archive_path: Option<PathBuf>,
-) -> bank_forks_utils::LoadResult {
+) -> Option<SnapshotConfig> {
     let bank_snapshots_dir = blockstore
         .ledger_path()
         .join(if blockstore.is_primary_access() {
@@ -714,7 +970,7 @@ fn load_bank_forks(
         } else {
             "snapshot.ledger-tool"
         });
-    let snapshot_config = if arg_matches.is_present("no_snapshot") {
+    if arg_matches.is_present("no_snapshot") {
         None
     } else {
         let snapshot_archives_dir =
@@ -726,8 +982,11 @@ fn load_bank_forks(
             bank_snapshots_dir,
             ..SnapshotConfig::default()
         })
-    };
-    let account_paths = if let Some(account_paths) = arg_matches.value_of("account_paths") {
+    }
+}
+
+fn get_account_paths(arg_matches: &ArgMatches, blockstore: &Blockstore) -> Vec<PathBuf> {
+    if let Some(account_paths) = arg_matches.value_of("account_paths") {
         if !blockstore.is_primary_access() {
             // Be defensive, when default account dir is explicitly specified, it's still possible
             // to wipe the dir possibly shared by the running validator!
@@ -744,8 +1003,18 @@ fn load_bank_forks(
             non_primary_accounts_path
         );
         vec![non_primary_accounts_path]
-    };
+    }
+}
 
+fn load_bank_forks(
+    arg_matches: &ArgMatches,
+    genesis_config: &GenesisConfig,
+    blockstore: &Blockstore,
+    process_options: ProcessOptions,
+    snapshot_archive_path: Option<PathBuf>,
+) -> bank_forks_utils::LoadResult {
+    let snapshot_config = get_snapshot_config(arg_matches, blockstore, snapshot_archive_path);
+    let account_paths = get_account_paths(arg_matches, blockstore);
     let (accounts_package_sender, _) = channel();
     bank_forks_utils::load(
         genesis_config,
@@ -761,6 +1030,45 @@ fn load_bank_forks(
     )
 }
 
+/// Load the bank of the latest snapshot as it is, without replaying the ledger on top of it
+fn load_snapshot_bank(
+    arg_matches: &ArgMatches,
+    genesis_config: &GenesisConfig,
+    blockstore: &Blockstore,
+    snapshot_archive_path: Option<PathBuf>,
+) -> Result<Bank, String> {
+    let snapshot_config = get_snapshot_config(arg_matches, blockstore, snapshot_archive_path)
+        .ok_or_else(|| "Snapshots are disabled".to_string())?;
+    let _ = fs::remove_dir_all(&snapshot_config.bank_snapshots_dir);
+    fs::create_dir_all(&snapshot_config.bank_snapshots_dir).map_err(|err| {
+        format!(
+            "Unable to create {:?}: {}",
+            snapshot_config.bank_snapshots_dir, err
+        )
+    })?;
+    let process_options = ProcessOptions::default();
+    snapshot_utils::bank_from_latest_snapshot_archives(
+        &snapshot_config.bank_snapshots_dir,
+        &snapshot_config.snapshot_archives_dir,
+        &get_account_paths(arg_matches, blockstore),
+        &process_options.frozen_accounts,
+        genesis_config,
+        process_options.debug_keys.clone(),
+        Some(&solana_ledger::builtins::get(process_options.bpf_jit)),
+        process_options.account_indexes.clone(),
+        process_options.accounts_db_caching_enabled,
+        process_options.limit_load_slot_count_from_snapshot,
+        process_options.shrink_ratio,
+        process_options.accounts_db_test_hash_calculation,
+        process_options.accounts_db_skip_shrink,
+        process_options.verify_index,
+        process_options.accounts_db_config.clone(),
+        None,
+    )
+    .map(|(bank, ..)| bank)
+    .map_err(|err| format!("Failed to load the latest snapshot: {}", err))
+}
+
 fn compute_slot_cost(blockstore: &Blockstore, slot: Slot) -> Result<(), String> {
     if blockstore.is_dead(slot) {
         return Err("Dead slot".to_string());
@@ -1036,6 +1344,35 @@ fn main() {
                     "Mode to recovery the ledger db write ahead log"
                 ),
         )
+        .arg(
+            Arg::with_name("blockstore_compression")
+                .long("blockstore-compression")
+                .value_name("COLUMN=TYPE")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .global(true)
+                .validator(|value| {
+                    BlockstoreCompressionConfig::parse_column_value(&value).map(|_| ())
+                })
+                .help(
+                    "Compress the COLUMN of the ledger db with TYPE: none, lz4 or zstd. \
+                     Only the data written afterwards is affected, e.g. by the copy command"
+                ),
+        )
+        .arg(
+            Arg::with_name("shred_zstd_dictionary_bytes")
+                .long("shred-zstd-dictionary-bytes")
+                .value_name("BYTES")
+                .takes_value(true)
+                .default_value("0")
+                .global(true)
+                .validator(is_parsable::<u32>)
+                .help(
+                    "Size of the zstd dictionary trained for the zstd compressed shred columns, \
+                     0 disables the dictionary"
+                ),
+        )
         .arg(
             Arg::with_name("snapshot_archive_path")
                 .long("snapshot-archive-path")
@@ -1052,7 +1389,8 @@ fn main() {
                 .takes_value(true)
                 .possible_values(&["json", "json-compact"])
                 .help("Return information in specified output format, \
-                       currently only available for bigtable subcommands"),
+                       currently only available for bigtable, verify-blockstore, diff \
+                       and replay-transaction subcommands"),
         )
         .arg(
             Arg::with_name("verbose")
@@ -1064,6 +1402,8 @@ fn main() {
                 .help("Show additional information where supported"),
         )
         .bigtable_subcommand()
+        .serve_subcommand()
+        .tower_subcommand()
         .subcommand(
             SubCommand::with_name("print")
             .about("Print the ledger")
@@ -1098,6 +1438,43 @@ fn main() {
                     .help("Target db"),
             )
         )
+        .subcommand(
+            SubCommand::with_name("export")
+            .about("Export the rooted blocks of a slot range to a portable archive")
+            .arg(&starting_slot_arg)
+            .arg(&ending_slot_arg)
+            .arg(&max_genesis_archive_unpacked_size_arg)
+            .arg(
+                Arg::with_name("archive")
+                    .long("archive")
+                    .value_name("PATH")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Path of the archive to create"),
+            )
+            .arg(
+                Arg::with_name("format")
+                    .long("format")
+                    .value_name("FORMAT")
+                    .takes_value(true)
+                    .possible_values(&["car", "protobuf-stream"])
+                    .default_value("car")
+                    .help("Archive format"),
+            )
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+            .about("Import the blocks of an archive created by the export command into \
+                    the ledger")
+            .arg(
+                Arg::with_name("archive")
+                    .long("archive")
+                    .value_name("PATH")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Path of the archive to import"),
+            )
+        )
         .subcommand(
             SubCommand::with_name("slot")
             .about("Print the contents of one or more slots")
@@ -1602,11 +1979,85 @@ fn main() {
                         .help("Override the maximum number of slots to check for root repair")
                 )
         )
+        .subcommand(
+            SubCommand::with_name("verify-blockstore")
+                .about("Check that the slot metas, shred indexes, erasure metas, shreds \
+                        and roots in the ledger agree with each other")
+                .arg(&starting_slot_arg)
+                .arg(&ending_slot_arg)
+                .arg(
+                    Arg::with_name("check_transaction_statuses")
+                        .long("check-transaction-statuses")
+                        .takes_value(false)
+                        .help("Require a transaction status for every transaction of a rooted \
+                               slot, for ledgers written with transaction history enabled")
+                )
+                .arg(
+                    Arg::with_name("repair")
+                        .long("repair")
+                        .takes_value(false)
+                        .help("Rewrite the slot metas, shred indexes and erasure metas that \
+                               disagree with the shreds present")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("Compare the slots of two ledgers and report the first divergence \
+                        in their shreds, entries, transaction statuses or bank hashes")
+                .arg(
+                    Arg::with_name("other_ledger")
+                        .long("other-ledger")
+                        .value_name("DIR")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Path to the ledger to compare against"),
+                )
+                .arg(
+                    Arg::with_name("slot")
+                        .long("slot")
+                        .value_name("SLOT")
+                        .validator(is_slot)
+                        .takes_value(true)
+                        .required(true)
+                        .help("Start comparing at this slot"),
+                )
+                .arg(&ending_slot_arg)
+        )
+        .subcommand(
+            SubCommand::with_name("replay-transaction")
+                .about("Re-execute a transaction on top of the bank at its parent slot and \
+                        trace its instructions, compute units, account changes and logs")
+                .arg(&no_snapshot_arg)
+                .arg(&account_paths_arg)
+                .arg(&max_genesis_archive_unpacked_size_arg)
+                .arg(
+                    Arg::with_name("signature")
+                        .index(1)
+                        .value_name("SIGNATURE")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Signature of the transaction to replay"),
+                )
+                .arg(
+                    Arg::with_name("slot")
+                        .long("slot")
+                        .value_name("SLOT")
+                        .validator(is_slot)
+                        .takes_value(true)
+                        .help("Slot containing the transaction, required when the ledger \
+                               has no transaction status for it"),
+                )
+        )
         .subcommand(
             SubCommand::with_name("analyze-storage")
                 .about("Output statistics in JSON format about \
                         all column families in the ledger rocksdb")
         )
+        .subcommand(
+            SubCommand::with_name("column-sizes")
+                .about("Print the on-disk size, the compression ratio and the configured \
+                        compression of all column families in the ledger rocksdb")
+        )
         .subcommand(
             SubCommand::with_name("compute-slot-cost")
             .about("runs cost_model over the block at the given slots, \
@@ -1652,10 +2103,27 @@ fn main() {
     let wal_recovery_mode = matches
         .value_of("wal_recovery_mode")
         .map(BlockstoreRecoveryMode::from);
+    let blockstore_compression = BlockstoreCompressionConfig::from_column_values(
+        matches
+            .values_of("blockstore_compression")
+            .into_iter()
+            .flatten(),
+        value_t_or_exit!(matches, "shred_zstd_dictionary_bytes", u32),
+    )
+    .unwrap_or_else(|err| {
+        eprintln!("Invalid --blockstore-compression: {}", err);
+        exit(1);
+    });
     let verbose_level = matches.occurrences_of("verbose");
 
     match matches.subcommand() {
         ("bigtable", Some(arg_matches)) => bigtable_process_command(&ledger_path, arg_matches),
+        ("serve", Some(arg_matches)) => {
+            serve_process_command(&ledger_path, arg_matches, wal_recovery_mode)
+        }
+        ("tower", Some(arg_matches)) => {
+            tower_process_command(&ledger_path, arg_matches, wal_recovery_mode)
+        }
         ("print", Some(arg_matches)) => {
             let starting_slot = value_t_or_exit!(arg_matches, "starting_slot", Slot);
             let ending_slot = value_t!(arg_matches, "ending_slot", Slot).unwrap_or(Slot::MAX);
@@ -1682,7 +2150,13 @@ fn main() {
             let ending_slot = value_t_or_exit!(arg_matches, "ending_slot", Slot);
             let target_db = PathBuf::from(value_t_or_exit!(arg_matches, "target_db", String));
             let source = open_blockstore(&ledger_path, AccessType::TryPrimaryThenSecondary, None);
-            let target = open_blockstore(&target_db, AccessType::PrimaryOnly, None);
+            let target = open_blockstore_with_options(
+                &target_db,
+                BlockstoreOptions {
+                    compression: blockstore_compression,
+                    ..BlockstoreOptions::default()
+                },
+            );
             for (slot, _meta) in source.slot_meta_iterator(starting_slot).unwrap() {
                 if slot > ending_slot {
                     break;
@@ -1694,6 +2168,91 @@ fn main() {
                 }
             }
         }
+        ("export", Some(arg_matches)) => {
+            let starting_slot = value_t_or_exit!(arg_matches, "starting_slot", Slot);
+            let ending_slot = value_t!(arg_matches, "ending_slot", Slot).unwrap_or(Slot::MAX);
+            let archive_path = PathBuf::from(value_t_or_exit!(arg_matches, "archive", String));
+            let format = value_t_or_exit!(arg_matches, "format", LedgerArchiveFormat);
+            let blockstore = open_blockstore(
+                &ledger_path,
+                AccessType::TryPrimaryThenSecondary,
+                wal_recovery_mode,
+            );
+            let genesis_config = open_genesis_config_by(&ledger_path, arg_matches);
+            let header = ArchiveHeader {
+                version: ARCHIVE_VERSION,
+                starting_slot,
+                ending_slot,
+                genesis_hash: genesis_config.hash().to_string(),
+                created_by: format!("{} {}", crate_name!(), solana_version::version!()),
+            };
+            // The feature account holds the slot the feature was activated at, it is read from
+            // the latest snapshot, or from the genesis config when there is no snapshot
+            let feature_id = feature_set::demote_program_write_locks::id();
+            let (feature_account, feature_slot) = match load_snapshot_bank(
+                arg_matches,
+                &genesis_config,
+                &blockstore,
+                snapshot_archive_path,
+            ) {
+                Ok(bank) => (bank.get_account(&feature_id), bank.slot()),
+                Err(err) => {
+                    warn!("{}, reading the features from the genesis config", err);
+                    (
+                        genesis_config
+                            .accounts
+                            .get(&feature_id)
+                            .cloned()
+                            .map(AccountSharedData::from),
+                        0,
+                    )
+                }
+            };
+            let demote_program_write_locks_slot = feature_account
+                .and_then(|account| feature::from_account(&account))
+                .and_then(|feature| feature.activated_at);
+            if demote_program_write_locks_slot.is_none() && ending_slot > feature_slot {
+                warn!(
+                    "The program write lock demotion is inactive as of slot {}, the later slots \
+                     are exported as if it is still inactive",
+                    feature_slot
+                );
+            }
+            match export_ledger(
+                &blockstore,
+                starting_slot,
+                ending_slot,
+                demote_program_write_locks_slot,
+                format,
+                header,
+                &archive_path,
+            ) {
+                Ok(num_slots) => println!("Exported {} slots to {:?}", num_slots, archive_path),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    exit(1);
+                }
+            }
+        }
+        ("import", Some(arg_matches)) => {
+            let archive_path = PathBuf::from(value_t_or_exit!(arg_matches, "archive", String));
+            let blockstore = open_blockstore_with_options(
+                &ledger_path,
+                BlockstoreOptions {
+                    recovery_mode: wal_recovery_mode,
+                    compression: blockstore_compression,
+                    enable_program_signatures: true,
+                    ..BlockstoreOptions::default()
+                },
+            );
+            match import_ledger(&blockstore, &ledger_path, &archive_path) {
+                Ok(num_slots) => println!("Imported {} slots", num_slots),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    exit(1);
+                }
+            }
+        }
         ("genesis", Some(arg_matches)) => {
             println!("{}", open_genesis_config_by(&ledger_path, arg_matches));
         }
@@ -3290,9 +3849,155 @@ fn main() {
             analyze_storage(&open_database(
                 &ledger_path,
                 AccessType::TryPrimaryThenSecondary,
+                blockstore_compression,
             ));
             println!("Ok.");
         }
+        ("verify-blockstore", Some(arg_matches)) => {
+            let starting_slot = value_t_or_exit!(arg_matches, "starting_slot", Slot);
+            let ending_slot = value_t!(arg_matches, "ending_slot", Slot).unwrap_or(Slot::MAX);
+            let options = IntegrityCheckOptions {
+                check_transaction_statuses: arg_matches.is_present("check_transaction_statuses"),
+                repair: arg_matches.is_present("repair"),
+            };
+            let access_type = if options.repair {
+                AccessType::PrimaryOnly
+            } else {
+                AccessType::TryPrimaryThenSecondary
+            };
+            let blockstore = open_blockstore(&ledger_path, access_type, wal_recovery_mode);
+            let exit_signal = Arc::new(AtomicBool::new(false));
+            match blockstore.check_integrity(starting_slot, ending_slot, &options, &exit_signal) {
+                Ok(report) => {
+                    output_integrity_report(&report, arg_matches.value_of("output_format"));
+                    if report.issues.iter().any(|issue| !issue.repaired) {
+                        exit(1);
+                    }
+                }
+                Err(err) => {
+                    eprintln!("Failed to check the blockstore integrity: {:?}", err);
+                    exit(1);
+                }
+            }
+        }
+        ("diff", Some(arg_matches)) => {
+            let other_ledger_path =
+                PathBuf::from(value_t_or_exit!(arg_matches, "other_ledger", String));
+            let slot = value_t_or_exit!(arg_matches, "slot", Slot);
+            let ending_slot = value_t!(arg_matches, "ending_slot", Slot).unwrap_or(Slot::MAX);
+            let blockstore = open_blockstore(
+                &ledger_path,
+                AccessType::TryPrimaryThenSecondary,
+                wal_recovery_mode,
+            );
+            let other_blockstore = open_blockstore(
+                &other_ledger_path,
+                AccessType::TryPrimaryThenSecondary,
+                wal_recovery_mode,
+            );
+            let exit_signal = Arc::new(AtomicBool::new(false));
+            match blockstore.diff(&other_blockstore, slot, ending_slot, &exit_signal) {
+                Ok(report) => {
+                    output_diff_report(&report, arg_matches.value_of("output_format"));
+                    if report.first_divergence.is_some() {
+                        exit(1);
+                    }
+                }
+                Err(err) => {
+                    eprintln!("Failed to compare the ledgers: {:?}", err);
+                    exit(1);
+                }
+            }
+        }
+        ("replay-transaction", Some(arg_matches)) => {
+            let signature = value_t_or_exit!(arg_matches, "signature", Signature);
+            let blockstore = open_blockstore(
+                &ledger_path,
+                AccessType::TryPrimaryThenSecondary,
+                wal_recovery_mode,
+            );
+            let slot = match value_t!(arg_matches, "slot", Slot) {
+                Ok(slot) => slot,
+                Err(_) => match blockstore.get_rooted_transaction_status(signature) {
+                    Ok(Some((slot, _))) => slot,
+                    Ok(None) => {
+                        eprintln!(
+                            "Error: No rooted transaction status for {}, use --slot",
+                            signature
+                        );
+                        exit(1);
+                    }
+                    Err(err) => {
+                        eprintln!("Failed to read the transaction status: {:?}", err);
+                        exit(1);
+                    }
+                },
+            };
+            let parent_slot = match blockstore.meta(slot) {
+                Ok(Some(meta)) if meta.is_parent_set() => meta.parent_slot,
+                _ => {
+                    eprintln!("Error: Parent of slot {} is unknown", slot);
+                    exit(1);
+                }
+            };
+            let entries = blockstore.get_slot_entries(slot, 0).unwrap_or_else(|err| {
+                eprintln!("Failed to load entries of slot {}: {:?}", slot, err);
+                exit(1);
+            });
+
+            let genesis_config = open_genesis_config_by(&ledger_path, arg_matches);
+            let bank_forks = match load_bank_forks(
+                arg_matches,
+                &genesis_config,
+                &blockstore,
+                ProcessOptions {
+                    dev_halt_at_slot: Some(parent_slot),
+                    poh_verify: false,
+                    ..ProcessOptions::default()
+                },
+                snapshot_archive_path,
+            ) {
+                Ok((bank_forks, ..)) => bank_forks,
+                Err(err) => {
+                    eprintln!("Failed to load ledger: {:?}", err);
+                    exit(1);
+                }
+            };
+            let parent_bank = bank_forks
+                .get(parent_slot)
+                .unwrap_or_else(|| {
+                    eprintln!("Error: Slot {} is not available", parent_slot);
+                    exit(1);
+                })
+                .clone();
+            let collector_id = leader_schedule_utils::slot_leader_at(slot, &parent_bank)
+                .unwrap_or_else(|| *parent_bank.collector_id());
+            let bank = Bank::new_from_parent(&parent_bank, &collector_id, slot);
+
+            match replay_transaction(&bank, entries, &signature) {
+                Ok(trace) => {
+                    output_transaction_trace(&trace, arg_matches.value_of("output_format"))
+                }
+                Err(err) => {
+                    eprintln!("Failed to replay transaction {}: {}", signature, err);
+                    exit(1);
+                }
+            }
+        }
+        ("column-sizes", _) => {
+            let database = open_database(
+                &ledger_path,
+                AccessType::TryPrimaryThenSecondary,
+                blockstore_compression,
+            );
+            match database.column_storage_stats() {
+                Ok(column_storage_stats) => output_column_storage_stats(&column_storage_stats),
+                Err(err) => {
+                    eprintln!("Failed to read the column sizes: {:?}", err);
+                    exit(1);
+                }
+            }
+        }
         ("compute-slot-cost", Some(arg_matches)) => {
             let blockstore = open_blockstore(
                 &ledger_path,
@@ -0,0 +1,533 @@
+/// The `replay-transaction` subcommand, re-executes a single transaction on top of the bank state
+/// that preceded it and traces every instruction and cross-program invocation it ran
+use serde::Serialize;
+use solana_entry::entry::Entry;
+use solana_program_runtime::stable_log::{parse_invoke_traces, InvokeOutcome, InvokeTrace};
+use solana_runtime::bank::{
+    Bank, ExecuteTimings, InnerInstructionsList, TransactionLogMessages, TransactionResults,
+};
+use solana_sdk::{
+    account::{AccountSharedData, ReadableAccount},
+    clock::{Slot, MAX_PROCESSING_AGE},
+    instruction::CompiledInstruction,
+    message::SanitizedMessage,
+    pubkey::Pubkey,
+    signature::Signature,
+    transaction::{SanitizedTransaction, VersionedTransaction},
+};
+use std::fmt;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionTrace {
+    pub signature: String,
+    pub slot: Slot,
+    pub parent_slot: Slot,
+    /// Number of transactions of the slot that were replayed ahead of this one
+    pub preceding_transactions: usize,
+    pub err: Option<String>,
+    pub compute_units_consumed: u64,
+    pub instructions: Vec<InstructionTrace>,
+    pub account_diffs: Vec<AccountDiff>,
+    pub log_messages: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstructionTrace {
+    pub program_id: String,
+    pub depth: usize,
+    pub accounts: Vec<String>,
+    /// Base58 encoded instruction data
+    pub data: String,
+    pub compute_units_consumed: Option<u64>,
+    pub compute_units_limit: Option<u64>,
+    pub logs: Vec<String>,
+    pub return_data: Option<String>,
+    /// `None` if the logs ended before the instruction did
+    pub succeeded: Option<bool>,
+    pub err: Option<String>,
+    pub inner_instructions: Vec<InstructionTrace>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountDiff {
+    pub pubkey: String,
+    pub pre_lamports: u64,
+    pub post_lamports: u64,
+    pub pre_owner: String,
+    pub post_owner: String,
+    pub pre_data_len: usize,
+    pub post_data_len: usize,
+    pub data_changes: Vec<DataChange>,
+}
+
+/// A run of bytes that differs between the pre and post account data, hex encoded
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataChange {
+    pub offset: usize,
+    pub pre: String,
+    pub post: String,
+}
+
+impl fmt::Display for TransactionTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Transaction: {}", self.signature)?;
+        writeln!(
+            f,
+            "Slot: {} (parent {}), replayed after {} preceding transactions",
+            self.slot, self.parent_slot, self.preceding_transactions
+        )?;
+        writeln!(
+            f,
+            "Status: {}",
+            self.err
+                .as_deref()
+                .map_or("Ok".to_string(), |err| format!("Error: {}", err))
+        )?;
+        writeln!(f, "Compute units consumed: {}", self.compute_units_consumed)?;
+        writeln!(f, "Instructions:")?;
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            write_instruction_trace(f, instruction, &format!("#{}", index + 1))?;
+        }
+        writeln!(f, "Account changes:")?;
+        if self.account_diffs.is_empty() {
+            writeln!(f, "  none")?;
+        }
+        for diff in &self.account_diffs {
+            writeln!(f, "  {}", diff.pubkey)?;
+            if diff.pre_lamports != diff.post_lamports {
+                writeln!(
+                    f,
+                    "    lamports: {} -> {}",
+                    diff.pre_lamports, diff.post_lamports
+                )?;
+            }
+            if diff.pre_owner != diff.post_owner {
+                writeln!(f, "    owner: {} -> {}", diff.pre_owner, diff.post_owner)?;
+            }
+            if diff.pre_data_len != diff.post_data_len {
+                writeln!(
+                    f,
+                    "    data length: {} -> {}",
+                    diff.pre_data_len, diff.post_data_len
+                )?;
+            }
+            for change in &diff.data_changes {
+                writeln!(
+                    f,
+                    "    data[{}..{}]: {} -> {}",
+                    change.offset,
+                    change.offset + change.pre.len().max(change.post.len()) / 2,
+                    change.pre,
+                    change.post
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_instruction_trace(
+    f: &mut fmt::Formatter,
+    trace: &InstructionTrace,
+    label: &str,
+) -> fmt::Result {
+    let indent = "  ".repeat(trace.depth);
+    let status = match (trace.succeeded, &trace.err) {
+        (Some(true), _) => "success".to_string(),
+        (Some(false), Some(err)) => format!("failed: {}", err),
+        _ => "incomplete".to_string(),
+    };
+    writeln!(f, "{}{} {} {}", indent, label, trace.program_id, status)?;
+    if let Some(consumed) = trace.compute_units_consumed {
+        writeln!(
+            f,
+            "{}  compute units: {} of {}",
+            indent,
+            consumed,
+            trace.compute_units_limit.unwrap_or_default()
+        )?;
+    }
+    writeln!(f, "{}  accounts: {}", indent, trace.accounts.join(", "))?;
+    writeln!(f, "{}  data: {}", indent, trace.data)?;
+    for log in &trace.logs {
+        writeln!(f, "{}  {}", indent, log)?;
+    }
+    if let Some(return_data) = &trace.return_data {
+        writeln!(f, "{}  return data: {}", indent, return_data)?;
+    }
+    for (index, inner) in trace.inner_instructions.iter().enumerate() {
+        write_instruction_trace(f, inner, &format!("{}.{}", label, index + 1))?;
+    }
+    Ok(())
+}
+
+/// Replay `entries` of `bank`'s slot up to and including the transaction with `signature`,
+/// recording the logs and inner instructions of that transaction only.
+///
+/// `bank` must be a fresh child of the bank that the slot was originally replayed on.
+pub fn replay_transaction(
+    bank: &Bank,
+    entries: Vec<Entry>,
+    signature: &Signature,
+) -> Result<TransactionTrace, String> {
+    let mut preceding_transactions = 0;
+    for entry in entries {
+        if entry.is_tick() {
+            bank.register_tick(&entry.hash);
+            continue;
+        }
+
+        let position = entry
+            .transactions
+            .iter()
+            .position(|transaction| transaction.signatures.first() == Some(signature));
+        let mut transactions = sanitize_transactions(bank, entry.transactions)?;
+        let position = match position {
+            Some(position) => position,
+            None => {
+                preceding_transactions += transactions.len();
+                execute_transactions(bank, &transactions, false);
+                continue;
+            }
+        };
+
+        transactions.truncate(position + 1);
+        let transaction = transactions.pop().unwrap();
+        if !transactions.is_empty() {
+            preceding_transactions += transactions.len();
+            execute_transactions(bank, &transactions, false);
+        }
+
+        let account_keys: Vec<Pubkey> =
+            transaction.message().account_keys_iter().copied().collect();
+        let pre_accounts = load_accounts(bank, &account_keys);
+        let (results, mut inner_instructions, mut transaction_logs) =
+            execute_transactions(bank, std::slice::from_ref(&transaction), true);
+        let post_accounts = load_accounts(bank, &account_keys);
+
+        let err = results
+            .fee_collection_results
+            .into_iter()
+            .next()
+            .and_then(|result| result.err())
+            .map(|err| err.to_string());
+        let log_messages = transaction_logs.pop().flatten().unwrap_or_default();
+        let inner_instructions = inner_instructions.pop().flatten().unwrap_or_default();
+        let instructions = build_instruction_traces(
+            transaction.message(),
+            &parse_invoke_traces(&log_messages),
+            &inner_instructions,
+        );
+
+        return Ok(TransactionTrace {
+            signature: signature.to_string(),
+            slot: bank.slot(),
+            parent_slot: bank.parent_slot(),
+            preceding_transactions,
+            err,
+            compute_units_consumed: instructions
+                .iter()
+                .filter_map(|instruction| instruction.compute_units_consumed)
+                .sum(),
+            instructions,
+            account_diffs: account_keys
+                .iter()
+                .zip(pre_accounts.iter().zip(post_accounts.iter()))
+                .filter_map(|(pubkey, (pre, post))| diff_account(pubkey, pre, post))
+                .collect(),
+            log_messages,
+        });
+    }
+    Err(format!(
+        "Transaction {} not found in slot {}",
+        signature,
+        bank.slot()
+    ))
+}
+
+fn sanitize_transactions(
+    bank: &Bank,
+    transactions: Vec<VersionedTransaction>,
+) -> Result<Vec<SanitizedTransaction>, String> {
+    transactions
+        .into_iter()
+        .map(|transaction| {
+            // The signatures were verified when the slot was first replayed
+            bank.verify_transaction(transaction, true)
+                .map_err(|err| format!("Failed to sanitize transaction: {}", err))
+        })
+        .collect()
+}
+
+fn execute_transactions(
+    bank: &Bank,
+    transactions: &[SanitizedTransaction],
+    record: bool,
+) -> (
+    TransactionResults,
+    Vec<Option<InnerInstructionsList>>,
+    Vec<Option<TransactionLogMessages>>,
+) {
+    let batch = bank.prepare_sanitized_batch(transactions);
+    let mut timings = ExecuteTimings::default();
+    let (results, _balances, inner_instructions, transaction_logs) = bank
+        .load_execute_and_commit_transactions(
+            &batch,
+            MAX_PROCESSING_AGE,
+            false,
+            record,
+            record,
+            &mut timings,
+        );
+    (results, inner_instructions, transaction_logs)
+}
+
+fn load_accounts(bank: &Bank, account_keys: &[Pubkey]) -> Vec<AccountSharedData> {
+    account_keys
+        .iter()
+        .map(|pubkey| bank.get_account(pubkey).unwrap_or_default())
+        .collect()
+}
+
+/// Attach the instructions of `message` and the recorded cross-program invocations to the
+/// invocation tree parsed from the logs.
+///
+/// Invocations are recorded in the order they are made, which is a pre-order walk of each
+/// top-level instruction's tree.
+fn build_instruction_traces(
+    message: &SanitizedMessage,
+    invoke_traces: &[InvokeTrace],
+    inner_instructions: &InnerInstructionsList,
+) -> Vec<InstructionTrace> {
+    message
+        .instructions()
+        .iter()
+        .zip(invoke_traces)
+        .enumerate()
+        .map(|(index, (instruction, invoke_trace))| {
+            let mut cpis = inner_instructions
+                .get(index)
+                .map(|cpis| cpis.iter())
+                .unwrap_or_else(|| [].iter());
+            build_instruction_trace(message, invoke_trace, Some(instruction), &mut cpis)
+        })
+        .collect()
+}
+
+fn build_instruction_trace<'a>(
+    message: &SanitizedMessage,
+    invoke_trace: &InvokeTrace,
+    instruction: Option<&CompiledInstruction>,
+    cpis: &mut dyn Iterator<Item = &'a CompiledInstruction>,
+) -> InstructionTrace {
+    let (accounts, data) = instruction
+        .map(|instruction| {
+            let accounts = instruction
+                .accounts
+                .iter()
+                .filter_map(|index| message.get_account_key(*index as usize))
+                .map(|pubkey| pubkey.to_string())
+                .collect();
+            (accounts, bs58::encode(&instruction.data).into_string())
+        })
+        .unwrap_or_default();
+    let inner_instructions = invoke_trace
+        .inner
+        .iter()
+        .map(|inner| {
+            let instruction = cpis.next();
+            build_instruction_trace(message, inner, instruction, cpis)
+        })
+        .collect();
+    let (succeeded, err) = match &invoke_trace.outcome {
+        Some(InvokeOutcome::Success) => (Some(true), None),
+        Some(InvokeOutcome::Failure(err)) => (Some(false), Some(err.clone())),
+        None => (None, None),
+    };
+
+    InstructionTrace {
+        program_id: invoke_trace.program_id.to_string(),
+        depth: invoke_trace.depth,
+        accounts,
+        data,
+        compute_units_consumed: invoke_trace.compute_units_consumed,
+        compute_units_limit: invoke_trace.compute_units_limit,
+        logs: invoke_trace.logs.clone(),
+        return_data: invoke_trace.return_data.clone(),
+        succeeded,
+        err,
+        inner_instructions,
+    }
+}
+
+fn diff_account(
+    pubkey: &Pubkey,
+    pre: &AccountSharedData,
+    post: &AccountSharedData,
+) -> Option<AccountDiff> {
+    if pre == post {
+        return None;
+    }
+    Some(AccountDiff {
+        pubkey: pubkey.to_string(),
+        pre_lamports: pre.lamports(),
+        post_lamports: post.lamports(),
+        pre_owner: pre.owner().to_string(),
+        post_owner: post.owner().to_string(),
+        pre_data_len: pre.data().len(),
+        post_data_len: post.data().len(),
+        data_changes: diff_data(pre.data(), post.data()),
+    })
+}
+
+/// Group the differing bytes of `pre` and `post` into contiguous runs; bytes past the end of the
+/// shorter buffer count as differing.
+fn diff_data(pre: &[u8], post: &[u8]) -> Vec<DataChange> {
+    let len = pre.len().max(post.len());
+    let differs = |offset: usize| pre.get(offset) != post.get(offset);
+    let hex = |data: &[u8], range: std::ops::Range<usize>| {
+        data.get(range.start.min(data.len())..range.end.min(data.len()))
+            .unwrap_or_default()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>()
+    };
+
+    let mut changes = vec![];
+    let mut offset = 0;
+    while offset < len {
+        if !differs(offset) {
+            offset += 1;
+            continue;
+        }
+        let start = offset;
+        while offset < len && differs(offset) {
+            offset += 1;
+        }
+        changes.push(DataChange {
+            offset: start,
+            pre: hex(pre, start..offset),
+            post: hex(post, start..offset),
+        });
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_sdk::{hash::Hash, message::Message},
+        std::convert::TryFrom,
+    };
+
+    fn data_changes(pre: &[u8], post: &[u8]) -> Vec<(usize, String, String)> {
+        diff_data(pre, post)
+            .into_iter()
+            .map(|change| (change.offset, change.pre, change.post))
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_data() {
+        assert!(data_changes(&[], &[]).is_empty());
+        assert!(data_changes(&[1, 2, 3], &[1, 2, 3]).is_empty());
+
+        // Contiguous differing bytes are grouped into one change
+        assert_eq!(
+            data_changes(&[1, 2, 3, 4, 5], &[1, 9, 9, 4, 6]),
+            vec![
+                (1, "0203".to_string(), "0909".to_string()),
+                (4, "05".to_string(), "06".to_string()),
+            ]
+        );
+
+        // Bytes past the end of the shorter data differ
+        assert_eq!(
+            data_changes(&[1], &[1, 2, 3]),
+            vec![(1, "".to_string(), "0203".to_string())]
+        );
+        assert_eq!(
+            data_changes(&[1, 2, 0xff], &[7]),
+            vec![(0, "0102ff".to_string(), "07".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_build_instruction_traces() {
+        let payer = Pubkey::new_unique();
+        let account = Pubkey::new_unique();
+        let outer = Pubkey::new_unique();
+        let inner = Pubkey::new_unique();
+        let message = SanitizedMessage::try_from(Message::new_with_compiled_instructions(
+            1,
+            0,
+            2,
+            vec![payer, account, outer, inner],
+            Hash::default(),
+            vec![
+                CompiledInstruction::new_from_raw_parts(2, vec![1, 2, 3], vec![0, 1]),
+                CompiledInstruction::new_from_raw_parts(3, vec![], vec![1]),
+            ],
+        ))
+        .unwrap();
+        let log_messages: Vec<String> = vec![
+            format!("Program {} invoke [1]", outer),
+            format!("Program {} invoke [2]", inner),
+            "Program log: inner".to_string(),
+            format!("Program {} consumed 100 of 199800 compute units", inner),
+            format!("Program {} success", inner),
+            format!("Program {} consumed 300 of 200000 compute units", outer),
+            format!("Program {} success", outer),
+            format!("Program {} invoke [1]", inner),
+            "Log truncated".to_string(),
+        ];
+        let invoke_traces = parse_invoke_traces(&log_messages);
+        let inner_instructions = vec![
+            vec![CompiledInstruction::new_from_raw_parts(3, vec![4], vec![1])],
+            vec![],
+        ];
+
+        let traces = build_instruction_traces(&message, &invoke_traces, &inner_instructions);
+        assert_eq!(traces.len(), 2);
+
+        let first = &traces[0];
+        assert_eq!(first.program_id, outer.to_string());
+        assert_eq!(first.depth, 1);
+        assert_eq!(first.accounts, vec![payer.to_string(), account.to_string()]);
+        assert_eq!(first.data, bs58::encode(&[1, 2, 3]).into_string());
+        assert_eq!(first.compute_units_consumed, Some(300));
+        assert_eq!(first.succeeded, Some(true));
+        assert_eq!(first.err, None);
+        assert_eq!(first.inner_instructions.len(), 1);
+
+        let cpi = &first.inner_instructions[0];
+        assert_eq!(cpi.program_id, inner.to_string());
+        assert_eq!(cpi.depth, 2);
+        assert_eq!(cpi.accounts, vec![account.to_string()]);
+        assert_eq!(cpi.data, bs58::encode(&[4]).into_string());
+        assert_eq!(cpi.logs, vec!["Program log: inner".to_string()]);
+        assert_eq!(cpi.compute_units_consumed, Some(100));
+        assert_eq!(cpi.succeeded, Some(true));
+
+        // The logs were truncated during the second instruction
+        let second = &traces[1];
+        assert_eq!(second.program_id, inner.to_string());
+        assert_eq!(second.accounts, vec![account.to_string()]);
+        assert_eq!(second.data, "");
+        assert_eq!(second.succeeded, None);
+        assert!(second.inner_instructions.is_empty());
+
+        // Invocations that were not recorded have no accounts or data
+        let traces =
+            build_instruction_traces(&message, &invoke_traces, &InnerInstructionsList::default());
+        let cpi = &traces[0].inner_instructions[0];
+        assert_eq!(cpi.program_id, inner.to_string());
+        assert!(cpi.accounts.is_empty());
+        assert_eq!(cpi.data, "");
+    }
+}
@@ -0,0 +1,682 @@
+/// The `serve` subcommand, a JSON-RPC endpoint for the historical queries a Blockstore can
+/// answer on its own
+use clap::{value_t_or_exit, App, Arg, ArgMatches, SubCommand};
+use jsonrpc_core::{Error, IoHandler, Params, Result, Value};
+use jsonrpc_http_server::{AccessControlAllowOrigin, DomainsValidation, ServerBuilder};
+use log::*;
+use serde::{de::DeserializeOwned, Serialize};
+use solana_clap_utils::input_validators::{is_parsable, is_port};
+use solana_client::{
+    rpc_config::{
+        RpcBlockConfig, RpcBlocksConfigWrapper, RpcEncodingConfigWrapper,
+        RpcSignaturesForAddressConfig, RpcTransactionConfig,
+    },
+    rpc_custom_error::RpcCustomError,
+    rpc_request::{
+        MAX_GET_CONFIRMED_BLOCKS_RANGE, MAX_GET_CONFIRMED_SIGNATURES_FOR_ADDRESS2_LIMIT,
+    },
+    rpc_response::RpcConfirmedTransactionStatusWithSignature,
+};
+use solana_ledger::{
+    blockstore::Blockstore,
+    blockstore_db::{AccessType, BlockstoreError, BlockstoreRecoveryMode},
+};
+use solana_sdk::{
+    clock::{Slot, UnixTimestamp},
+    commitment_config::CommitmentConfig,
+    pubkey::Pubkey,
+    signature::Signature,
+};
+use solana_transaction_status::{
+    EncodedConfirmedTransaction, TransactionConfirmationStatus, TransactionDetails,
+    UiConfirmedBlock, UiTransactionEncoding,
+};
+use std::{
+    cmp::{max, min},
+    net::{IpAddr, SocketAddr},
+    path::Path,
+    process::exit,
+    sync::Arc,
+    thread::{self, sleep},
+    time::Duration,
+};
+
+const DEFAULT_RPC_PORT: &str = "8899";
+const DEFAULT_RPC_THREADS: &str = "4";
+const DEFAULT_CATCH_UP_INTERVAL_MS: &str = "1000";
+
+/// Answers the RPC methods that only need the ledger, see `JsonRpcRequestProcessor` of the
+/// validator's RPC service for their semantics. A Blockstore has no bank to tell which slots
+/// are confirmed, so every query is served from rooted slots only.
+#[derive(Clone)]
+pub struct BlockstoreRpc {
+    blockstore: Arc<Blockstore>,
+}
+
+impl BlockstoreRpc {
+    pub fn new(blockstore: Arc<Blockstore>) -> Self {
+        Self { blockstore }
+    }
+
+    pub fn get_block(
+        &self,
+        slot: Slot,
+        config: Option<RpcEncodingConfigWrapper<RpcBlockConfig>>,
+    ) -> Result<Option<UiConfirmedBlock>> {
+        let config = config
+            .map(|config| config.convert_to_current())
+            .unwrap_or_default();
+        check_commitment(config.commitment)?;
+        let encoding = config.encoding.unwrap_or(UiTransactionEncoding::Json);
+        let transaction_details = config
+            .transaction_details
+            .unwrap_or(TransactionDetails::Full);
+        let show_rewards = config.rewards.unwrap_or(true);
+
+        if slot > self.blockstore.max_root() {
+            return Err(RpcCustomError::BlockNotAvailable { slot }.into());
+        }
+        let result = self.blockstore.get_rooted_block(slot, true);
+        self.check_blockstore_result(&result, slot)?;
+        Ok(result
+            .ok()
+            .map(|block| block.configure(encoding, transaction_details, show_rewards)))
+    }
+
+    pub fn get_transaction(
+        &self,
+        signature: String,
+        config: Option<RpcEncodingConfigWrapper<RpcTransactionConfig>>,
+    ) -> Result<Option<EncodedConfirmedTransaction>> {
+        let signature = verify_signature(&signature)?;
+        let config = config
+            .map(|config| config.convert_to_current())
+            .unwrap_or_default();
+        check_commitment(config.commitment)?;
+        let encoding = config.encoding.unwrap_or(UiTransactionEncoding::Json);
+
+        Ok(self
+            .blockstore
+            .get_rooted_transaction(signature)
+            .map_err(internal_error)?
+            .map(|transaction| transaction.encode(encoding)))
+    }
+
+    pub fn get_signatures_for_address(
+        &self,
+        address: String,
+        config: Option<RpcSignaturesForAddressConfig>,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        let address = verify_pubkey(&address)?;
+        let config = config.unwrap_or_default();
+        check_commitment(config.commitment)?;
+        let before = config
+            .before
+            .map(|signature| verify_signature(&signature))
+            .transpose()?;
+        let until = config
+            .until
+            .map(|signature| verify_signature(&signature))
+            .transpose()?;
+        let limit = config
+            .limit
+            .unwrap_or(MAX_GET_CONFIRMED_SIGNATURES_FOR_ADDRESS2_LIMIT);
+        if limit == 0 || limit > MAX_GET_CONFIRMED_SIGNATURES_FOR_ADDRESS2_LIMIT {
+            return Err(Error::invalid_params(format!(
+                "Invalid limit; max {}",
+                MAX_GET_CONFIRMED_SIGNATURES_FOR_ADDRESS2_LIMIT
+            )));
+        }
+
+        let statuses = self
+            .blockstore
+            .get_confirmed_signatures_for_address2(
+                address,
+                self.blockstore.max_root(),
+                before,
+                until,
+                limit,
+            )
+            .map_err(internal_error)?;
+        Ok(statuses
+            .into_iter()
+            .map(|status| {
+                let mut status = RpcConfirmedTransactionStatusWithSignature::from(status);
+                status.confirmation_status = Some(TransactionConfirmationStatus::Finalized);
+                status
+            })
+            .collect())
+    }
+
+    pub fn get_block_time(&self, slot: Slot) -> Result<Option<UnixTimestamp>> {
+        if slot > self.blockstore.max_root() {
+            return Err(RpcCustomError::BlockNotAvailable { slot }.into());
+        }
+        let result = self.blockstore.get_block_time(slot);
+        self.check_blockstore_result(&result, slot)?;
+        match result {
+            Ok(Some(timestamp)) => Ok(Some(timestamp)),
+            _ => Err(RpcCustomError::BlockNotAvailable { slot }.into()),
+        }
+    }
+
+    pub fn get_blocks(
+        &self,
+        start_slot: Slot,
+        config: Option<RpcBlocksConfigWrapper>,
+        commitment: Option<CommitmentConfig>,
+    ) -> Result<Vec<Slot>> {
+        let (end_slot, maybe_commitment) = config.map(|config| config.unzip()).unwrap_or_default();
+        check_commitment(commitment.or(maybe_commitment))?;
+
+        let end_slot = min(
+            end_slot.unwrap_or_else(|| start_slot.saturating_add(MAX_GET_CONFIRMED_BLOCKS_RANGE)),
+            self.blockstore.max_root(),
+        );
+        if end_slot < start_slot {
+            return Ok(vec![]);
+        }
+        if end_slot - start_slot > MAX_GET_CONFIRMED_BLOCKS_RANGE {
+            return Err(Error::invalid_params(format!(
+                "Slot range too large; max {}",
+                MAX_GET_CONFIRMED_BLOCKS_RANGE
+            )));
+        }
+
+        Ok(self
+            .blockstore
+            .rooted_slot_iterator(max(start_slot, self.blockstore.lowest_slot()))
+            .map_err(internal_error)?
+            .take_while(|slot| *slot <= end_slot)
+            .collect())
+    }
+
+    pub fn get_first_available_block(&self) -> Result<Slot> {
+        Ok(self
+            .blockstore
+            .get_first_available_block()
+            .unwrap_or_default())
+    }
+
+    /// Map the Blockstore errors of a rooted slot query onto the RPC errors of the validator
+    fn check_blockstore_result<T>(
+        &self,
+        result: &std::result::Result<T, BlockstoreError>,
+        slot: Slot,
+    ) -> Result<()> {
+        match result {
+            Ok(_) => Ok(()),
+            Err(BlockstoreError::SlotCleanedUp) => Err(RpcCustomError::BlockCleanedUp {
+                slot,
+                first_available_block: self.get_first_available_block()?,
+            }
+            .into()),
+            Err(_) if self.blockstore.is_skipped(slot) => {
+                Err(RpcCustomError::SlotSkipped { slot }.into())
+            }
+            Err(_) => Err(RpcCustomError::BlockNotAvailable { slot }.into()),
+        }
+    }
+
+    pub fn into_io_handler(self) -> IoHandler {
+        let mut io = IoHandler::default();
+
+        let rpc = self.clone();
+        io.add_sync_method("getBlock", move |params| {
+            let (slot, config) = parse_params(params, 2)?;
+            to_value(rpc.get_block(slot, config)?)
+        });
+        let rpc = self.clone();
+        io.add_sync_method("getTransaction", move |params| {
+            let (signature, config) = parse_params(params, 2)?;
+            to_value(rpc.get_transaction(signature, config)?)
+        });
+        let rpc = self.clone();
+        io.add_sync_method("getSignaturesForAddress", move |params| {
+            let (address, config) = parse_params(params, 2)?;
+            to_value(rpc.get_signatures_for_address(address, config)?)
+        });
+        let rpc = self.clone();
+        io.add_sync_method("getBlockTime", move |params| {
+            let (slot,) = parse_params(params, 1)?;
+            to_value(rpc.get_block_time(slot)?)
+        });
+        let rpc = self.clone();
+        io.add_sync_method("getBlocks", move |params| {
+            let (start_slot, config, commitment) = parse_params(params, 3)?;
+            to_value(rpc.get_blocks(start_slot, config, commitment)?)
+        });
+        let rpc = self;
+        io.add_sync_method("getFirstAvailableBlock", move |_params| {
+            to_value(rpc.get_first_available_block()?)
+        });
+
+        io
+    }
+}
+
+/// Deserialize positional parameters into a tuple, treating missing trailing parameters as
+/// `null` like the `#[rpc]` derived methods of the validator do
+fn parse_params<T: DeserializeOwned>(params: Params, num_params: usize) -> Result<T> {
+    let mut values = match params {
+        Params::None => vec![],
+        Params::Array(values) => values,
+        Params::Map(_) => {
+            return Err(Error::invalid_params("Expected an array of parameters"));
+        }
+    };
+    if values.len() > num_params {
+        return Err(Error::invalid_params(format!(
+            "Expected at most {} parameters, found {}",
+            num_params,
+            values.len()
+        )));
+    }
+    values.resize(num_params, Value::Null);
+    serde_json::from_value(Value::Array(values))
+        .map_err(|err| Error::invalid_params(format!("Invalid params: {}", err)))
+}
+
+fn to_value<T: Serialize>(value: T) -> Result<Value> {
+    serde_json::to_value(value).map_err(internal_error)
+}
+
+fn internal_error<E: std::fmt::Debug>(err: E) -> Error {
+    warn!("blockstore rpc error: {:?}", err);
+    Error::internal_error()
+}
+
+fn check_commitment(commitment: Option<CommitmentConfig>) -> Result<()> {
+    match commitment {
+        Some(commitment) if !commitment.is_at_least_confirmed() => Err(Error::invalid_params(
+            "Method does not support commitment below `confirmed`",
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn verify_signature(input: &str) -> Result<Signature> {
+    input
+        .parse()
+        .map_err(|e| Error::invalid_params(format!("Invalid param: {:?}", e)))
+}
+
+fn verify_pubkey(input: &str) -> Result<Pubkey> {
+    input
+        .parse()
+        .map_err(|e| Error::invalid_params(format!("Invalid param: {:?}", e)))
+}
+
+pub trait ServeSubCommand {
+    fn serve_subcommand(self) -> Self;
+}
+
+impl ServeSubCommand for App<'_, '_> {
+    fn serve_subcommand(self) -> Self {
+        self.subcommand(
+            SubCommand::with_name("serve")
+                .about(
+                    "Serve getBlock, getTransaction, getSignaturesForAddress, getBlockTime, \
+                     getBlocks and getFirstAvailableBlock over JSON-RPC from the ledger, \
+                     alongside a running validator",
+                )
+                .arg(
+                    Arg::with_name("rpc_bind_address")
+                        .long("rpc-bind-address")
+                        .value_name("HOST")
+                        .takes_value(true)
+                        .default_value("127.0.0.1")
+                        .validator(is_parsable::<IpAddr>)
+                        .help("IP address to bind the RPC port"),
+                )
+                .arg(
+                    Arg::with_name("rpc_port")
+                        .long("rpc-port")
+                        .value_name("PORT")
+                        .takes_value(true)
+                        .default_value(DEFAULT_RPC_PORT)
+                        .validator(is_port)
+                        .help("Port for the JSON RPC API"),
+                )
+                .arg(
+                    Arg::with_name("rpc_threads")
+                        .long("rpc-threads")
+                        .value_name("NUMBER")
+                        .takes_value(true)
+                        .default_value(DEFAULT_RPC_THREADS)
+                        .validator(is_parsable::<usize>)
+                        .help("Number of threads to use for servicing RPC requests"),
+                )
+                .arg(
+                    Arg::with_name("catch_up_interval_ms")
+                        .long("catch-up-interval-ms")
+                        .value_name("MILLISECONDS")
+                        .takes_value(true)
+                        .default_value(DEFAULT_CATCH_UP_INTERVAL_MS)
+                        .validator(is_parsable::<u64>)
+                        .help(
+                            "How often to pick up the writes of the validator when the ledger \
+                             was opened as a secondary",
+                        ),
+                ),
+        )
+    }
+}
+
+pub fn serve_process_command(
+    ledger_path: &Path,
+    matches: &ArgMatches<'_>,
+    wal_recovery_mode: Option<BlockstoreRecoveryMode>,
+) {
+    let bind_address = value_t_or_exit!(matches, "rpc_bind_address", IpAddr);
+    let rpc_port = value_t_or_exit!(matches, "rpc_port", u16);
+    let rpc_threads = value_t_or_exit!(matches, "rpc_threads", usize);
+    let catch_up_interval =
+        Duration::from_millis(value_t_or_exit!(matches, "catch_up_interval_ms", u64));
+
+    let blockstore = Arc::new(crate::open_blockstore(
+        ledger_path,
+        AccessType::TryPrimaryThenSecondary,
+        wal_recovery_mode,
+    ));
+    if !blockstore.is_primary_access() {
+        let blockstore = blockstore.clone();
+        thread::Builder::new()
+            .name("sol-serve-catchup".to_string())
+            .spawn(move || loop {
+                if let Err(err) = blockstore.try_catch_up_with_primary() {
+                    warn!("Failed to catch up with the primary blockstore: {:?}", err);
+                }
+                sleep(catch_up_interval);
+            })
+            .unwrap();
+    }
+
+    let io = BlockstoreRpc::new(blockstore).into_io_handler();
+    let rpc_addr = SocketAddr::new(bind_address, rpc_port);
+    let server = ServerBuilder::new(io)
+        .threads(rpc_threads)
+        .cors(DomainsValidation::AllowOnly(vec![
+            AccessControlAllowOrigin::Any,
+        ]))
+        .start_http(&rpc_addr)
+        .unwrap_or_else(|err| {
+            eprintln!("Unable to start the RPC service on {}: {}", rpc_addr, err);
+            exit(1);
+        });
+    println!(
+        "Serving the ledger at {} on http://{}",
+        ledger_path.display(),
+        rpc_addr
+    );
+    server.wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        jsonrpc_core::ErrorCode,
+        serde_json::json,
+        solana_client::rpc_custom_error::JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE,
+        solana_entry::entry::next_entry,
+        solana_ledger::{blockstore::entries_to_test_shreds, get_tmp_ledger_path_auto_delete},
+        solana_sdk::{
+            hash::Hash,
+            signature::{Keypair, Signer},
+            system_transaction,
+        },
+        solana_transaction_status::TransactionStatusMeta,
+    };
+
+    // Slots 0 to 2 are rooted and slot 3 is not. The only transaction, in slot 1, transfers to
+    // the returned address and its slot has a block time of 1_000.
+    fn setup_blockstore_rpc(ledger_path: &Path) -> (BlockstoreRpc, Signature, Pubkey) {
+        let blockstore = Blockstore::open(ledger_path).unwrap();
+        let payer = Keypair::new();
+        let address = solana_sdk::pubkey::new_rand();
+        let transaction = system_transaction::transfer(&payer, &address, 1, Hash::default());
+        let signature = transaction.signatures[0];
+        for slot in 0..=3 {
+            let transactions = if slot == 1 {
+                vec![transaction.clone()]
+            } else {
+                vec![]
+            };
+            let entries = vec![next_entry(&Hash::default(), 1, transactions)];
+            let shreds = entries_to_test_shreds(entries, slot, slot.saturating_sub(1), true, 0);
+            blockstore.insert_shreds(shreds, None, false).unwrap();
+        }
+        blockstore
+            .write_transaction_status(
+                1,
+                signature,
+                vec![&payer.pubkey(), &address],
+                vec![],
+                TransactionStatusMeta::default(),
+            )
+            .unwrap();
+        blockstore.cache_block_time(1, 1_000).unwrap();
+        blockstore.set_roots(vec![0, 1, 2].iter()).unwrap();
+        (BlockstoreRpc::new(Arc::new(blockstore)), signature, address)
+    }
+
+    fn processed_commitment() -> Option<CommitmentConfig> {
+        Some(CommitmentConfig::processed())
+    }
+
+    #[test]
+    fn test_get_block() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let (rpc, signature, _) = setup_blockstore_rpc(ledger_path.path());
+
+        let block = rpc.get_block(1, None).unwrap().unwrap();
+        assert_eq!(block.parent_slot, 0);
+        assert_eq!(block.block_time, Some(1_000));
+        let transactions = block.transactions.unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert!(transactions[0].meta.is_some());
+
+        let config = RpcBlockConfig {
+            transaction_details: Some(TransactionDetails::Signatures),
+            ..RpcBlockConfig::default()
+        };
+        let block = rpc
+            .get_block(1, Some(RpcEncodingConfigWrapper::Current(Some(config))))
+            .unwrap()
+            .unwrap();
+        assert!(block.transactions.is_none());
+        assert_eq!(block.signatures, Some(vec![signature.to_string()]));
+
+        // Unrooted slots are not available
+        let err = rpc.get_block(3, None).unwrap_err();
+        assert_eq!(
+            err.code,
+            ErrorCode::ServerError(JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE)
+        );
+
+        let config = RpcBlockConfig {
+            commitment: processed_commitment(),
+            ..RpcBlockConfig::default()
+        };
+        let err = rpc
+            .get_block(1, Some(RpcEncodingConfigWrapper::Current(Some(config))))
+            .unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidParams);
+    }
+
+    #[test]
+    fn test_get_transaction() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let (rpc, signature, _) = setup_blockstore_rpc(ledger_path.path());
+
+        let transaction = rpc
+            .get_transaction(signature.to_string(), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(transaction.slot, 1);
+        assert_eq!(transaction.block_time, Some(1_000));
+        assert!(transaction.transaction.meta.is_some());
+
+        assert_eq!(
+            rpc.get_transaction(Signature::new(&[1; 64]).to_string(), None)
+                .unwrap(),
+            None
+        );
+        let err = rpc
+            .get_transaction("not a signature".to_string(), None)
+            .unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidParams);
+    }
+
+    #[test]
+    fn test_get_signatures_for_address() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let (rpc, signature, address) = setup_blockstore_rpc(ledger_path.path());
+
+        let statuses = rpc
+            .get_signatures_for_address(address.to_string(), None)
+            .unwrap();
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].signature, signature.to_string());
+        assert_eq!(statuses[0].slot, 1);
+        assert_eq!(
+            statuses[0].confirmation_status,
+            Some(TransactionConfirmationStatus::Finalized)
+        );
+
+        let config = RpcSignaturesForAddressConfig {
+            until: Some(signature.to_string()),
+            ..RpcSignaturesForAddressConfig::default()
+        };
+        assert!(rpc
+            .get_signatures_for_address(address.to_string(), Some(config))
+            .unwrap()
+            .is_empty());
+
+        let config = RpcSignaturesForAddressConfig {
+            limit: Some(0),
+            ..RpcSignaturesForAddressConfig::default()
+        };
+        let err = rpc
+            .get_signatures_for_address(address.to_string(), Some(config))
+            .unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidParams);
+
+        let config = RpcSignaturesForAddressConfig {
+            before: Some("not a signature".to_string()),
+            ..RpcSignaturesForAddressConfig::default()
+        };
+        let err = rpc
+            .get_signatures_for_address(address.to_string(), Some(config))
+            .unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidParams);
+
+        let err = rpc
+            .get_signatures_for_address("not an address".to_string(), None)
+            .unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidParams);
+    }
+
+    #[test]
+    fn test_get_block_time() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let (rpc, _, _) = setup_blockstore_rpc(ledger_path.path());
+
+        assert_eq!(rpc.get_block_time(1).unwrap(), Some(1_000));
+        // Slot 2 is rooted but has no block time
+        let err = rpc.get_block_time(2).unwrap_err();
+        assert_eq!(
+            err.code,
+            ErrorCode::ServerError(JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE)
+        );
+        let err = rpc.get_block_time(3).unwrap_err();
+        assert_eq!(
+            err.code,
+            ErrorCode::ServerError(JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE)
+        );
+    }
+
+    #[test]
+    fn test_get_blocks() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let (rpc, _, _) = setup_blockstore_rpc(ledger_path.path());
+
+        // Slot 0 is never reported, like the validator, and slot 3 is not rooted
+        assert_eq!(rpc.get_blocks(0, None, None).unwrap(), vec![1, 2]);
+        assert_eq!(
+            rpc.get_blocks(1, Some(RpcBlocksConfigWrapper::EndSlotOnly(Some(1))), None)
+                .unwrap(),
+            vec![1]
+        );
+        assert!(rpc
+            .get_blocks(2, Some(RpcBlocksConfigWrapper::EndSlotOnly(Some(1))), None)
+            .unwrap()
+            .is_empty());
+
+        let err = rpc.get_blocks(0, None, processed_commitment()).unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidParams);
+        let err = rpc
+            .get_blocks(
+                0,
+                Some(RpcBlocksConfigWrapper::CommitmentOnly(
+                    processed_commitment(),
+                )),
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidParams);
+    }
+
+    #[test]
+    fn test_get_first_available_block() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let (rpc, _, _) = setup_blockstore_rpc(ledger_path.path());
+
+        assert_eq!(rpc.get_first_available_block().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_io_handler() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let (rpc, _, _) = setup_blockstore_rpc(ledger_path.path());
+        let io = rpc.into_io_handler();
+
+        let request = json!({"jsonrpc": "2.0", "id": 1, "method": "getBlockTime", "params": [1]});
+        let response: Value =
+            serde_json::from_str(&io.handle_request_sync(&request.to_string()).unwrap()).unwrap();
+        assert_eq!(response["result"], json!(1_000));
+
+        let request = json!({"jsonrpc": "2.0", "id": 1, "method": "getFirstAvailableBlock"});
+        let response: Value =
+            serde_json::from_str(&io.handle_request_sync(&request.to_string()).unwrap()).unwrap();
+        assert_eq!(response["result"], json!(1));
+    }
+
+    #[test]
+    fn test_parse_params() {
+        // Missing trailing parameters are null
+        let (slot, config): (Slot, Option<RpcBlocksConfigWrapper>) =
+            parse_params(Params::Array(vec![json!(5)]), 2).unwrap();
+        assert_eq!(slot, 5);
+        assert!(config.is_none());
+        let (config,): (Option<RpcBlocksConfigWrapper>,) = parse_params(Params::None, 1).unwrap();
+        assert!(config.is_none());
+
+        let err = parse_params::<(Slot,)>(Params::Map(serde_json::Map::new()), 1).unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidParams);
+        assert_eq!(err.message, "Expected an array of parameters");
+
+        let err = parse_params::<(Slot,)>(Params::Array(vec![json!(1), json!(2)]), 1).unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidParams);
+        assert_eq!(err.message, "Expected at most 1 parameters, found 2");
+
+        let err = parse_params::<(Slot,)>(Params::Array(vec![json!("one")]), 1).unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidParams);
+        assert!(err.message.starts_with("Invalid params: "));
+
+        // A required parameter can not be left out
+        let err = parse_params::<(Slot,)>(Params::None, 1).unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidParams);
+    }
+}
@@ -0,0 +1,548 @@
+/// The `tower` subcommand, to inspect the towers saved by a validator
+use chrono::{TimeZone, Utc};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use solana_clap_utils::{input_parsers::pubkey_of, input_validators::is_pubkey};
+use solana_core::{
+    consensus::{
+        reconcile_blockstore_roots_with_tower, reconcile_blockstore_roots_with_tower_dry_run,
+        RootsReconciliation, Tower,
+    },
+    tower_storage::{FileTowerStorage, SavedTower, TowerStorage},
+};
+use solana_ledger::{
+    blockstore::Blockstore,
+    blockstore_db::{AccessType, BlockstoreRecoveryMode},
+};
+use solana_sdk::{clock::Slot, pubkey::Pubkey, slot_history::MAX_ENTRIES};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    process::exit,
+};
+
+fn load_saved_tower(path: &Path) -> Result<SavedTower, String> {
+    let file = File::open(path)
+        .map_err(|err| format!("Unable to open tower {}: {}", path.display(), err))?;
+    bincode::deserialize_from(BufReader::new(file))
+        .map_err(|err| format!("Unable to read tower {}: {}", path.display(), err))
+}
+
+/// Decodes a saved tower, along with the outcome of verifying it was signed by `identity`, or
+/// by the node it claims to be from if `identity` is not known
+fn decode_saved_tower(
+    saved_tower: SavedTower,
+    identity: Option<&Pubkey>,
+) -> Result<(Tower, Result<(), String>), String> {
+    let tower = saved_tower
+        .tower_unverified()
+        .map_err(|err| format!("Unable to decode tower: {}", err))?;
+    let node_pubkey = identity.copied().unwrap_or_else(|| tower.node_pubkey());
+    let verified = saved_tower
+        .try_into_tower(&node_pubkey)
+        .map(|_| ())
+        .map_err(|err| err.to_string());
+    Ok((tower, verified))
+}
+
+fn decode_tower_file(
+    path: &Path,
+    identity: Option<&Pubkey>,
+) -> Result<(Tower, Result<(), String>), String> {
+    decode_saved_tower(load_saved_tower(path)?, identity)
+        .map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+/// The tower file named by the `tower` argument, or else the one of `identity` in the ledger
+fn tower_file_of(ledger_path: &Path, matches: &ArgMatches<'_>) -> PathBuf {
+    match matches.value_of("tower") {
+        Some(tower) => PathBuf::from(tower),
+        None => FileTowerStorage::new(ledger_path.to_path_buf())
+            .filename(&pubkey_of(matches, "identity").unwrap()),
+    }
+}
+
+fn print_tower(tower: &Tower, verified: &Result<(), String>) {
+    println!("Node: {}", tower.node_pubkey());
+    match verified {
+        Ok(()) => println!("Signature: valid"),
+        Err(err) => println!("Signature: INVALID ({})", err),
+    }
+    let vote_state = tower.vote_state();
+    match vote_state.root_slot {
+        Some(root_slot) => println!("Root: {}", root_slot),
+        None => println!("Root: none"),
+    }
+    match tower.last_voted_slot_hash() {
+        Some((slot, hash)) => println!("Last vote: slot {} bank hash {}", slot, hash),
+        None => println!("Last vote: none"),
+    }
+    println!("Lockouts:");
+    println!(
+        "  {:>12} {:>14} {:>18}",
+        "Slot", "Confirmations", "Locked out until"
+    );
+    for lockout in vote_state.votes.iter().rev() {
+        println!(
+            "  {:>12} {:>14} {:>18}",
+            lockout.slot,
+            lockout.confirmation_count,
+            lockout.last_locked_out_slot()
+        );
+    }
+}
+
+/// The differences between two towers, one per line
+fn tower_differences(tower: &Tower, other_tower: &Tower) -> Vec<String> {
+    let mut differences = vec![];
+    if tower.node_pubkey() != other_tower.node_pubkey() {
+        differences.push(format!(
+            "Node: {} -> {}",
+            tower.node_pubkey(),
+            other_tower.node_pubkey()
+        ));
+    }
+    let (vote_state, other_vote_state) = (tower.vote_state(), other_tower.vote_state());
+    if vote_state.root_slot != other_vote_state.root_slot {
+        differences.push(format!(
+            "Root: {:?} -> {:?}",
+            vote_state.root_slot, other_vote_state.root_slot
+        ));
+    }
+    if tower.last_voted_slot_hash() != other_tower.last_voted_slot_hash() {
+        differences.push(format!(
+            "Last vote: {:?} -> {:?}",
+            tower.last_voted_slot_hash(),
+            other_tower.last_voted_slot_hash()
+        ));
+    }
+
+    let confirmations: BTreeMap<Slot, u32> = vote_state
+        .votes
+        .iter()
+        .map(|lockout| (lockout.slot, lockout.confirmation_count))
+        .collect();
+    let other_confirmations: BTreeMap<Slot, u32> = other_vote_state
+        .votes
+        .iter()
+        .map(|lockout| (lockout.slot, lockout.confirmation_count))
+        .collect();
+    let slots: BTreeSet<Slot> = confirmations
+        .keys()
+        .chain(other_confirmations.keys())
+        .copied()
+        .collect();
+    for slot in slots {
+        match (confirmations.get(&slot), other_confirmations.get(&slot)) {
+            (Some(confirmation_count), None) => {
+                differences.push(format!(
+                    "- lockout {} ({} confirmations)",
+                    slot, confirmation_count
+                ));
+            }
+            (None, Some(confirmation_count)) => {
+                differences.push(format!(
+                    "+ lockout {} ({} confirmations)",
+                    slot, confirmation_count
+                ));
+            }
+            (Some(confirmation_count), Some(other_confirmation_count))
+                if confirmation_count != other_confirmation_count =>
+            {
+                differences.push(format!(
+                    "~ lockout {} ({} -> {} confirmations)",
+                    slot, confirmation_count, other_confirmation_count
+                ));
+            }
+            _ => (),
+        }
+    }
+    differences
+}
+
+fn diff_towers(tower: &Tower, other_tower: &Tower) {
+    let differences = tower_differences(tower, other_tower);
+    if differences.is_empty() {
+        println!("Towers are identical");
+    }
+    for difference in differences {
+        println!("{}", difference);
+    }
+}
+
+/// Reports how the tower relates to the roots of the blockstore, which is what a validator
+/// checks on startup, and marks the tower root and its ancestors as roots in the blockstore
+/// like `reconcile_blockstore_roots_with_tower` if `reconcile` is set
+fn check_tower(tower: &Tower, blockstore: &Blockstore, reconcile: bool) -> Result<(), String> {
+    let tower_root = tower
+        .vote_state()
+        .root_slot
+        .ok_or_else(|| "The tower has no root".to_string())?;
+    let last_blockstore_root = blockstore.last_root();
+    println!("Tower root: {}", tower_root);
+    println!("Blockstore root: {}", last_blockstore_root);
+    println!("Blockstore lowest slot: {}", blockstore.lowest_slot());
+
+    if let Some(last_voted_slot) = tower.last_voted_slot() {
+        if last_voted_slot.saturating_add(MAX_ENTRIES) <= last_blockstore_root {
+            println!(
+                "Last voted slot {} is too old for the slot history of the blockstore root, \
+                 the validator will refuse to restore the tower",
+                last_voted_slot
+            );
+        }
+        if blockstore
+            .meta(last_voted_slot)
+            .map_err(|err| format!("Unable to read slot {}: {:?}", last_voted_slot, err))?
+            .is_none()
+        {
+            println!(
+                "Last voted slot {} is not in the blockstore",
+                last_voted_slot
+            );
+        }
+    }
+
+    match reconcile_blockstore_roots_with_tower_dry_run(tower, blockstore) {
+        RootsReconciliation::UpToDate => {
+            if blockstore.is_root(tower_root) {
+                println!("Tower root is a blockstore root, nothing to reconcile");
+            } else {
+                println!(
+                    "Tower root {} is behind the blockstore root but isn't a blockstore root \
+                     itself: the tower is from another fork or another ledger",
+                    tower_root
+                );
+            }
+            Ok(())
+        }
+        RootsReconciliation::MissingTowerRoot => Err(format!(
+            "Tower root {} is not in the blockstore: blockstore pruned or only tower \
+             moved into a new ledger?",
+            tower_root
+        )),
+        RootsReconciliation::NotDescending => Err(format!(
+            "Tower root {} does not descend from the blockstore root {}, \
+             the validator will panic on startup",
+            tower_root, last_blockstore_root
+        )),
+        RootsReconciliation::NewRoots(new_roots) => {
+            println!(
+                "Tower root is ahead of the blockstore, {} slots to mark as roots: {:?}",
+                new_roots.len(),
+                new_roots
+            );
+            if reconcile {
+                reconcile_blockstore_roots_with_tower(tower, blockstore)
+                    .map_err(|err| format!("Unable to reconcile roots: {:?}", err))?;
+                println!("Reconciled blockstore roots with the tower");
+            }
+            Ok(())
+        }
+    }
+}
+
+pub trait TowerSubCommand {
+    fn tower_subcommand(self) -> Self;
+}
+
+impl TowerSubCommand for App<'_, '_> {
+    fn tower_subcommand(self) -> Self {
+        let tower_arg = Arg::with_name("tower")
+            .index(1)
+            .value_name("TOWER_FILE")
+            .takes_value(true)
+            .required_unless("identity")
+            .help("Saved tower file, a tower-<IDENTITY>.bin or one of its history files");
+        let identity_arg = Arg::with_name("identity")
+            .long("identity")
+            .value_name("PUBKEY")
+            .takes_value(true)
+            .validator(is_pubkey)
+            .help(
+                "Verify the tower was signed by this validator identity [default: the node \
+                 recorded in the tower]. Without TOWER_FILE, use the tower of this identity \
+                 in the ledger directory",
+            );
+        self.subcommand(
+            SubCommand::with_name("tower")
+                .about("Inspect the towers saved by a validator")
+                .setting(AppSettings::InferSubcommands)
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .about("Decode and verify a saved tower")
+                        .arg(&tower_arg)
+                        .arg(&identity_arg),
+                )
+                .subcommand(
+                    SubCommand::with_name("history")
+                        .about("List the saved towers retained by a validator")
+                        .arg(
+                            Arg::with_name("identity")
+                                .index(1)
+                                .value_name("PUBKEY")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(is_pubkey)
+                                .help("Validator identity"),
+                        )
+                        .arg(
+                            Arg::with_name("tower_path")
+                                .long("tower-path")
+                                .value_name("DIR")
+                                .takes_value(true)
+                                .help("Directory of the saved towers [default: the ledger]"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("diff")
+                        .about("Show the differences between two saved towers")
+                        .arg(
+                            Arg::with_name("tower")
+                                .index(1)
+                                .value_name("TOWER_FILE")
+                                .takes_value(true)
+                                .required(true)
+                                .help("Saved tower file"),
+                        )
+                        .arg(
+                            Arg::with_name("other_tower")
+                                .index(2)
+                                .value_name("OTHER_TOWER_FILE")
+                                .takes_value(true)
+                                .required(true)
+                                .help("Saved tower file to compare with"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("check")
+                        .about("Check a saved tower against the roots of the ledger")
+                        .arg(&tower_arg)
+                        .arg(&identity_arg)
+                        .arg(
+                            Arg::with_name("reconcile")
+                                .long("reconcile")
+                                .takes_value(false)
+                                .help(
+                                    "Mark the tower root and its ancestors as roots in the \
+                                     ledger, as the validator does on startup",
+                                ),
+                        ),
+                ),
+        )
+    }
+}
+
+pub fn tower_process_command(
+    ledger_path: &Path,
+    matches: &ArgMatches<'_>,
+    wal_recovery_mode: Option<BlockstoreRecoveryMode>,
+) {
+    let result = match matches.subcommand() {
+        ("show", Some(arg_matches)) => {
+            let identity = pubkey_of(arg_matches, "identity");
+            decode_tower_file(&tower_file_of(ledger_path, arg_matches), identity.as_ref()).map(
+                |(tower, verified)| {
+                    print_tower(&tower, &verified);
+                },
+            )
+        }
+        ("history", Some(arg_matches)) => {
+            let identity = pubkey_of(arg_matches, "identity").unwrap();
+            let tower_path = arg_matches
+                .value_of("tower_path")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| ledger_path.to_path_buf());
+            FileTowerStorage::new(tower_path)
+                .load_history(&identity)
+                .map_err(|err| format!("Unable to load tower history: {}", err))
+                .and_then(|history| {
+                    if history.is_empty() {
+                        println!("No tower history for {}", identity);
+                    }
+                    for entry in history {
+                        let (tower, verified) =
+                            decode_saved_tower(entry.saved_tower, Some(&identity))?;
+                        println!(
+                            "{}  root {:>12}  last vote {:>12}  {}",
+                            Utc.timestamp_millis(entry.timestamp as i64).to_rfc3339(),
+                            tower
+                                .vote_state()
+                                .root_slot
+                                .map(|slot| slot.to_string())
+                                .unwrap_or_default(),
+                            tower
+                                .last_voted_slot()
+                                .map(|slot| slot.to_string())
+                                .unwrap_or_default(),
+                            if verified.is_ok() {
+                                "signature valid"
+                            } else {
+                                "signature INVALID"
+                            },
+                        );
+                    }
+                    Ok(())
+                })
+        }
+        ("diff", Some(arg_matches)) => {
+            let tower = decode_tower_file(Path::new(arg_matches.value_of("tower").unwrap()), None);
+            let other_tower = decode_tower_file(
+                Path::new(arg_matches.value_of("other_tower").unwrap()),
+                None,
+            );
+            tower.and_then(|(tower, _)| {
+                other_tower.map(|(other_tower, _)| diff_towers(&tower, &other_tower))
+            })
+        }
+        ("check", Some(arg_matches)) => {
+            let identity = pubkey_of(arg_matches, "identity");
+            let reconcile = arg_matches.is_present("reconcile");
+            decode_tower_file(&tower_file_of(ledger_path, arg_matches), identity.as_ref()).and_then(
+                |(tower, verified)| {
+                    if let Err(err) = verified {
+                        println!("Warning: the tower signature is invalid: {}", err);
+                    }
+                    let access_type = if reconcile {
+                        AccessType::PrimaryOnly
+                    } else {
+                        AccessType::TryPrimaryThenSecondary
+                    };
+                    let blockstore =
+                        crate::open_blockstore(ledger_path, access_type, wal_recovery_mode);
+                    check_tower(&tower, &blockstore, reconcile)
+                },
+            )
+        }
+        _ => unreachable!(),
+    };
+
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_ledger::{blockstore::make_slot_entries, get_tmp_ledger_path_auto_delete},
+        solana_sdk::{
+            hash::Hash,
+            signature::{Keypair, Signer},
+        },
+        solana_vote_program::vote_state::MAX_LOCKOUT_HISTORY,
+    };
+
+    // A tower of consecutive votes, from slot 1, up to the ones that root `root`
+    fn new_tower(node_pubkey: &Pubkey, root: Slot) -> Tower {
+        let mut tower = Tower::new_with_key(node_pubkey);
+        for slot in 1..=root + MAX_LOCKOUT_HISTORY as Slot {
+            tower.record_vote(slot, Hash::default());
+        }
+        assert_eq!(tower.root(), root);
+        tower
+    }
+
+    // Round trip through the serialization, like a tower loaded from storage
+    fn save_tower(tower: &Tower, keypair: &Keypair) -> SavedTower {
+        let saved_tower = SavedTower::new(tower, keypair).unwrap();
+        bincode::deserialize(&bincode::serialize(&saved_tower).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_decode_saved_tower() {
+        let keypair = Keypair::new();
+        let tower = new_tower(&keypair.pubkey(), 3);
+
+        let (decoded_tower, verified) =
+            decode_saved_tower(save_tower(&tower, &keypair), None).unwrap();
+        assert_eq!(decoded_tower.node_pubkey(), keypair.pubkey());
+        assert_eq!(decoded_tower.root(), 3);
+        assert!(tower_differences(&tower, &decoded_tower).is_empty());
+        assert!(verified.is_ok());
+        let (_, verified) =
+            decode_saved_tower(save_tower(&tower, &keypair), Some(&keypair.pubkey())).unwrap();
+        assert!(verified.is_ok());
+
+        // The tower is still decoded when it wasn't signed by the expected identity
+        let (decoded_tower, verified) =
+            decode_saved_tower(save_tower(&tower, &keypair), Some(&Pubkey::new_unique())).unwrap();
+        assert_eq!(decoded_tower.node_pubkey(), keypair.pubkey());
+        assert!(verified.is_err());
+
+        assert!(decode_saved_tower(SavedTower::default(), None).is_err());
+    }
+
+    #[test]
+    fn test_tower_differences() {
+        let node_pubkey = Pubkey::new_unique();
+        let tower = new_tower(&node_pubkey, 1);
+        assert!(tower_differences(&tower, &tower).is_empty());
+
+        // Voting on slot 34 roots slot 2, the confirmations of the other lockouts go up by one
+        let mut other_tower = tower.clone();
+        other_tower.record_vote(34, Hash::default());
+        let differences = tower_differences(&tower, &other_tower);
+        assert_eq!(differences[0], "Root: Some(1) -> Some(2)");
+        assert!(differences[1].starts_with("Last vote: Some((33, "));
+        assert_eq!(differences[2], "- lockout 2 (32 confirmations)");
+        assert_eq!(differences[3], "~ lockout 3 (31 -> 32 confirmations)");
+        assert_eq!(differences[34], "+ lockout 34 (1 confirmations)");
+        assert_eq!(differences.len(), 35);
+
+        let other_tower = new_tower(&Pubkey::new_unique(), 1);
+        assert_eq!(
+            tower_differences(&tower, &other_tower),
+            vec![format!(
+                "Node: {} -> {}",
+                node_pubkey,
+                other_tower.node_pubkey()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_check_tower() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let blockstore = Blockstore::open(ledger_path.path()).unwrap();
+        for (slot, parent_slot) in &[(1, 0), (2, 1), (3, 1), (4, 3)] {
+            let (shreds, _) = make_slot_entries(*slot, *parent_slot, 42);
+            blockstore.insert_shreds(shreds, None, false).unwrap();
+        }
+        let node_pubkey = Pubkey::new_unique();
+
+        // The tower is ahead of the blockstore, the roots are only reconciled when asked to
+        let tower = new_tower(&node_pubkey, 4);
+        check_tower(&tower, &blockstore, false).unwrap();
+        assert_eq!(blockstore.last_root(), 0);
+        check_tower(&tower, &blockstore, true).unwrap();
+        assert_eq!(blockstore.last_root(), 4);
+        assert!(blockstore.is_root(1));
+        assert!(!blockstore.is_root(2));
+        assert!(blockstore.is_root(3));
+        check_tower(&tower, &blockstore, false).unwrap();
+
+        // Slot 5 was pruned
+        assert!(check_tower(&new_tower(&node_pubkey, 5), &blockstore, false).is_err());
+    }
+
+    #[test]
+    fn test_check_tower_not_descending() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let blockstore = Blockstore::open(ledger_path.path()).unwrap();
+        for (slot, parent_slot) in &[(1, 0), (2, 1), (3, 1), (4, 3)] {
+            let (shreds, _) = make_slot_entries(*slot, *parent_slot, 42);
+            blockstore.insert_shreds(shreds, None, false).unwrap();
+        }
+        blockstore.set_roots(std::iter::once(&2)).unwrap();
+
+        let tower = new_tower(&Pubkey::new_unique(), 4);
+        assert!(check_tower(&tower, &blockstore, true).is_err());
+        assert_eq!(blockstore.last_root(), 2);
+        assert!(!blockstore.is_root(4));
+    }
+}
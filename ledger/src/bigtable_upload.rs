@@ -1,16 +1,21 @@
-use crate::blockstore::Blockstore;
+use crate::{blockstore::Blockstore, long_term_storage::LongTermStorage};
+use futures::stream::StreamExt;
 use log::*;
 use solana_measure::measure::Measure;
 use solana_sdk::clock::Slot;
+use solana_transaction_status::ConfirmedBlock;
 use std::{
-    collections::HashSet,
+    cmp::min,
+    collections::{BTreeSet, HashSet},
     result::Result,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc,
+        Arc, Mutex,
     },
+    thread::JoinHandle,
     time::Duration,
 };
+use tokio_stream::wrappers::ReceiverStream;
 
 // Attempt to upload this many blocks in parallel
 const NUM_BLOCKS_TO_UPLOAD_IN_PARALLEL: usize = 32;
@@ -18,35 +23,150 @@ const NUM_BLOCKS_TO_UPLOAD_IN_PARALLEL: usize = 32;
 // Read up to this many blocks from blockstore before blocking on the upload process
 const BLOCK_READ_AHEAD_DEPTH: usize = NUM_BLOCKS_TO_UPLOAD_IN_PARALLEL * 2;
 
+#[derive(Clone, Debug)]
+pub struct ConfirmedBlockUploadConfig {
+    /// Upload the blocks that long-term storage already has, starting before the checkpoint
+    pub force_reupload: bool,
+    /// Upload blocks with transactions that have no status metadata instead of panicking
+    pub allow_missing_metadata: bool,
+    /// Compare every local root of the range, ignoring the checkpoint, with the blocks in
+    /// long-term storage and only upload the missing ones
+    pub reconcile: bool,
+    /// Split the blocks to upload into this many slot ranges that are uploaded concurrently
+    pub num_upload_ranges: usize,
+    /// Persist the upload progress in the blockstore under this name, usually the
+    /// `LongTermStorage::id` of the destination, so an interrupted upload resumes where it
+    /// stopped
+    pub checkpoint: Option<String>,
+}
+
+impl Default for ConfirmedBlockUploadConfig {
+    fn default() -> Self {
+        Self {
+            force_reupload: false,
+            allow_missing_metadata: false,
+            reconcile: false,
+            num_upload_ranges: 1,
+            checkpoint: None,
+        }
+    }
+}
+
+/// The blocks of an upload that are not in long-term storage yet. The checkpoint is the slot
+/// before the first of them, or the end of the upload once they are all uploaded.
+struct UploadCheckpoint<'a> {
+    blockstore: &'a Blockstore,
+    name: &'a str,
+    end_slot: Slot,
+    state: Mutex<UploadCheckpointState>,
+}
+
+struct UploadCheckpointState {
+    pending: BTreeSet<Slot>,
+    saved: Option<Slot>,
+}
+
+impl<'a> UploadCheckpoint<'a> {
+    fn complete(&self, slots: &[Slot]) {
+        let mut state = self.state.lock().unwrap();
+        for slot in slots {
+            state.pending.remove(slot);
+        }
+        let checkpoint = match state.pending.iter().next() {
+            Some(first_pending) => first_pending.checked_sub(1),
+            None => Some(self.end_slot),
+        }
+        .map(|slot| min(slot, self.end_slot));
+
+        if checkpoint > state.saved {
+            let checkpoint = checkpoint.unwrap();
+            match self
+                .blockstore
+                .write_upload_checkpoint(self.name, checkpoint)
+            {
+                Ok(()) => state.saved = Some(checkpoint),
+                Err(err) => warn!(
+                    "Failed to save the upload checkpoint {}: {:?}",
+                    self.name, err
+                ),
+            }
+        }
+    }
+}
+
+/// Upload the rooted blocks in \[starting_slot, ending_slot\] that long-term storage doesn't
+/// have yet
 pub async fn upload_confirmed_blocks(
     blockstore: Arc<Blockstore>,
-    bigtable: solana_storage_bigtable::LedgerStorage,
+    bigtable: Arc<dyn LongTermStorage>,
     starting_slot: Slot,
     ending_slot: Option<Slot>,
-    allow_missing_metadata: bool,
-    force_reupload: bool,
+    config: &ConfirmedBlockUploadConfig,
     exit: Arc<AtomicBool>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut measure = Measure::start("entire upload");
 
-    info!("Loading ledger slots starting at {}...", starting_slot);
-    let blockstore_slots: Vec<_> = blockstore
-        .slot_meta_iterator(starting_slot)
-        .map_err(|err| {
-            format!(
-                "Failed to load entries starting from slot {}: {:?}",
-                starting_slot, err
-            )
-        })?
-        .filter_map(|(slot, _slot_meta)| {
-            if let Some(ending_slot) = &ending_slot {
-                if slot > *ending_slot {
-                    return None;
-                }
+    let checkpoint_name = config.checkpoint.as_deref();
+    let saved_checkpoint = match checkpoint_name {
+        Some(name) => blockstore
+            .read_upload_checkpoint(name)
+            .map_err(|err| format!("Failed to read checkpoint {}: {:?}", name, err))?,
+        None => None,
+    };
+    let starting_slot = match saved_checkpoint {
+        Some(checkpoint) if !config.force_reupload && !config.reconcile => {
+            let resume_slot = checkpoint.saturating_add(1);
+            if resume_slot > starting_slot {
+                info!(
+                    "Resuming the upload after checkpoint {} at slot {}",
+                    checkpoint, resume_slot
+                );
             }
-            Some(slot)
-        })
-        .collect();
+            starting_slot.max(resume_slot)
+        }
+        _ => starting_slot,
+    };
+    if let Some(ending_slot) = ending_slot {
+        if starting_slot > ending_slot {
+            info!("All blocks up to slot {} are already uploaded", ending_slot);
+            return Ok(());
+        }
+    }
+
+    let blockstore_slots = if config.reconcile {
+        info!("Loading ledger roots starting at {}...", starting_slot);
+        blockstore
+            .rooted_slot_iterator(starting_slot)
+            .map_err(|err| {
+                format!(
+                    "Failed to load roots starting from slot {}: {:?}",
+                    starting_slot, err
+                )
+            })?
+            .take_while(|slot| ending_slot.map_or(true, |ending_slot| *slot <= ending_slot))
+            .collect::<Vec<_>>()
+    } else {
+        info!("Loading ledger slots starting at {}...", starting_slot);
+        blockstore
+            .slot_meta_iterator(starting_slot)
+            .map_err(|err| {
+                format!(
+                    "Failed to load entries starting from slot {}: {:?}",
+                    starting_slot, err
+                )
+            })?
+            .filter_map(|(slot, _slot_meta)| {
+                if let Some(ending_slot) = &ending_slot {
+                    if slot > *ending_slot {
+                        return None;
+                    }
+                }
+                // The blocks of the unrooted and dead slots can't be loaded, and would hold back
+                // the checkpoint forever
+                blockstore.is_root(slot).then(|| slot)
+            })
+            .collect::<Vec<_>>()
+    };
 
     if blockstore_slots.is_empty() {
         return Err(format!(
@@ -64,7 +184,7 @@ pub async fn upload_confirmed_blocks(
     );
 
     // Gather the blocks that are already present in bigtable, by slot
-    let bigtable_slots = if !force_reupload {
+    let bigtable_slots = if !config.force_reupload || config.reconcile {
         let mut bigtable_slots = vec![];
         let first_blockstore_slot = *blockstore_slots.first().unwrap();
         let last_blockstore_slot = *blockstore_slots.last().unwrap();
@@ -113,10 +233,40 @@ pub async fn upload_confirmed_blocks(
         blocks_to_upload
     };
 
+    // Only advance the checkpoint over slots that this upload has checked. Unrooted slots may
+    // still be rooted later, so the checkpoint stays at or below the highest root. A secondary
+    // blockstore can resume from the checkpoint but not save it.
+    let checkpoint = checkpoint_name
+        .filter(|_| blockstore.is_primary_access())
+        .filter(|_| {
+            saved_checkpoint.map_or(true, |checkpoint| {
+                *blockstore_slots.first().unwrap() <= checkpoint.saturating_add(1)
+            })
+        })
+        .map(|name| UploadCheckpoint {
+            blockstore: &blockstore,
+            name,
+            end_slot: min(*blockstore_slots.last().unwrap(), blockstore.max_root()),
+            state: Mutex::new(UploadCheckpointState {
+                pending: blocks_to_upload.iter().cloned().collect(),
+                saved: saved_checkpoint,
+            }),
+        });
+
     if blocks_to_upload.is_empty() {
         info!("No blocks need to be uploaded to bigtable");
+        if let Some(checkpoint) = &checkpoint {
+            checkpoint.complete(&[]);
+        }
         return Ok(());
     }
+    if config.reconcile {
+        info!(
+            "{} of {} roots are missing from the bucket",
+            blocks_to_upload.len(),
+            blockstore_slots.len()
+        );
+    }
     info!(
         "{} blocks to be uploaded to the bucket in the range ({}, {})",
         blocks_to_upload.len(),
@@ -124,51 +274,49 @@ pub async fn upload_confirmed_blocks(
         blocks_to_upload.last().unwrap()
     );
 
-    // Load the blocks out of blockstore in a separate thread to allow for concurrent block uploading
-    let (_loader_thread, receiver) = {
-        let exit = exit.clone();
-
-        let (sender, receiver) = std::sync::mpsc::sync_channel(BLOCK_READ_AHEAD_DEPTH);
-        (
-            std::thread::spawn(move || {
-                let mut measure = Measure::start("block loader thread");
-                for (i, slot) in blocks_to_upload.iter().enumerate() {
-                    if exit.load(Ordering::Relaxed) {
-                        break;
-                    }
-
-                    let _ = match blockstore.get_rooted_block(*slot, true) {
-                        Ok(confirmed_block) => sender.send((*slot, Some(confirmed_block))),
-                        Err(err) => {
-                            warn!(
-                                "Failed to get load confirmed block from slot {}: {:?}",
-                                slot, err
-                            );
-                            sender.send((*slot, None))
-                        }
-                    };
-
-                    if i > 0 && i % NUM_BLOCKS_TO_UPLOAD_IN_PARALLEL == 0 {
-                        info!(
-                            "{}% of blocks processed ({}/{})",
-                            i * 100 / blocks_to_upload.len(),
-                            i,
-                            blocks_to_upload.len()
-                        );
-                    }
-                }
-                measure.stop();
-                info!("{} to load {} blocks", measure, blocks_to_upload.len());
-            }),
-            receiver,
+    let num_upload_ranges = config.num_upload_ranges.max(1);
+    let range_len = (blocks_to_upload.len() + num_upload_ranges - 1) / num_upload_ranges;
+    let uploads = blocks_to_upload.chunks(range_len).map(|slots| {
+        upload_slot_range(
+            blockstore.clone(),
+            bigtable.clone(),
+            slots.to_vec(),
+            config.allow_missing_metadata,
+            checkpoint.as_ref(),
+            exit.clone(),
         )
-    };
+    });
+    let failures: usize = futures::future::join_all(uploads).await.into_iter().sum();
 
-    let mut failures = 0;
-    use futures::stream::StreamExt;
+    measure.stop();
+    info!("{}", measure);
+    if failures > 0 {
+        Err(format!("Incomplete upload, {} operations failed", failures).into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Upload the blocks of a slot range, returning how many uploads failed
+async fn upload_slot_range(
+    blockstore: Arc<Blockstore>,
+    bigtable: Arc<dyn LongTermStorage>,
+    slots: Vec<Slot>,
+    allow_missing_metadata: bool,
+    checkpoint: Option<&UploadCheckpoint<'_>>,
+    exit: Arc<AtomicBool>,
+) -> usize {
+    info!(
+        "Uploading {} blocks in the range ({}, {})",
+        slots.len(),
+        slots.first().unwrap(),
+        slots.last().unwrap()
+    );
+    // Load the blocks out of blockstore in a separate thread to allow for concurrent block uploading
+    let (_loader_thread, receiver) = spawn_block_loader(blockstore, slots, exit.clone());
 
-    let mut stream =
-        tokio_stream::iter(receiver.into_iter()).chunks(NUM_BLOCKS_TO_UPLOAD_IN_PARALLEL);
+    let mut failures = 0;
+    let mut stream = ReceiverStream::new(receiver).chunks(NUM_BLOCKS_TO_UPLOAD_IN_PARALLEL);
 
     while let Some(blocks) = stream.next().await {
         if exit.load(Ordering::Relaxed) {
@@ -176,46 +324,279 @@ pub async fn upload_confirmed_blocks(
         }
 
         let mut measure_upload = Measure::start("Upload");
-        let mut num_blocks = blocks.len();
-        info!("Preparing the next {} blocks for upload", num_blocks);
+        info!("Preparing the next {} blocks for upload", blocks.len());
 
-        let uploads = blocks.into_iter().filter_map(|(slot, block)| match block {
-            None => {
-                num_blocks -= 1;
-                None
-            }
-            Some(confirmed_block) => {
-                if confirmed_block
-                    .transactions
-                    .iter()
-                    .any(|transaction| transaction.meta.is_none())
-                {
-                    if allow_missing_metadata {
-                        info!("Transaction metadata missing from slot {}", slot);
-                    } else {
-                        panic!("Transaction metadata missing from slot {}", slot);
+        // Blocks that can't be loaded hold back the checkpoint like failed uploads, so that they
+        // are retried once the upload resumes
+        let mut completed_slots = vec![];
+        let mut uploaded_slots = vec![];
+        let mut uploads = vec![];
+        for (slot, block) in blocks {
+            match block {
+                None => failures += 1,
+                Some(confirmed_block) => {
+                    if confirmed_block
+                        .transactions
+                        .iter()
+                        .any(|transaction| transaction.meta.is_none())
+                    {
+                        if allow_missing_metadata {
+                            info!("Transaction metadata missing from slot {}", slot);
+                        } else {
+                            panic!("Transaction metadata missing from slot {}", slot);
+                        }
                     }
+                    uploaded_slots.push(slot);
+                    uploads.push(bigtable.upload_confirmed_block(slot, confirmed_block));
                 }
-                Some(bigtable.upload_confirmed_block(slot, confirmed_block))
             }
-        });
+        }
+        let num_blocks = uploads.len();
 
-        for result in futures::future::join_all(uploads).await {
-            if result.is_err() {
-                error!("upload_confirmed_block() failed: {:?}", result.err());
-                failures += 1;
+        for (slot, result) in uploaded_slots
+            .into_iter()
+            .zip(futures::future::join_all(uploads).await)
+        {
+            match result {
+                Ok(()) => completed_slots.push(slot),
+                Err(err) => {
+                    error!(
+                        "upload_confirmed_block() failed for slot {}: {:?}",
+                        slot, err
+                    );
+                    failures += 1;
+                }
             }
         }
+        if let Some(checkpoint) = checkpoint {
+            checkpoint.complete(&completed_slots);
+        }
 
         measure_upload.stop();
         info!("{} for {} blocks", measure_upload, num_blocks);
     }
+    failures
+}
 
-    measure.stop();
-    info!("{}", measure);
-    if failures > 0 {
-        Err(format!("Incomplete upload, {} operations failed", failures).into())
-    } else {
-        Ok(())
+fn spawn_block_loader(
+    blockstore: Arc<Blockstore>,
+    blocks_to_upload: Vec<Slot>,
+    exit: Arc<AtomicBool>,
+) -> (
+    JoinHandle<()>,
+    tokio::sync::mpsc::Receiver<(Slot, Option<ConfirmedBlock>)>,
+) {
+    let (sender, receiver) = tokio::sync::mpsc::channel(BLOCK_READ_AHEAD_DEPTH);
+    let loader_thread = std::thread::spawn(move || {
+        let mut measure = Measure::start("block loader thread");
+        for (i, slot) in blocks_to_upload.iter().enumerate() {
+            if exit.load(Ordering::Relaxed) {
+                break;
+            }
+
+            let result = match blockstore.get_rooted_block(*slot, true) {
+                Ok(confirmed_block) => sender.blocking_send((*slot, Some(confirmed_block))),
+                Err(err) => {
+                    warn!(
+                        "Failed to get load confirmed block from slot {}: {:?}",
+                        slot, err
+                    );
+                    sender.blocking_send((*slot, None))
+                }
+            };
+            if result.is_err() {
+                break;
+            }
+
+            if i > 0 && i % NUM_BLOCKS_TO_UPLOAD_IN_PARALLEL == 0 {
+                info!(
+                    "{}% of blocks processed ({}/{})",
+                    i * 100 / blocks_to_upload.len(),
+                    i,
+                    blocks_to_upload.len()
+                );
+            }
+        }
+        measure.stop();
+        info!("{} to load {} blocks", measure, blocks_to_upload.len());
+    });
+    (loader_thread, receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            blockstore::{make_many_slot_entries, make_slot_entries},
+            get_tmp_ledger_path_auto_delete,
+            long_term_storage::{ArchiveLedgerStorage, FilesystemObjectStore},
+        },
+        tempfile::TempDir,
+    };
+
+    #[tokio::test]
+    async fn test_upload_confirmed_blocks_checkpoint() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let blockstore = Arc::new(Blockstore::open(ledger_path.path()).unwrap());
+        let (shreds, _) = make_many_slot_entries(0, 10, 2);
+        blockstore.insert_shreds(shreds, None, false).unwrap();
+        blockstore
+            .set_roots((0..10).collect::<Vec<_>>().iter())
+            .unwrap();
+
+        let archive_dir = TempDir::new().unwrap();
+        let storage: Arc<dyn LongTermStorage> = Arc::new(ArchiveLedgerStorage::new(
+            FilesystemObjectStore::new(archive_dir.path()).unwrap(),
+            false,
+        ));
+        let uploaded_slots = || async { storage.get_confirmed_blocks(0, 100).await.unwrap() };
+        let config = ConfirmedBlockUploadConfig {
+            num_upload_ranges: 3,
+            checkpoint: Some("test".to_string()),
+            ..ConfirmedBlockUploadConfig::default()
+        };
+        let exit = Arc::new(AtomicBool::new(false));
+
+        upload_confirmed_blocks(
+            blockstore.clone(),
+            storage.clone(),
+            0,
+            Some(5),
+            &config,
+            exit.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(uploaded_slots().await, (0..=5).collect::<Vec<_>>());
+        assert_eq!(blockstore.read_upload_checkpoint("test").unwrap(), Some(5));
+
+        // The next upload resumes after the checkpoint and doesn't see the removed block
+        storage.delete_confirmed_block(2, false).await.unwrap();
+        upload_confirmed_blocks(
+            blockstore.clone(),
+            storage.clone(),
+            0,
+            None,
+            &config,
+            exit.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(uploaded_slots().await, vec![0, 1, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(blockstore.read_upload_checkpoint("test").unwrap(), Some(9));
+
+        // Reconciling with the roots fills the gap
+        let config = ConfirmedBlockUploadConfig {
+            reconcile: true,
+            ..config
+        };
+        upload_confirmed_blocks(blockstore.clone(), storage.clone(), 0, None, &config, exit)
+            .await
+            .unwrap();
+        assert_eq!(uploaded_slots().await, (0..10).collect::<Vec<_>>());
+        assert_eq!(blockstore.read_upload_checkpoint("test").unwrap(), Some(9));
+    }
+
+    #[tokio::test]
+    async fn test_upload_confirmed_blocks_skips_unrooted_slots() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let blockstore = Arc::new(Blockstore::open(ledger_path.path()).unwrap());
+        let (shreds, _) = make_many_slot_entries(0, 10, 2);
+        blockstore.insert_shreds(shreds, None, false).unwrap();
+        // Slot 10 forks off slot 3 and is never rooted, slot 11 is rooted after slot 9
+        let (fork_shreds, _) = make_slot_entries(10, 3, 2);
+        blockstore.insert_shreds(fork_shreds, None, false).unwrap();
+        let (shreds, _) = make_slot_entries(11, 9, 2);
+        blockstore.insert_shreds(shreds, None, false).unwrap();
+        let roots: Vec<Slot> = (0..10).chain(std::iter::once(11)).collect();
+        blockstore.set_roots(roots.iter()).unwrap();
+
+        let archive_dir = TempDir::new().unwrap();
+        let storage: Arc<dyn LongTermStorage> = Arc::new(ArchiveLedgerStorage::new(
+            FilesystemObjectStore::new(archive_dir.path()).unwrap(),
+            false,
+        ));
+        let config = ConfirmedBlockUploadConfig {
+            checkpoint: Some(storage.id()),
+            ..ConfirmedBlockUploadConfig::default()
+        };
+
+        upload_confirmed_blocks(
+            blockstore.clone(),
+            storage.clone(),
+            0,
+            None,
+            &config,
+            Arc::new(AtomicBool::new(false)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(storage.get_confirmed_blocks(0, 100).await.unwrap(), roots);
+        assert_eq!(
+            blockstore.read_upload_checkpoint(&storage.id()).unwrap(),
+            Some(11)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_upload_confirmed_blocks_unloadable_block() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let blockstore = Arc::new(Blockstore::open(ledger_path.path()).unwrap());
+        let (shreds, _) = make_many_slot_entries(0, 10, 2);
+        let (slot_3_shreds, other_shreds): (Vec<_>, Vec<_>) =
+            shreds.into_iter().partition(|shred| shred.slot() == 3);
+        blockstore.insert_shreds(other_shreds, None, false).unwrap();
+        blockstore
+            .set_roots((0..10).collect::<Vec<_>>().iter())
+            .unwrap();
+
+        let archive_dir = TempDir::new().unwrap();
+        let storage: Arc<dyn LongTermStorage> = Arc::new(ArchiveLedgerStorage::new(
+            FilesystemObjectStore::new(archive_dir.path()).unwrap(),
+            false,
+        ));
+        let config = ConfirmedBlockUploadConfig {
+            checkpoint: Some(storage.id()),
+            ..ConfirmedBlockUploadConfig::default()
+        };
+        let exit = Arc::new(AtomicBool::new(false));
+
+        // The root without its shreds, and its child missing the previous blockhash, fail the
+        // upload and hold back the checkpoint
+        assert!(upload_confirmed_blocks(
+            blockstore.clone(),
+            storage.clone(),
+            0,
+            None,
+            &config,
+            exit.clone(),
+        )
+        .await
+        .is_err());
+        assert_eq!(
+            storage.get_confirmed_blocks(0, 100).await.unwrap(),
+            vec![0, 1, 2, 5, 6, 7, 8, 9]
+        );
+        assert_eq!(
+            blockstore.read_upload_checkpoint(&storage.id()).unwrap(),
+            Some(2)
+        );
+
+        // Once the blocks can be loaded, the upload resumes with them
+        blockstore
+            .insert_shreds(slot_3_shreds, None, false)
+            .unwrap();
+        upload_confirmed_blocks(blockstore.clone(), storage.clone(), 0, None, &config, exit)
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.get_confirmed_blocks(0, 100).await.unwrap(),
+            (0..10).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            blockstore.read_upload_checkpoint(&storage.id()).unwrap(),
+            Some(9)
+        );
     }
 }
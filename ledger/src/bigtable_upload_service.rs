@@ -1,5 +1,9 @@
 use {
-    crate::{bigtable_upload, blockstore::Blockstore},
+    crate::{
+        bigtable_upload::{self, ConfirmedBlockUploadConfig},
+        blockstore::Blockstore,
+        long_term_storage::LongTermStorage,
+    },
     solana_runtime::commitment::BlockCommitmentCache,
     std::{
         sync::atomic::{AtomicBool, Ordering},
@@ -24,9 +28,34 @@ pub struct BigTableUploadService {
 impl BigTableUploadService {
     pub fn new(
         runtime: Arc<Runtime>,
-        bigtable_ledger_storage: solana_storage_bigtable::LedgerStorage,
+        bigtable_ledger_storage: Arc<dyn LongTermStorage>,
+        blockstore: Arc<Blockstore>,
+        block_commitment_cache: Arc<RwLock<BlockCommitmentCache>>,
+        exit: Arc<AtomicBool>,
+    ) -> Self {
+        let config = ConfirmedBlockUploadConfig {
+            allow_missing_metadata: true,
+            checkpoint: Some(bigtable_ledger_storage.id()),
+            ..ConfirmedBlockUploadConfig::default()
+        };
+        Self::new_with_config(
+            runtime,
+            bigtable_ledger_storage,
+            blockstore,
+            block_commitment_cache,
+            config,
+            exit,
+        )
+    }
+
+    /// The upload progress is saved in the blockstore when `config` names a checkpoint, and a
+    /// restarted service resumes from it
+    pub fn new_with_config(
+        runtime: Arc<Runtime>,
+        bigtable_ledger_storage: Arc<dyn LongTermStorage>,
         blockstore: Arc<Blockstore>,
         block_commitment_cache: Arc<RwLock<BlockCommitmentCache>>,
+        config: ConfirmedBlockUploadConfig,
         exit: Arc<AtomicBool>,
     ) -> Self {
         info!("Starting BigTable upload service");
@@ -38,6 +67,7 @@ impl BigTableUploadService {
                     bigtable_ledger_storage,
                     blockstore,
                     block_commitment_cache,
+                    config,
                     exit,
                 )
             })
@@ -48,9 +78,10 @@ impl BigTableUploadService {
 
     fn run(
         runtime: Arc<Runtime>,
-        bigtable_ledger_storage: solana_storage_bigtable::LedgerStorage,
+        bigtable_ledger_storage: Arc<dyn LongTermStorage>,
         blockstore: Arc<Blockstore>,
         block_commitment_cache: Arc<RwLock<BlockCommitmentCache>>,
+        config: ConfirmedBlockUploadConfig,
         exit: Arc<AtomicBool>,
     ) {
         let mut start_slot = 0;
@@ -75,8 +106,7 @@ impl BigTableUploadService {
                 bigtable_ledger_storage.clone(),
                 start_slot,
                 Some(end_slot),
-                true,
-                false,
+                &config,
                 exit.clone(),
             ));
 
@@ -6,8 +6,9 @@ use {
     crate::{
         ancestor_iterator::AncestorIterator,
         blockstore_db::{
-            columns as cf, AccessType, BlockstoreRecoveryMode, Column, Database, IteratorDirection,
-            IteratorMode, LedgerColumn, Result, WriteBatch,
+            columns as cf, AccessType, BlockstoreOptions, BlockstoreRecoveryMode, Column,
+            ColumnGroup, ColumnName, Database, IteratorDirection, IteratorMode, LedgerColumn,
+            Result, WriteBatch,
         },
         blockstore_meta::*,
         erasure::ErasureConfig,
@@ -67,6 +68,8 @@ use {
     trees::{Tree, TreeWalk},
 };
 
+pub mod blockstore_diff;
+pub mod blockstore_integrity;
 pub mod blockstore_purge;
 
 pub const BLOCKSTORE_DIRECTORY: &str = "rocksdb";
@@ -143,6 +146,8 @@ pub struct Blockstore {
     code_shred_cf: LedgerColumn<cf::ShredCode>,
     transaction_status_cf: LedgerColumn<cf::TransactionStatus>,
     address_signatures_cf: LedgerColumn<cf::AddressSignatures>,
+    program_signatures_cf: LedgerColumn<cf::ProgramSignatures>,
+    program_signatures_enabled: bool,
     transaction_memos_cf: LedgerColumn<cf::TransactionMemos>,
     transaction_status_index_cf: LedgerColumn<cf::TransactionStatusIndex>,
     active_transaction_status_index: RwLock<u64>,
@@ -151,12 +156,16 @@ pub struct Blockstore {
     perf_samples_cf: LedgerColumn<cf::PerfSamples>,
     block_height_cf: LedgerColumn<cf::BlockHeight>,
     program_costs_cf: LedgerColumn<cf::ProgramCosts>,
+    upload_checkpoints_cf: LedgerColumn<cf::UploadCheckpoints>,
     bank_hash_cf: LedgerColumn<cf::BankHash>,
     last_root: Arc<RwLock<Slot>>,
     insert_shreds_lock: Arc<Mutex<()>>,
     pub new_shreds_signals: Vec<SyncSender<bool>>,
     pub completed_slots_senders: Vec<CompletedSlotsSender>,
     pub lowest_cleanup_slot: Arc<RwLock<Slot>>,
+    /// Trails `lowest_cleanup_slot` when LedgerCleanupService retains the transaction statuses
+    /// for longer than the shreds
+    pub transaction_status_lowest_cleanup_slot: Arc<RwLock<Slot>>,
     no_compaction: bool,
     slots_stats: Arc<Mutex<SlotsStats>>,
 }
@@ -335,7 +344,7 @@ impl Blockstore {
 
     /// Opens a Ledger in directory, provides "infinite" window of shreds
     pub fn open(ledger_path: &Path) -> Result<Blockstore> {
-        Self::do_open(ledger_path, AccessType::PrimaryOnly, None, true)
+        Self::do_open(ledger_path, BlockstoreOptions::default())
     }
 
     pub fn open_with_access_type(
@@ -346,27 +355,30 @@ impl Blockstore {
     ) -> Result<Blockstore> {
         Self::do_open(
             ledger_path,
-            access_type,
-            recovery_mode,
-            enforce_ulimit_nofile,
+            BlockstoreOptions {
+                access_type,
+                recovery_mode,
+                enforce_ulimit_nofile,
+                ..BlockstoreOptions::default()
+            },
         )
     }
 
-    fn do_open(
-        ledger_path: &Path,
-        access_type: AccessType,
-        recovery_mode: Option<BlockstoreRecoveryMode>,
-        enforce_ulimit_nofile: bool,
-    ) -> Result<Blockstore> {
+    pub fn open_with_options(ledger_path: &Path, options: BlockstoreOptions) -> Result<Blockstore> {
+        Self::do_open(ledger_path, options)
+    }
+
+    fn do_open(ledger_path: &Path, options: BlockstoreOptions) -> Result<Blockstore> {
         fs::create_dir_all(&ledger_path)?;
         let blockstore_path = ledger_path.join(BLOCKSTORE_DIRECTORY);
 
-        adjust_ulimit_nofile(enforce_ulimit_nofile)?;
+        adjust_ulimit_nofile(options.enforce_ulimit_nofile)?;
+        let program_signatures_enabled = options.enable_program_signatures;
 
         // Open the database
         let mut measure = Measure::start("open");
         info!("Opening database at {:?}", blockstore_path);
-        let db = Database::open(&blockstore_path, access_type, recovery_mode)?;
+        let db = Database::open_with_options(&blockstore_path, options)?;
 
         // Create the metadata column family
         let meta_cf = db.column();
@@ -386,6 +398,7 @@ impl Blockstore {
         let code_shred_cf = db.column();
         let transaction_status_cf = db.column();
         let address_signatures_cf = db.column();
+        let program_signatures_cf = db.column();
         let transaction_memos_cf = db.column();
         let transaction_status_index_cf = db.column();
         let rewards_cf = db.column();
@@ -393,6 +406,7 @@ impl Blockstore {
         let perf_samples_cf = db.column();
         let block_height_cf = db.column();
         let program_costs_cf = db.column();
+        let upload_checkpoints_cf = db.column();
         let bank_hash_cf = db.column();
 
         let db = Arc::new(db);
@@ -436,6 +450,8 @@ impl Blockstore {
             code_shred_cf,
             transaction_status_cf,
             address_signatures_cf,
+            program_signatures_cf,
+            program_signatures_enabled,
             transaction_memos_cf,
             transaction_status_index_cf,
             active_transaction_status_index: RwLock::new(active_transaction_status_index),
@@ -444,12 +460,14 @@ impl Blockstore {
             perf_samples_cf,
             block_height_cf,
             program_costs_cf,
+            upload_checkpoints_cf,
             bank_hash_cf,
             new_shreds_signals: vec![],
             completed_slots_senders: vec![],
             insert_shreds_lock: Arc::new(Mutex::new(())),
             last_root,
             lowest_cleanup_slot: Arc::new(RwLock::new(0)),
+            transaction_status_lowest_cleanup_slot: Arc::new(RwLock::new(0)),
             no_compaction: false,
             slots_stats: Arc::new(Mutex::new(SlotsStats::default())),
         };
@@ -461,15 +479,9 @@ impl Blockstore {
 
     pub fn open_with_signal(
         ledger_path: &Path,
-        recovery_mode: Option<BlockstoreRecoveryMode>,
-        enforce_ulimit_nofile: bool,
+        options: BlockstoreOptions,
     ) -> Result<BlockstoreSignals> {
-        let mut blockstore = Self::open_with_access_type(
-            ledger_path,
-            AccessType::PrimaryOnly,
-            recovery_mode,
-            enforce_ulimit_nofile,
-        )?;
+        let mut blockstore = Self::open_with_options(ledger_path, options)?;
         let (ledger_signal_sender, ledger_signal_receiver) = sync_channel(1);
         let (completed_slots_sender, completed_slots_receiver) =
             sync_channel(MAX_COMPLETED_SLOTS_IN_CHANNEL);
@@ -1822,7 +1834,7 @@ impl Blockstore {
             "blockstore-rpc-api",
             ("method", "get_block_time".to_string(), String)
         );
-        let _lock = self.check_lowest_cleanup_slot(slot)?;
+        let _lock = self.check_block_time_cleanup_slot(slot)?;
         self.blocktime_cf.get(slot)
     }
 
@@ -1830,6 +1842,29 @@ impl Blockstore {
         self.blocktime_cf.put(slot, &timestamp)
     }
 
+    /// The newest slot from `start_slot` with a cached block time before `timestamp`. The block
+    /// times of the slots before the oldest cached one are unknown, so nothing is returned when
+    /// every cached block time is at or after `timestamp`. Seeking to `start_slot`, the oldest
+    /// slot that was not purged, skips over the deleted block times.
+    pub fn latest_slot_with_block_time_before(
+        &self,
+        start_slot: Slot,
+        timestamp: UnixTimestamp,
+    ) -> Result<Option<Slot>> {
+        let mut latest_slot = None;
+        for (slot, block_time) in self
+            .blocktime_cf
+            .iter(IteratorMode::From(start_slot, IteratorDirection::Forward))?
+        {
+            let block_time: UnixTimestamp = deserialize(&block_time)?;
+            if block_time >= timestamp {
+                break;
+            }
+            latest_slot = Some(slot);
+        }
+        Ok(latest_slot)
+    }
+
     pub fn get_block_height(&self, slot: Slot) -> Result<Option<u64>> {
         datapoint_info!(
             "blockstore-rpc-api",
@@ -1964,9 +1999,9 @@ impl Blockstore {
     }
 
     /// Initializes the TransactionStatusIndex column family with two records, `0` and `1`,
-    /// which are used as the primary index for entries in the TransactionStatus and
-    /// AddressSignatures columns. At any given time, one primary index is active (ie. new records
-    /// are stored under this index), the other is frozen.
+    /// which are used as the primary index for entries in the TransactionStatus,
+    /// AddressSignatures and ProgramSignatures columns. At any given time, one primary index is
+    /// active (ie. new records are stored under this index), the other is frozen.
     fn initialize_transaction_status_index(&self) -> Result<()> {
         self.transaction_status_index_cf
             .put(0, &TransactionStatusIndexMeta::default())?;
@@ -1979,6 +2014,10 @@ impl Blockstore {
         self.address_signatures_cf.put(
             cf::AddressSignatures::as_index(2),
             &AddressSignatureMeta::default(),
+        )?;
+        self.program_signatures_cf.put(
+            cf::ProgramSignatures::as_index(2),
+            &ProgramSignatureMeta::default(),
         )
     }
 
@@ -2071,6 +2110,11 @@ impl Blockstore {
         }
     }
 
+    /// Write the status of a transaction and index it under the addresses it locks. When the
+    /// ProgramSignatures column is enabled, the transaction is also indexed under the programs
+    /// its log messages show it invoked, directly or through inner instructions, under the same
+    /// primary index. Transactions whose log messages were not recorded are not indexed by
+    /// program.
     pub fn write_transaction_status(
         &self,
         slot: Slot,
@@ -2079,6 +2123,12 @@ impl Blockstore {
         readonly_keys: Vec<&Pubkey>,
         status: TransactionStatusMeta,
     ) -> Result<()> {
+        let program_ids = match &status.log_messages {
+            Some(log_messages) if self.program_signatures_enabled => {
+                invoked_program_ids(log_messages)
+            }
+            _ => vec![],
+        };
         let status = status.into();
         // This write lock prevents interleaving issues with the transaction_status_index_cf by gating
         // writes to that column
@@ -2100,6 +2150,12 @@ impl Blockstore {
                 &AddressSignatureMeta { writeable: false },
             )?;
         }
+        for (program_id, inner_only) in program_ids {
+            self.program_signatures_cf.put(
+                (primary_index, program_id, slot, signature),
+                &ProgramSignatureMeta { inner_only },
+            )?;
+        }
         Ok(())
     }
 
@@ -2122,11 +2178,31 @@ impl Blockstore {
         Ok(lowest_cleanup_slot)
     }
 
-    fn ensure_lowest_cleanup_slot(&self) -> (std::sync::RwLockReadGuard<Slot>, Slot) {
-        // Ensures consistent result by using lowest_cleanup_slot as the lower bound
-        // for reading columns that do not employ strong read consistency with slot-based
-        // delete_range
+    // Block times are kept until both column groups have been cleaned up past them, so the
+    // caller holds both locks
+    fn check_block_time_cleanup_slot(
+        &self,
+        slot: Slot,
+    ) -> Result<(
+        std::sync::RwLockReadGuard<Slot>,
+        std::sync::RwLockReadGuard<Slot>,
+    )> {
         let lowest_cleanup_slot = self.lowest_cleanup_slot.read().unwrap();
+        let transaction_status_lowest_cleanup_slot =
+            self.transaction_status_lowest_cleanup_slot.read().unwrap();
+        let block_time_cleanup_slot =
+            (*lowest_cleanup_slot).min(*transaction_status_lowest_cleanup_slot);
+        if block_time_cleanup_slot > 0 && block_time_cleanup_slot >= slot {
+            return Err(BlockstoreError::SlotCleanedUp);
+        }
+        Ok((lowest_cleanup_slot, transaction_status_lowest_cleanup_slot))
+    }
+
+    fn ensure_lowest_cleanup_slot(&self) -> (std::sync::RwLockReadGuard<Slot>, Slot) {
+        // Ensures consistent result by using the transaction status group's lowest cleanup slot
+        // as the lower bound for reading columns that do not employ strong read consistency with
+        // slot-based delete_range
+        let lowest_cleanup_slot = self.transaction_status_lowest_cleanup_slot.read().unwrap();
         let lowest_available_slot = (*lowest_cleanup_slot)
             .checked_add(1)
             .expect("overflow from trusted value");
@@ -2338,10 +2414,23 @@ impl Blockstore {
         pubkey: Pubkey,
         slot: Slot,
     ) -> Result<Vec<(Slot, Signature)>> {
+        self.find_signatures_for_slot(&self.address_signatures_cf, pubkey, slot)
+    }
+
+    // Like `find_address_signatures_for_slot`, for any column keyed like AddressSignatures
+    fn find_signatures_for_slot<C>(
+        &self,
+        column: &LedgerColumn<C>,
+        pubkey: Pubkey,
+        slot: Slot,
+    ) -> Result<Vec<(Slot, Signature)>>
+    where
+        C: Column<Index = (u64, Pubkey, Slot, Signature)> + ColumnName,
+    {
         let (lock, lowest_available_slot) = self.ensure_lowest_cleanup_slot();
         let mut signatures: Vec<(Slot, Signature)> = vec![];
         for transaction_status_cf_primary_index in 0..=1 {
-            let index_iterator = self.address_signatures_cf.iter(IteratorMode::From(
+            let index_iterator = column.iter(IteratorMode::From(
                 (
                     transaction_status_cf_primary_index,
                     pubkey,
@@ -2429,6 +2518,63 @@ impl Blockstore {
                 String
             )
         );
+        self.get_confirmed_signatures_for_index(
+            &self.address_signatures_cf,
+            "blockstore-get-conf-sigs-for-addr-2",
+            address,
+            highest_slot,
+            before,
+            until,
+            limit,
+        )
+    }
+
+    /// Like `get_confirmed_signatures_for_address2`, for the transactions that invoked
+    /// `program_id`, directly or through inner instructions. Only the transactions written
+    /// while the ProgramSignatures column is enabled are found.
+    pub fn get_confirmed_signatures_for_program(
+        &self,
+        program_id: Pubkey,
+        highest_slot: Slot, // highest_confirmed_root or highest_confirmed_slot
+        before: Option<Signature>,
+        until: Option<Signature>,
+        limit: usize,
+    ) -> Result<Vec<ConfirmedTransactionStatusWithSignature>> {
+        datapoint_info!(
+            "blockstore-rpc-api",
+            (
+                "method",
+                "get_confirmed_signatures_for_program".to_string(),
+                String
+            )
+        );
+        self.get_confirmed_signatures_for_index(
+            &self.program_signatures_cf,
+            "blockstore-get-conf-sigs-for-program",
+            program_id,
+            highest_slot,
+            before,
+            until,
+            limit,
+        )
+    }
+
+    // Lists the signatures indexed under `address` by a column keyed like AddressSignatures,
+    // newest first
+    #[allow(clippy::too_many_arguments)]
+    fn get_confirmed_signatures_for_index<C>(
+        &self,
+        column: &LedgerColumn<C>,
+        metrics_name: &'static str,
+        address: Pubkey,
+        highest_slot: Slot,
+        before: Option<Signature>,
+        until: Option<Signature>,
+        limit: usize,
+    ) -> Result<Vec<ConfirmedTransactionStatusWithSignature>>
+    where
+        C: Column<Index = (u64, Pubkey, Slot, Signature)> + ColumnName,
+    {
         let last_root = self.last_root();
         let confirmed_unrooted_slots: Vec<_> = AncestorIterator::new_inclusive(highest_slot, self)
             .take_while(|&slot| slot > last_root)
@@ -2490,7 +2636,7 @@ impl Blockstore {
 
         // Get signatures in `slot`
         let mut get_initial_slot_timer = Measure::start("get_initial_slot_timer");
-        let mut signatures = self.find_address_signatures_for_slot(address, slot)?;
+        let mut signatures = self.find_signatures_for_slot(column, address, slot)?;
         signatures.reverse();
         if let Some(excluded_signatures) = before_excluded_signatures.take() {
             address_signatures.extend(
@@ -2515,7 +2661,7 @@ impl Blockstore {
 
         let mut starting_primary_index_iter_timer = Measure::start("starting_primary_index_iter");
         if slot > next_max_slot {
-            let mut starting_iterator = self.address_signatures_cf.iter(IteratorMode::From(
+            let mut starting_iterator = column.iter(IteratorMode::From(
                 (starting_primary_index, address, slot, Signature::default()),
                 IteratorDirection::Reverse,
             ))?;
@@ -2542,7 +2688,7 @@ impl Blockstore {
             // Handle slots that cross primary indexes
             if next_max_slot >= lowest_slot {
                 let mut signatures =
-                    self.find_address_signatures_for_slot(address, next_max_slot)?;
+                    self.find_signatures_for_slot(column, address, next_max_slot)?;
                 signatures.reverse();
                 address_signatures.append(&mut signatures);
             }
@@ -2551,7 +2697,7 @@ impl Blockstore {
 
         // Iterate through next_iterator until limit is reached
         let mut next_primary_index_iter_timer = Measure::start("next_primary_index_iter_timer");
-        let mut next_iterator = self.address_signatures_cf.iter(IteratorMode::From(
+        let mut next_iterator = column.iter(IteratorMode::From(
             (next_primary_index, address, slot, Signature::default()),
             IteratorDirection::Reverse,
         ))?;
@@ -2603,7 +2749,7 @@ impl Blockstore {
         get_status_info_timer.stop();
 
         datapoint_info!(
-            "blockstore-get-conf-sigs-for-addr-2",
+            metrics_name,
             (
                 "get_before_slot_us",
                 get_before_slot_timer.as_us() as i64,
@@ -2686,6 +2832,17 @@ impl Blockstore {
         self.program_costs_cf.delete(*key)
     }
 
+    /// The slot up to which every block has been uploaded to the long-term storage
+    /// `destination`, see `bigtable_upload::upload_confirmed_blocks`
+    pub fn read_upload_checkpoint(&self, destination: &str) -> Result<Option<Slot>> {
+        self.upload_checkpoints_cf.get(destination.to_string())
+    }
+
+    pub fn write_upload_checkpoint(&self, destination: &str, slot: Slot) -> Result<()> {
+        self.upload_checkpoints_cf
+            .put(destination.to_string(), &slot)
+    }
+
     /// Returns the entry vector for the slot starting with `shred_start_index`
     pub fn get_slot_entries(&self, slot: Slot, shred_start_index: u64) -> Result<Vec<Entry>> {
         self.get_slot_entries_with_shred_info(slot, shred_start_index, false)
@@ -3098,14 +3255,43 @@ impl Blockstore {
         *self.lowest_cleanup_slot.read().unwrap()
     }
 
+    /// The lowest cleanup slot of the column families of `group`
+    pub fn column_group_lowest_cleanup_slot(&self, group: ColumnGroup) -> Slot {
+        match group {
+            ColumnGroup::Shreds => self.lowest_cleanup_slot(),
+            ColumnGroup::TransactionStatus => {
+                *self.transaction_status_lowest_cleanup_slot.read().unwrap()
+            }
+        }
+    }
+
+    /// Move the lowest cleanup slot of `group`, waiting for the readers of that group to finish
+    pub fn set_column_group_lowest_cleanup_slot(&self, group: ColumnGroup, slot: Slot) {
+        match group {
+            ColumnGroup::Shreds => *self.lowest_cleanup_slot.write().unwrap() = slot,
+            ColumnGroup::TransactionStatus => {
+                *self.transaction_status_lowest_cleanup_slot.write().unwrap() = slot
+            }
+        }
+    }
+
     pub fn storage_size(&self) -> Result<u64> {
         self.db.storage_size()
     }
 
+    pub fn column_group_storage_size(&self, group: ColumnGroup) -> Result<u64> {
+        self.db.column_group_storage_size(group)
+    }
+
     pub fn is_primary_access(&self) -> bool {
         self.db.is_primary_access()
     }
 
+    /// Make the writes of the primary instance visible to a secondary Blockstore
+    pub fn try_catch_up_with_primary(&self) -> Result<()> {
+        self.db.try_catch_up_with_primary()
+    }
+
     pub fn scan_and_fix_roots(&self, exit: &Arc<AtomicBool>) -> Result<()> {
         let ancestor_iterator = AncestorIterator::new(self.last_root(), self)
             .take_while(|&slot| slot >= self.lowest_cleanup_slot());
@@ -3288,6 +3474,31 @@ fn get_last_hash<'a>(iterator: impl Iterator<Item = &'a Entry> + 'a) -> Option<H
     iterator.last().map(|entry| entry.hash)
 }
 
+/// Returns the programs invoked by a transaction according to the "Program <id> invoke [<depth>]"
+/// lines of its log messages, each with whether it was only invoked through inner instructions
+fn invoked_program_ids(log_messages: &[String]) -> Vec<(Pubkey, bool)> {
+    let mut program_ids = BTreeMap::new();
+    for log_message in log_messages {
+        let mut words = match log_message.strip_prefix("Program ") {
+            Some(rest) => rest.split_whitespace(),
+            None => continue,
+        };
+        let (program_id, invoke, depth) = (words.next(), words.next(), words.next());
+        if invoke != Some("invoke") {
+            continue;
+        }
+        let program_id = program_id.and_then(|program_id| program_id.parse::<Pubkey>().ok());
+        let depth = depth
+            .and_then(|depth| depth.strip_prefix('[')?.strip_suffix(']'))
+            .and_then(|depth| depth.parse::<usize>().ok());
+        if let (Some(program_id), Some(depth)) = (program_id, depth) {
+            let inner_only = program_ids.entry(program_id).or_insert(true);
+            *inner_only &= depth > 1;
+        }
+    }
+    program_ids.into_iter().collect()
+}
+
 fn is_valid_write_to_slot_0(slot_to_write: u64, parent_slot: Slot, last_root: u64) -> bool {
     slot_to_write == 0 && last_root == 0 && parent_slot == 0
 }
@@ -4509,7 +4720,7 @@ pub mod tests {
     fn test_data_set_completed_on_insert() {
         let ledger_path = get_tmp_ledger_path_auto_delete!();
         let BlockstoreSignals { blockstore, .. } =
-            Blockstore::open_with_signal(ledger_path.path(), None, true).unwrap();
+            Blockstore::open_with_signal(ledger_path.path(), BlockstoreOptions::default()).unwrap();
 
         // Create enough entries to fill 2 shreds, only the later one is data complete
         let slot = 0;
@@ -4550,7 +4761,7 @@ pub mod tests {
             blockstore,
             ledger_signal_receiver: recvr,
             ..
-        } = Blockstore::open_with_signal(ledger_path.path(), None, true).unwrap();
+        } = Blockstore::open_with_signal(ledger_path.path(), BlockstoreOptions::default()).unwrap();
         //let blockstore = Arc::new(blockstore);
 
         let entries_per_slot = 50;
@@ -4634,7 +4845,7 @@ pub mod tests {
             blockstore,
             completed_slots_receiver: recvr,
             ..
-        } = Blockstore::open_with_signal(ledger_path.path(), None, true).unwrap();
+        } = Blockstore::open_with_signal(ledger_path.path(), BlockstoreOptions::default()).unwrap();
         // let blockstore = Arc::new(blockstore);
 
         let entries_per_slot = 10;
@@ -4660,7 +4871,7 @@ pub mod tests {
             blockstore,
             completed_slots_receiver: recvr,
             ..
-        } = Blockstore::open_with_signal(ledger_path.path(), None, true).unwrap();
+        } = Blockstore::open_with_signal(ledger_path.path(), BlockstoreOptions::default()).unwrap();
         // let blockstore = Arc::new(blockstore);
 
         let entries_per_slot = 10;
@@ -4706,7 +4917,7 @@ pub mod tests {
             blockstore,
             completed_slots_receiver: recvr,
             ..
-        } = Blockstore::open_with_signal(ledger_path.path(), None, true).unwrap();
+        } = Blockstore::open_with_signal(ledger_path.path(), BlockstoreOptions::default()).unwrap();
         // let blockstore = Arc::new(blockstore);
 
         let entries_per_slot = 10;
@@ -6840,6 +7051,10 @@ pub mod tests {
 
         if simulate_ledger_cleanup_service {
             *blockstore.lowest_cleanup_slot.write().unwrap() = lowest_cleanup_slot;
+            *blockstore
+                .transaction_status_lowest_cleanup_slot
+                .write()
+                .unwrap() = lowest_cleanup_slot;
         }
 
         let are_missing = check_for_missing();
@@ -7728,6 +7943,194 @@ pub mod tests {
         assert!(results2.len() < results.len());
     }
 
+    #[test]
+    fn test_get_confirmed_signatures_for_program() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let blockstore = Blockstore::open_with_options(
+            ledger_path.path(),
+            BlockstoreOptions {
+                enable_program_signatures: true,
+                ..BlockstoreOptions::default()
+            },
+        )
+        .unwrap();
+
+        let program_id = solana_sdk::pubkey::new_rand();
+        let other_program_id = solana_sdk::pubkey::new_rand();
+        let mut inner_only_signatures = vec![];
+        for slot in 1..=3 {
+            // Invokes program_id
+            let direct_transaction = Transaction::new_with_compiled_instructions(
+                &[&Keypair::new()],
+                &[],
+                Hash::default(),
+                vec![program_id],
+                vec![CompiledInstruction::new(1, &(), vec![0])],
+            );
+            // Invokes other_program_id, which invokes program_id
+            let inner_transaction = Transaction::new_with_compiled_instructions(
+                &[&Keypair::new()],
+                &[program_id],
+                Hash::default(),
+                vec![other_program_id],
+                vec![CompiledInstruction::new(2, &(), vec![0])],
+            );
+            // Only invokes other_program_id
+            let other_transaction = Transaction::new_with_compiled_instructions(
+                &[&Keypair::new()],
+                &[],
+                Hash::default(),
+                vec![other_program_id],
+                vec![CompiledInstruction::new(1, &(), vec![0])],
+            );
+            let transactions = vec![direct_transaction, inner_transaction, other_transaction];
+            let entries = vec![next_entry_mut(
+                &mut Hash::default(),
+                0,
+                transactions.clone(),
+            )];
+            let shreds = entries_to_test_shreds(entries, slot, slot - 1, true, 0);
+            blockstore.insert_shreds(shreds, None, false).unwrap();
+
+            for (index, transaction) in transactions.into_iter().enumerate() {
+                let invoked = if index == 1 {
+                    inner_only_signatures.push(transaction.signatures[0]);
+                    vec![(other_program_id, 1), (program_id, 2)]
+                } else {
+                    vec![(transaction.message.account_keys[1], 1)]
+                };
+                let log_messages = invoked
+                    .into_iter()
+                    .flat_map(|(invoked_program_id, depth)| {
+                        vec![
+                            format!("Program {} invoke [{}]", invoked_program_id, depth),
+                            "Program log: invoke [1]".to_string(),
+                            format!("Program {} success", invoked_program_id),
+                        ]
+                    })
+                    .collect();
+                blockstore
+                    .write_transaction_status(
+                        slot,
+                        transaction.signatures[0],
+                        vec![&transaction.message.account_keys[0]],
+                        transaction.message.account_keys[1..].iter().collect(),
+                        TransactionStatusMeta {
+                            log_messages: Some(log_messages),
+                            ..TransactionStatusMeta::default()
+                        },
+                    )
+                    .unwrap();
+            }
+        }
+        blockstore.set_roots(vec![0, 1, 2, 3].iter()).unwrap();
+
+        let all_signatures = blockstore
+            .get_confirmed_signatures_for_program(program_id, 3, None, None, usize::MAX)
+            .unwrap();
+        assert_eq!(
+            all_signatures
+                .iter()
+                .map(|status| status.slot)
+                .collect::<Vec<_>>(),
+            vec![3, 3, 2, 2, 1, 1]
+        );
+        for signature in &inner_only_signatures {
+            assert!(all_signatures
+                .iter()
+                .any(|status| status.signature == *signature));
+        }
+        assert_eq!(
+            blockstore
+                .get_confirmed_signatures_for_program(other_program_id, 3, None, None, usize::MAX)
+                .unwrap()
+                .len(),
+            6
+        );
+
+        // Paginate
+        let results = blockstore
+            .get_confirmed_signatures_for_program(
+                program_id,
+                3,
+                Some(all_signatures[1].signature),
+                None,
+                2,
+            )
+            .unwrap();
+        assert_eq!(results, all_signatures[2..4]);
+        let results = blockstore
+            .get_confirmed_signatures_for_program(
+                program_id,
+                3,
+                None,
+                Some(all_signatures[4].signature),
+                usize::MAX,
+            )
+            .unwrap();
+        assert_eq!(results, all_signatures[..4]);
+
+        // The program of the inner instructions is flagged
+        let (_, meta) = blockstore
+            .program_signatures_cf
+            .iter(IteratorMode::From(
+                (0, program_id, 1, inner_only_signatures[0]),
+                IteratorDirection::Forward,
+            ))
+            .unwrap()
+            .next()
+            .unwrap();
+        assert!(
+            deserialize::<ProgramSignatureMeta>(&meta)
+                .unwrap()
+                .inner_only
+        );
+
+        // Program signatures are purged along with the other transaction statuses
+        blockstore.run_purge(0, 1, PurgeType::Exact).unwrap();
+        assert_eq!(
+            blockstore
+                .get_confirmed_signatures_for_program(program_id, 3, None, None, usize::MAX)
+                .unwrap()
+                .len(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_program_signatures_disabled() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let blockstore = Blockstore::open(ledger_path.path()).unwrap();
+        let program_id = solana_sdk::pubkey::new_rand();
+        let transaction = Transaction::new_with_compiled_instructions(
+            &[&Keypair::new()],
+            &[],
+            Hash::default(),
+            vec![program_id],
+            vec![CompiledInstruction::new(1, &(), vec![0])],
+        );
+        blockstore
+            .write_transaction_status(
+                1,
+                transaction.signatures[0],
+                vec![],
+                vec![],
+                TransactionStatusMeta {
+                    log_messages: Some(vec![format!("Program {} invoke [1]", program_id)]),
+                    ..TransactionStatusMeta::default()
+                },
+            )
+            .unwrap();
+        assert!(blockstore
+            .program_signatures_cf
+            .iter(IteratorMode::From(
+                (0, program_id, 0, Signature::default()),
+                IteratorDirection::Forward,
+            ))
+            .unwrap()
+            .all(|((_, key, _, _), _)| key != program_id));
+    }
+
     #[test]
     #[allow(clippy::same_item_push)]
     fn test_get_last_hash() {
@@ -8888,4 +9291,56 @@ pub mod tests {
             assert_eq!(read_cost, *cost_table.get(&read_key).unwrap());
         }
     }
+
+    #[test]
+    fn test_latest_slot_with_block_time_before() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let blockstore = Blockstore::open(ledger_path.path()).unwrap();
+        assert_eq!(
+            blockstore
+                .latest_slot_with_block_time_before(0, 100)
+                .unwrap(),
+            None
+        );
+
+        for (slot, timestamp) in [(2, 100), (3, 100), (5, 110), (8, 130)] {
+            blockstore.cache_block_time(slot, timestamp).unwrap();
+        }
+        assert_eq!(
+            blockstore
+                .latest_slot_with_block_time_before(0, 100)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            blockstore
+                .latest_slot_with_block_time_before(0, 101)
+                .unwrap(),
+            Some(3)
+        );
+        assert_eq!(
+            blockstore
+                .latest_slot_with_block_time_before(0, 130)
+                .unwrap(),
+            Some(5)
+        );
+        assert_eq!(
+            blockstore
+                .latest_slot_with_block_time_before(0, 1000)
+                .unwrap(),
+            Some(8)
+        );
+        assert_eq!(
+            blockstore
+                .latest_slot_with_block_time_before(4, 101)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            blockstore
+                .latest_slot_with_block_time_before(4, 130)
+                .unwrap(),
+            Some(5)
+        );
+    }
 }
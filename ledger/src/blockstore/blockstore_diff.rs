@@ -0,0 +1,545 @@
+use super::*;
+use serde::Serialize;
+use solana_sdk::hash::hashv;
+use std::fmt;
+
+/// Which of the two compared ledgers
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DiffSide {
+    Ledger,
+    OtherLedger,
+}
+
+impl fmt::Display for DiffSide {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ledger => write!(f, "ledger"),
+            Self::OtherLedger => write!(f, "other ledger"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum SlotDivergenceKind {
+    /// Only one of the ledgers has a `SlotMeta` for the slot
+    MissingSlot { missing_from: DiffSide },
+    /// The slot chains to a different parent
+    ParentMismatch { parent: Slot, other_parent: Slot },
+    /// The slot is dead in only one of the ledgers
+    DeadSlotMismatch { dead_in: DiffSide },
+    /// The data shreds at an index differ, or only one of the ledgers has it
+    DataShredMismatch {
+        index: u64,
+        hash: Option<String>,
+        other_hash: Option<String>,
+    },
+    /// The ledgers hold different coding shreds at the same index
+    CodingShredMismatch {
+        index: u64,
+        hash: String,
+        other_hash: String,
+    },
+    /// The entries at an index differ, or only one of the ledgers has it
+    EntryMismatch {
+        index: usize,
+        hash: Option<String>,
+        other_hash: Option<String>,
+    },
+    /// Only one of the ledgers has a status for a transaction of the slot
+    MissingTransactionStatus {
+        signature: String,
+        missing_from: DiffSide,
+    },
+    /// The `TransactionStatusMeta` fields that differ for a transaction of the slot
+    TransactionStatusMismatch {
+        signature: String,
+        fields: Vec<&'static str>,
+    },
+    /// The frozen bank hashes differ, or only one of the ledgers has one
+    BankHashMismatch {
+        hash: Option<String>,
+        other_hash: Option<String>,
+    },
+}
+
+fn or_missing(value: &Option<String>) -> &str {
+    value.as_deref().unwrap_or("missing")
+}
+
+impl fmt::Display for SlotDivergenceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingSlot { missing_from } => {
+                write!(f, "slot is missing from {}", missing_from)
+            }
+            Self::ParentMismatch {
+                parent,
+                other_parent,
+            } => write!(f, "parent is {} vs {}", parent, other_parent),
+            Self::DeadSlotMismatch { dead_in } => write!(f, "slot is only dead in {}", dead_in),
+            Self::DataShredMismatch {
+                index,
+                hash,
+                other_hash,
+            } => write!(
+                f,
+                "data shred {} hashes to {} vs {}",
+                index,
+                or_missing(hash),
+                or_missing(other_hash)
+            ),
+            Self::CodingShredMismatch {
+                index,
+                hash,
+                other_hash,
+            } => write!(
+                f,
+                "coding shred {} hashes to {} vs {}",
+                index, hash, other_hash
+            ),
+            Self::EntryMismatch {
+                index,
+                hash,
+                other_hash,
+            } => write!(
+                f,
+                "entry {} hash is {} vs {}",
+                index,
+                or_missing(hash),
+                or_missing(other_hash)
+            ),
+            Self::MissingTransactionStatus {
+                signature,
+                missing_from,
+            } => write!(
+                f,
+                "transaction {} has no status in {}",
+                signature, missing_from
+            ),
+            Self::TransactionStatusMismatch { signature, fields } => write!(
+                f,
+                "transaction {} status differs in {}",
+                signature,
+                fields.join(", ")
+            ),
+            Self::BankHashMismatch { hash, other_hash } => write!(
+                f,
+                "bank hash is {} vs {}",
+                or_missing(hash),
+                or_missing(other_hash)
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SlotDivergence {
+    pub slot: Slot,
+    pub divergences: Vec<SlotDivergenceKind>,
+}
+
+impl fmt::Display for SlotDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "slot {} diverges:", self.slot)?;
+        for divergence in &self.divergences {
+            write!(f, "\n  {}", divergence)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerDiffReport {
+    pub start_slot: Slot,
+    pub end_slot: Slot,
+    pub slots_compared: u64,
+    pub first_divergence: Option<SlotDivergence>,
+}
+
+impl Blockstore {
+    /// Compare the slots in \[start_slot, end_slot\] of two ledgers in slot order: their
+    /// slot metas, data and coding shreds, entries, transaction statuses and bank hashes.
+    /// Stops at the first slot where the ledgers differ.
+    pub fn diff(
+        &self,
+        other: &Blockstore,
+        start_slot: Slot,
+        end_slot: Slot,
+        exit: &Arc<AtomicBool>,
+    ) -> Result<LedgerDiffReport> {
+        let mut diff_time = Measure::start("diff");
+        let mut report = LedgerDiffReport {
+            start_slot,
+            end_slot,
+            ..LedgerDiffReport::default()
+        };
+        let slots: BTreeSet<Slot> = self
+            .slot_meta_iterator(start_slot)?
+            .map(|(slot, _)| slot)
+            .take_while(|slot| *slot <= end_slot)
+            .chain(
+                other
+                    .slot_meta_iterator(start_slot)?
+                    .map(|(slot, _)| slot)
+                    .take_while(|slot| *slot <= end_slot),
+            )
+            .collect();
+
+        for slot in slots {
+            if exit.load(Ordering::Relaxed) {
+                break;
+            }
+            let divergences = self.diff_slot(other, slot)?;
+            report.slots_compared += 1;
+            if !divergences.is_empty() {
+                report.first_divergence = Some(SlotDivergence { slot, divergences });
+                break;
+            }
+        }
+        diff_time.stop();
+
+        datapoint_info!(
+            "blockstore-diff",
+            ("start_slot", start_slot as i64, i64),
+            ("end_slot", end_slot as i64, i64),
+            ("slots_compared", report.slots_compared as i64, i64),
+            (
+                "first_divergent_slot",
+                report
+                    .first_divergence
+                    .as_ref()
+                    .map(|divergence| divergence.slot as i64)
+                    .unwrap_or(-1),
+                i64
+            ),
+            ("diff_us", diff_time.as_us() as i64, i64),
+        );
+        Ok(report)
+    }
+
+    fn diff_slot(&self, other: &Blockstore, slot: Slot) -> Result<Vec<SlotDivergenceKind>> {
+        let (meta, other_meta) = match (self.meta(slot)?, other.meta(slot)?) {
+            (Some(meta), Some(other_meta)) => (meta, other_meta),
+            (None, _) => {
+                return Ok(vec![SlotDivergenceKind::MissingSlot {
+                    missing_from: DiffSide::Ledger,
+                }])
+            }
+            (_, None) => {
+                return Ok(vec![SlotDivergenceKind::MissingSlot {
+                    missing_from: DiffSide::OtherLedger,
+                }])
+            }
+        };
+
+        let mut divergences = vec![];
+        if meta.parent_slot != other_meta.parent_slot {
+            divergences.push(SlotDivergenceKind::ParentMismatch {
+                parent: meta.parent_slot,
+                other_parent: other_meta.parent_slot,
+            });
+        }
+        let is_dead = self.is_dead(slot);
+        if is_dead != other.is_dead(slot) {
+            divergences.push(SlotDivergenceKind::DeadSlotMismatch {
+                dead_in: if is_dead {
+                    DiffSide::Ledger
+                } else {
+                    DiffSide::OtherLedger
+                },
+            });
+        }
+
+        let data_shreds = shred_hashes(self.slot_data_iterator(slot, 0)?);
+        let other_data_shreds = shred_hashes(other.slot_data_iterator(slot, 0)?);
+        if let Some((index, hash, other_hash)) = first_difference(&data_shreds, &other_data_shreds)
+        {
+            divergences.push(SlotDivergenceKind::DataShredMismatch {
+                index,
+                hash,
+                other_hash,
+            });
+        }
+
+        // Coding shreds are only kept for recovery and validators commonly receive different
+        // subsets of them, so only conflicting coding shreds count as a divergence
+        let coding_shreds = shred_hashes(self.slot_coding_iterator(slot, 0)?);
+        let other_coding_shreds = shred_hashes(other.slot_coding_iterator(slot, 0)?);
+        if let Some((index, hash, other_hash)) =
+            coding_shreds
+                .iter()
+                .find_map(|(index, hash)| match other_coding_shreds.get(index) {
+                    Some(other_hash) if other_hash != hash => {
+                        Some((*index, hash.clone(), other_hash.clone()))
+                    }
+                    _ => None,
+                })
+        {
+            divergences.push(SlotDivergenceKind::CodingShredMismatch {
+                index,
+                hash,
+                other_hash,
+            });
+        }
+
+        // Slots that aren't full in both ledgers have already diverged on their data shreds
+        if let (Ok(entries), Ok(other_entries)) = (
+            self.get_slot_entries(slot, 0),
+            other.get_slot_entries(slot, 0),
+        ) {
+            if let Some((index, hash, other_hash)) =
+                first_difference(&entry_hashes(&entries), &entry_hashes(&other_entries))
+            {
+                divergences.push(SlotDivergenceKind::EntryMismatch {
+                    index,
+                    hash,
+                    other_hash,
+                });
+            } else if let Some(divergence) =
+                self.diff_transaction_statuses(other, slot, &entries)?
+            {
+                divergences.push(divergence);
+            }
+        }
+
+        let bank_hash = self.get_bank_hash(slot);
+        let other_bank_hash = other.get_bank_hash(slot);
+        if bank_hash != other_bank_hash {
+            divergences.push(SlotDivergenceKind::BankHashMismatch {
+                hash: bank_hash.map(|hash| hash.to_string()),
+                other_hash: other_bank_hash.map(|hash| hash.to_string()),
+            });
+        }
+        Ok(divergences)
+    }
+
+    /// Compare the statuses of the transactions of a slot, the first divergent one is returned.
+    /// Ledgers written without transaction history have no statuses to compare.
+    fn diff_transaction_statuses(
+        &self,
+        other: &Blockstore,
+        slot: Slot,
+        entries: &[Entry],
+    ) -> Result<Option<SlotDivergenceKind>> {
+        let signatures = entries
+            .iter()
+            .flat_map(|entry| entry.transactions.iter())
+            .filter_map(|transaction| transaction.signatures.get(0));
+        for signature in signatures {
+            let divergence = match (
+                self.read_transaction_status((*signature, slot))?,
+                other.read_transaction_status((*signature, slot))?,
+            ) {
+                (Some(status), Some(other_status)) => {
+                    let fields = transaction_status_differences(&status, &other_status);
+                    (!fields.is_empty()).then(|| SlotDivergenceKind::TransactionStatusMismatch {
+                        signature: signature.to_string(),
+                        fields,
+                    })
+                }
+                (Some(_), None) => Some(SlotDivergenceKind::MissingTransactionStatus {
+                    signature: signature.to_string(),
+                    missing_from: DiffSide::OtherLedger,
+                }),
+                (None, Some(_)) => Some(SlotDivergenceKind::MissingTransactionStatus {
+                    signature: signature.to_string(),
+                    missing_from: DiffSide::Ledger,
+                }),
+                (None, None) => None,
+            };
+            if divergence.is_some() {
+                return Ok(divergence);
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn shred_hashes(shreds: impl Iterator<Item = ((Slot, u64), Box<[u8]>)>) -> BTreeMap<u64, String> {
+    shreds
+        .map(|((_, index), payload)| (index, hashv(&[&payload]).to_string()))
+        .collect()
+}
+
+fn entry_hashes(entries: &[Entry]) -> BTreeMap<usize, String> {
+    entries
+        .iter()
+        .map(|entry| entry.hash.to_string())
+        .enumerate()
+        .collect()
+}
+
+/// The lowest key with a different value in the two maps, along with the value in each map
+fn first_difference<K: Ord + Copy, V: Clone + PartialEq>(
+    map: &BTreeMap<K, V>,
+    other: &BTreeMap<K, V>,
+) -> Option<(K, Option<V>, Option<V>)> {
+    let key = map
+        .keys()
+        .chain(other.keys())
+        .filter(|key| map.get(key) != other.get(key))
+        .min()?;
+    Some((*key, map.get(key).cloned(), other.get(key).cloned()))
+}
+
+fn transaction_status_differences(
+    status: &TransactionStatusMeta,
+    other: &TransactionStatusMeta,
+) -> Vec<&'static str> {
+    let mut fields = vec![];
+    if status.status != other.status {
+        fields.push("status");
+    }
+    if status.fee != other.fee {
+        fields.push("fee");
+    }
+    if status.pre_balances != other.pre_balances {
+        fields.push("preBalances");
+    }
+    if status.post_balances != other.post_balances {
+        fields.push("postBalances");
+    }
+    if status.inner_instructions != other.inner_instructions {
+        fields.push("innerInstructions");
+    }
+    if status.log_messages != other.log_messages {
+        fields.push("logMessages");
+    }
+    if status.pre_token_balances != other.pre_token_balances {
+        fields.push("preTokenBalances");
+    }
+    if status.post_token_balances != other.post_token_balances {
+        fields.push("postTokenBalances");
+    }
+    if status.rewards != other.rewards {
+        fields.push("rewards");
+    }
+    fields
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{
+        blockstore::tests::make_slot_entries_with_transactions, get_tmp_ledger_path_auto_delete,
+    };
+
+    fn diff(blockstore: &Blockstore, other: &Blockstore) -> LedgerDiffReport {
+        blockstore
+            .diff(other, 0, std::u64::MAX, &Arc::new(AtomicBool::new(false)))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_diff_identical_ledgers() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let blockstore = Blockstore::open(ledger_path.path()).unwrap();
+        let other_ledger_path = get_tmp_ledger_path_auto_delete!();
+        let other = Blockstore::open(other_ledger_path.path()).unwrap();
+
+        let (shreds, _) = make_many_slot_entries(0, 5, 5);
+        blockstore
+            .insert_shreds(shreds.clone(), None, false)
+            .unwrap();
+        other.insert_shreds(shreds, None, false).unwrap();
+        for slot in 0..5 {
+            let bank_hash = Hash::new_unique();
+            blockstore.insert_bank_hash(slot, bank_hash, false);
+            other.insert_bank_hash(slot, bank_hash, false);
+        }
+
+        let report = diff(&blockstore, &other);
+        assert_eq!(report.slots_compared, 5);
+        assert_eq!(report.first_divergence, None);
+    }
+
+    #[test]
+    fn test_diff_first_divergence() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let blockstore = Blockstore::open(ledger_path.path()).unwrap();
+        let other_ledger_path = get_tmp_ledger_path_auto_delete!();
+        let other = Blockstore::open(other_ledger_path.path()).unwrap();
+
+        let entries = make_slot_entries_with_transactions(5);
+        let shreds = entries_to_test_shreds(entries.clone(), 1, 0, true, 0);
+        blockstore
+            .insert_shreds(shreds.clone(), None, false)
+            .unwrap();
+        other.insert_shreds(shreds, None, false).unwrap();
+        let (shreds, _) = make_slot_entries(2, 1, 5);
+        blockstore.insert_shreds(shreds, None, false).unwrap();
+
+        // Slot 2 is only in the first ledger
+        let report = diff(&blockstore, &other);
+        assert_eq!(report.slots_compared, 3);
+        assert_eq!(
+            report.first_divergence,
+            Some(SlotDivergence {
+                slot: 2,
+                divergences: vec![SlotDivergenceKind::MissingSlot {
+                    missing_from: DiffSide::OtherLedger
+                }],
+            })
+        );
+
+        let signature = entries
+            .iter()
+            .find_map(|entry| entry.transactions.get(0))
+            .unwrap()
+            .signatures[0];
+        let status = TransactionStatusMeta {
+            fee: 5000,
+            ..TransactionStatusMeta::default()
+        };
+        blockstore
+            .write_transaction_status(1, signature, vec![], vec![], status.clone())
+            .unwrap();
+        let bank_hash = Hash::new_unique();
+        blockstore.insert_bank_hash(1, bank_hash, false);
+
+        let report = diff(&blockstore, &other);
+        assert_eq!(
+            report.first_divergence,
+            Some(SlotDivergence {
+                slot: 1,
+                divergences: vec![
+                    SlotDivergenceKind::MissingTransactionStatus {
+                        signature: signature.to_string(),
+                        missing_from: DiffSide::OtherLedger,
+                    },
+                    SlotDivergenceKind::BankHashMismatch {
+                        hash: Some(bank_hash.to_string()),
+                        other_hash: None,
+                    },
+                ],
+            })
+        );
+
+        other
+            .write_transaction_status(
+                1,
+                signature,
+                vec![],
+                vec![],
+                TransactionStatusMeta {
+                    fee: 10000,
+                    ..status
+                },
+            )
+            .unwrap();
+        other.insert_bank_hash(1, bank_hash, false);
+
+        let report = diff(&blockstore, &other);
+        assert_eq!(
+            report.first_divergence.unwrap().divergences,
+            vec![SlotDivergenceKind::TransactionStatusMismatch {
+                signature: signature.to_string(),
+                fields: vec!["fee"],
+            }]
+        );
+    }
+}
@@ -0,0 +1,706 @@
+use super::*;
+use serde::Serialize;
+use std::fmt;
+
+/// Which of the optional `Blockstore::check_integrity` checks and fixes are run
+#[derive(Clone, Debug, Default)]
+pub struct IntegrityCheckOptions {
+    /// Require a transaction status for every transaction of a rooted slot, only meaningful
+    /// when the ledger was written with transaction history enabled
+    pub check_transaction_statuses: bool,
+    /// Rewrite the `SlotMeta`, `Index` and `ErasureMeta` entries that disagree with the
+    /// shreds present. Requires primary access to the blockstore.
+    pub repair: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum IntegrityIssueKind {
+    /// `SlotMeta::consumed` is not the number of consecutive data shreds from index 0
+    ConsumedMismatch { recorded: u64, actual: u64 },
+    /// `SlotMeta::received` is not the highest data shred index plus one
+    ReceivedMismatch { recorded: u64, actual: u64 },
+    /// Shreds are present but the slot has no `Index`
+    MissingIndex,
+    /// The `Index` disagrees with the data shreds present
+    DataIndexMismatch { unindexed: usize, missing: usize },
+    /// The `Index` disagrees with the coding shreds present
+    CodingIndexMismatch { unindexed: usize, missing: usize },
+    /// A coding shred is present without the `ErasureMeta` of its erasure set
+    MissingErasureMeta { fec_set_index: u64 },
+    /// A shred could not be deserialized
+    CorruptShred { index: u64, is_data: bool },
+    /// A rooted slot has no `SlotMeta`
+    MissingRootedSlotMeta,
+    /// A rooted slot is missing data shreds
+    IncompleteRootedSlot {
+        consumed: u64,
+        last_index: Option<u64>,
+    },
+    /// An erasure set of a rooted slot is missing data shreds and can't be recovered
+    UnrecoverableErasureSet {
+        fec_set_index: u64,
+        still_need: usize,
+    },
+    /// The parent chain of a root does not lead to the previous root
+    UnchainedRoot { previous_root: Slot },
+    /// The entries of a rooted slot could not be read
+    UnreadableEntries { error: String },
+    /// Transactions of a rooted slot have no transaction status
+    MissingTransactionStatuses { missing: usize, transactions: usize },
+}
+
+impl IntegrityIssueKind {
+    fn is_repairable(&self) -> bool {
+        matches!(
+            self,
+            Self::ConsumedMismatch { .. }
+                | Self::ReceivedMismatch { .. }
+                | Self::MissingIndex
+                | Self::DataIndexMismatch { .. }
+                | Self::CodingIndexMismatch { .. }
+                | Self::MissingErasureMeta { .. }
+        )
+    }
+}
+
+impl fmt::Display for IntegrityIssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ConsumedMismatch { recorded, actual } => write!(
+                f,
+                "slot meta records {} consumed shreds, {} are present",
+                recorded, actual
+            ),
+            Self::ReceivedMismatch { recorded, actual } => write!(
+                f,
+                "slot meta records {} received shreds, highest present is {}",
+                recorded, actual
+            ),
+            Self::MissingIndex => write!(f, "shreds are present without an index"),
+            Self::DataIndexMismatch { unindexed, missing } => write!(
+                f,
+                "{} data shreds are not indexed, {} indexed data shreds are missing",
+                unindexed, missing
+            ),
+            Self::CodingIndexMismatch { unindexed, missing } => write!(
+                f,
+                "{} coding shreds are not indexed, {} indexed coding shreds are missing",
+                unindexed, missing
+            ),
+            Self::MissingErasureMeta { fec_set_index } => {
+                write!(f, "erasure set {} has no erasure meta", fec_set_index)
+            }
+            Self::CorruptShred { index, is_data } => write!(
+                f,
+                "{} shred {} can't be deserialized",
+                if *is_data { "data" } else { "coding" },
+                index
+            ),
+            Self::MissingRootedSlotMeta => write!(f, "rooted slot has no slot meta"),
+            Self::IncompleteRootedSlot {
+                consumed,
+                last_index,
+            } => match last_index {
+                Some(last_index) => write!(
+                    f,
+                    "rooted slot is not full, {} of {} shreds consumed",
+                    consumed,
+                    last_index + 1
+                ),
+                None => write!(f, "rooted slot is not full, last shred is unknown"),
+            },
+            Self::UnrecoverableErasureSet {
+                fec_set_index,
+                still_need,
+            } => write!(
+                f,
+                "erasure set {} can't be recovered, {} more shreds needed",
+                fec_set_index, still_need
+            ),
+            Self::UnchainedRoot { previous_root } => write!(
+                f,
+                "root does not chain to the previous root {}",
+                previous_root
+            ),
+            Self::UnreadableEntries { error } => {
+                write!(f, "entries of rooted slot can't be read: {}", error)
+            }
+            Self::MissingTransactionStatuses {
+                missing,
+                transactions,
+            } => write!(
+                f,
+                "{} of {} transactions have no transaction status",
+                missing, transactions
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityIssue {
+    pub slot: Slot,
+    #[serde(flatten)]
+    pub kind: IntegrityIssueKind,
+    pub repaired: bool,
+}
+
+impl fmt::Display for IntegrityIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "slot {}: {}", self.slot, self.kind)?;
+        if self.repaired {
+            write!(f, " (repaired)")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityReport {
+    pub start_slot: Slot,
+    pub end_slot: Slot,
+    pub slots_checked: u64,
+    pub roots_checked: u64,
+    pub data_shreds_checked: u64,
+    pub coding_shreds_checked: u64,
+    pub issues: Vec<IntegrityIssue>,
+}
+
+impl IntegrityReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    pub fn num_repaired(&self) -> usize {
+        self.issues.iter().filter(|issue| issue.repaired).count()
+    }
+}
+
+impl Blockstore {
+    /// Walk the `SlotMeta`, `Index`, `ErasureMeta`, `ShredData`, `ShredCode` and `Root`
+    /// columns of the slots in \[start_slot, end_slot\] and report where they disagree.
+    /// Slots purged by the `LedgerCleanupService` while the check is running are skipped.
+    pub fn check_integrity(
+        &self,
+        start_slot: Slot,
+        end_slot: Slot,
+        options: &IntegrityCheckOptions,
+        exit: &Arc<AtomicBool>,
+    ) -> Result<IntegrityReport> {
+        let mut check_time = Measure::start("check_integrity");
+        let mut report = IntegrityReport {
+            start_slot,
+            end_slot,
+            ..IntegrityReport::default()
+        };
+        let roots = self.check_roots(start_slot, end_slot, &mut report, exit)?;
+
+        for (slot, meta) in self.slot_meta_iterator(start_slot)? {
+            if slot > end_slot || exit.load(Ordering::Relaxed) {
+                break;
+            }
+            let is_root = roots.contains(&slot);
+            let (issues, is_full) = {
+                // Hold off the LedgerCleanupService until the repairs of the slot are written, so
+                // the metadata of a purged slot is not written back
+                let lowest_cleanup_slot = self.lowest_cleanup_slot.read().unwrap();
+                if *lowest_cleanup_slot > 0 && slot <= *lowest_cleanup_slot {
+                    continue;
+                }
+                // Hold off shred insertion while the metadata of the slot may be rewritten
+                let _insert_lock = options
+                    .repair
+                    .then(|| self.insert_shreds_lock.lock().unwrap());
+                self.check_slot_integrity(slot, meta, is_root, options, &mut report)?
+            };
+            report.issues.extend(issues);
+            // Reading the entries takes the lowest cleanup slot lock on its own
+            if is_root && is_full && options.check_transaction_statuses {
+                match self.check_transaction_statuses(slot) {
+                    Err(BlockstoreError::SlotCleanedUp) => continue,
+                    result => report.issues.extend(result?),
+                }
+            }
+            report.slots_checked += 1;
+        }
+        check_time.stop();
+
+        datapoint_info!(
+            "blockstore-check_integrity",
+            ("start_slot", start_slot as i64, i64),
+            ("end_slot", end_slot as i64, i64),
+            ("slots_checked", report.slots_checked as i64, i64),
+            ("roots_checked", report.roots_checked as i64, i64),
+            ("issues", report.issues.len() as i64, i64),
+            ("repaired", report.num_repaired() as i64, i64),
+            ("check_integrity_us", check_time.as_us() as i64, i64),
+        );
+        Ok(report)
+    }
+
+    /// Check that every root in the range chains back to the root before it, returning the
+    /// roots that were seen
+    fn check_roots(
+        &self,
+        start_slot: Slot,
+        end_slot: Slot,
+        report: &mut IntegrityReport,
+        exit: &Arc<AtomicBool>,
+    ) -> Result<HashSet<Slot>> {
+        let lowest_root = self.rooted_slot_iterator(0)?.next();
+        let mut previous_root = self
+            .db
+            .iter::<cf::Root>(IteratorMode::From(start_slot, IteratorDirection::Reverse))?
+            .map(|(root, _)| root)
+            .find(|root| *root < start_slot);
+
+        let mut roots = HashSet::new();
+        for root in self.rooted_slot_iterator(start_slot)? {
+            if root > end_slot || exit.load(Ordering::Relaxed) {
+                break;
+            }
+            roots.insert(root);
+            report.roots_checked += 1;
+
+            // The root a validator boots from may come from a snapshot without any shreds
+            if self.meta(root)?.is_none() && Some(root) != lowest_root {
+                report.issues.push(IntegrityIssue {
+                    slot: root,
+                    kind: IntegrityIssueKind::MissingRootedSlotMeta,
+                    repaired: false,
+                });
+            }
+            if let Some(previous_root) = previous_root {
+                if !self.is_ancestor_through_slot_metas(previous_root, root)? {
+                    report.issues.push(IntegrityIssue {
+                        slot: root,
+                        kind: IntegrityIssueKind::UnchainedRoot { previous_root },
+                        repaired: false,
+                    });
+                }
+            }
+            previous_root = Some(root);
+        }
+        Ok(roots)
+    }
+
+    fn is_ancestor_through_slot_metas(&self, ancestor: Slot, slot: Slot) -> Result<bool> {
+        let mut parent = match self.meta(slot)? {
+            Some(meta) => meta.parent_slot,
+            None => return Ok(false),
+        };
+        while parent > ancestor && parent != std::u64::MAX {
+            parent = match self.meta(parent)? {
+                Some(meta) => meta.parent_slot,
+                None => return Ok(false),
+            };
+        }
+        Ok(parent == ancestor)
+    }
+
+    fn check_slot_integrity(
+        &self,
+        slot: Slot,
+        mut meta: SlotMeta,
+        is_root: bool,
+        options: &IntegrityCheckOptions,
+        report: &mut IntegrityReport,
+    ) -> Result<(Vec<IntegrityIssue>, bool)> {
+        let mut issues = vec![];
+        let mut write_batch = self.db.batch()?;
+
+        let data_indexes: Vec<u64> = self
+            .slot_data_iterator(slot, 0)?
+            .map(|((_, index), _)| index)
+            .collect();
+        let coding_indexes: Vec<u64> = self
+            .slot_coding_iterator(slot, 0)?
+            .map(|((_, index), _)| index)
+            .collect();
+        report.data_shreds_checked += data_indexes.len() as u64;
+        report.coding_shreds_checked += coding_indexes.len() as u64;
+
+        // SlotMeta against the data shreds
+        let consumed = data_indexes
+            .iter()
+            .zip(0..)
+            .take_while(|(index, expected)| *index == expected)
+            .count() as u64;
+        let received = data_indexes.last().map(|index| index + 1).unwrap_or(0);
+        let mut meta_issues = vec![];
+        if meta.consumed != consumed {
+            meta_issues.push(IntegrityIssueKind::ConsumedMismatch {
+                recorded: meta.consumed,
+                actual: consumed,
+            });
+        }
+        if meta.received != received {
+            meta_issues.push(IntegrityIssueKind::ReceivedMismatch {
+                recorded: meta.received,
+                actual: received,
+            });
+        }
+        if options.repair && !meta_issues.is_empty() {
+            meta.consumed = consumed;
+            meta.received = received;
+            write_batch.put::<cf::SlotMeta>(slot, &meta)?;
+        }
+        issues.extend(meta_issues);
+
+        // Index against the data and coding shreds
+        let mut actual_index = Index::new(slot);
+        actual_index
+            .data_mut()
+            .set_many_present(data_indexes.iter().map(|index| (*index, true)));
+        actual_index
+            .coding_mut()
+            .set_many_present(coding_indexes.iter().map(|index| (*index, true)));
+        let mut index_issues = vec![];
+        match self.get_index(slot)? {
+            None if !data_indexes.is_empty() || !coding_indexes.is_empty() => {
+                index_issues.push(IntegrityIssueKind::MissingIndex);
+            }
+            None => (),
+            Some(index) => {
+                let (unindexed, missing) = Self::compare_shred_index(index.data(), &data_indexes);
+                if unindexed > 0 || missing > 0 {
+                    index_issues.push(IntegrityIssueKind::DataIndexMismatch { unindexed, missing });
+                }
+                let (unindexed, missing) =
+                    Self::compare_shred_index(index.coding(), &coding_indexes);
+                if unindexed > 0 || missing > 0 {
+                    index_issues
+                        .push(IntegrityIssueKind::CodingIndexMismatch { unindexed, missing });
+                }
+            }
+        }
+        if options.repair && !index_issues.is_empty() {
+            write_batch.put::<cf::Index>(slot, &actual_index)?;
+        }
+        issues.extend(index_issues);
+
+        // ErasureMeta against the coding shreds
+        let mut erasure_metas: Vec<(u64, ErasureMeta)> = self
+            .erasure_meta_cf
+            .iter(IteratorMode::From((slot, 0), IteratorDirection::Forward))?
+            .take_while(|((erasure_slot, _), _)| *erasure_slot == slot)
+            .map(|((_, set_index), bytes)| Ok((set_index, deserialize(&bytes)?)))
+            .collect::<Result<_>>()?;
+        for index in &coding_indexes {
+            if erasure_metas
+                .iter()
+                .any(|(_, erasure_meta)| erasure_meta.coding_shreds_indices().contains(index))
+            {
+                continue;
+            }
+            let shred = self
+                .get_coding_shred(slot, *index)?
+                .and_then(|payload| Shred::new_from_serialized_shred(payload).ok());
+            let shred = match shred {
+                Some(shred) => shred,
+                None => {
+                    issues.push(IntegrityIssueKind::CorruptShred {
+                        index: *index,
+                        is_data: false,
+                    });
+                    continue;
+                }
+            };
+            let fec_set_index = u64::from(shred.common_header.fec_set_index);
+            let erasure_meta = ErasureMeta::new(
+                fec_set_index,
+                ErasureConfig::new(
+                    shred.coding_header.num_data_shreds as usize,
+                    shred.coding_header.num_coding_shreds as usize,
+                ),
+            );
+            if options.repair {
+                write_batch.put::<cf::ErasureMeta>((slot, fec_set_index), &erasure_meta)?;
+            }
+            erasure_metas.push((fec_set_index, erasure_meta));
+            issues.push(IntegrityIssueKind::MissingErasureMeta { fec_set_index });
+        }
+
+        // A rooted slot must be complete, slots on other forks may be abandoned mid-way
+        if is_root && !meta.is_full() {
+            issues.push(IntegrityIssueKind::IncompleteRootedSlot {
+                consumed,
+                last_index: meta.known_last_index(),
+            });
+            for (fec_set_index, erasure_meta) in &erasure_metas {
+                if let ErasureMetaStatus::StillNeed(still_need) = erasure_meta.status(&actual_index)
+                {
+                    issues.push(IntegrityIssueKind::UnrecoverableErasureSet {
+                        fec_set_index: *fec_set_index,
+                        still_need,
+                    });
+                }
+            }
+        }
+
+        if options.repair {
+            self.db.write(write_batch)?;
+        }
+        let issues = issues
+            .into_iter()
+            .map(|kind| IntegrityIssue {
+                slot,
+                repaired: options.repair && kind.is_repairable(),
+                kind,
+            })
+            .collect();
+        Ok((issues, meta.is_full()))
+    }
+
+    /// Check that every transaction of a complete rooted slot has a status
+    fn check_transaction_statuses(&self, slot: Slot) -> Result<Vec<IntegrityIssue>> {
+        let kind = match self.get_slot_entries(slot, 0) {
+            Ok(entries) => {
+                let signatures: Vec<Signature> = entries
+                    .iter()
+                    .flat_map(|entry| entry.transactions.iter())
+                    .filter_map(|transaction| transaction.signatures.get(0).copied())
+                    .collect();
+                let mut missing = 0;
+                for signature in &signatures {
+                    if self.read_transaction_status((*signature, slot))?.is_none() {
+                        missing += 1;
+                    }
+                }
+                if missing == 0 {
+                    return Ok(vec![]);
+                }
+                IntegrityIssueKind::MissingTransactionStatuses {
+                    missing,
+                    transactions: signatures.len(),
+                }
+            }
+            Err(BlockstoreError::SlotCleanedUp) => return Err(BlockstoreError::SlotCleanedUp),
+            Err(err) => IntegrityIssueKind::UnreadableEntries {
+                error: format!("{:?}", err),
+            },
+        };
+        Ok(vec![IntegrityIssue {
+            slot,
+            repaired: false,
+            kind,
+        }])
+    }
+
+    /// Returns the number of shreds present but not indexed, and indexed but not present
+    fn compare_shred_index(shred_index: &ShredIndex, present: &[u64]) -> (usize, usize) {
+        let indexed = present
+            .iter()
+            .filter(|index| shred_index.is_present(**index))
+            .count();
+        (present.len() - indexed, shred_index.num_shreds() - indexed)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::get_tmp_ledger_path_auto_delete;
+
+    fn check(blockstore: &Blockstore, options: &IntegrityCheckOptions) -> IntegrityReport {
+        blockstore
+            .check_integrity(0, std::u64::MAX, options, &Arc::new(AtomicBool::new(false)))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_check_integrity_healthy_ledger() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let blockstore = Blockstore::open(ledger_path.path()).unwrap();
+
+        let (shreds, _) = make_many_slot_entries(0, 10, 5);
+        blockstore.insert_shreds(shreds, None, false).unwrap();
+        blockstore.set_roots([0, 1, 2, 3].iter()).unwrap();
+
+        let report = check(&blockstore, &IntegrityCheckOptions::default());
+        assert!(report.is_ok(), "{:?}", report.issues);
+        assert_eq!(report.slots_checked, 10);
+        assert_eq!(report.roots_checked, 4);
+        assert!(report.data_shreds_checked > 0);
+    }
+
+    #[test]
+    fn test_check_integrity_repair_metadata() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let blockstore = Blockstore::open(ledger_path.path()).unwrap();
+
+        let (shreds, _) = make_slot_entries(1, 0, 10);
+        let num_shreds = shreds.len() as u64;
+        blockstore.insert_shreds(shreds, None, false).unwrap();
+
+        let mut meta = blockstore.meta(1).unwrap().unwrap();
+        meta.consumed = 0;
+        blockstore.meta_cf.put(1, &meta).unwrap();
+        blockstore.index_cf.delete(1).unwrap();
+
+        let report = check(&blockstore, &IntegrityCheckOptions::default());
+        assert_eq!(
+            report.issues,
+            vec![
+                IntegrityIssue {
+                    slot: 1,
+                    kind: IntegrityIssueKind::ConsumedMismatch {
+                        recorded: 0,
+                        actual: num_shreds,
+                    },
+                    repaired: false,
+                },
+                IntegrityIssue {
+                    slot: 1,
+                    kind: IntegrityIssueKind::MissingIndex,
+                    repaired: false,
+                },
+            ]
+        );
+
+        let repair = IntegrityCheckOptions {
+            repair: true,
+            ..IntegrityCheckOptions::default()
+        };
+        let report = check(&blockstore, &repair);
+        assert_eq!(report.issues.len(), 2);
+        assert_eq!(report.num_repaired(), 2);
+        assert!(blockstore.is_full(1));
+        assert_eq!(
+            blockstore
+                .get_index(1)
+                .unwrap()
+                .unwrap()
+                .data()
+                .num_shreds() as u64,
+            num_shreds
+        );
+        assert!(check(&blockstore, &repair).is_ok());
+    }
+
+    #[test]
+    fn test_check_integrity_skips_cleaned_up_slots() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let blockstore = Blockstore::open(ledger_path.path()).unwrap();
+
+        let (shreds, _) = make_many_slot_entries(1, 2, 5);
+        blockstore.insert_shreds(shreds, None, false).unwrap();
+        for slot in [1, 2] {
+            let mut meta = blockstore.meta(slot).unwrap().unwrap();
+            meta.consumed = 0;
+            blockstore.meta_cf.put(slot, &meta).unwrap();
+        }
+        // Slot 1 is being purged, its metadata must not be written back
+        *blockstore.lowest_cleanup_slot.write().unwrap() = 1;
+
+        let repair = IntegrityCheckOptions {
+            repair: true,
+            ..IntegrityCheckOptions::default()
+        };
+        let report = check(&blockstore, &repair);
+        assert_eq!(report.slots_checked, 1);
+        assert!(report.issues.iter().all(|issue| issue.slot == 2));
+        assert_eq!(report.num_repaired(), 1);
+        assert_eq!(blockstore.meta(1).unwrap().unwrap().consumed, 0);
+        assert!(blockstore.is_full(2));
+    }
+
+    #[test]
+    fn test_check_integrity_rooted_slots() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let blockstore = Blockstore::open(ledger_path.path()).unwrap();
+
+        let (shreds, _) = make_many_slot_entries(0, 4, 5);
+        blockstore.insert_shreds(shreds, None, false).unwrap();
+        // Slot 5 chains to slot 3, skipping the root at slot 4
+        let (shreds, _) = make_slot_entries(4, 2, 5);
+        blockstore.insert_shreds(shreds, None, false).unwrap();
+        let (shreds, _) = make_slot_entries(5, 3, 5);
+        blockstore.insert_shreds(shreds, None, false).unwrap();
+        blockstore.set_roots([0, 1, 2, 4, 5].iter()).unwrap();
+
+        // Drop the last data shred of rooted slot 1 and its index entry
+        let last_index = blockstore.meta(1).unwrap().unwrap().last_index;
+        blockstore.data_shred_cf.delete((1, last_index)).unwrap();
+        let mut index = blockstore.get_index(1).unwrap().unwrap();
+        index.data_mut().set_present(last_index, false);
+        blockstore.index_cf.put(1, &index).unwrap();
+        let mut meta = blockstore.meta(1).unwrap().unwrap();
+        meta.consumed = last_index;
+        meta.received = last_index;
+        blockstore.meta_cf.put(1, &meta).unwrap();
+
+        let report = check(&blockstore, &IntegrityCheckOptions::default());
+        assert_eq!(
+            report.issues,
+            vec![
+                IntegrityIssue {
+                    slot: 5,
+                    kind: IntegrityIssueKind::UnchainedRoot { previous_root: 4 },
+                    repaired: false,
+                },
+                IntegrityIssue {
+                    slot: 1,
+                    kind: IntegrityIssueKind::IncompleteRootedSlot {
+                        consumed: last_index,
+                        last_index: Some(last_index),
+                    },
+                    repaired: false,
+                },
+            ]
+        );
+
+        // The ticks of these slots don't carry transactions, so every status is present
+        let statuses = IntegrityCheckOptions {
+            check_transaction_statuses: true,
+            ..IntegrityCheckOptions::default()
+        };
+        assert_eq!(check(&blockstore, &statuses).issues.len(), 2);
+    }
+
+    #[test]
+    fn test_check_integrity_missing_transaction_statuses() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let blockstore = Blockstore::open(ledger_path.path()).unwrap();
+
+        let entries = crate::blockstore::tests::make_slot_entries_with_transactions(3);
+        let shreds = entries_to_test_shreds(entries.clone(), 1, 0, true, 0);
+        blockstore.insert_shreds(shreds, None, false).unwrap();
+        blockstore.set_roots([0, 1].iter()).unwrap();
+
+        let signature = entries
+            .iter()
+            .find_map(|entry| entry.transactions.get(0))
+            .unwrap()
+            .signatures[0];
+        blockstore
+            .write_transaction_status(
+                1,
+                signature,
+                vec![],
+                vec![],
+                TransactionStatusMeta::default(),
+            )
+            .unwrap();
+
+        let options = IntegrityCheckOptions {
+            check_transaction_statuses: true,
+            ..IntegrityCheckOptions::default()
+        };
+        let report = check(&blockstore, &options);
+        assert_eq!(
+            report.issues,
+            vec![IntegrityIssue {
+                slot: 1,
+                kind: IntegrityIssueKind::MissingTransactionStatuses {
+                    missing: 2,
+                    transactions: 3,
+                },
+                repaired: false,
+            }]
+        );
+    }
+}
@@ -45,6 +45,18 @@ impl Blockstore {
         self.db.set_oldest_slot(to_slot);
     }
 
+    /// Like set_max_expired_slot(), but only for the column families of `group`, so that
+    /// LedgerCleanupService can retain the groups for different ranges of slots.
+    pub fn set_column_group_max_expired_slot(&self, group: ColumnGroup, to_slot: Slot) {
+        let to_slot = to_slot.checked_add(1).unwrap();
+        self.db.set_column_group_oldest_slot(group, to_slot);
+    }
+
+    /// The oldest slot kept by the compaction filters of the column families of `group`
+    pub fn column_group_oldest_slot(&self, group: ColumnGroup) -> Slot {
+        self.db.column_group_oldest_slot(group)
+    }
+
     pub fn purge_and_compact_slots(&self, from_slot: Slot, to_slot: Slot) {
         self.purge_slots(from_slot, to_slot, PurgeType::Exact);
         if let Err(e) = self.compact_storage(from_slot, to_slot) {
@@ -175,10 +187,6 @@ impl Blockstore {
                 .db
                 .delete_range_cf::<cf::Rewards>(&mut write_batch, from_slot, to_slot)
                 .is_ok()
-            & self
-                .db
-                .delete_range_cf::<cf::Blocktime>(&mut write_batch, from_slot, to_slot)
-                .is_ok()
             & self
                 .db
                 .delete_range_cf::<cf::PerfSamples>(&mut write_batch, from_slot, to_slot)
@@ -191,9 +199,17 @@ impl Blockstore {
             self.active_transaction_status_index.write().unwrap();
         match purge_type {
             PurgeType::Exact => {
+                columns_purged &= self
+                    .db
+                    .delete_range_cf::<cf::Blocktime>(&mut write_batch, from_slot, to_slot)
+                    .is_ok();
                 self.purge_special_columns_exact(&mut write_batch, from_slot, to_slot)?;
             }
             PurgeType::PrimaryIndex => {
+                columns_purged &= self
+                    .db
+                    .delete_range_cf::<cf::Blocktime>(&mut write_batch, from_slot, to_slot)
+                    .is_ok();
                 self.purge_special_columns_with_primary_index(
                     &mut write_batch,
                     &mut columns_purged,
@@ -207,6 +223,8 @@ impl Blockstore {
                 // special column families, never toggling the primary index from the current
                 // one. Overall, this enables well uniformly distributed writes, resulting
                 // in no spiky periodic huge delete_range for them.
+                // Blocktime is left to its compaction filter too, as it is kept until the
+                // transaction statuses of the slot expire, which can be after its shreds.
             }
         }
         delete_range_timer.stop();
@@ -284,6 +302,10 @@ impl Blockstore {
                 .address_signatures_cf
                 .compact_range(0, 2)
                 .unwrap_or(false)
+            && self
+                .program_signatures_cf
+                .compact_range(0, 2)
+                .unwrap_or(false)
             && self
                 .transaction_status_index_cf
                 .compact_range(0, 2)
@@ -340,6 +362,9 @@ impl Blockstore {
                     for pubkey in transaction.message.unmapped_keys() {
                         batch.delete::<cf::AddressSignatures>((0, pubkey, slot, signature))?;
                         batch.delete::<cf::AddressSignatures>((1, pubkey, slot, signature))?;
+                        // Programs are account keys too
+                        batch.delete::<cf::ProgramSignatures>((0, pubkey, slot, signature))?;
+                        batch.delete::<cf::ProgramSignatures>((1, pubkey, slot, signature))?;
                     }
                 }
             }
@@ -384,6 +409,14 @@ impl Blockstore {
                         purged_index,
                         purged_index + 1,
                     )
+                    .is_ok()
+                & self
+                    .db
+                    .delete_range_cf::<cf::ProgramSignatures>(
+                        write_batch,
+                        purged_index,
+                        purged_index + 1,
+                    )
                     .is_ok();
         }
         Ok(())
@@ -487,6 +520,15 @@ pub mod tests {
                     slot >= min_slot || (primary_index == 2 && slot == 0)
                 })
                 .unwrap_or(true)
+            & blockstore
+                .db
+                .iter::<cf::ProgramSignatures>(IteratorMode::Start)
+                .unwrap()
+                .next()
+                .map(|((primary_index, _, slot, _), _)| {
+                    slot >= min_slot || (primary_index == 2 && slot == 0)
+                })
+                .unwrap_or(true)
             & blockstore
                 .db
                 .iter::<cf::Rewards>(IteratorMode::Start)
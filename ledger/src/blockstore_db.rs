@@ -8,8 +8,9 @@ use rocksdb::{
     self,
     compaction_filter::CompactionFilter,
     compaction_filter_factory::{CompactionFilterContext, CompactionFilterFactory},
-    ColumnFamily, ColumnFamilyDescriptor, CompactionDecision, DBIterator, DBRawIterator,
-    DBRecoveryMode, IteratorMode as RocksIteratorMode, Options, WriteBatch as RWriteBatch, DB,
+    ColumnFamily, ColumnFamilyDescriptor, CompactionDecision, DBCompressionType, DBIterator,
+    DBRawIterator, DBRecoveryMode, IteratorMode as RocksIteratorMode, Options,
+    WriteBatch as RWriteBatch, DB,
 };
 
 use serde::de::DeserializeOwned;
@@ -27,6 +28,7 @@ use std::{
     fs,
     marker::PhantomData,
     path::Path,
+    str::FromStr,
     sync::{
         atomic::{AtomicU64, Ordering},
         Arc,
@@ -77,6 +79,10 @@ const PERF_SAMPLES_CF: &str = "perf_samples";
 const BLOCK_HEIGHT_CF: &str = "block_height";
 /// Column family for ProgramCosts
 const PROGRAM_COSTS_CF: &str = "program_costs";
+/// Column family for Program Signatures
+const PROGRAM_SIGNATURES_CF: &str = "program_signatures";
+/// Column family for the long-term storage upload checkpoints
+const UPLOAD_CHECKPOINTS_CF: &str = "upload_checkpoints";
 
 // 1 day is chosen for the same reasoning of DEFAULT_COMPACTION_SLOT_INTERVAL
 const PERIODIC_COMPACTION_SECONDS: u64 = 60 * 60 * 24;
@@ -193,6 +199,14 @@ pub mod columns {
     #[derive(Debug)]
     // The program costs column
     pub struct ProgramCosts;
+
+    #[derive(Debug)]
+    /// The program signatures column
+    pub struct ProgramSignatures;
+
+    #[derive(Debug)]
+    /// The long-term storage upload checkpoints column, keyed by upload destination
+    pub struct UploadCheckpoints;
 }
 
 pub enum AccessType {
@@ -244,6 +258,153 @@ impl From<BlockstoreRecoveryMode> for DBRecoveryMode {
     }
 }
 
+/// The compression of the SST files of a column family. Changing it only affects the files
+/// written afterwards, by flushes and compactions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockstoreCompressionType {
+    None,
+    Lz4,
+    Zstd,
+}
+
+impl FromStr for BlockstoreCompressionType {
+    type Err = String;
+
+    fn from_str(string: &str) -> std::result::Result<Self, Self::Err> {
+        match string {
+            "none" => Ok(BlockstoreCompressionType::None),
+            "lz4" => Ok(BlockstoreCompressionType::Lz4),
+            "zstd" => Ok(BlockstoreCompressionType::Zstd),
+            bad_type => Err(format!("Invalid compression type: {}", bad_type)),
+        }
+    }
+}
+
+impl From<BlockstoreCompressionType> for DBCompressionType {
+    fn from(compression_type: BlockstoreCompressionType) -> Self {
+        match compression_type {
+            BlockstoreCompressionType::None => DBCompressionType::None,
+            BlockstoreCompressionType::Lz4 => DBCompressionType::Lz4,
+            BlockstoreCompressionType::Zstd => DBCompressionType::Zstd,
+        }
+    }
+}
+
+/// The compression of every column family, the columns not listed keep the RocksDB default
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BlockstoreCompressionConfig {
+    pub columns: HashMap<String, BlockstoreCompressionType>,
+    /// The maximum size of the zstd dictionary trained for the shred columns when they are
+    /// zstd compressed, 0 disables the dictionary. Shreds are small and similar to each
+    /// other, a shared dictionary compresses them much better than each block alone.
+    pub shred_zstd_dictionary_bytes: u32,
+}
+
+impl BlockstoreCompressionConfig {
+    /// Parse `<COLUMN>=<none|lz4|zstd>` entries
+    pub fn from_column_values<'a>(
+        values: impl IntoIterator<Item = &'a str>,
+        shred_zstd_dictionary_bytes: u32,
+    ) -> std::result::Result<Self, String> {
+        let columns = values
+            .into_iter()
+            .map(Self::parse_column_value)
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(Self {
+            columns,
+            shred_zstd_dictionary_bytes,
+        })
+    }
+
+    pub fn parse_column_value(
+        value: &str,
+    ) -> std::result::Result<(String, BlockstoreCompressionType), String> {
+        let mut parts = value.splitn(2, '=');
+        let column = parts.next().unwrap_or_default();
+        let compression_type = parts
+            .next()
+            .ok_or_else(|| format!("Expected <COLUMN>=<TYPE>, got {}", value))?
+            .parse()?;
+        if !Rocks::columns().contains(&column) {
+            return Err(format!("Unknown column: {}", column));
+        }
+        Ok((column.to_string(), compression_type))
+    }
+
+    fn compression_type(&self, column: &str) -> Option<BlockstoreCompressionType> {
+        self.columns.get(column).cloned()
+    }
+}
+
+/// The options of the blockstore database
+pub struct BlockstoreOptions {
+    pub access_type: AccessType,
+    pub recovery_mode: Option<BlockstoreRecoveryMode>,
+    pub enforce_ulimit_nofile: bool,
+    pub compression: BlockstoreCompressionConfig,
+    /// Index the transactions by the programs they invoke in the ProgramSignatures column, which
+    /// is derived from the recorded transaction log messages
+    pub enable_program_signatures: bool,
+}
+
+impl Default for BlockstoreOptions {
+    fn default() -> Self {
+        Self {
+            access_type: AccessType::PrimaryOnly,
+            recovery_mode: None,
+            enforce_ulimit_nofile: true,
+            compression: BlockstoreCompressionConfig::default(),
+            enable_program_signatures: false,
+        }
+    }
+}
+
+/// The on-disk size of a column family and how well it compresses
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ColumnStorageStats {
+    pub name: &'static str,
+    /// The compression the column family is opened with, the SST files written before keep
+    /// the compression they were written with
+    pub configured_compression_type: Option<BlockstoreCompressionType>,
+    /// The size of the live SST files
+    pub sst_files_bytes: u64,
+    /// The size of the keys and values before compression
+    pub raw_bytes: u64,
+    /// The size of the data blocks of the SST files, after compression
+    pub data_block_bytes: u64,
+}
+
+impl ColumnStorageStats {
+    pub fn compression_ratio(&self) -> Option<f64> {
+        if self.data_block_bytes == 0 {
+            None
+        } else {
+            Some(self.raw_bytes as f64 / self.data_block_bytes as f64)
+        }
+    }
+}
+
+/// Sum the `raw key size`, `raw value size` and `data block size` of the aggregated table
+/// properties of a column family, formatted like `# entries=2; raw key size=32; ...`
+fn parse_aggregated_table_properties(properties: &str) -> (u64, u64) {
+    let mut raw_bytes = 0;
+    let mut data_block_bytes = 0;
+    for property in properties.split(';') {
+        let mut property = property.splitn(2, '=');
+        let name = property.next().unwrap_or_default().trim();
+        let value = property
+            .next()
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .unwrap_or_default();
+        match name {
+            "raw key size" | "raw value size" => raw_bytes += value,
+            "data block size" => data_block_bytes += value,
+            _ => {}
+        }
+    }
+    (raw_bytes, data_block_bytes)
+}
+
 #[derive(Default, Clone, Debug)]
 struct OldestSlot(Arc<AtomicU64>);
 
@@ -266,17 +427,107 @@ impl OldestSlot {
     }
 }
 
+/// The column families that share a retention, so that `LedgerCleanupService` can keep the
+/// transaction history for a different range of slots than the shreds
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColumnGroup {
+    /// The shreds and every other column, including `Blocktime` and `Rewards`. The block times
+    /// are nonetheless kept until the longer retained of the two groups expires them, as the
+    /// transaction statuses are looked up along with them
+    Shreds,
+    /// `TransactionStatus`, `AddressSignatures`, `ProgramSignatures`, `TransactionMemos` and
+    /// the `TransactionStatusIndex`
+    TransactionStatus,
+}
+
+impl ColumnGroup {
+    pub fn of(column_name: &str) -> Self {
+        if column_name == columns::TransactionStatus::NAME
+            || column_name == columns::AddressSignatures::NAME
+            || column_name == columns::ProgramSignatures::NAME
+            || column_name == columns::TransactionMemos::NAME
+            || column_name == columns::TransactionStatusIndex::NAME
+        {
+            ColumnGroup::TransactionStatus
+        } else {
+            ColumnGroup::Shreds
+        }
+    }
+
+    /// The names of the column families in this group
+    pub fn columns(&self) -> Vec<&'static str> {
+        Rocks::columns()
+            .into_iter()
+            .filter(|column_name| ColumnGroup::of(column_name) == *self)
+            .collect()
+    }
+}
+
+impl FromStr for ColumnGroup {
+    type Err = String;
+
+    fn from_str(string: &str) -> std::result::Result<Self, Self::Err> {
+        match string {
+            "shreds" => Ok(ColumnGroup::Shreds),
+            "transaction-status" => Ok(ColumnGroup::TransactionStatus),
+            bad_group => Err(format!("Invalid column group: {}", bad_group)),
+        }
+    }
+}
+
+impl std::fmt::Display for ColumnGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColumnGroup::Shreds => write!(f, "shreds"),
+            ColumnGroup::TransactionStatus => write!(f, "transaction-status"),
+        }
+    }
+}
+
+/// The oldest slot kept by the compaction filters of each `ColumnGroup`, and of `Blocktime`
+#[derive(Default, Clone, Debug)]
+struct OldestSlots {
+    shreds: OldestSlot,
+    transaction_status: OldestSlot,
+    block_time: OldestSlot,
+}
+
+impl OldestSlots {
+    fn group(&self, group: ColumnGroup) -> &OldestSlot {
+        match group {
+            ColumnGroup::Shreds => &self.shreds,
+            ColumnGroup::TransactionStatus => &self.transaction_status,
+        }
+    }
+
+    fn column(&self, column_name: &str) -> &OldestSlot {
+        if column_name == columns::Blocktime::NAME {
+            &self.block_time
+        } else {
+            self.group(ColumnGroup::of(column_name))
+        }
+    }
+
+    fn set_group(&self, group: ColumnGroup, oldest_slot: Slot) {
+        self.group(group).set(oldest_slot);
+        self.block_time
+            .set(self.shreds.get().min(self.transaction_status.get()));
+    }
+}
+
 #[derive(Debug)]
-struct Rocks(rocksdb::DB, ActualAccessType, OldestSlot);
+struct Rocks(rocksdb::DB, ActualAccessType, OldestSlots);
 
 impl Rocks {
-    fn open(
-        path: &Path,
-        access_type: AccessType,
-        recovery_mode: Option<BlockstoreRecoveryMode>,
-    ) -> Result<Rocks> {
+    fn open(path: &Path, options: BlockstoreOptions) -> Result<Rocks> {
         use columns::*;
 
+        let BlockstoreOptions {
+            access_type,
+            recovery_mode,
+            compression,
+            ..
+        } = options;
         fs::create_dir_all(&path)?;
 
         // Use default database options
@@ -288,84 +539,92 @@ impl Rocks {
             db_options.set_wal_recovery_mode(recovery_mode.into());
         }
 
-        let oldest_slot = OldestSlot::default();
+        let oldest_slots = OldestSlots::default();
 
         // Column family names
         let meta_cf_descriptor = ColumnFamilyDescriptor::new(
             SlotMeta::NAME,
-            get_cf_options::<SlotMeta>(&access_type, &oldest_slot),
+            get_cf_options::<SlotMeta>(&access_type, &oldest_slots, &compression),
         );
         let dead_slots_cf_descriptor = ColumnFamilyDescriptor::new(
             DeadSlots::NAME,
-            get_cf_options::<DeadSlots>(&access_type, &oldest_slot),
+            get_cf_options::<DeadSlots>(&access_type, &oldest_slots, &compression),
         );
         let duplicate_slots_cf_descriptor = ColumnFamilyDescriptor::new(
             DuplicateSlots::NAME,
-            get_cf_options::<DuplicateSlots>(&access_type, &oldest_slot),
+            get_cf_options::<DuplicateSlots>(&access_type, &oldest_slots, &compression),
         );
         let erasure_meta_cf_descriptor = ColumnFamilyDescriptor::new(
             ErasureMeta::NAME,
-            get_cf_options::<ErasureMeta>(&access_type, &oldest_slot),
+            get_cf_options::<ErasureMeta>(&access_type, &oldest_slots, &compression),
         );
         let orphans_cf_descriptor = ColumnFamilyDescriptor::new(
             Orphans::NAME,
-            get_cf_options::<Orphans>(&access_type, &oldest_slot),
+            get_cf_options::<Orphans>(&access_type, &oldest_slots, &compression),
         );
         let bank_hash_cf_descriptor = ColumnFamilyDescriptor::new(
             BankHash::NAME,
-            get_cf_options::<BankHash>(&access_type, &oldest_slot),
+            get_cf_options::<BankHash>(&access_type, &oldest_slots, &compression),
         );
         let root_cf_descriptor = ColumnFamilyDescriptor::new(
             Root::NAME,
-            get_cf_options::<Root>(&access_type, &oldest_slot),
+            get_cf_options::<Root>(&access_type, &oldest_slots, &compression),
         );
         let index_cf_descriptor = ColumnFamilyDescriptor::new(
             Index::NAME,
-            get_cf_options::<Index>(&access_type, &oldest_slot),
+            get_cf_options::<Index>(&access_type, &oldest_slots, &compression),
         );
         let shred_data_cf_descriptor = ColumnFamilyDescriptor::new(
             ShredData::NAME,
-            get_cf_options::<ShredData>(&access_type, &oldest_slot),
+            get_cf_options::<ShredData>(&access_type, &oldest_slots, &compression),
         );
         let shred_code_cf_descriptor = ColumnFamilyDescriptor::new(
             ShredCode::NAME,
-            get_cf_options::<ShredCode>(&access_type, &oldest_slot),
+            get_cf_options::<ShredCode>(&access_type, &oldest_slots, &compression),
         );
         let transaction_status_cf_descriptor = ColumnFamilyDescriptor::new(
             TransactionStatus::NAME,
-            get_cf_options::<TransactionStatus>(&access_type, &oldest_slot),
+            get_cf_options::<TransactionStatus>(&access_type, &oldest_slots, &compression),
         );
         let address_signatures_cf_descriptor = ColumnFamilyDescriptor::new(
             AddressSignatures::NAME,
-            get_cf_options::<AddressSignatures>(&access_type, &oldest_slot),
+            get_cf_options::<AddressSignatures>(&access_type, &oldest_slots, &compression),
         );
         let transaction_memos_cf_descriptor = ColumnFamilyDescriptor::new(
             TransactionMemos::NAME,
-            get_cf_options::<TransactionMemos>(&access_type, &oldest_slot),
+            get_cf_options::<TransactionMemos>(&access_type, &oldest_slots, &compression),
         );
         let transaction_status_index_cf_descriptor = ColumnFamilyDescriptor::new(
             TransactionStatusIndex::NAME,
-            get_cf_options::<TransactionStatusIndex>(&access_type, &oldest_slot),
+            get_cf_options::<TransactionStatusIndex>(&access_type, &oldest_slots, &compression),
         );
         let rewards_cf_descriptor = ColumnFamilyDescriptor::new(
             Rewards::NAME,
-            get_cf_options::<Rewards>(&access_type, &oldest_slot),
+            get_cf_options::<Rewards>(&access_type, &oldest_slots, &compression),
         );
         let blocktime_cf_descriptor = ColumnFamilyDescriptor::new(
             Blocktime::NAME,
-            get_cf_options::<Blocktime>(&access_type, &oldest_slot),
+            get_cf_options::<Blocktime>(&access_type, &oldest_slots, &compression),
         );
         let perf_samples_cf_descriptor = ColumnFamilyDescriptor::new(
             PerfSamples::NAME,
-            get_cf_options::<PerfSamples>(&access_type, &oldest_slot),
+            get_cf_options::<PerfSamples>(&access_type, &oldest_slots, &compression),
         );
         let block_height_cf_descriptor = ColumnFamilyDescriptor::new(
             BlockHeight::NAME,
-            get_cf_options::<BlockHeight>(&access_type, &oldest_slot),
+            get_cf_options::<BlockHeight>(&access_type, &oldest_slots, &compression),
         );
         let program_costs_cf_descriptor = ColumnFamilyDescriptor::new(
             ProgramCosts::NAME,
-            get_cf_options::<ProgramCosts>(&access_type, &oldest_slot),
+            get_cf_options::<ProgramCosts>(&access_type, &oldest_slots, &compression),
+        );
+        let program_signatures_cf_descriptor = ColumnFamilyDescriptor::new(
+            ProgramSignatures::NAME,
+            get_cf_options::<ProgramSignatures>(&access_type, &oldest_slots, &compression),
+        );
+        let upload_checkpoints_cf_descriptor = ColumnFamilyDescriptor::new(
+            UploadCheckpoints::NAME,
+            get_cf_options::<UploadCheckpoints>(&access_type, &oldest_slots, &compression),
         );
         // Don't forget to add to both run_purge_with_stats() and
         // compact_storage() in ledger/src/blockstore/blockstore_purge.rs!!
@@ -393,6 +652,8 @@ impl Rocks {
             (PerfSamples::NAME, perf_samples_cf_descriptor),
             (BlockHeight::NAME, block_height_cf_descriptor),
             (ProgramCosts::NAME, program_costs_cf_descriptor),
+            (ProgramSignatures::NAME, program_signatures_cf_descriptor),
+            (UploadCheckpoints::NAME, upload_checkpoints_cf_descriptor),
         ];
         let cf_names: Vec<_> = cfs.iter().map(|c| c.0).collect();
 
@@ -490,7 +751,7 @@ impl Rocks {
         Ok(db)
     }
 
-    fn columns(&self) -> Vec<&'static str> {
+    fn columns() -> Vec<&'static str> {
         use columns::*;
 
         vec![
@@ -513,6 +774,8 @@ impl Rocks {
             PerfSamples::NAME,
             BlockHeight::NAME,
             ProgramCosts::NAME,
+            ProgramSignatures::NAME,
+            UploadCheckpoints::NAME,
         ]
     }
 
@@ -528,6 +791,16 @@ impl Rocks {
             .expect("should never get an unknown column")
     }
 
+    fn property_int_value_cf(&self, cf: &ColumnFamily, name: &str) -> Result<Option<u64>> {
+        let value = self.0.property_int_value_cf(cf, name)?;
+        Ok(value)
+    }
+
+    fn property_value_cf(&self, cf: &ColumnFamily, name: &str) -> Result<Option<String>> {
+        let value = self.0.property_value_cf(cf, name)?;
+        Ok(value)
+    }
+
     fn get_cf(&self, cf: &ColumnFamily, key: &[u8]) -> Result<Option<Vec<u8>>> {
         let opt = self.0.get_cf(cf, key)?;
         Ok(opt)
@@ -575,6 +848,11 @@ impl Rocks {
     fn is_primary_access(&self) -> bool {
         self.1 == ActualAccessType::Primary
     }
+
+    fn try_catch_up_with_primary(&self) -> Result<()> {
+        self.0.try_catch_up_with_primary()?;
+        Ok(())
+    }
 }
 
 pub trait Column {
@@ -607,6 +885,10 @@ impl TypedColumn for columns::AddressSignatures {
     type Type = blockstore_meta::AddressSignatureMeta;
 }
 
+impl TypedColumn for columns::ProgramSignatures {
+    type Type = blockstore_meta::ProgramSignatureMeta;
+}
+
 impl TypedColumn for columns::TransactionMemos {
     type Type = String;
 }
@@ -725,6 +1007,35 @@ impl ColumnName for columns::AddressSignatures {
     const NAME: &'static str = ADDRESS_SIGNATURES_CF;
 }
 
+impl Column for columns::ProgramSignatures {
+    type Index = (u64, Pubkey, Slot, Signature);
+
+    fn key((index, program_id, slot, signature): (u64, Pubkey, Slot, Signature)) -> Vec<u8> {
+        columns::AddressSignatures::key((index, program_id, slot, signature))
+    }
+
+    fn index(key: &[u8]) -> (u64, Pubkey, Slot, Signature) {
+        columns::AddressSignatures::index(key)
+    }
+
+    fn primary_index(index: Self::Index) -> u64 {
+        index.0
+    }
+
+    fn slot(index: Self::Index) -> Slot {
+        index.2
+    }
+
+    #[allow(clippy::wrong_self_convention)]
+    fn as_index(index: u64) -> Self::Index {
+        (index, Pubkey::default(), 0, Signature::default())
+    }
+}
+
+impl ColumnName for columns::ProgramSignatures {
+    const NAME: &'static str = PROGRAM_SIGNATURES_CF;
+}
+
 impl Column for columns::TransactionMemos {
     type Index = Signature;
 
@@ -819,6 +1130,37 @@ impl TypedColumn for columns::BlockHeight {
     type Type = u64;
 }
 
+impl ColumnName for columns::UploadCheckpoints {
+    const NAME: &'static str = UPLOAD_CHECKPOINTS_CF;
+}
+impl TypedColumn for columns::UploadCheckpoints {
+    type Type = Slot;
+}
+impl Column for columns::UploadCheckpoints {
+    type Index = String;
+
+    fn key(destination: String) -> Vec<u8> {
+        destination.into_bytes()
+    }
+
+    fn index(key: &[u8]) -> Self::Index {
+        String::from_utf8_lossy(key).into_owned()
+    }
+
+    fn primary_index(_index: Self::Index) -> u64 {
+        unimplemented!()
+    }
+
+    fn slot(_index: Self::Index) -> Slot {
+        unimplemented!()
+    }
+
+    #[allow(clippy::wrong_self_convention)]
+    fn as_index(_index: u64) -> Self::Index {
+        String::new()
+    }
+}
+
 impl ColumnName for columns::ProgramCosts {
     const NAME: &'static str = PROGRAM_COSTS_CF;
 }
@@ -1000,6 +1342,7 @@ impl TypedColumn for columns::ErasureMeta {
 pub struct Database {
     backend: Arc<Rocks>,
     path: Arc<Path>,
+    compression: BlockstoreCompressionConfig,
 }
 
 #[derive(Debug, Clone)]
@@ -1022,11 +1365,24 @@ impl Database {
         access_type: AccessType,
         recovery_mode: Option<BlockstoreRecoveryMode>,
     ) -> Result<Self> {
-        let backend = Arc::new(Rocks::open(path, access_type, recovery_mode)?);
+        Self::open_with_options(
+            path,
+            BlockstoreOptions {
+                access_type,
+                recovery_mode,
+                ..BlockstoreOptions::default()
+            },
+        )
+    }
+
+    pub fn open_with_options(path: &Path, options: BlockstoreOptions) -> Result<Self> {
+        let compression = options.compression.clone();
+        let backend = Arc::new(Rocks::open(path, options)?);
 
         Ok(Database {
             backend,
             path: Arc::from(path),
+            compression,
         })
     }
 
@@ -1086,9 +1442,7 @@ impl Database {
 
     pub fn batch(&self) -> Result<WriteBatch> {
         let write_batch = self.backend.batch();
-        let map = self
-            .backend
-            .columns()
+        let map = Rocks::columns()
             .into_iter()
             .map(|desc| (desc, self.backend.cf_handle(desc)))
             .collect();
@@ -1119,8 +1473,64 @@ impl Database {
         self.backend.is_primary_access()
     }
 
+    /// Replay the primary's latest writes into a secondary instance, a no-op for the primary
+    pub fn try_catch_up_with_primary(&self) -> Result<()> {
+        if self.is_primary_access() {
+            return Ok(());
+        }
+        self.backend.try_catch_up_with_primary()
+    }
+
     pub fn set_oldest_slot(&self, oldest_slot: Slot) {
-        self.backend.2.set(oldest_slot);
+        self.backend.2.shreds.set(oldest_slot);
+        self.backend.2.transaction_status.set(oldest_slot);
+        self.backend.2.block_time.set(oldest_slot);
+    }
+
+    pub fn set_column_group_oldest_slot(&self, group: ColumnGroup, oldest_slot: Slot) {
+        self.backend.2.set_group(group, oldest_slot);
+    }
+
+    pub fn column_group_oldest_slot(&self, group: ColumnGroup) -> Slot {
+        self.backend.2.group(group).get()
+    }
+
+    /// The on-disk size and compression ratio of every column, from the RocksDB properties
+    pub fn column_storage_stats(&self) -> Result<Vec<ColumnStorageStats>> {
+        Rocks::columns()
+            .into_iter()
+            .map(|name| {
+                let cf = self.backend.cf_handle(name);
+                let sst_files_bytes = self
+                    .backend
+                    .property_int_value_cf(cf, "rocksdb.total-sst-files-size")?
+                    .unwrap_or_default();
+                let (raw_bytes, data_block_bytes) = self
+                    .backend
+                    .property_value_cf(cf, "rocksdb.aggregated-table-properties")?
+                    .map(|properties| parse_aggregated_table_properties(&properties))
+                    .unwrap_or_default();
+                Ok(ColumnStorageStats {
+                    name,
+                    configured_compression_type: self.compression.compression_type(name),
+                    sst_files_bytes,
+                    raw_bytes,
+                    data_block_bytes,
+                })
+            })
+            .collect()
+    }
+
+    /// The size of the SST files of the column families in `group`
+    pub fn column_group_storage_size(&self, group: ColumnGroup) -> Result<u64> {
+        group.columns().into_iter().try_fold(0, |size, name| {
+            let cf = self.backend.cf_handle(name);
+            let sst_files_bytes = self
+                .backend
+                .property_int_value_cf(cf, "rocksdb.total-sst-files-size")?
+                .unwrap_or_default();
+            Ok(size + sst_files_bytes)
+        })
     }
 }
 
@@ -1358,7 +1768,8 @@ impl<C: Column + ColumnName> CompactionFilterFactory for PurgedSlotFilterFactory
 
 fn get_cf_options<C: 'static + Column + ColumnName>(
     access_type: &AccessType,
-    oldest_slot: &OldestSlot,
+    oldest_slots: &OldestSlots,
+    compression: &BlockstoreCompressionConfig,
 ) -> Options {
     let mut options = Options::default();
     // 256 * 8 = 2GB. 6 of these columns should take at most 12GB of RAM
@@ -1374,11 +1785,11 @@ fn get_cf_options<C: 'static + Column + ColumnName>(
     options.set_max_bytes_for_level_base(total_size_base);
     options.set_target_file_size_base(file_size_base);
 
-    // TransactionStatusIndex and ProgramCosts must be excluded from LedgerCleanupService's rocksdb
+    // TransactionStatusIndex, ProgramCosts and UploadCheckpoints must be excluded from LedgerCleanupService's rocksdb
     // compactions....
     if matches!(access_type, AccessType::PrimaryOnly) && !excludes_from_compaction(C::NAME) {
         options.set_compaction_filter_factory(PurgedSlotFilterFactory::<C> {
-            oldest_slot: oldest_slot.clone(),
+            oldest_slot: oldest_slots.column(C::NAME).clone(),
             name: CString::new(format!("purged_slot_filter_factory({})", C::NAME)).unwrap(),
             _phantom: PhantomData::default(),
         });
@@ -1388,9 +1799,27 @@ fn get_cf_options<C: 'static + Column + ColumnName>(
         options.set_disable_auto_compactions(true);
     }
 
+    if let Some(compression_type) = compression.compression_type(C::NAME) {
+        options.set_compression_type(compression_type.into());
+        if compression_type == BlockstoreCompressionType::Zstd
+            && is_shred_column(C::NAME)
+            && compression.shred_zstd_dictionary_bytes > 0
+        {
+            let dictionary_bytes = compression.shred_zstd_dictionary_bytes as i32;
+            // window_bits, level and strategy are the RocksDB defaults
+            options.set_compression_options(-14, 32767, 0, dictionary_bytes);
+            // Train the dictionary on samples of ~100x its size, as zstd recommends
+            options.set_zstd_max_train_bytes(dictionary_bytes.saturating_mul(100));
+        }
+    }
+
     options
 }
 
+fn is_shred_column(cf_name: &str) -> bool {
+    cf_name == columns::ShredData::NAME || cf_name == columns::ShredCode::NAME
+}
+
 fn get_db_options(access_type: &AccessType) -> Options {
     let mut options = Options::default();
     options.create_if_missing(true);
@@ -1422,6 +1851,7 @@ fn excludes_from_compaction(cf_name: &str) -> bool {
         columns::TransactionStatusIndex::NAME,
         columns::ProgramCosts::NAME,
         columns::TransactionMemos::NAME,
+        columns::UploadCheckpoints::NAME,
     ]
     .into_iter()
     .collect();
@@ -1490,6 +1920,119 @@ pub mod tests {
         ));
         assert!(excludes_from_compaction(columns::ProgramCosts::NAME));
         assert!(excludes_from_compaction(columns::TransactionMemos::NAME));
+        assert!(excludes_from_compaction(columns::UploadCheckpoints::NAME));
         assert!(!excludes_from_compaction("something else"));
     }
+
+    #[test]
+    fn test_blockstore_compression_config() {
+        let config = BlockstoreCompressionConfig::from_column_values(
+            vec!["data_shred=zstd", "transaction_status=lz4", "meta=none"],
+            16384,
+        )
+        .unwrap();
+        assert_eq!(
+            config.compression_type(columns::ShredData::NAME),
+            Some(BlockstoreCompressionType::Zstd)
+        );
+        assert_eq!(
+            config.compression_type(columns::TransactionStatus::NAME),
+            Some(BlockstoreCompressionType::Lz4)
+        );
+        assert_eq!(
+            config.compression_type(columns::SlotMeta::NAME),
+            Some(BlockstoreCompressionType::None)
+        );
+        assert_eq!(config.compression_type(columns::ShredCode::NAME), None);
+        assert_eq!(config.shred_zstd_dictionary_bytes, 16384);
+
+        assert!(BlockstoreCompressionConfig::parse_column_value("data_shred").is_err());
+        assert!(BlockstoreCompressionConfig::parse_column_value("data_shred=gzip").is_err());
+        assert!(BlockstoreCompressionConfig::parse_column_value("no_such_column=lz4").is_err());
+    }
+
+    #[test]
+    fn test_column_group() {
+        assert_eq!(
+            ColumnGroup::of(columns::ShredData::NAME),
+            ColumnGroup::Shreds
+        );
+        assert_eq!(
+            ColumnGroup::of(columns::Blocktime::NAME),
+            ColumnGroup::Shreds
+        );
+        assert_eq!(
+            ColumnGroup::of(columns::AddressSignatures::NAME),
+            ColumnGroup::TransactionStatus
+        );
+        let columns = ColumnGroup::TransactionStatus.columns();
+        assert!(columns.contains(&columns::TransactionStatus::NAME));
+        assert!(!columns.contains(&columns::SlotMeta::NAME));
+
+        assert_eq!(
+            "transaction-status".parse::<ColumnGroup>(),
+            Ok(ColumnGroup::TransactionStatus)
+        );
+        assert_eq!(
+            ColumnGroup::Shreds.to_string().parse::<ColumnGroup>(),
+            Ok(ColumnGroup::Shreds)
+        );
+        assert!("rewards".parse::<ColumnGroup>().is_err());
+
+        // Blocktime expires with the longer retained group
+        let oldest_slots = OldestSlots::default();
+        oldest_slots.set_group(ColumnGroup::Shreds, 64);
+        oldest_slots.set_group(ColumnGroup::TransactionStatus, 32);
+        assert_eq!(oldest_slots.column(columns::ShredData::NAME).get(), 64);
+        assert_eq!(oldest_slots.column(columns::Blocktime::NAME).get(), 32);
+        oldest_slots.set_group(ColumnGroup::TransactionStatus, 96);
+        assert_eq!(oldest_slots.column(columns::Blocktime::NAME).get(), 64);
+    }
+
+    #[test]
+    fn test_parse_aggregated_table_properties() {
+        assert_eq!(
+            parse_aggregated_table_properties(
+                "# data blocks=3; # entries=100; raw key size=1600; raw value size=98400; \
+                data block size=25000; index block size (user-key? 0, delta-value? 0)=120;"
+            ),
+            (100_000, 25_000)
+        );
+        assert_eq!(parse_aggregated_table_properties(""), (0, 0));
+    }
+
+    #[test]
+    fn test_column_storage_stats() {
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let compression =
+            BlockstoreCompressionConfig::from_column_values(vec!["data_shred=zstd"], 4096).unwrap();
+        let db = Database::open_with_options(
+            ledger_path.path(),
+            BlockstoreOptions {
+                compression,
+                ..BlockstoreOptions::default()
+            },
+        )
+        .unwrap();
+
+        let column = db.column::<ShredData>();
+        for index in 0..1000 {
+            column.put_bytes((0, index), &[index as u8; 1024]).unwrap();
+        }
+        // Flushes the memtable to SST files
+        column.compact_range(0, 1).unwrap();
+
+        let stats = db.column_storage_stats().unwrap();
+        assert_eq!(stats.len(), Rocks::columns().len());
+        let data_shred_stats = stats
+            .iter()
+            .find(|stats| stats.name == ShredData::NAME)
+            .unwrap();
+        assert_eq!(
+            data_shred_stats.configured_compression_type,
+            Some(BlockstoreCompressionType::Zstd)
+        );
+        assert!(data_shred_stats.sst_files_bytes > 0);
+        assert!(data_shred_stats.compression_ratio().unwrap() > 1.0);
+    }
 }
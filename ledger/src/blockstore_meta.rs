@@ -289,6 +289,12 @@ pub struct AddressSignatureMeta {
     pub writeable: bool,
 }
 
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct ProgramSignatureMeta {
+    /// The program was only invoked through inner instructions
+    pub inner_only: bool,
+}
+
 #[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
 pub struct PerfSample {
     pub num_transactions: u64,
@@ -4,7 +4,7 @@ use crate::{
 };
 use chrono_humanize::{Accuracy, HumanTime, Tense};
 use crossbeam_channel::Sender;
-use itertools::Itertools;
+use itertools::{izip, Itertools};
 use log::*;
 use rand::{seq::SliceRandom, thread_rng};
 use rayon::{prelude::*, ThreadPool};
@@ -43,8 +43,9 @@ use solana_sdk::{
     timing,
     transaction::{Result, SanitizedTransaction, TransactionError, VersionedTransaction},
 };
-use solana_transaction_status::token_balances::{
-    collect_token_balances, TransactionTokenBalancesSet,
+use solana_transaction_status::{
+    token_balances::{collect_token_balances, TransactionTokenBalancesSet},
+    InnerInstructions, Reward, TransactionStatusMeta,
 };
 use std::{
     cell::RefCell,
@@ -1372,6 +1373,103 @@ pub struct TransactionStatusBatch {
     pub rent_debits: Vec<RentDebits>,
 }
 
+impl TransactionStatusBatch {
+    /// The status meta of each committed transaction of the batch, as stored for RPC and as
+    /// notified to the AccountsDb plugins
+    pub fn committed_transaction_status_metas(
+        &self,
+    ) -> impl Iterator<Item = (&SanitizedTransaction, TransactionStatusMeta)> + '_ {
+        let bank = &self.bank;
+        let inner_instructions_iter: Box<dyn Iterator<Item = Option<&InnerInstructionsList>>> =
+            if let Some(inner_instructions) = &self.inner_instructions {
+                Box::new(inner_instructions.iter().map(Option::as_ref))
+            } else {
+                Box::new(std::iter::repeat_with(|| None))
+            };
+        let transaction_logs_iter: Box<dyn Iterator<Item = Option<&TransactionLogMessages>>> =
+            if let Some(transaction_logs) = &self.transaction_logs {
+                Box::new(transaction_logs.iter().map(Option::as_ref))
+            } else {
+                Box::new(std::iter::repeat_with(|| None))
+            };
+
+        izip!(
+            &self.transactions,
+            &self.statuses,
+            &self.balances.pre_balances,
+            &self.balances.post_balances,
+            &self.token_balances.pre_token_balances,
+            &self.token_balances.post_token_balances,
+            inner_instructions_iter,
+            transaction_logs_iter,
+            &self.rent_debits,
+        )
+        .filter(|(_, (status, _), ..)| Bank::can_commit(status))
+        .map(
+            move |(
+                transaction,
+                (status, nonce),
+                pre_balances,
+                post_balances,
+                pre_token_balances,
+                post_token_balances,
+                inner_instructions,
+                log_messages,
+                rent_debits,
+            )| {
+                let lamports_per_signature = match nonce {
+                    Some(nonce) => nonce.lamports_per_signature(),
+                    None => bank.get_lamports_per_signature_for_blockhash(
+                        transaction.message().recent_blockhash(),
+                    ),
+                }
+                .unwrap_or_default();
+                let fee = Bank::get_fee_for_message_with_lamports_per_signature(
+                    transaction.message(),
+                    lamports_per_signature,
+                );
+                let inner_instructions = inner_instructions.map(|inner_instructions| {
+                    inner_instructions
+                        .iter()
+                        .enumerate()
+                        .map(|(index, instructions)| InnerInstructions {
+                            index: index as u8,
+                            instructions: instructions.clone(),
+                        })
+                        .filter(|i| !i.instructions.is_empty())
+                        .collect()
+                });
+                let rewards = rent_debits
+                    .clone()
+                    .into_unordered_rewards_iter()
+                    .map(|(pubkey, reward_info)| Reward {
+                        pubkey: pubkey.to_string(),
+                        lamports: reward_info.lamports,
+                        post_balance: reward_info.post_balance,
+                        reward_type: Some(reward_info.reward_type),
+                        commission: reward_info.commission,
+                    })
+                    .collect();
+
+                (
+                    transaction,
+                    TransactionStatusMeta {
+                        status: status.clone(),
+                        fee,
+                        pre_balances: pre_balances.clone(),
+                        post_balances: post_balances.clone(),
+                        inner_instructions,
+                        log_messages: log_messages.cloned(),
+                        pre_token_balances: Some(pre_token_balances.clone()),
+                        post_token_balances: Some(post_token_balances.clone()),
+                        rewards: Some(rewards),
+                    },
+                )
+            },
+        )
+    }
+}
+
 #[derive(Clone)]
 pub struct TransactionStatusSender {
     pub sender: Sender<TransactionStatusMessage>,
@@ -0,0 +1,817 @@
+//! The `ledger_archive` module exports rooted blocks to a portable archive and imports them
+//! back, so that ledger segments can be shared without depending on the RocksDB version of the
+//! ledger they come from.
+//!
+//! An archive is a stream of `ArchiveRecord` protobuf messages: the header, one record per slot
+//! in ascending order, and the end, which makes truncated archives detectable. Two encodings
+//! are supported, both checksumming every record with sha2-256:
+//! * `car`: a CARv1 file of raw blocks addressed by their CID, whose root is the header
+//! * `protobuf-stream`: a magic number followed by length-delimited records, each one followed
+//!   by its digest
+
+use {
+    crate::{
+        blockstore::Blockstore,
+        blockstore_db::BlockstoreError,
+        shred::{Shred, ShredError},
+    },
+    prost::Message,
+    sha2::{Digest, Sha256},
+    solana_sdk::{clock::Slot, pubkey::Pubkey, signature::Signature},
+    solana_storage_proto::convert::generated,
+    solana_transaction_status::{TransactionStatusMeta, TransactionWithStatusMeta},
+    std::{
+        convert::TryFrom,
+        io::{self, BufRead, Read, Write},
+        str::FromStr,
+    },
+    thiserror::Error,
+};
+
+pub const ARCHIVE_VERSION: u32 = 1;
+
+const PROTOBUF_STREAM_MAGIC: &[u8] = b"SOLARCHV";
+
+/// Records larger than this are rejected instead of being allocated
+const MAX_RECORD_SIZE: u64 = 256 * 1024 * 1024;
+
+/// The prefix of the CID of a raw block: CIDv1, raw codec, sha2-256 multihash of 32 bytes
+const CID_PREFIX: [u8; 4] = [0x01, 0x55, 0x12, 0x20];
+const CID_SIZE: usize = CID_PREFIX.len() + 32;
+/// The offset of the root CID in the CAR header, see `car_header`
+const CAR_HEADER_ROOT_OFFSET: usize = 13;
+
+#[derive(Debug, Error)]
+pub enum LedgerArchiveError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Decode: {0}")]
+    Decode(#[from] prost::DecodeError),
+
+    #[error("Blockstore: {0}")]
+    Blockstore(#[from] BlockstoreError),
+
+    #[error("Shred: {0}")]
+    Shred(#[from] ShredError),
+
+    #[error("Corrupt archive: {0}")]
+    Corrupt(String),
+
+    #[error("Transaction {1} of slot {0} has no status meta")]
+    MissingStatusMeta(Slot, Signature),
+}
+
+pub type Result<T> = std::result::Result<T, LedgerArchiveError>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LedgerArchiveFormat {
+    Car,
+    ProtobufStream,
+}
+
+impl FromStr for LedgerArchiveFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "car" => Ok(LedgerArchiveFormat::Car),
+            "protobuf-stream" => Ok(LedgerArchiveFormat::ProtobufStream),
+            bad_format => Err(format!("Invalid archive format: {}", bad_format)),
+        }
+    }
+}
+
+/// Exactly one of the fields is set
+#[derive(Clone, PartialEq, Message)]
+pub struct ArchiveRecord {
+    #[prost(message, optional, tag = "1")]
+    pub header: Option<ArchiveHeader>,
+    #[prost(message, optional, tag = "2")]
+    pub slot: Option<ArchiveSlot>,
+    #[prost(message, optional, tag = "3")]
+    pub end: Option<ArchiveEnd>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ArchiveHeader {
+    #[prost(uint32, tag = "1")]
+    pub version: u32,
+    #[prost(uint64, tag = "2")]
+    pub starting_slot: u64,
+    #[prost(uint64, tag = "3")]
+    pub ending_slot: u64,
+    #[prost(string, tag = "4")]
+    pub genesis_hash: String,
+    #[prost(string, tag = "5")]
+    pub created_by: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ArchiveSlot {
+    #[prost(uint64, tag = "1")]
+    pub slot: u64,
+    #[prost(uint64, tag = "2")]
+    pub parent_slot: u64,
+    /// The serialized data shreds, which hold the entries of the slot
+    #[prost(bytes = "vec", repeated, tag = "3")]
+    pub data_shreds: Vec<Vec<u8>>,
+    #[prost(message, repeated, tag = "4")]
+    pub transactions: Vec<ArchiveTransaction>,
+    #[prost(message, optional, tag = "5")]
+    pub rewards: Option<generated::Rewards>,
+    #[prost(int64, optional, tag = "6")]
+    pub block_time: Option<i64>,
+    #[prost(uint64, optional, tag = "7")]
+    pub block_height: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ArchiveTransaction {
+    #[prost(bytes = "vec", tag = "1")]
+    pub signature: Vec<u8>,
+    #[prost(message, optional, tag = "2")]
+    pub meta: Option<generated::TransactionStatusMeta>,
+    #[prost(bytes = "vec", repeated, tag = "3")]
+    pub writable_keys: Vec<Vec<u8>>,
+    #[prost(bytes = "vec", repeated, tag = "4")]
+    pub readonly_keys: Vec<Vec<u8>>,
+    #[prost(string, optional, tag = "5")]
+    pub memos: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ArchiveEnd {
+    #[prost(uint64, tag = "1")]
+    pub slot_count: u64,
+}
+
+fn cid(data: &[u8]) -> [u8; CID_SIZE] {
+    let mut cid = [0; CID_SIZE];
+    cid[..CID_PREFIX.len()].copy_from_slice(&CID_PREFIX);
+    cid[CID_PREFIX.len()..].copy_from_slice(&Sha256::digest(data));
+    cid
+}
+
+/// The DAG-CBOR encoding of `{"roots": [root], "version": 1}`
+fn car_header(root: &[u8; CID_SIZE]) -> Vec<u8> {
+    let mut header = vec![0xa2, 0x65];
+    header.extend_from_slice(b"roots");
+    // An array of one CID: tag 42 wrapping a byte string of the CID prefixed with 0
+    header.extend_from_slice(&[0x81, 0xd8, 0x2a, 0x58, CID_SIZE as u8 + 1, 0x00]);
+    header.extend_from_slice(root);
+    header.push(0x67);
+    header.extend_from_slice(b"version");
+    header.push(0x01);
+    header
+}
+
+fn write_varint(writer: &mut impl Write, value: u64) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(10);
+    prost::encoding::encode_varint(value, &mut buf);
+    writer.write_all(&buf)
+}
+
+fn read_varint(reader: &mut impl Read) -> Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        value |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(LedgerArchiveError::Corrupt("varint overflow".to_string()))
+}
+
+fn read_bytes(reader: &mut impl Read, size: u64) -> Result<Vec<u8>> {
+    if size > MAX_RECORD_SIZE {
+        return Err(LedgerArchiveError::Corrupt(format!(
+            "record of {} bytes is too large",
+            size
+        )));
+    }
+    let mut bytes = vec![0; size as usize];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+pub struct LedgerArchiveWriter<W: Write> {
+    format: LedgerArchiveFormat,
+    writer: W,
+    slot_count: u64,
+}
+
+impl<W: Write> LedgerArchiveWriter<W> {
+    pub fn new(mut writer: W, format: LedgerArchiveFormat, header: ArchiveHeader) -> Result<Self> {
+        let header = ArchiveRecord {
+            header: Some(header),
+            ..ArchiveRecord::default()
+        }
+        .encode_to_vec();
+        match format {
+            LedgerArchiveFormat::Car => {
+                let header_bytes = car_header(&cid(&header));
+                write_varint(&mut writer, header_bytes.len() as u64)?;
+                writer.write_all(&header_bytes)?;
+            }
+            LedgerArchiveFormat::ProtobufStream => writer.write_all(PROTOBUF_STREAM_MAGIC)?,
+        }
+        let mut archive_writer = Self {
+            format,
+            writer,
+            slot_count: 0,
+        };
+        archive_writer.write_record(&header)?;
+        Ok(archive_writer)
+    }
+
+    pub fn write_slot(&mut self, slot: ArchiveSlot) -> Result<()> {
+        let record = ArchiveRecord {
+            slot: Some(slot),
+            ..ArchiveRecord::default()
+        };
+        self.write_record(&record.encode_to_vec())?;
+        self.slot_count += 1;
+        Ok(())
+    }
+
+    /// Write the end of the archive and return the underlying writer
+    pub fn finish(mut self) -> Result<W> {
+        let record = ArchiveRecord {
+            end: Some(ArchiveEnd {
+                slot_count: self.slot_count,
+            }),
+            ..ArchiveRecord::default()
+        };
+        self.write_record(&record.encode_to_vec())?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_record(&mut self, record: &[u8]) -> Result<()> {
+        match self.format {
+            LedgerArchiveFormat::Car => {
+                write_varint(&mut self.writer, (CID_SIZE + record.len()) as u64)?;
+                self.writer.write_all(&cid(record))?;
+                self.writer.write_all(record)?;
+            }
+            LedgerArchiveFormat::ProtobufStream => {
+                write_varint(&mut self.writer, record.len() as u64)?;
+                self.writer.write_all(record)?;
+                self.writer.write_all(&Sha256::digest(record))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads an archive of either format, yielding its slots. The iteration fails if the archive
+/// is corrupt or truncated.
+pub struct LedgerArchiveReader<R: BufRead> {
+    format: LedgerArchiveFormat,
+    reader: R,
+    header: ArchiveHeader,
+    last_slot: Option<Slot>,
+    slot_count: u64,
+    done: bool,
+}
+
+impl<R: BufRead> LedgerArchiveReader<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        let format = if reader.fill_buf()?.first() == PROTOBUF_STREAM_MAGIC.first() {
+            let magic = read_bytes(&mut reader, PROTOBUF_STREAM_MAGIC.len() as u64)?;
+            if magic != PROTOBUF_STREAM_MAGIC {
+                return Err(LedgerArchiveError::Corrupt("unknown format".to_string()));
+            }
+            LedgerArchiveFormat::ProtobufStream
+        } else {
+            LedgerArchiveFormat::Car
+        };
+
+        let root = match format {
+            LedgerArchiveFormat::Car => {
+                let header_size = read_varint(&mut reader)?;
+                let header_bytes = read_bytes(&mut reader, header_size)?;
+                let mut root = [0; CID_SIZE];
+                if let Some(cid) =
+                    header_bytes.get(CAR_HEADER_ROOT_OFFSET..CAR_HEADER_ROOT_OFFSET + CID_SIZE)
+                {
+                    root.copy_from_slice(cid);
+                }
+                if header_bytes != car_header(&root) {
+                    return Err(LedgerArchiveError::Corrupt(
+                        "unsupported CAR header".to_string(),
+                    ));
+                }
+                Some(root)
+            }
+            LedgerArchiveFormat::ProtobufStream => None,
+        };
+
+        let mut archive_reader = Self {
+            format,
+            reader,
+            header: ArchiveHeader::default(),
+            last_slot: None,
+            slot_count: 0,
+            done: false,
+        };
+        let (header_cid, header) = archive_reader.read_record()?;
+        if root.map_or(false, |root| root != header_cid) {
+            return Err(LedgerArchiveError::Corrupt(
+                "the CAR root is not the header".to_string(),
+            ));
+        }
+        archive_reader.header = header
+            .header
+            .ok_or_else(|| LedgerArchiveError::Corrupt("missing header".to_string()))?;
+        if archive_reader.header.version != ARCHIVE_VERSION {
+            return Err(LedgerArchiveError::Corrupt(format!(
+                "unsupported version {}",
+                archive_reader.header.version
+            )));
+        }
+        Ok(archive_reader)
+    }
+
+    pub fn format(&self) -> LedgerArchiveFormat {
+        self.format
+    }
+
+    pub fn header(&self) -> &ArchiveHeader {
+        &self.header
+    }
+
+    /// Read and verify the next record, returning it along with its CID
+    fn read_record(&mut self) -> Result<([u8; CID_SIZE], ArchiveRecord)> {
+        let size = read_varint(&mut self.reader)?;
+        let (record_cid, record) = match self.format {
+            LedgerArchiveFormat::Car => {
+                if size < CID_SIZE as u64 {
+                    return Err(LedgerArchiveError::Corrupt(format!(
+                        "section of {} bytes is too small",
+                        size
+                    )));
+                }
+                let mut section = read_bytes(&mut self.reader, size)?;
+                let record = section.split_off(CID_SIZE);
+                let mut record_cid = [0; CID_SIZE];
+                record_cid.copy_from_slice(&section);
+                (record_cid, record)
+            }
+            LedgerArchiveFormat::ProtobufStream => {
+                let record = read_bytes(&mut self.reader, size)?;
+                let digest = read_bytes(&mut self.reader, 32)?;
+                let mut record_cid = [0; CID_SIZE];
+                record_cid[..CID_PREFIX.len()].copy_from_slice(&CID_PREFIX);
+                record_cid[CID_PREFIX.len()..].copy_from_slice(&digest);
+                (record_cid, record)
+            }
+        };
+        if cid(&record) != record_cid {
+            return Err(LedgerArchiveError::Corrupt("checksum mismatch".to_string()));
+        }
+        Ok((record_cid, ArchiveRecord::decode(record.as_slice())?))
+    }
+
+    fn next_slot(&mut self) -> Result<Option<ArchiveSlot>> {
+        let (_, record) = self.read_record()?;
+        if let Some(slot) = record.slot {
+            if self
+                .last_slot
+                .map_or(false, |last_slot| slot.slot <= last_slot)
+                || slot.slot < self.header.starting_slot
+                || slot.slot > self.header.ending_slot
+            {
+                return Err(LedgerArchiveError::Corrupt(format!(
+                    "slot {} is out of order",
+                    slot.slot
+                )));
+            }
+            self.last_slot = Some(slot.slot);
+            self.slot_count += 1;
+            Ok(Some(slot))
+        } else if let Some(end) = record.end {
+            if end.slot_count != self.slot_count {
+                return Err(LedgerArchiveError::Corrupt(format!(
+                    "expected {} slots, read {}",
+                    end.slot_count, self.slot_count
+                )));
+            }
+            Ok(None)
+        } else {
+            Err(LedgerArchiveError::Corrupt("unexpected record".to_string()))
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for LedgerArchiveReader<R> {
+    type Item = Result<ArchiveSlot>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.next_slot().transpose();
+        self.done = !matches!(result, Some(Ok(_)));
+        result
+    }
+}
+
+/// Read a rooted slot of the blockstore into an archive record. The addresses of a transaction
+/// are split between writable and read-only the way the runtime did in the slot, according to
+/// whether the program write lock demotion feature was active.
+pub fn read_archive_slot(
+    blockstore: &Blockstore,
+    slot: Slot,
+    demote_program_write_locks: bool,
+) -> Result<ArchiveSlot> {
+    let block = blockstore.get_rooted_block(slot, false)?;
+    let data_shreds = blockstore
+        .get_data_shreds_for_slot(slot, 0)?
+        .into_iter()
+        .map(|shred| shred.payload)
+        .collect();
+
+    let mut transactions = vec![];
+    for TransactionWithStatusMeta { transaction, meta } in block.transactions {
+        let signature = transaction.signatures[0];
+        let meta = meta.ok_or(LedgerArchiveError::MissingStatusMeta(slot, signature))?;
+        let message = &transaction.message;
+        let (writable_keys, readonly_keys): (Vec<_>, Vec<_>) = message
+            .account_keys
+            .iter()
+            .enumerate()
+            .partition(|(index, _)| message.is_writable(*index, demote_program_write_locks));
+        transactions.push(ArchiveTransaction {
+            signature: signature.as_ref().to_vec(),
+            meta: Some(meta.into()),
+            writable_keys: writable_keys
+                .into_iter()
+                .map(|(_, key)| key.to_bytes().to_vec())
+                .collect(),
+            readonly_keys: readonly_keys
+                .into_iter()
+                .map(|(_, key)| key.to_bytes().to_vec())
+                .collect(),
+            memos: blockstore.read_transaction_memos(signature)?,
+        });
+    }
+
+    Ok(ArchiveSlot {
+        slot,
+        parent_slot: block.parent_slot,
+        data_shreds,
+        transactions,
+        rewards: Some(block.rewards.into()),
+        block_time: block.block_time,
+        block_height: block.block_height,
+    })
+}
+
+fn pubkey_from_bytes(bytes: &[u8]) -> Result<Pubkey> {
+    if bytes.len() != std::mem::size_of::<Pubkey>() {
+        return Err(LedgerArchiveError::Corrupt(format!(
+            "invalid address of {} bytes",
+            bytes.len()
+        )));
+    }
+    Ok(Pubkey::new(bytes))
+}
+
+/// A transaction of an archived slot, decoded
+struct DecodedArchiveTransaction {
+    signature: Signature,
+    meta: TransactionStatusMeta,
+    writable_keys: Vec<Pubkey>,
+    readonly_keys: Vec<Pubkey>,
+    memos: Option<String>,
+}
+
+/// An archived slot, decoded and checked to be complete
+struct DecodedArchiveSlot {
+    slot: Slot,
+    shreds: Vec<Shred>,
+    transactions: Vec<DecodedArchiveTransaction>,
+    rewards: Option<generated::Rewards>,
+    block_time: Option<i64>,
+    block_height: Option<u64>,
+}
+
+fn decode_archive_slot(archive_slot: ArchiveSlot) -> Result<DecodedArchiveSlot> {
+    let ArchiveSlot {
+        slot,
+        data_shreds,
+        transactions,
+        rewards,
+        block_time,
+        block_height,
+        ..
+    } = archive_slot;
+
+    let shreds = data_shreds
+        .into_iter()
+        .map(Shred::new_from_serialized_shred)
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    if let Some(shred) = shreds.iter().find(|shred| shred.slot() != slot) {
+        return Err(LedgerArchiveError::Corrupt(format!(
+            "slot {} holds a shred of slot {}",
+            slot,
+            shred.slot()
+        )));
+    }
+    if shreds.iter().any(|shred| !shred.is_data()) {
+        return Err(LedgerArchiveError::Corrupt(format!(
+            "slot {} holds a coding shred",
+            slot
+        )));
+    }
+    // The data shreds of a complete slot are indexed from 0, the last one ending the slot
+    let mut indexes: Vec<_> = shreds.iter().map(|shred| shred.index()).collect();
+    indexes.sort_unstable();
+    let is_complete = indexes
+        .iter()
+        .enumerate()
+        .all(|(position, index)| *index as usize == position)
+        && shreds
+            .iter()
+            .filter(|shred| shred.last_in_slot())
+            .map(|shred| shred.index() as usize + 1)
+            .eq(std::iter::once(shreds.len()));
+    if !is_complete {
+        return Err(LedgerArchiveError::Corrupt(format!(
+            "slot {} is incomplete",
+            slot
+        )));
+    }
+
+    let transactions = transactions
+        .into_iter()
+        .map(|transaction| {
+            if transaction.signature.len() != std::mem::size_of::<Signature>() {
+                return Err(LedgerArchiveError::Corrupt(format!(
+                    "invalid signature of {} bytes",
+                    transaction.signature.len()
+                )));
+            }
+            let signature = Signature::new(&transaction.signature);
+            let meta = transaction
+                .meta
+                .ok_or_else(|| {
+                    LedgerArchiveError::Corrupt(format!("missing status of {}", signature))
+                })
+                .and_then(|meta| {
+                    TransactionStatusMeta::try_from(meta)
+                        .map_err(|err| LedgerArchiveError::Corrupt(err.to_string()))
+                })?;
+            let writable_keys = transaction
+                .writable_keys
+                .iter()
+                .map(|key| pubkey_from_bytes(key))
+                .collect::<Result<Vec<_>>>()?;
+            let readonly_keys = transaction
+                .readonly_keys
+                .iter()
+                .map(|key| pubkey_from_bytes(key))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(DecodedArchiveTransaction {
+                signature,
+                meta,
+                writable_keys,
+                readonly_keys,
+                memos: transaction.memos,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(DecodedArchiveSlot {
+        slot,
+        shreds,
+        transactions,
+        rewards,
+        block_time,
+        block_height,
+    })
+}
+
+/// Check that an archived slot can be written to the blockstore, without writing it. An
+/// archive is verified as a whole before any of its slots is imported.
+pub fn verify_archive_slot(archive_slot: ArchiveSlot) -> Result<()> {
+    decode_archive_slot(archive_slot).map(|_| ())
+}
+
+/// Write an archived slot to the blockstore. The caller roots the imported slots.
+pub fn write_archive_slot(blockstore: &Blockstore, archive_slot: ArchiveSlot) -> Result<()> {
+    let DecodedArchiveSlot {
+        slot,
+        shreds,
+        transactions,
+        rewards,
+        block_time,
+        block_height,
+    } = decode_archive_slot(archive_slot)?;
+
+    blockstore.insert_shreds(shreds, None, false)?;
+    if !blockstore.is_full(slot) {
+        return Err(LedgerArchiveError::Corrupt(format!(
+            "slot {} is incomplete",
+            slot
+        )));
+    }
+    for DecodedArchiveTransaction {
+        signature,
+        meta,
+        writable_keys,
+        readonly_keys,
+        memos,
+    } in transactions
+    {
+        blockstore.write_transaction_status(
+            slot,
+            signature,
+            writable_keys.iter().collect(),
+            readonly_keys.iter().collect(),
+            meta,
+        )?;
+        if let Some(memos) = memos {
+            blockstore.write_transaction_memos(&signature, memos)?;
+        }
+    }
+
+    if let Some(rewards) = rewards {
+        blockstore.write_rewards(slot, rewards.into())?;
+    }
+    if let Some(block_time) = block_time {
+        blockstore.cache_block_time(slot, block_time)?;
+    }
+    if let Some(block_height) = block_height {
+        blockstore.cache_block_height(slot, block_height)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::blockstore::{entries_to_test_shreds, tests::make_slot_entries_with_transactions},
+    };
+
+    fn test_header() -> ArchiveHeader {
+        ArchiveHeader {
+            version: ARCHIVE_VERSION,
+            starting_slot: 10,
+            ending_slot: 20,
+            genesis_hash: "genesis".to_string(),
+            created_by: "test".to_string(),
+        }
+    }
+
+    fn test_slot(slot: Slot) -> ArchiveSlot {
+        ArchiveSlot {
+            slot,
+            parent_slot: slot - 1,
+            data_shreds: vec![vec![slot as u8; 16]],
+            block_time: Some(slot as i64),
+            ..ArchiveSlot::default()
+        }
+    }
+
+    fn write_archive(format: LedgerArchiveFormat, slots: &[Slot]) -> Vec<u8> {
+        let mut writer = LedgerArchiveWriter::new(vec![], format, test_header()).unwrap();
+        for slot in slots {
+            writer.write_slot(test_slot(*slot)).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn test_archive_round_trip() {
+        for format in [
+            LedgerArchiveFormat::Car,
+            LedgerArchiveFormat::ProtobufStream,
+        ] {
+            let archive = write_archive(format, &[10, 12, 20]);
+            let reader = LedgerArchiveReader::new(archive.as_slice()).unwrap();
+            assert_eq!(reader.format(), format);
+            assert_eq!(reader.header(), &test_header());
+            assert_eq!(
+                reader.collect::<Result<Vec<_>>>().unwrap(),
+                vec![test_slot(10), test_slot(12), test_slot(20)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_archive_corruption() {
+        for format in [
+            LedgerArchiveFormat::Car,
+            LedgerArchiveFormat::ProtobufStream,
+        ] {
+            let archive = write_archive(format, &[10, 11]);
+
+            // Flipping a byte of the last slot fails its checksum
+            let mut corrupt_archive = archive.clone();
+            let index = archive
+                .windows(16)
+                .position(|window| window == [11; 16])
+                .unwrap();
+            corrupt_archive[index] ^= 0xff;
+            let results: Vec<_> = LedgerArchiveReader::new(corrupt_archive.as_slice())
+                .unwrap()
+                .collect();
+            assert_eq!(results.len(), 2);
+            assert!(results[0].is_ok());
+            assert!(results[1].is_err());
+
+            // A truncated archive misses its end
+            let truncated_archive = &archive[..archive.len() - 8];
+            assert!(LedgerArchiveReader::new(truncated_archive)
+                .unwrap()
+                .any(|result| result.is_err()));
+        }
+
+        // Slots out of the header range
+        let archive = write_archive(LedgerArchiveFormat::ProtobufStream, &[10, 21]);
+        assert!(LedgerArchiveReader::new(archive.as_slice())
+            .unwrap()
+            .any(|result| result.is_err()));
+        assert!(LedgerArchiveReader::new(&b"garbage"[..]).is_err());
+    }
+
+    #[test]
+    fn test_export_import_blockstore() {
+        let slot = 10;
+        let entries = make_slot_entries_with_transactions(5);
+        let shreds = entries_to_test_shreds(entries.clone(), slot, slot - 1, true, 0);
+        let ledger_path = get_tmp_ledger_path_auto_delete!();
+        let blockstore = Blockstore::open(ledger_path.path()).unwrap();
+        blockstore.insert_shreds(shreds, None, false).unwrap();
+        blockstore.set_roots(vec![slot].iter()).unwrap();
+        for (index, transaction) in entries
+            .iter()
+            .flat_map(|entry| entry.transactions.iter())
+            .enumerate()
+        {
+            let message = transaction
+                .clone()
+                .into_legacy_transaction()
+                .unwrap()
+                .message;
+            blockstore
+                .write_transaction_status(
+                    slot,
+                    transaction.signatures[0],
+                    vec![&message.account_keys[0]],
+                    message.account_keys[1..].iter().collect(),
+                    TransactionStatusMeta {
+                        fee: index as u64,
+                        ..TransactionStatusMeta::default()
+                    },
+                )
+                .unwrap();
+        }
+        blockstore.cache_block_time(slot, 1_000).unwrap();
+        blockstore.cache_block_height(slot, 5).unwrap();
+
+        let mut writer = LedgerArchiveWriter::new(
+            vec![],
+            LedgerArchiveFormat::Car,
+            ArchiveHeader {
+                starting_slot: slot,
+                ending_slot: slot,
+                ..test_header()
+            },
+        )
+        .unwrap();
+        let archive_slot = read_archive_slot(&blockstore, slot, true).unwrap();
+        verify_archive_slot(archive_slot.clone()).unwrap();
+        // An incomplete slot fails the verification
+        let mut incomplete_archive_slot = archive_slot.clone();
+        incomplete_archive_slot.data_shreds.pop();
+        assert!(verify_archive_slot(incomplete_archive_slot).is_err());
+        let mut mislabeled_archive_slot = archive_slot.clone();
+        mislabeled_archive_slot.slot += 1;
+        assert!(verify_archive_slot(mislabeled_archive_slot).is_err());
+        writer.write_slot(archive_slot).unwrap();
+        let archive = writer.finish().unwrap();
+
+        let imported_ledger_path = get_tmp_ledger_path_auto_delete!();
+        let imported_blockstore = Blockstore::open(imported_ledger_path.path()).unwrap();
+        for archive_slot in LedgerArchiveReader::new(archive.as_slice()).unwrap() {
+            write_archive_slot(&imported_blockstore, archive_slot.unwrap()).unwrap();
+        }
+        imported_blockstore.set_roots(vec![slot].iter()).unwrap();
+
+        assert_eq!(
+            imported_blockstore.get_rooted_block(slot, false).unwrap(),
+            blockstore.get_rooted_block(slot, false).unwrap()
+        );
+        assert_eq!(
+            imported_blockstore.get_slot_entries(slot, 0).unwrap(),
+            entries
+        );
+    }
+}
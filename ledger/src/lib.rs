@@ -20,6 +20,8 @@ pub mod genesis_utils;
 pub mod leader_schedule;
 pub mod leader_schedule_cache;
 pub mod leader_schedule_utils;
+pub mod ledger_archive;
+pub mod long_term_storage;
 pub mod next_slots_iterator;
 pub mod rooted_slot_iterator;
 pub mod shred;
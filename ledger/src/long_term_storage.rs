@@ -0,0 +1,283 @@
+//! The `long_term_storage` module abstracts where confirmed blocks are archived by
+//! `bigtable_upload` and queried by `ledger-tool bigtable`: Google BigTable, a local
+//! directory or an S3 compatible object store.
+use {
+    async_trait::async_trait,
+    solana_sdk::{clock::Slot, pubkey::Pubkey, signature::Signature},
+    solana_transaction_status::{
+        ConfirmedBlock, ConfirmedTransaction, ConfirmedTransactionStatusWithSignature,
+        TransactionStatus,
+    },
+    std::{fmt, path::PathBuf, str::FromStr, sync::Arc},
+    thiserror::Error,
+};
+
+pub mod archive_storage;
+pub mod filesystem_store;
+pub mod s3_store;
+
+pub use {
+    archive_storage::{ArchiveLedgerStorage, ObjectStore},
+    filesystem_store::FilesystemObjectStore,
+    s3_store::{S3Config, S3ObjectStore},
+};
+
+#[derive(Debug, Error)]
+pub enum LongTermStorageError {
+    #[error("BigTable: {0}")]
+    BigTable(#[from] solana_storage_bigtable::Error),
+
+    #[error("I/O: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Object store: {0}")]
+    ObjectStore(String),
+
+    #[error("Object corrupt: {0}")]
+    ObjectCorrupt(String),
+
+    #[error("Block not found: {0}")]
+    BlockNotFound(Slot),
+
+    #[error("Signature not found")]
+    SignatureNotFound,
+
+    #[error("The storage was opened read-only")]
+    ReadOnly,
+}
+
+pub type Result<T> = std::result::Result<T, LongTermStorageError>;
+
+/// The operations on archived blocks, see `solana_storage_bigtable::LedgerStorage` for their
+/// semantics.
+#[async_trait]
+pub trait LongTermStorage: Send + Sync {
+    /// Where the blocks are archived, formatted like the `LongTermStorageConfig` the storage is
+    /// opened from. Names the upload checkpoint of the storage.
+    fn id(&self) -> String;
+
+    /// Return the available slot that contains a block
+    async fn get_first_available_block(&self) -> Result<Option<Slot>>;
+
+    /// Fetch the next slots after the provided slot that contains a block
+    async fn get_confirmed_blocks(&self, start_slot: Slot, limit: usize) -> Result<Vec<Slot>>;
+
+    /// Fetch the confirmed block from the desired slot
+    async fn get_confirmed_block(&self, slot: Slot) -> Result<ConfirmedBlock>;
+
+    async fn get_signature_status(&self, signature: &Signature) -> Result<TransactionStatus>;
+
+    /// Fetch a confirmed transaction
+    async fn get_confirmed_transaction(
+        &self,
+        signature: &Signature,
+    ) -> Result<Option<ConfirmedTransaction>>;
+
+    /// Get confirmed signatures for the provided address, in descending ledger order, along
+    /// with the index of each transaction in its block
+    async fn get_confirmed_signatures_for_address(
+        &self,
+        address: &Pubkey,
+        before_signature: Option<&Signature>,
+        until_signature: Option<&Signature>,
+        limit: usize,
+    ) -> Result<Vec<(ConfirmedTransactionStatusWithSignature, u32)>>;
+
+    /// Upload a new confirmed block and associated meta data
+    async fn upload_confirmed_block(
+        &self,
+        slot: Slot,
+        confirmed_block: ConfirmedBlock,
+    ) -> Result<()>;
+
+    /// Delete a confirmed block and associated meta data, only logging what would be deleted
+    /// when `dry_run` is set
+    async fn delete_confirmed_block(&self, slot: Slot, dry_run: bool) -> Result<()>;
+}
+
+#[async_trait]
+impl LongTermStorage for solana_storage_bigtable::LedgerStorage {
+    fn id(&self) -> String {
+        LongTermStorageConfig::BigTable.to_string()
+    }
+
+    async fn get_first_available_block(&self) -> Result<Option<Slot>> {
+        Ok(self.get_first_available_block().await?)
+    }
+
+    async fn get_confirmed_blocks(&self, start_slot: Slot, limit: usize) -> Result<Vec<Slot>> {
+        Ok(self.get_confirmed_blocks(start_slot, limit).await?)
+    }
+
+    async fn get_confirmed_block(&self, slot: Slot) -> Result<ConfirmedBlock> {
+        Ok(self.get_confirmed_block(slot).await?)
+    }
+
+    async fn get_signature_status(&self, signature: &Signature) -> Result<TransactionStatus> {
+        Ok(self.get_signature_status(signature).await?)
+    }
+
+    async fn get_confirmed_transaction(
+        &self,
+        signature: &Signature,
+    ) -> Result<Option<ConfirmedTransaction>> {
+        Ok(self.get_confirmed_transaction(signature).await?)
+    }
+
+    async fn get_confirmed_signatures_for_address(
+        &self,
+        address: &Pubkey,
+        before_signature: Option<&Signature>,
+        until_signature: Option<&Signature>,
+        limit: usize,
+    ) -> Result<Vec<(ConfirmedTransactionStatusWithSignature, u32)>> {
+        Ok(self
+            .get_confirmed_signatures_for_address(address, before_signature, until_signature, limit)
+            .await?)
+    }
+
+    async fn upload_confirmed_block(
+        &self,
+        slot: Slot,
+        confirmed_block: ConfirmedBlock,
+    ) -> Result<()> {
+        Ok(self.upload_confirmed_block(slot, confirmed_block).await?)
+    }
+
+    async fn delete_confirmed_block(&self, slot: Slot, dry_run: bool) -> Result<()> {
+        Ok(self.delete_confirmed_block(slot, dry_run).await?)
+    }
+}
+
+/// Where the blocks are archived, parsed from `bigtable`, `file://<DIRECTORY>` or
+/// `s3://<BUCKET>[/<PREFIX>]`
+#[derive(Clone, Debug, PartialEq)]
+pub enum LongTermStorageConfig {
+    BigTable,
+    Filesystem(PathBuf),
+    S3 { bucket: String, prefix: String },
+}
+
+impl Default for LongTermStorageConfig {
+    fn default() -> Self {
+        Self::BigTable
+    }
+}
+
+impl FromStr for LongTermStorageConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if s == "bigtable" {
+            Ok(Self::BigTable)
+        } else if let Some(path) = s.strip_prefix("file://") {
+            if path.is_empty() {
+                return Err("The archive directory is missing".to_string());
+            }
+            Ok(Self::Filesystem(PathBuf::from(path)))
+        } else if let Some(location) = s.strip_prefix("s3://") {
+            let mut location = location.splitn(2, '/');
+            let bucket = location.next().unwrap_or_default().to_string();
+            if bucket.is_empty() {
+                return Err("The S3 bucket is missing".to_string());
+            }
+            let prefix = location.next().unwrap_or_default().trim_end_matches('/');
+            Ok(Self::S3 {
+                bucket,
+                prefix: prefix.to_string(),
+            })
+        } else {
+            Err(format!(
+                "Unknown long-term storage {:?}, expected bigtable, file://<DIRECTORY> or \
+                s3://<BUCKET>[/<PREFIX>]",
+                s
+            ))
+        }
+    }
+}
+
+impl fmt::Display for LongTermStorageConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BigTable => write!(f, "bigtable"),
+            Self::Filesystem(path) => write!(f, "file://{}", path.display()),
+            Self::S3 { bucket, prefix } if prefix.is_empty() => write!(f, "s3://{}", bucket),
+            Self::S3 { bucket, prefix } => write!(f, "s3://{}/{}", bucket, prefix),
+        }
+    }
+}
+
+/// Open the long-term storage. The S3 credentials, region and endpoint are read from the
+/// environment, see `S3Config::from_env`.
+pub async fn open_long_term_storage(
+    config: &LongTermStorageConfig,
+    read_only: bool,
+) -> Result<Arc<dyn LongTermStorage>> {
+    Ok(match config {
+        LongTermStorageConfig::BigTable => {
+            Arc::new(solana_storage_bigtable::LedgerStorage::new(read_only, None).await?)
+        }
+        LongTermStorageConfig::Filesystem(path) => Arc::new(ArchiveLedgerStorage::new(
+            FilesystemObjectStore::new(path)?,
+            read_only,
+        )),
+        LongTermStorageConfig::S3 { bucket, prefix } => {
+            let s3_config = S3Config::from_env(bucket, prefix)?;
+            Arc::new(ArchiveLedgerStorage::new(
+                S3ObjectStore::new(s3_config)?,
+                read_only,
+            ))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_long_term_storage_config_from_str() {
+        assert_eq!(
+            "bigtable".parse::<LongTermStorageConfig>(),
+            Ok(LongTermStorageConfig::BigTable)
+        );
+        assert_eq!(
+            "file:///mnt/archive".parse::<LongTermStorageConfig>(),
+            Ok(LongTermStorageConfig::Filesystem(PathBuf::from(
+                "/mnt/archive"
+            )))
+        );
+        assert_eq!(
+            "s3://ledger".parse::<LongTermStorageConfig>(),
+            Ok(LongTermStorageConfig::S3 {
+                bucket: "ledger".to_string(),
+                prefix: "".to_string(),
+            })
+        );
+        assert_eq!(
+            "s3://ledger/mainnet/".parse::<LongTermStorageConfig>(),
+            Ok(LongTermStorageConfig::S3 {
+                bucket: "ledger".to_string(),
+                prefix: "mainnet".to_string(),
+            })
+        );
+        assert!("file://".parse::<LongTermStorageConfig>().is_err());
+        assert!("s3://".parse::<LongTermStorageConfig>().is_err());
+        assert!("gs://ledger".parse::<LongTermStorageConfig>().is_err());
+
+        for storage in [
+            "bigtable",
+            "file:///mnt/archive",
+            "s3://ledger",
+            "s3://ledger/mainnet",
+        ] {
+            assert_eq!(
+                storage
+                    .parse::<LongTermStorageConfig>()
+                    .unwrap()
+                    .to_string(),
+                storage
+            );
+        }
+    }
+}
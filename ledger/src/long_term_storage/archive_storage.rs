@@ -0,0 +1,604 @@
+//! A long-term storage keeping the archive in an object store, e.g. a local directory or an
+//! S3 bucket. The objects are laid out as follows:
+//!
+//! * `blocks/<SLOT / 2^16>/<SLOT>`: the zstd compressed protobuf `ConfirmedBlock` of the slot
+//! * `tx/<SIGNATURE[..2]>/<SIGNATURE>`: the slot and the index of the transaction, and its
+//!   status
+//! * `tx-by-addr/<ADDRESS[..2]>/<ADDRESS>/<!SLOT>`: the transactions of the slot touching
+//!   the address, the slot being inverted to list the newest first
+//!
+//! Slots are formatted as fixed width hexadecimal numbers so that the keys sort like the
+//! slots. The block of a slot is written after its indexes, the presence of the block
+//! implies its indexes are complete.
+use {
+    super::{LongTermStorage, LongTermStorageError, Result},
+    async_trait::async_trait,
+    log::*,
+    prost::Message,
+    serde::{Deserialize, Serialize},
+    solana_sdk::{
+        clock::{Slot, UnixTimestamp},
+        pubkey::Pubkey,
+        signature::Signature,
+        sysvar::is_sysvar_id,
+        transaction::TransactionError,
+    },
+    solana_storage_proto::convert::generated,
+    solana_transaction_status::{
+        extract_memos::extract_and_fmt_memos, ConfirmedBlock, ConfirmedTransaction,
+        ConfirmedTransactionStatusWithSignature, TransactionConfirmationStatus, TransactionStatus,
+    },
+    std::{collections::HashMap, convert::TryFrom},
+};
+
+const BLOCKS_PREFIX: &str = "blocks/";
+const TX_PREFIX: &str = "tx/";
+const TX_BY_ADDR_PREFIX: &str = "tx-by-addr/";
+/// The number of keys listed at once from the object store
+const LIST_CHUNK_SIZE: usize = 1000;
+const BLOCK_COMPRESSION_LEVEL: i32 = 3;
+
+/// A flat key-value store, where keys are `/` separated paths.
+#[async_trait]
+pub trait ObjectStore: Send + Sync {
+    /// Where the objects are stored, see `LongTermStorage::id`
+    fn id(&self) -> String;
+
+    /// Fetch the object, None if it does not exist
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Create or replace the object
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()>;
+
+    /// Delete the object, deleting a missing object is not an error
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// List up to `limit` keys starting with `prefix`, which ends with `/`, and sorting after
+    /// `start_after`, in lexicographic order
+    async fn list(
+        &self,
+        prefix: &str,
+        start_after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>>;
+}
+
+/// The object stored under `tx/`
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct TransactionInfo {
+    slot: Slot,
+    index: u32,
+    err: Option<TransactionError>,
+    memo: Option<String>,
+}
+
+/// An entry of the objects stored under `tx-by-addr/`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct TransactionByAddrInfo {
+    signature: Signature,
+    err: Option<TransactionError>,
+    index: u32,
+    memo: Option<String>,
+    block_time: Option<UnixTimestamp>,
+}
+
+fn block_key(slot: Slot) -> String {
+    format!("{}{:012x}/{:016x}", BLOCKS_PREFIX, slot >> 16, slot)
+}
+
+fn slot_from_block_key(key: &str) -> Result<Slot> {
+    key.rsplit('/')
+        .next()
+        .and_then(|slot| Slot::from_str_radix(slot, 16).ok())
+        .ok_or_else(|| LongTermStorageError::ObjectCorrupt(format!("Invalid block key {}", key)))
+}
+
+fn transaction_key(signature: &Signature) -> String {
+    let signature = signature.to_string();
+    format!("{}{}/{}", TX_PREFIX, &signature[..2], signature)
+}
+
+fn address_prefix(address: &Pubkey) -> String {
+    let address = address.to_string();
+    format!("{}{}/{}/", TX_BY_ADDR_PREFIX, &address[..2], address)
+}
+
+fn address_slot_key(address: &Pubkey, slot: Slot) -> String {
+    format!("{}{:016x}", address_prefix(address), !slot)
+}
+
+fn slot_from_address_slot_key(key: &str) -> Result<Slot> {
+    key.rsplit('/')
+        .next()
+        .and_then(|slot| Slot::from_str_radix(slot, 16).ok())
+        .map(|slot| !slot)
+        .ok_or_else(|| {
+            LongTermStorageError::ObjectCorrupt(format!("Invalid address slot key {}", key))
+        })
+}
+
+fn serialize<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    bincode::serialize(value).map_err(|err| LongTermStorageError::ObjectCorrupt(err.to_string()))
+}
+
+fn deserialize<T: serde::de::DeserializeOwned>(key: &str, data: &[u8]) -> Result<T> {
+    bincode::deserialize(data)
+        .map_err(|err| LongTermStorageError::ObjectCorrupt(format!("{}: {}", key, err)))
+}
+
+fn encode_block(confirmed_block: ConfirmedBlock) -> Result<Vec<u8>> {
+    let block = generated::ConfirmedBlock::from(confirmed_block);
+    zstd::stream::encode_all(block.encode_to_vec().as_slice(), BLOCK_COMPRESSION_LEVEL)
+        .map_err(LongTermStorageError::Io)
+}
+
+fn decode_block(key: &str, data: &[u8]) -> Result<ConfirmedBlock> {
+    let corrupt = |err: String| LongTermStorageError::ObjectCorrupt(format!("{}: {}", key, err));
+    let data = zstd::stream::decode_all(data).map_err(|err| corrupt(err.to_string()))?;
+    let block = generated::ConfirmedBlock::decode(data.as_slice())
+        .map_err(|err| corrupt(err.to_string()))?;
+    ConfirmedBlock::try_from(block).map_err(|err| corrupt(err.to_string()))
+}
+
+pub struct ArchiveLedgerStorage<S: ObjectStore> {
+    store: S,
+    read_only: bool,
+}
+
+impl<S: ObjectStore> ArchiveLedgerStorage<S> {
+    pub fn new(store: S, read_only: bool) -> Self {
+        Self { store, read_only }
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            Err(LongTermStorageError::ReadOnly)
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn get_transaction_info(&self, signature: &Signature) -> Result<TransactionInfo> {
+        let key = transaction_key(signature);
+        let data = self
+            .store
+            .get(&key)
+            .await?
+            .ok_or(LongTermStorageError::SignatureNotFound)?;
+        deserialize(&key, &data)
+    }
+
+    /// The keys of the objects indexing the transactions of the block, along with their
+    /// contents
+    fn index_block(
+        slot: Slot,
+        confirmed_block: &ConfirmedBlock,
+    ) -> Result<(Vec<(String, Vec<u8>)>, Vec<(String, Vec<u8>)>)> {
+        let mut tx_objects = vec![];
+        let mut by_addr: HashMap<&Pubkey, Vec<TransactionByAddrInfo>> = HashMap::new();
+        for (index, transaction_with_meta) in confirmed_block.transactions.iter().enumerate() {
+            let transaction = &transaction_with_meta.transaction;
+            let signature = transaction.signatures[0];
+            let index = index as u32;
+            let err = transaction_with_meta
+                .meta
+                .as_ref()
+                .and_then(|meta| meta.status.clone().err());
+            let memo = extract_and_fmt_memos(&transaction.message);
+
+            for address in &transaction.message.account_keys {
+                if !is_sysvar_id(address) {
+                    by_addr
+                        .entry(address)
+                        .or_default()
+                        .push(TransactionByAddrInfo {
+                            signature,
+                            err: err.clone(),
+                            index,
+                            memo: memo.clone(),
+                            block_time: confirmed_block.block_time,
+                        });
+                }
+            }
+
+            tx_objects.push((
+                transaction_key(&signature),
+                serialize(&TransactionInfo {
+                    slot,
+                    index,
+                    err,
+                    memo,
+                })?,
+            ));
+        }
+
+        let tx_by_addr_objects = by_addr
+            .into_iter()
+            .map(|(address, infos)| Ok((address_slot_key(address, slot), serialize(&infos)?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok((tx_objects, tx_by_addr_objects))
+    }
+}
+
+#[async_trait]
+impl<S: ObjectStore> LongTermStorage for ArchiveLedgerStorage<S> {
+    fn id(&self) -> String {
+        self.store.id()
+    }
+
+    async fn get_first_available_block(&self) -> Result<Option<Slot>> {
+        Ok(self.get_confirmed_blocks(0, 1).await?.first().cloned())
+    }
+
+    async fn get_confirmed_blocks(&self, start_slot: Slot, limit: usize) -> Result<Vec<Slot>> {
+        let start_after = start_slot.checked_sub(1).map(block_key);
+        let keys = self
+            .store
+            .list(BLOCKS_PREFIX, start_after.as_deref(), limit)
+            .await?;
+        keys.iter().map(|key| slot_from_block_key(key)).collect()
+    }
+
+    async fn get_confirmed_block(&self, slot: Slot) -> Result<ConfirmedBlock> {
+        let key = block_key(slot);
+        let data = self
+            .store
+            .get(&key)
+            .await?
+            .ok_or(LongTermStorageError::BlockNotFound(slot))?;
+        decode_block(&key, &data)
+    }
+
+    async fn get_signature_status(&self, signature: &Signature) -> Result<TransactionStatus> {
+        let TransactionInfo { slot, err, .. } = self.get_transaction_info(signature).await?;
+        Ok(TransactionStatus {
+            slot,
+            confirmations: None,
+            status: match &err {
+                None => Ok(()),
+                Some(err) => Err(err.clone()),
+            },
+            err,
+            confirmation_status: Some(TransactionConfirmationStatus::Finalized),
+        })
+    }
+
+    async fn get_confirmed_transaction(
+        &self,
+        signature: &Signature,
+    ) -> Result<Option<ConfirmedTransaction>> {
+        let TransactionInfo { slot, index, .. } = match self.get_transaction_info(signature).await {
+            Ok(info) => info,
+            Err(LongTermStorageError::SignatureNotFound) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let block = self.get_confirmed_block(slot).await?;
+        let block_time = block.block_time;
+        match block.transactions.into_iter().nth(index as usize) {
+            Some(transaction) if transaction.transaction.signatures[0] == *signature => {
+                Ok(Some(ConfirmedTransaction {
+                    slot,
+                    transaction,
+                    block_time,
+                }))
+            }
+            _ => {
+                warn!(
+                    "Transaction info for {} is corrupt, not found at index {} of slot {}",
+                    signature, index, slot
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    async fn get_confirmed_signatures_for_address(
+        &self,
+        address: &Pubkey,
+        before_signature: Option<&Signature>,
+        until_signature: Option<&Signature>,
+        limit: usize,
+    ) -> Result<Vec<(ConfirmedTransactionStatusWithSignature, u32)>> {
+        // Start at the slot of the `before` signature, skipping the transactions at and after it
+        let (first_slot, before_index) = match before_signature {
+            Some(signature) => {
+                let info = self.get_transaction_info(signature).await?;
+                (info.slot, Some(info.index))
+            }
+            None => (Slot::MAX, None),
+        };
+        // Stop at the slot of the `until` signature, skipping the transactions at and before it
+        let (last_slot, until_index) = match until_signature {
+            Some(signature) => {
+                let info = self.get_transaction_info(signature).await?;
+                (info.slot, Some(info.index))
+            }
+            None => (0, None),
+        };
+
+        let prefix = address_prefix(address);
+        let mut start_after = first_slot
+            .checked_add(1)
+            .map(|slot| address_slot_key(address, slot));
+        let mut infos = vec![];
+        'outer: while infos.len() < limit {
+            let keys = self
+                .store
+                .list(&prefix, start_after.as_deref(), LIST_CHUNK_SIZE)
+                .await?;
+            if keys.is_empty() {
+                break;
+            }
+            for key in &keys {
+                let slot = slot_from_address_slot_key(key)?;
+                if slot < last_slot {
+                    break 'outer;
+                }
+                let data = match self.store.get(key).await? {
+                    Some(data) => data,
+                    None => continue,
+                };
+                let tx_by_addr_infos: Vec<TransactionByAddrInfo> = deserialize(key, &data)?;
+                for tx_by_addr_info in tx_by_addr_infos.into_iter().rev() {
+                    if slot == first_slot
+                        && before_index.map_or(false, |index| tx_by_addr_info.index >= index)
+                    {
+                        continue;
+                    }
+                    if slot == last_slot
+                        && until_index.map_or(false, |index| tx_by_addr_info.index <= index)
+                    {
+                        break 'outer;
+                    }
+                    infos.push((
+                        ConfirmedTransactionStatusWithSignature {
+                            signature: tx_by_addr_info.signature,
+                            slot,
+                            err: tx_by_addr_info.err,
+                            memo: tx_by_addr_info.memo,
+                            block_time: tx_by_addr_info.block_time,
+                        },
+                        tx_by_addr_info.index,
+                    ));
+                    if infos.len() >= limit {
+                        break 'outer;
+                    }
+                }
+            }
+            start_after = keys.last().cloned();
+        }
+        Ok(infos)
+    }
+
+    async fn upload_confirmed_block(
+        &self,
+        slot: Slot,
+        confirmed_block: ConfirmedBlock,
+    ) -> Result<()> {
+        self.check_writable()?;
+        let (tx_objects, tx_by_addr_objects) = Self::index_block(slot, &confirmed_block)?;
+        for (key, data) in tx_objects.into_iter().chain(tx_by_addr_objects) {
+            self.store.put(&key, data).await?;
+        }
+        // Write the block last, its presence implies the indexes are complete
+        self.store
+            .put(&block_key(slot), encode_block(confirmed_block)?)
+            .await
+    }
+
+    async fn delete_confirmed_block(&self, slot: Slot, dry_run: bool) -> Result<()> {
+        if !dry_run {
+            self.check_writable()?;
+        }
+        let confirmed_block = self.get_confirmed_block(slot).await?;
+        let (tx_objects, tx_by_addr_objects) = Self::index_block(slot, &confirmed_block)?;
+        info!(
+            "{}Deleting block {} with {} transactions and {} address entries",
+            if dry_run { "Dry run: " } else { "" },
+            slot,
+            tx_objects.len(),
+            tx_by_addr_objects.len()
+        );
+        if dry_run {
+            return Ok(());
+        }
+        // Delete the block first, its absence marks the indexes as incomplete
+        self.store.delete(&block_key(slot)).await?;
+        for (key, _) in tx_by_addr_objects.into_iter().chain(tx_objects) {
+            self.store.delete(&key).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use {
+        super::*,
+        crate::long_term_storage::FilesystemObjectStore,
+        solana_sdk::{
+            hash::Hash, signature::Keypair, system_transaction, transaction::Transaction,
+        },
+        solana_transaction_status::{TransactionStatusMeta, TransactionWithStatusMeta},
+        tempfile::TempDir,
+    };
+
+    fn transaction_with_meta(
+        transaction: Transaction,
+        status: solana_sdk::transaction::Result<()>,
+    ) -> TransactionWithStatusMeta {
+        TransactionWithStatusMeta {
+            transaction,
+            meta: Some(TransactionStatusMeta {
+                status,
+                fee: 5000,
+                pre_balances: vec![10, 0, 1],
+                post_balances: vec![5, 5, 1],
+                inner_instructions: Some(vec![]),
+                log_messages: Some(vec![]),
+                pre_token_balances: Some(vec![]),
+                post_token_balances: Some(vec![]),
+                rewards: Some(vec![]),
+            }),
+        }
+    }
+
+    fn confirmed_block(slot: Slot, transactions: Vec<TransactionWithStatusMeta>) -> ConfirmedBlock {
+        ConfirmedBlock {
+            previous_blockhash: Hash::new_unique().to_string(),
+            blockhash: Hash::new_unique().to_string(),
+            parent_slot: slot.saturating_sub(1),
+            transactions,
+            rewards: vec![],
+            block_time: Some(slot as UnixTimestamp),
+            block_height: Some(slot),
+        }
+    }
+
+    /// Exercise a long-term storage holding no block yet, shared with the S3 store tests
+    pub(crate) async fn check_long_term_storage(storage: &dyn LongTermStorage) {
+        let payer = Keypair::new();
+        let recipient = Pubkey::new_unique();
+        let transfer = |lamports| {
+            system_transaction::transfer(&payer, &recipient, lamports, Hash::new_unique())
+        };
+        let transactions = [transfer(1), transfer(2), transfer(3), transfer(4)];
+        let signatures: Vec<_> = transactions
+            .iter()
+            .map(|transaction| transaction.signatures[0])
+            .collect();
+
+        assert_eq!(storage.get_first_available_block().await.unwrap(), None);
+        storage
+            .upload_confirmed_block(
+                3,
+                confirmed_block(
+                    3,
+                    vec![
+                        transaction_with_meta(transactions[0].clone(), Ok(())),
+                        transaction_with_meta(
+                            transactions[1].clone(),
+                            Err(TransactionError::AccountNotFound),
+                        ),
+                    ],
+                ),
+            )
+            .await
+            .unwrap();
+        storage
+            .upload_confirmed_block(
+                70_000,
+                confirmed_block(
+                    70_000,
+                    vec![
+                        transaction_with_meta(transactions[2].clone(), Ok(())),
+                        transaction_with_meta(transactions[3].clone(), Ok(())),
+                    ],
+                ),
+            )
+            .await
+            .unwrap();
+        storage
+            .upload_confirmed_block(5, confirmed_block(5, vec![]))
+            .await
+            .unwrap();
+
+        assert_eq!(storage.get_first_available_block().await.unwrap(), Some(3));
+        assert_eq!(
+            storage.get_confirmed_blocks(0, 10).await.unwrap(),
+            vec![3, 5, 70_000]
+        );
+        assert_eq!(storage.get_confirmed_blocks(4, 1).await.unwrap(), vec![5]);
+        assert_eq!(
+            storage.get_confirmed_blocks(6, 10).await.unwrap(),
+            vec![70_000]
+        );
+
+        let block = storage.get_confirmed_block(70_000).await.unwrap();
+        assert_eq!(block.block_time, Some(70_000));
+        assert_eq!(block.transactions[1].transaction, transactions[3]);
+        assert!(matches!(
+            storage.get_confirmed_block(4).await,
+            Err(LongTermStorageError::BlockNotFound(4))
+        ));
+
+        let status = storage.get_signature_status(&signatures[1]).await.unwrap();
+        assert_eq!(status.slot, 3);
+        assert_eq!(status.err, Some(TransactionError::AccountNotFound));
+        let transaction = storage
+            .get_confirmed_transaction(&signatures[2])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(transaction.slot, 70_000);
+        assert_eq!(transaction.transaction.transaction, transactions[2]);
+        assert!(storage
+            .get_confirmed_transaction(&Signature::new(&[1; 64]))
+            .await
+            .unwrap()
+            .is_none());
+
+        // Newest first
+        let signatures_for_address = |before, until, limit| async move {
+            storage
+                .get_confirmed_signatures_for_address(&recipient, before, until, limit)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|(info, index)| (info.signature, info.slot, index))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            signatures_for_address(None, None, 10).await,
+            vec![
+                (signatures[3], 70_000, 1),
+                (signatures[2], 70_000, 0),
+                (signatures[1], 3, 1),
+                (signatures[0], 3, 0),
+            ]
+        );
+        assert_eq!(
+            signatures_for_address(Some(&signatures[3]), Some(&signatures[0]), 10).await,
+            vec![(signatures[2], 70_000, 0), (signatures[1], 3, 1)]
+        );
+        assert_eq!(
+            signatures_for_address(Some(&signatures[2]), None, 1).await,
+            vec![(signatures[1], 3, 1)]
+        );
+
+        storage.delete_confirmed_block(3, true).await.unwrap();
+        assert_eq!(storage.get_first_available_block().await.unwrap(), Some(3));
+        storage.delete_confirmed_block(3, false).await.unwrap();
+        assert_eq!(storage.get_first_available_block().await.unwrap(), Some(5));
+        assert!(matches!(
+            storage.get_signature_status(&signatures[0]).await,
+            Err(LongTermStorageError::SignatureNotFound)
+        ));
+        assert_eq!(signatures_for_address(None, None, 10).await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_filesystem_archive_storage() {
+        let archive_dir = TempDir::new().unwrap();
+        let storage = ArchiveLedgerStorage::new(
+            FilesystemObjectStore::new(archive_dir.path()).unwrap(),
+            false,
+        );
+        check_long_term_storage(&storage).await;
+
+        let read_only_storage = ArchiveLedgerStorage::new(
+            FilesystemObjectStore::new(archive_dir.path()).unwrap(),
+            true,
+        );
+        assert_eq!(
+            read_only_storage.get_confirmed_blocks(0, 10).await.unwrap(),
+            vec![5, 70_000]
+        );
+        assert!(matches!(
+            read_only_storage
+                .upload_confirmed_block(6, confirmed_block(6, vec![]))
+                .await,
+            Err(LongTermStorageError::ReadOnly)
+        ));
+    }
+}
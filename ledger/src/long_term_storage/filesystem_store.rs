@@ -0,0 +1,190 @@
+//! An object store keeping every object in a file under a local directory, the `/` of the
+//! keys separating directories.
+use {
+    super::{archive_storage::ObjectStore, LongTermStorageConfig, Result},
+    async_trait::async_trait,
+    std::{
+        fs, io,
+        path::{Path, PathBuf},
+        process,
+        sync::atomic::{AtomicU64, Ordering},
+    },
+};
+
+/// Objects are written to a file with this extension first, then renamed, so that a partially
+/// written object is never read
+const TEMPORARY_FILE_EXTENSION: &str = "tmp";
+
+/// Makes the temporary file names unique, so that concurrent writes of the same key do not
+/// write to the same temporary file
+static TEMPORARY_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+pub struct FilesystemObjectStore {
+    root: PathBuf,
+}
+
+impl FilesystemObjectStore {
+    pub fn new(root: &Path) -> Result<Self> {
+        fs::create_dir_all(root)?;
+        Ok(Self {
+            root: root.to_path_buf(),
+        })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+
+    /// A unique path next to the file of the object, for the object to be written to first
+    fn temporary_path(path: &Path) -> PathBuf {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        path.with_file_name(format!(
+            "{}.{}-{}.{}",
+            file_name,
+            process::id(),
+            TEMPORARY_FILE_COUNTER.fetch_add(1, Ordering::Relaxed),
+            TEMPORARY_FILE_EXTENSION
+        ))
+    }
+
+    /// Append to `keys` the keys under the directory of `dir_key`, which is empty or ends with
+    /// `/`, in lexicographic order
+    fn list_dir(
+        &self,
+        dir_key: &str,
+        start_after: Option<&str>,
+        limit: usize,
+        keys: &mut Vec<String>,
+    ) -> io::Result<()> {
+        let mut entries = match fs::read_dir(self.path(dir_key)) {
+            Ok(entries) => entries
+                .map(|entry| {
+                    let entry = entry?;
+                    let mut key = format!("{}{}", dir_key, entry.file_name().to_string_lossy());
+                    if entry.file_type()?.is_dir() {
+                        key.push('/');
+                    }
+                    Ok(key)
+                })
+                .collect::<io::Result<Vec<_>>>()?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        // Sorting the directories with their trailing `/` sorts them like the keys they hold
+        entries.sort_unstable();
+
+        for key in entries {
+            if keys.len() >= limit {
+                break;
+            }
+            if key.ends_with('/') {
+                // Skip the directories holding only keys up to `start_after`
+                let skip = start_after.map_or(false, |start_after| {
+                    key.as_str() < start_after && !start_after.starts_with(&key)
+                });
+                if !skip {
+                    self.list_dir(&key, start_after, limit, keys)?;
+                }
+            } else if start_after.map_or(true, |start_after| key.as_str() > start_after)
+                && Path::new(&key).extension() != Some(TEMPORARY_FILE_EXTENSION.as_ref())
+            {
+                keys.push(key);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ObjectStore for FilesystemObjectStore {
+    fn id(&self) -> String {
+        LongTermStorageConfig::Filesystem(self.root.clone()).to_string()
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.path(key)).await {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()> {
+        let path = self.path(key);
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let temporary_path = Self::temporary_path(&path);
+        if let Err(err) = tokio::fs::write(&temporary_path, data).await {
+            let _ = tokio::fs::remove_file(&temporary_path).await;
+            return Err(err.into());
+        }
+        tokio::fs::rename(&temporary_path, &path).await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match tokio::fs::remove_file(self.path(key)).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn list(
+        &self,
+        prefix: &str,
+        start_after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        let mut keys = vec![];
+        self.list_dir(prefix, start_after, limit, &mut keys)?;
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, tempfile::TempDir};
+
+    #[tokio::test]
+    async fn test_filesystem_object_store() {
+        let root = TempDir::new().unwrap();
+        let store = FilesystemObjectStore::new(root.path()).unwrap();
+        assert_eq!(store.id(), format!("file://{}", root.path().display()));
+        for key in ["a/0/1", "a/0/0", "a/1/0", "a/10", "b/0"] {
+            store.put(key, key.as_bytes().to_vec()).await.unwrap();
+        }
+
+        assert_eq!(store.get("a/0/1").await.unwrap(), Some(b"a/0/1".to_vec()));
+        assert_eq!(store.get("a/0/2").await.unwrap(), None);
+        assert_eq!(
+            store.list("a/", None, 10).await.unwrap(),
+            vec!["a/0/0", "a/0/1", "a/1/0", "a/10"]
+        );
+        assert_eq!(
+            store.list("a/", Some("a/0/0"), 2).await.unwrap(),
+            vec!["a/0/1", "a/1/0"]
+        );
+        assert_eq!(
+            store.list("a/", Some("a/1/0"), 10).await.unwrap(),
+            vec!["a/10"]
+        );
+        assert!(store.list("c/", None, 10).await.unwrap().is_empty());
+
+        store.delete("a/0/0").await.unwrap();
+        store.delete("a/0/0").await.unwrap();
+        assert_eq!(store.get("a/0/0").await.unwrap(), None);
+        assert_eq!(store.list("a/0/", None, 10).await.unwrap(), vec!["a/0/1"]);
+
+        // Concurrent writes of the same key go through their own temporary files
+        let path = store.path("b/0");
+        assert_ne!(
+            FilesystemObjectStore::temporary_path(&path),
+            FilesystemObjectStore::temporary_path(&path)
+        );
+        let (first, second) = tokio::join!(store.put("b/0", vec![1]), store.put("b/0", vec![2]));
+        first.unwrap();
+        second.unwrap();
+        assert_eq!(store.list("b/", None, 10).await.unwrap(), vec!["b/0"]);
+    }
+}
@@ -18,6 +18,7 @@ pub fn safe_clone_config(config: &ValidatorConfig) -> ValidatorConfig {
         pubsub_config: config.pubsub_config.clone(),
         snapshot_config: config.snapshot_config.clone(),
         max_ledger_shreds: config.max_ledger_shreds,
+        ledger_retention_policies: config.ledger_retention_policies.clone(),
         broadcast_stage_type: config.broadcast_stage_type.clone(),
         enable_partition: config.enable_partition.clone(),
         enforce_ulimit_nofile: config.enforce_ulimit_nofile,
@@ -37,6 +38,10 @@ pub fn safe_clone_config(config: &ValidatorConfig) -> ValidatorConfig {
         accounts_hash_interval_slots: config.accounts_hash_interval_slots,
         max_genesis_archive_unpacked_size: config.max_genesis_archive_unpacked_size,
         wal_recovery_mode: config.wal_recovery_mode.clone(),
+        blockstore_compression: config.blockstore_compression.clone(),
+        enable_program_signatures: config.enable_program_signatures,
+        blockstore_integrity_check_interval: config.blockstore_integrity_check_interval,
+        blockstore_integrity_check_repair: config.blockstore_integrity_check_repair,
         poh_verify: config.poh_verify,
         require_tower: config.require_tower,
         tower_storage: config.tower_storage.clone(),
@@ -55,12 +60,14 @@ pub fn safe_clone_config(config: &ValidatorConfig) -> ValidatorConfig {
         accounts_db_skip_shrink: config.accounts_db_skip_shrink,
         accounts_db_use_index_hash_calculation: config.accounts_db_use_index_hash_calculation,
         tpu_coalesce_ms: config.tpu_coalesce_ms,
+        transaction_scheduling: config.transaction_scheduling,
         validator_exit: Arc::new(RwLock::new(Exit::default())),
         poh_hashes_per_batch: config.poh_hashes_per_batch,
         no_wait_for_vote_to_start_leader: config.no_wait_for_vote_to_start_leader,
         accounts_shrink_ratio: config.accounts_shrink_ratio,
         accounts_db_config: config.accounts_db_config.clone(),
         disable_epoch_boundary_optimization: config.disable_epoch_boundary_optimization,
+        vote_signer_addr: config.vote_signer_addr.clone(),
     }
 }
 
@@ -92,3 +92,170 @@ pub fn program_failure(
 ) {
     ic_logger_msg!(logger, "Program {} failed: {}", program_id, err);
 }
+
+/// The outcome of a program invocation as recorded by [`program_success`] or
+/// [`program_failure`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum InvokeOutcome {
+    Success,
+    Failure(String),
+}
+
+/// A program invocation reconstructed from the stable log messages.
+///
+/// `depth` is the invoke depth logged by [`program_invoke`]: top-level instructions have depth 1
+/// and the cross-program invocations they make are collected in `inner`, in invocation order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InvokeTrace {
+    pub program_id: Pubkey,
+    pub depth: usize,
+    pub compute_units_consumed: Option<u64>,
+    pub compute_units_limit: Option<u64>,
+    pub logs: Vec<String>,
+    pub return_data: Option<String>,
+    /// `None` if the log ended before the invocation did, eg. because it was truncated
+    pub outcome: Option<InvokeOutcome>,
+    pub inner: Vec<InvokeTrace>,
+}
+
+impl InvokeTrace {
+    fn new(program_id: Pubkey, depth: usize) -> Self {
+        Self {
+            program_id,
+            depth,
+            compute_units_consumed: None,
+            compute_units_limit: None,
+            logs: vec![],
+            return_data: None,
+            outcome: None,
+            inner: vec![],
+        }
+    }
+}
+
+/// Rebuild the invocation tree of a transaction from its stable log messages.
+///
+/// Every message that isn't one of the invoke, consumed, return, success or failure messages is
+/// attributed to the innermost invocation in progress. Messages logged outside of any invocation
+/// are dropped.
+pub fn parse_invoke_traces(log_messages: &[String]) -> Vec<InvokeTrace> {
+    fn finish(stack: &mut Vec<InvokeTrace>, traces: &mut Vec<InvokeTrace>) {
+        if let Some(trace) = stack.pop() {
+            match stack.last_mut() {
+                Some(parent) => parent.inner.push(trace),
+                None => traces.push(trace),
+            }
+        }
+    }
+
+    let mut traces = vec![];
+    let mut stack: Vec<InvokeTrace> = vec![];
+    for message in log_messages {
+        if let Some(return_data) = message.strip_prefix("Program return: ") {
+            if let Some(current) = stack.last_mut() {
+                let data = return_data.split_once(' ').map(|(_, data)| data);
+                current.return_data = Some(data.unwrap_or_default().to_string());
+            }
+            continue;
+        }
+
+        let program_message = message.strip_prefix("Program ").and_then(|rest| {
+            let (program_id, rest) = rest.split_once(' ')?;
+            Some((program_id.parse::<Pubkey>().ok()?, rest))
+        });
+        match program_message {
+            Some((program_id, rest)) if rest.starts_with("invoke [") => {
+                let depth = rest
+                    .trim_start_matches("invoke [")
+                    .trim_end_matches(']')
+                    .parse()
+                    .unwrap_or(stack.len() + 1);
+                stack.push(InvokeTrace::new(program_id, depth));
+            }
+            Some((_, rest)) if rest.starts_with("consumed ") => {
+                let mut units = rest.split(' ').filter_map(|word| word.parse::<u64>().ok());
+                if let Some(current) = stack.last_mut() {
+                    current.compute_units_consumed = units.next();
+                    current.compute_units_limit = units.next();
+                }
+            }
+            Some((_, "success")) => {
+                if let Some(current) = stack.last_mut() {
+                    current.outcome = Some(InvokeOutcome::Success);
+                }
+                finish(&mut stack, &mut traces);
+            }
+            Some((_, rest)) if rest.starts_with("failed: ") => {
+                if let Some(current) = stack.last_mut() {
+                    let err = rest.trim_start_matches("failed: ").to_string();
+                    current.outcome = Some(InvokeOutcome::Failure(err));
+                }
+                finish(&mut stack, &mut traces);
+            }
+            _ => {
+                if let Some(current) = stack.last_mut() {
+                    current.logs.push(message.clone());
+                }
+            }
+        }
+    }
+    while !stack.is_empty() {
+        finish(&mut stack, &mut traces);
+    }
+    traces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_invoke_traces() {
+        let outer = Pubkey::new_unique();
+        let inner = Pubkey::new_unique();
+        let log_messages: Vec<String> = vec![
+            format!("Program {} invoke [1]", outer),
+            "Program log: outer".to_string(),
+            format!("Program {} invoke [2]", inner),
+            "Program log: inner".to_string(),
+            format!("Program return: {} AQI=", inner),
+            format!("Program {} consumed 100 of 199800 compute units", inner),
+            format!("Program {} success", inner),
+            format!("Program {} consumed 300 of 200000 compute units", outer),
+            format!("Program {} failed: custom program error: 0x1", outer),
+            format!("Program {} invoke [1]", inner),
+            "Log truncated".to_string(),
+        ];
+
+        let traces = parse_invoke_traces(&log_messages);
+        assert_eq!(traces.len(), 2);
+
+        let first = &traces[0];
+        assert_eq!(first.program_id, outer);
+        assert_eq!(first.depth, 1);
+        assert_eq!(first.logs, vec!["Program log: outer".to_string()]);
+        assert_eq!(first.compute_units_consumed, Some(300));
+        assert_eq!(first.compute_units_limit, Some(200_000));
+        assert_eq!(
+            first.outcome,
+            Some(InvokeOutcome::Failure(
+                "custom program error: 0x1".to_string()
+            ))
+        );
+        assert_eq!(first.inner.len(), 1);
+
+        let cpi = &first.inner[0];
+        assert_eq!(cpi.program_id, inner);
+        assert_eq!(cpi.depth, 2);
+        assert_eq!(cpi.logs, vec!["Program log: inner".to_string()]);
+        assert_eq!(cpi.return_data, Some("AQI=".to_string()));
+        assert_eq!(cpi.compute_units_consumed, Some(100));
+        assert_eq!(cpi.outcome, Some(InvokeOutcome::Success));
+        assert!(cpi.inner.is_empty());
+
+        let truncated = &traces[1];
+        assert_eq!(truncated.program_id, inner);
+        assert_eq!(truncated.logs, vec!["Log truncated".to_string()]);
+        assert_eq!(truncated.outcome, None);
+    }
+}
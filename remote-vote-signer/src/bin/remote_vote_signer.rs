@@ -0,0 +1,112 @@
+use {
+    clap::{crate_description, crate_name, value_t_or_exit, App, Arg},
+    log::*,
+    solana_clap_utils::{
+        input_parsers::pubkeys_of,
+        input_validators::{is_keypair, is_parsable, is_pubkey},
+    },
+    solana_remote_vote_signer::{
+        protection_db::{ProtectionDb, DEFAULT_MAX_SLOTS_AHEAD},
+        vote_signer::VoteSignerAddr,
+        vote_signer_server::{run_vote_signer_server, VoteSignerServer},
+    },
+    solana_sdk::signature::read_keypair_file,
+    std::{path::PathBuf, process::exit, sync::Arc},
+};
+
+fn main() {
+    solana_logger::setup_with_default("solana=info");
+    let default_max_slots_ahead = DEFAULT_MAX_SLOTS_AHEAD.to_string();
+    let matches = App::new(crate_name!())
+        .about(crate_description!())
+        .version(solana_version::version!())
+        .arg(
+            Arg::with_name("keypair")
+                .short("k")
+                .long("keypair")
+                .value_name("PATH")
+                .takes_value(true)
+                .required(true)
+                .validator(is_keypair)
+                .help("File from which to read the authorized voter keypair"),
+        )
+        .arg(
+            Arg::with_name("protection_db")
+                .long("protection-db")
+                .value_name("DIR")
+                .takes_value(true)
+                .required(true)
+                .help(
+                    "Directory in which to record the signed votes; \
+                    must be kept across restarts to prevent double votes",
+                ),
+        )
+        .arg(
+            Arg::with_name("listen")
+                .long("listen")
+                .value_name("HOST:PORT or unix:PATH")
+                .takes_value(true)
+                .required(true)
+                .validator(is_parsable::<VoteSignerAddr>)
+                .help("Address on which to serve the vote signing requests"),
+        )
+        .arg(
+            Arg::with_name("allow_non_loopback")
+                .long("allow-non-loopback")
+                .takes_value(false)
+                .help(
+                    "Allow listening on a non-loopback TCP address; \
+                    the requests and responses are not encrypted",
+                ),
+        )
+        .arg(
+            Arg::with_name("authorized_node")
+                .long("authorized-node")
+                .value_name("PUBKEY")
+                .takes_value(true)
+                .multiple(true)
+                .required(true)
+                .validator(is_pubkey)
+                .help("Identity of a validator allowed to request vote signatures"),
+        )
+        .arg(
+            Arg::with_name("max_slots_ahead")
+                .long("max-slots-ahead")
+                .value_name("SLOTS")
+                .takes_value(true)
+                .default_value(&default_max_slots_ahead)
+                .validator(is_parsable::<u64>)
+                .help("Refuse the votes that are further ahead of the last signed vote"),
+        )
+        .get_matches();
+
+    let keypair = read_keypair_file(matches.value_of("keypair").unwrap())
+        .expect("failed to read authorized voter keypair");
+    let protection_db_path = value_t_or_exit!(matches, "protection_db", PathBuf);
+    let addr = value_t_or_exit!(matches, "listen", VoteSignerAddr);
+    let allow_non_loopback = matches.is_present("allow_non_loopback");
+    let authorized_nodes = pubkeys_of(&matches, "authorized_node")
+        .unwrap()
+        .into_iter()
+        .collect();
+    let max_slots_ahead = value_t_or_exit!(matches, "max_slots_ahead", u64);
+
+    let protection_db = ProtectionDb::open(protection_db_path.clone(), max_slots_ahead)
+        .unwrap_or_else(|err| {
+            error!(
+                "Failed to open protection database at {}: {}",
+                protection_db_path.display(),
+                err
+            );
+            exit(1);
+        });
+    let server = Arc::new(VoteSignerServer::new(
+        keypair,
+        authorized_nodes,
+        protection_db,
+    ));
+    if let Err(err) = run_vote_signer_server(server, &addr, allow_non_loopback) {
+        error!("Vote signer failed: {}", err);
+        exit(1);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod protection_db;
+pub mod vote_signer;
+pub mod vote_signer_server;
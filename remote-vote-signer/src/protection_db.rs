@@ -0,0 +1,454 @@
+//! The `protection_db` module keeps a persistent record of the votes signed for each vote
+//! account and refuses to sign the votes that would violate the lockouts implied by them, so
+//! that validators sharing a vote signer can't double vote.
+
+use {
+    log::*,
+    serde_derive::{Deserialize, Serialize},
+    solana_sdk::{clock::Slot, hash::Hash, pubkey::Pubkey},
+    solana_vote_program::vote_state::{Lockout, Vote, VoteState},
+    std::{
+        collections::{HashMap, HashSet, VecDeque},
+        fs::{self, File},
+        io::{self, BufReader},
+        path::PathBuf,
+    },
+    thiserror::Error,
+};
+
+/// How far ahead of the last signed slot a vote may be by default. A vote far in the future
+/// would lock the vote account out of the actual tip of the cluster for that long.
+pub const DEFAULT_MAX_SLOTS_AHEAD: Slot = 432_000;
+
+#[derive(Error, Debug)]
+pub enum ProtectionError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("serialization error: {0}")]
+    Serialize(#[from] bincode::Error),
+
+    #[error("vote has no slots")]
+    EmptyVote,
+
+    #[error("slot {slot} was already signed with hash {signed_hash}, not {hash}")]
+    DoubleVote {
+        slot: Slot,
+        hash: Hash,
+        signed_hash: Hash,
+    },
+
+    #[error("slot {slot} is older than the last signed slot {last_signed_slot}")]
+    SlotTooOld { slot: Slot, last_signed_slot: Slot },
+
+    #[error(
+        "slot {slot} is more than {max_slots_ahead} slots ahead of \
+        the last signed slot {last_signed_slot}"
+    )]
+    SlotTooFarAhead {
+        slot: Slot,
+        last_signed_slot: Slot,
+        max_slots_ahead: Slot,
+    },
+
+    #[error(
+        "slot {slot} does not descend from slot {locked_out_slot}, \
+        which is locked out until slot {last_locked_out_slot}"
+    )]
+    LockoutViolation {
+        slot: Slot,
+        locked_out_slot: Slot,
+        last_locked_out_slot: Slot,
+    },
+
+    #[error("slot {slot} does not descend from the root slot {root_slot}")]
+    RootViolation { slot: Slot, root_slot: Slot },
+}
+
+pub type Result<T> = std::result::Result<T, ProtectionError>;
+
+/// The lockouts of the votes signed for a vote account, as the vote program would apply them
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SignedVotes {
+    pub votes: VecDeque<Lockout>,
+    pub root_slot: Option<Slot>,
+    /// The last signed slot and its bank hash
+    pub last_vote: Option<(Slot, Hash)>,
+}
+
+impl SignedVotes {
+    /// The slots of the signed votes which lock the vote account to their fork, and the root
+    pub fn locked_slots(&self) -> Vec<Slot> {
+        self.votes
+            .iter()
+            .map(|lockout| lockout.slot)
+            .chain(self.root_slot)
+            .collect()
+    }
+
+    /// Returns the signed votes once `vote` is signed as well, or `None` if `vote` is the last
+    /// signed vote again, as when a vote is refreshed. `ancestors` are the slots of the fork of
+    /// the last slot of `vote`, which must include the `locked_slots` on that fork. A vote more
+    /// than `max_slots_ahead` slots ahead of the last signed one is refused.
+    pub fn check_vote(
+        &self,
+        vote: &Vote,
+        ancestors: &[Slot],
+        max_slots_ahead: Slot,
+    ) -> Result<Option<Self>> {
+        let (slot, hash) = vote
+            .last_voted_slot_hash()
+            .ok_or(ProtectionError::EmptyVote)?;
+        if let Some((last_signed_slot, signed_hash)) = self.last_vote {
+            if slot == last_signed_slot {
+                return if hash == signed_hash {
+                    Ok(None)
+                } else {
+                    Err(ProtectionError::DoubleVote {
+                        slot,
+                        hash,
+                        signed_hash,
+                    })
+                };
+            }
+            if slot < last_signed_slot {
+                return Err(ProtectionError::SlotTooOld {
+                    slot,
+                    last_signed_slot,
+                });
+            }
+            if slot - last_signed_slot > max_slots_ahead {
+                return Err(ProtectionError::SlotTooFarAhead {
+                    slot,
+                    last_signed_slot,
+                    max_slots_ahead,
+                });
+            }
+        }
+
+        // The slots of a vote are on the fork of its last slot
+        let mut ancestors: HashSet<Slot> =
+            ancestors.iter().chain(vote.slots.iter()).copied().collect();
+        let mut vote_state = VoteState {
+            votes: self.votes.clone(),
+            root_slot: self.root_slot,
+            ..VoteState::default()
+        };
+        let last_signed_slot = self.last_vote.map(|(slot, _)| slot);
+        for vote_slot in vote
+            .slots
+            .iter()
+            .copied()
+            .filter(|vote_slot| Some(*vote_slot) > last_signed_slot)
+        {
+            // Every vote that the new one doesn't pop off the stack locks it to its fork
+            let num_expired = vote_state
+                .votes
+                .iter()
+                .rev()
+                .take_while(|lockout| !lockout.is_locked_out_at_slot(vote_slot))
+                .count();
+            let num_locked_out = vote_state.votes.len() - num_expired;
+            if let Some(lockout) = vote_state
+                .votes
+                .iter()
+                .take(num_locked_out)
+                .find(|lockout| !ancestors.contains(&lockout.slot))
+            {
+                return Err(ProtectionError::LockoutViolation {
+                    slot: vote_slot,
+                    locked_out_slot: lockout.slot,
+                    last_locked_out_slot: lockout.last_locked_out_slot(),
+                });
+            }
+            if let Some(root_slot) = vote_state.root_slot {
+                if !ancestors.contains(&root_slot) {
+                    return Err(ProtectionError::RootViolation {
+                        slot: vote_slot,
+                        root_slot,
+                    });
+                }
+            }
+            vote_state.process_slot_vote_unchecked(vote_slot);
+            ancestors.insert(vote_slot);
+        }
+
+        Ok(Some(Self {
+            votes: vote_state.votes,
+            root_slot: vote_state.root_slot,
+            last_vote: Some((slot, hash)),
+        }))
+    }
+}
+
+/// Stores the `SignedVotes` of each vote account in its own file under `path`
+pub struct ProtectionDb {
+    path: PathBuf,
+    max_slots_ahead: Slot,
+    signed_votes: HashMap<Pubkey, SignedVotes>,
+}
+
+impl ProtectionDb {
+    pub fn open(path: PathBuf, max_slots_ahead: Slot) -> Result<Self> {
+        fs::create_dir_all(&path)?;
+        Ok(Self {
+            path,
+            max_slots_ahead,
+            signed_votes: HashMap::new(),
+        })
+    }
+
+    pub fn filename(&self, vote_account: &Pubkey) -> PathBuf {
+        self.path
+            .join(format!("signed-votes-{}", vote_account))
+            .with_extension("bin")
+    }
+
+    pub fn signed_votes(&mut self, vote_account: &Pubkey) -> Result<&SignedVotes> {
+        if !self.signed_votes.contains_key(vote_account) {
+            let signed_votes = self.load(vote_account)?;
+            self.signed_votes.insert(*vote_account, signed_votes);
+        }
+        Ok(&self.signed_votes[vote_account])
+    }
+
+    /// Records `vote` as signed for `vote_account`, unless it violates the lockouts of the
+    /// votes signed before. The record is on disk once this returns `Ok`.
+    pub fn record_vote(
+        &mut self,
+        vote_account: &Pubkey,
+        vote: &Vote,
+        ancestors: &[Slot],
+    ) -> Result<()> {
+        let max_slots_ahead = self.max_slots_ahead;
+        let signed_votes = self.signed_votes(vote_account)?;
+        if let Some(signed_votes) = signed_votes.check_vote(vote, ancestors, max_slots_ahead)? {
+            self.store(vote_account, &signed_votes)?;
+            self.signed_votes.insert(*vote_account, signed_votes);
+        }
+        Ok(())
+    }
+
+    fn load(&self, vote_account: &Pubkey) -> Result<SignedVotes> {
+        let filename = self.filename(vote_account);
+        trace!("load {}", filename.display());
+        match File::open(&filename) {
+            Ok(file) => {
+                let mut stream = BufReader::new(file);
+                bincode::deserialize_from(&mut stream).map_err(|err| err.into())
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                info!("No votes signed yet for vote account {}", vote_account);
+                Ok(SignedVotes::default())
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn store(&self, vote_account: &Pubkey, signed_votes: &SignedVotes) -> Result<()> {
+        let filename = self.filename(vote_account);
+        trace!("store: {}", filename.display());
+        let new_filename = filename.with_extension("bin.new");
+        {
+            let mut file = File::create(&new_filename)?;
+            bincode::serialize_into(&mut file, signed_votes)?;
+            // The vote must not be signed before its record is durable
+            file.sync_all()?;
+        }
+        fs::rename(&new_filename, &filename)?;
+        File::open(&self.path)?.sync_all()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, tempfile::TempDir};
+
+    #[test]
+    fn test_check_vote() {
+        let signed_votes = SignedVotes::default();
+        let signed_votes = signed_votes
+            .check_vote(
+                &Vote::new(vec![1, 2], Hash::new_unique()),
+                &[0],
+                DEFAULT_MAX_SLOTS_AHEAD,
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(signed_votes.votes.len(), 2);
+        let last_hash = signed_votes.last_vote.unwrap().1;
+
+        // Signing the last vote again is fine, with another hash it's a double vote
+        assert_eq!(
+            signed_votes
+                .check_vote(
+                    &Vote::new(vec![2], last_hash),
+                    &[0, 1],
+                    DEFAULT_MAX_SLOTS_AHEAD
+                )
+                .unwrap(),
+            None
+        );
+        assert!(matches!(
+            signed_votes.check_vote(
+                &Vote::new(vec![2], Hash::new_unique()),
+                &[0, 1],
+                DEFAULT_MAX_SLOTS_AHEAD
+            ),
+            Err(ProtectionError::DoubleVote { slot: 2, .. })
+        ));
+        assert!(matches!(
+            signed_votes.check_vote(
+                &Vote::new(vec![1], Hash::new_unique()),
+                &[0],
+                DEFAULT_MAX_SLOTS_AHEAD
+            ),
+            Err(ProtectionError::SlotTooOld {
+                slot: 1,
+                last_signed_slot: 2
+            })
+        ));
+
+        // Slot 2 is locked out until slot 4 and slot 1 until slot 5
+        assert!(matches!(
+            signed_votes.check_vote(
+                &Vote::new(vec![3], Hash::new_unique()),
+                &[0, 1],
+                DEFAULT_MAX_SLOTS_AHEAD
+            ),
+            Err(ProtectionError::LockoutViolation {
+                slot: 3,
+                locked_out_slot: 2,
+                last_locked_out_slot: 4,
+            })
+        ));
+        assert!(matches!(
+            signed_votes.check_vote(
+                &Vote::new(vec![5], Hash::new_unique()),
+                &[0],
+                DEFAULT_MAX_SLOTS_AHEAD
+            ),
+            Err(ProtectionError::LockoutViolation {
+                slot: 5,
+                locked_out_slot: 1,
+                last_locked_out_slot: 5,
+            })
+        ));
+        let switched_votes = signed_votes
+            .check_vote(
+                &Vote::new(vec![6], Hash::new_unique()),
+                &[0],
+                DEFAULT_MAX_SLOTS_AHEAD,
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            switched_votes
+                .votes
+                .iter()
+                .map(|lockout| lockout.slot)
+                .collect::<Vec<_>>(),
+            vec![6]
+        );
+        // Voting on the same fork is fine
+        assert!(signed_votes
+            .check_vote(
+                &Vote::new(vec![3], Hash::new_unique()),
+                &[0, 1, 2],
+                DEFAULT_MAX_SLOTS_AHEAD
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn test_check_vote_root() {
+        let mut signed_votes = SignedVotes::default();
+        let mut ancestors = vec![];
+        for slot in 1..=32 {
+            signed_votes = signed_votes
+                .check_vote(
+                    &Vote::new(vec![slot], Hash::new_unique()),
+                    &ancestors,
+                    DEFAULT_MAX_SLOTS_AHEAD,
+                )
+                .unwrap()
+                .unwrap();
+            ancestors.push(slot);
+        }
+        assert_eq!(signed_votes.root_slot, Some(1));
+
+        // Long after every lockout expired, the votes still have to descend from the root
+        let slot = 1 << 40;
+        assert!(matches!(
+            signed_votes.check_vote(&Vote::new(vec![slot], Hash::new_unique()), &[], Slot::MAX),
+            Err(ProtectionError::RootViolation { root_slot: 1, .. })
+        ));
+        assert!(signed_votes
+            .check_vote(&Vote::new(vec![slot], Hash::new_unique()), &[1], Slot::MAX)
+            .is_ok());
+        // Unless they are too far ahead of the last signed vote
+        assert!(matches!(
+            signed_votes.check_vote(
+                &Vote::new(vec![slot], Hash::new_unique()),
+                &[1],
+                DEFAULT_MAX_SLOTS_AHEAD
+            ),
+            Err(ProtectionError::SlotTooFarAhead {
+                last_signed_slot: 32,
+                ..
+            })
+        ));
+        assert!(signed_votes
+            .check_vote(
+                &Vote::new(vec![32 + DEFAULT_MAX_SLOTS_AHEAD], Hash::new_unique()),
+                &ancestors,
+                DEFAULT_MAX_SLOTS_AHEAD,
+            )
+            .is_ok());
+
+        assert_eq!(
+            signed_votes.locked_slots(),
+            (2..=32).chain(Some(1)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_protection_db_persistence() {
+        let protection_dir = TempDir::new().unwrap();
+        let vote_account = Pubkey::new_unique();
+        let vote = Vote::new(vec![1], Hash::new_unique());
+        {
+            let mut protection_db =
+                ProtectionDb::open(protection_dir.path().to_path_buf(), DEFAULT_MAX_SLOTS_AHEAD)
+                    .unwrap();
+            assert_eq!(
+                protection_db.signed_votes(&vote_account).unwrap(),
+                &SignedVotes::default()
+            );
+            protection_db
+                .record_vote(&vote_account, &vote, &[])
+                .unwrap();
+        }
+
+        let mut protection_db =
+            ProtectionDb::open(protection_dir.path().to_path_buf(), DEFAULT_MAX_SLOTS_AHEAD)
+                .unwrap();
+        assert_eq!(
+            protection_db.signed_votes(&vote_account).unwrap().last_vote,
+            Some((1, vote.hash))
+        );
+        assert!(matches!(
+            protection_db.record_vote(&vote_account, &Vote::new(vec![1], Hash::new_unique()), &[]),
+            Err(ProtectionError::DoubleVote { slot: 1, .. })
+        ));
+        // Every vote account has its own record
+        protection_db
+            .record_vote(
+                &Pubkey::new_unique(),
+                &Vote::new(vec![1], Hash::new_unique()),
+                &[],
+            )
+            .unwrap();
+    }
+}
@@ -0,0 +1,321 @@
+//! The `vote_signer` module defines the protocol spoken with a remote vote signer and the client
+//! that validators use to get their votes signed by one.
+
+use {
+    bincode::{deserialize, serialize},
+    log::*,
+    serde::de::DeserializeOwned,
+    serde_derive::{Deserialize, Serialize},
+    solana_sdk::{
+        clock::Slot,
+        message::Message,
+        pubkey::Pubkey,
+        signature::Signature,
+        slot_history::{Check, SlotHistory},
+        transaction::Transaction,
+    },
+    solana_vote_program::vote_transaction::parse_vote_transaction,
+    std::{
+        fmt,
+        io::{self, Read, Write},
+        net::{SocketAddr, TcpStream},
+        os::unix::net::UnixStream,
+        path::PathBuf,
+        str::FromStr,
+        time::Duration,
+    },
+    thiserror::Error,
+};
+
+/// Requests and responses are prefixed with their length, and never need to be larger than this
+pub const MAX_VOTE_SIGNER_MESSAGE_SIZE: usize = 1024 * 1024;
+
+const VOTE_SIGNER_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Error, Debug)]
+pub enum VoteSignerError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("serialization error: {0}")]
+    Serialize(#[from] bincode::Error),
+
+    #[error("message length {0} exceeds limit")]
+    MessageTooLarge(usize),
+
+    #[error("vote signer refused to sign: {0}")]
+    Refused(String),
+
+    #[error("vote signer returned an invalid signature")]
+    InvalidSignature,
+
+    #[error("invalid vote message: {0}")]
+    InvalidVoteMessage(String),
+}
+
+pub type Result<T> = std::result::Result<T, VoteSignerError>;
+
+/// Address of a vote signer, either `<host>:<port>` or `unix:<path>`
+#[derive(Clone, Debug, PartialEq)]
+pub enum VoteSignerAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl FromStr for VoteSignerAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            Ok(Self::Unix(PathBuf::from(path)))
+        } else {
+            solana_net_utils::parse_host_port(s).map(Self::Tcp)
+        }
+    }
+}
+
+impl fmt::Display for VoteSignerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{}", addr),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// The fork of a voted bank, which the vote signer checks the lockouts of the vote against.
+/// The slot history of the bank reaches further back than its ancestors, down to the votes
+/// signed before the root of a validator started from a newer snapshot.
+#[derive(Clone, Debug)]
+pub struct VoteAncestry {
+    pub ancestors: Vec<Slot>,
+    pub slot_history: SlotHistory,
+}
+
+impl VoteAncestry {
+    pub fn contains(&self, slot: Slot) -> bool {
+        self.ancestors.contains(&slot) || self.slot_history.check(slot) == Check::Found
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum VoteSignerRequest {
+    /// Get the slots that the votes signed for `vote_account` lock it to the fork of
+    LockedSlots { vote_account: Pubkey },
+    /// Sign a message holding a single `Vote` or `VoteSwitch` instruction. `node_signature` is
+    /// the signature of the message by its fee payer, the identity of the requesting node.
+    /// `ancestors` are the slots of the fork of the voted bank which the signer checks the vote
+    /// lockouts against, they must include the locked slots on that fork.
+    SignVote {
+        message: Message,
+        node_signature: Signature,
+        ancestors: Vec<Slot>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum VoteSignerResponse {
+    LockedSlots(Vec<Slot>),
+    Signature(Signature),
+    Refused(String),
+}
+
+pub fn write_message<W: Write + ?Sized, T: serde::Serialize>(
+    stream: &mut W,
+    message: &T,
+) -> Result<()> {
+    let data = serialize(message)?;
+    if data.len() > MAX_VOTE_SIGNER_MESSAGE_SIZE {
+        return Err(VoteSignerError::MessageTooLarge(data.len()));
+    }
+    stream.write_all(&(data.len() as u32).to_le_bytes())?;
+    stream.write_all(&data)?;
+    stream.flush()?;
+    Ok(())
+}
+
+pub fn read_message<R: Read + ?Sized, T: DeserializeOwned>(stream: &mut R) -> Result<T> {
+    let mut length = [0u8; 4];
+    stream.read_exact(&mut length)?;
+    let length = u32::from_le_bytes(length) as usize;
+    if length > MAX_VOTE_SIGNER_MESSAGE_SIZE {
+        return Err(VoteSignerError::MessageTooLarge(length));
+    }
+    let mut data = vec![0u8; length];
+    stream.read_exact(&mut data)?;
+    Ok(deserialize(&data)?)
+}
+
+trait Stream: Read + Write {}
+
+impl<S: Read + Write> Stream for S {}
+
+fn send_request(
+    stream: &mut dyn Stream,
+    request: &VoteSignerRequest,
+) -> Result<VoteSignerResponse> {
+    write_message(stream, request)?;
+    read_message(stream)
+}
+
+/// Client of a remote vote signer
+pub struct RemoteVoteSigner {
+    addr: VoteSignerAddr,
+    timeout: Duration,
+}
+
+impl RemoteVoteSigner {
+    pub fn new(addr: VoteSignerAddr) -> Self {
+        Self {
+            addr,
+            timeout: VOTE_SIGNER_TIMEOUT,
+        }
+    }
+
+    pub fn addr(&self) -> &VoteSignerAddr {
+        &self.addr
+    }
+
+    fn connect(&self) -> Result<Box<dyn Stream>> {
+        Ok(match &self.addr {
+            VoteSignerAddr::Tcp(addr) => {
+                let stream = TcpStream::connect_timeout(addr, self.timeout)?;
+                stream.set_read_timeout(Some(self.timeout))?;
+                stream.set_write_timeout(Some(self.timeout))?;
+                Box::new(stream)
+            }
+            VoteSignerAddr::Unix(path) => {
+                let stream = UnixStream::connect(path)?;
+                stream.set_read_timeout(Some(self.timeout))?;
+                stream.set_write_timeout(Some(self.timeout))?;
+                Box::new(stream)
+            }
+        })
+    }
+
+    fn locked_slots(stream: &mut dyn Stream, vote_account: &Pubkey) -> Result<Vec<Slot>> {
+        let request = VoteSignerRequest::LockedSlots {
+            vote_account: *vote_account,
+        };
+        match send_request(stream, &request)? {
+            VoteSignerResponse::LockedSlots(slots) => Ok(slots),
+            VoteSignerResponse::Refused(reason) => Err(VoteSignerError::Refused(reason)),
+            response => Err(VoteSignerError::InvalidVoteMessage(format!(
+                "unexpected response {:?}",
+                response
+            ))),
+        }
+    }
+
+    fn sign_vote(
+        stream: &mut dyn Stream,
+        message: &Message,
+        node_signature: Signature,
+        ancestors: Vec<Slot>,
+    ) -> Result<Signature> {
+        let request = VoteSignerRequest::SignVote {
+            message: message.clone(),
+            node_signature,
+            ancestors,
+        };
+        match send_request(stream, &request)? {
+            VoteSignerResponse::Signature(signature) => Ok(signature),
+            VoteSignerResponse::Refused(reason) => Err(VoteSignerError::Refused(reason)),
+            response => Err(VoteSignerError::InvalidVoteMessage(format!(
+                "unexpected response {:?}",
+                response
+            ))),
+        }
+    }
+
+    /// Fill in the vote authority signature missing from a vote transaction signed by the node.
+    /// The signer is told which of the slots its signed votes are locked to are on `ancestry`.
+    pub fn sign_vote_transaction(
+        &self,
+        transaction: &mut Transaction,
+        ancestry: &VoteAncestry,
+    ) -> Result<()> {
+        let position = transaction
+            .signatures
+            .iter()
+            .position(|signature| *signature == Signature::default())
+            .ok_or_else(|| {
+                VoteSignerError::InvalidVoteMessage("transaction is fully signed".to_string())
+            })?;
+        let node_signature = *transaction.signatures.first().ok_or_else(|| {
+            VoteSignerError::InvalidVoteMessage("transaction has no signatures".to_string())
+        })?;
+        let (vote_account, ..) = parse_vote_transaction(transaction).ok_or_else(|| {
+            VoteSignerError::InvalidVoteMessage("not a vote transaction".to_string())
+        })?;
+
+        let mut stream = self.connect()?;
+        let mut ancestors = ancestry.ancestors.clone();
+        ancestors.extend(
+            Self::locked_slots(stream.as_mut(), &vote_account)?
+                .into_iter()
+                .filter(|slot| ancestry.contains(*slot)),
+        );
+        let signature = Self::sign_vote(
+            stream.as_mut(),
+            &transaction.message,
+            node_signature,
+            ancestors,
+        )?;
+        let signer = transaction.message.account_keys[position];
+        if !signature.verify(signer.as_ref(), &transaction.message_data()) {
+            warn!(
+                "{} signed a vote of {} with the wrong key",
+                self.addr, signer
+            );
+            return Err(VoteSignerError::InvalidSignature);
+        }
+        transaction.signatures[position] = signature;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vote_signer_addr() {
+        let addr: VoteSignerAddr = "127.0.0.1:8000".parse().unwrap();
+        assert_eq!(addr, VoteSignerAddr::Tcp("127.0.0.1:8000".parse().unwrap()));
+        assert_eq!(addr.to_string(), "127.0.0.1:8000");
+
+        let addr: VoteSignerAddr = "unix:/tmp/vote-signer.sock".parse().unwrap();
+        assert_eq!(
+            addr,
+            VoteSignerAddr::Unix(PathBuf::from("/tmp/vote-signer.sock"))
+        );
+        assert_eq!(addr.to_string(), "unix:/tmp/vote-signer.sock");
+
+        assert!("vote-signer".parse::<VoteSignerAddr>().is_err());
+    }
+
+    #[test]
+    fn test_message_framing() {
+        let request = VoteSignerRequest::SignVote {
+            message: Message::default(),
+            node_signature: Signature::default(),
+            ancestors: vec![1, 2, 3],
+        };
+        let mut buffer = vec![];
+        write_message(&mut buffer, &request).unwrap();
+        assert_eq!(
+            read_message::<_, VoteSignerRequest>(&mut buffer.as_slice()).unwrap(),
+            request
+        );
+
+        let mut buffer = ((MAX_VOTE_SIGNER_MESSAGE_SIZE + 1) as u32)
+            .to_le_bytes()
+            .to_vec();
+        buffer.resize(MAX_VOTE_SIGNER_MESSAGE_SIZE + 5, 0);
+        assert!(matches!(
+            read_message::<_, VoteSignerRequest>(&mut buffer.as_slice()),
+            Err(VoteSignerError::MessageTooLarge(_))
+        ));
+    }
+}
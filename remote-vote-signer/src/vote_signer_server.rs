@@ -0,0 +1,423 @@
+//! The `vote_signer_server` module serves the vote signing requests of validators with the
+//! authorized voter keypair, refusing the votes that its `ProtectionDb` rejects. Only the votes
+//! of authorized nodes, which must have signed the vote transaction, are signed.
+
+use {
+    crate::{
+        protection_db::ProtectionDb,
+        vote_signer::{
+            read_message, write_message, VoteSignerAddr, VoteSignerError, VoteSignerRequest,
+            VoteSignerResponse,
+        },
+    },
+    log::*,
+    solana_sdk::{
+        clock::Slot,
+        message::Message,
+        program_utils::limited_deserialize,
+        pubkey::Pubkey,
+        signature::{Keypair, Signature, Signer},
+    },
+    solana_vote_program::{vote_instruction::VoteInstruction, vote_state::Vote},
+    std::{
+        collections::HashSet,
+        fs,
+        io::{self, Read, Write},
+        net::TcpListener,
+        os::unix::net::UnixListener,
+        sync::{Arc, Mutex},
+        thread,
+    },
+};
+
+// Index of the vote authority in the accounts of a `Vote` or `VoteSwitch` instruction
+const VOTE_AUTHORITY_INDEX: usize = 3;
+
+pub struct VoteSignerServer {
+    keypair: Keypair,
+    authorized_nodes: HashSet<Pubkey>,
+    protection_db: Mutex<ProtectionDb>,
+}
+
+impl VoteSignerServer {
+    pub fn new(
+        keypair: Keypair,
+        authorized_nodes: HashSet<Pubkey>,
+        protection_db: ProtectionDb,
+    ) -> Self {
+        Self {
+            keypair,
+            authorized_nodes,
+            protection_db: Mutex::new(protection_db),
+        }
+    }
+
+    pub fn pubkey(&self) -> Pubkey {
+        self.keypair.pubkey()
+    }
+
+    pub fn process_request(&self, request: VoteSignerRequest) -> VoteSignerResponse {
+        match request {
+            VoteSignerRequest::LockedSlots { vote_account } => {
+                let mut protection_db = self.protection_db.lock().unwrap();
+                match protection_db.signed_votes(&vote_account) {
+                    Ok(signed_votes) => {
+                        VoteSignerResponse::LockedSlots(signed_votes.locked_slots())
+                    }
+                    Err(err) => VoteSignerResponse::Refused(err.to_string()),
+                }
+            }
+            VoteSignerRequest::SignVote {
+                message,
+                node_signature,
+                ancestors,
+            } => match self.sign_vote(&message, &node_signature, &ancestors) {
+                Ok(signature) => VoteSignerResponse::Signature(signature),
+                Err(err) => {
+                    warn!("Refused to sign vote: {}", err);
+                    VoteSignerResponse::Refused(err.to_string())
+                }
+            },
+        }
+    }
+
+    /// Checks that the fee payer of `message` is an authorized node, which signed it
+    fn verify_node(&self, message: &Message, node_signature: &Signature) -> Result<(), String> {
+        let node = message
+            .account_keys
+            .first()
+            .ok_or("message has no fee payer")?;
+        if !self.authorized_nodes.contains(node) {
+            return Err(format!("{} is not an authorized node", node));
+        }
+        if !node_signature.verify(node.as_ref(), &message.serialize()) {
+            return Err(format!("invalid signature of node {}", node));
+        }
+        Ok(())
+    }
+
+    /// Returns the vote account and the vote of `message`, as long as it is nothing but a vote
+    /// authorized by the keypair of the server
+    fn parse_vote_message(&self, message: &Message) -> Result<(Pubkey, Vote), String> {
+        let pubkey = self.keypair.pubkey();
+        if !message
+            .account_keys
+            .iter()
+            .take(message.header.num_required_signatures as usize)
+            .any(|key| *key == pubkey)
+        {
+            return Err(format!("{} is not a signer of the message", pubkey));
+        }
+        let instruction = match message.instructions.as_slice() {
+            [instruction] => instruction,
+            _ => return Err("message must have a single instruction".to_string()),
+        };
+        let key = |index: usize| {
+            instruction
+                .accounts
+                .get(index)
+                .and_then(|index| message.account_keys.get(*index as usize))
+        };
+        if message
+            .account_keys
+            .get(instruction.program_id_index as usize)
+            != Some(&solana_vote_program::id())
+        {
+            return Err("not a vote program instruction".to_string());
+        }
+        let vote = match limited_deserialize(&instruction.data) {
+            Ok(VoteInstruction::Vote(vote)) | Ok(VoteInstruction::VoteSwitch(vote, _)) => vote,
+            _ => return Err("not a vote instruction".to_string()),
+        };
+        if key(VOTE_AUTHORITY_INDEX) != Some(&pubkey) {
+            return Err(format!("{} is not the vote authority", pubkey));
+        }
+        let vote_account = key(0).ok_or("missing vote account")?;
+        Ok((*vote_account, vote))
+    }
+
+    fn sign_vote(
+        &self,
+        message: &Message,
+        node_signature: &Signature,
+        ancestors: &[Slot],
+    ) -> Result<Signature, String> {
+        self.verify_node(message, node_signature)?;
+        let (vote_account, vote) = self.parse_vote_message(message)?;
+        // The lock is held until the vote is signed so that concurrent requests are checked
+        // against each other
+        let mut protection_db = self.protection_db.lock().unwrap();
+        protection_db
+            .record_vote(&vote_account, &vote, ancestors)
+            .map_err(|err| err.to_string())?;
+        debug!(
+            "Signing vote of {} for slots {:?}",
+            vote_account, vote.slots
+        );
+        Ok(self.keypair.sign_message(&message.serialize()))
+    }
+
+    fn handle_connection<S: Read + Write>(&self, stream: &mut S) {
+        loop {
+            let request = match read_message(stream) {
+                Ok(request) => request,
+                Err(VoteSignerError::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    return;
+                }
+                Err(err) => {
+                    warn!("Failed to read vote signer request: {}", err);
+                    return;
+                }
+            };
+            let response = self.process_request(request);
+            if let Err(err) = write_message(stream, &response) {
+                warn!("Failed to write vote signer response: {}", err);
+                return;
+            }
+        }
+    }
+}
+
+pub fn serve_tcp(server: Arc<VoteSignerServer>, listener: TcpListener) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        let server = server.clone();
+        thread::Builder::new()
+            .name("solana-vote-signer-connection".to_string())
+            .spawn(move || server.handle_connection(&mut stream))?;
+    }
+    Ok(())
+}
+
+pub fn serve_unix(server: Arc<VoteSignerServer>, listener: UnixListener) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        let server = server.clone();
+        thread::Builder::new()
+            .name("solana-vote-signer-connection".to_string())
+            .spawn(move || server.handle_connection(&mut stream))?;
+    }
+    Ok(())
+}
+
+/// Serve the vote signing requests sent to `addr` until an error occurs. TCP addresses must be
+/// loopback ones unless `allow_non_loopback`, the requests are not encrypted.
+pub fn run_vote_signer_server(
+    server: Arc<VoteSignerServer>,
+    addr: &VoteSignerAddr,
+    allow_non_loopback: bool,
+) -> io::Result<()> {
+    if let VoteSignerAddr::Tcp(addr) = addr {
+        if !allow_non_loopback && !addr.ip().is_loopback() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("refusing to listen on non-loopback address {}", addr),
+            ));
+        }
+    }
+    info!("Vote signer for {} listening on {}", server.pubkey(), addr);
+    match addr {
+        VoteSignerAddr::Tcp(addr) => serve_tcp(server, TcpListener::bind(addr)?),
+        VoteSignerAddr::Unix(path) => {
+            // Remove the socket left behind by a previous run
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+            serve_unix(server, UnixListener::bind(path)?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            protection_db::DEFAULT_MAX_SLOTS_AHEAD,
+            vote_signer::{RemoteVoteSigner, VoteAncestry},
+        },
+        solana_sdk::{hash::Hash, slot_history::SlotHistory, transaction::Transaction},
+        solana_vote_program::vote_transaction,
+        tempfile::TempDir,
+    };
+
+    fn new_vote_transaction(
+        slots: Vec<Slot>,
+        node_keypair: &Keypair,
+        vote_account: &Pubkey,
+        authorized_voter: &Pubkey,
+    ) -> Transaction {
+        let vote_ix = solana_vote_program::vote_instruction::vote(
+            vote_account,
+            authorized_voter,
+            Vote::new(slots, Hash::new_unique()),
+        );
+        let mut transaction = Transaction::new_with_payer(&[vote_ix], Some(&node_keypair.pubkey()));
+        transaction.partial_sign(&[node_keypair], Hash::default());
+        transaction
+    }
+
+    fn start_server(
+        authorized_voter: Keypair,
+        authorized_nodes: HashSet<Pubkey>,
+        protection_dir: &TempDir,
+    ) -> RemoteVoteSigner {
+        let server = Arc::new(VoteSignerServer::new(
+            authorized_voter,
+            authorized_nodes,
+            ProtectionDb::open(protection_dir.path().to_path_buf(), DEFAULT_MAX_SLOTS_AHEAD)
+                .unwrap(),
+        ));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = VoteSignerAddr::Tcp(listener.local_addr().unwrap());
+        thread::spawn(move || serve_tcp(server, listener));
+        RemoteVoteSigner::new(addr)
+    }
+
+    fn new_ancestry(ancestors: Vec<Slot>, slot_history: &[Slot]) -> VoteAncestry {
+        let mut ancestry = VoteAncestry {
+            ancestors,
+            slot_history: SlotHistory::default(),
+        };
+        for slot in slot_history {
+            ancestry.slot_history.add(*slot);
+        }
+        ancestry
+    }
+
+    #[test]
+    fn test_remote_vote_signer() {
+        solana_logger::setup();
+        let protection_dir = TempDir::new().unwrap();
+        let authorized_voter = Keypair::new();
+        let authorized_voter_pubkey = authorized_voter.pubkey();
+        let node_keypair = Keypair::new();
+        let signer = start_server(
+            authorized_voter,
+            vec![node_keypair.pubkey()].into_iter().collect(),
+            &protection_dir,
+        );
+
+        let vote_account = Pubkey::new_unique();
+        let mut transaction = new_vote_transaction(
+            vec![1],
+            &node_keypair,
+            &vote_account,
+            &authorized_voter_pubkey,
+        );
+        signer
+            .sign_vote_transaction(&mut transaction, &new_ancestry(vec![0], &[]))
+            .unwrap();
+        assert!(transaction.verify().is_ok());
+        assert_eq!(
+            vote_transaction::parse_vote_transaction(&transaction)
+                .unwrap()
+                .0,
+            vote_account
+        );
+
+        // Slot 1 is locked out at slot 2
+        let mut transaction = new_vote_transaction(
+            vec![2],
+            &node_keypair,
+            &vote_account,
+            &authorized_voter_pubkey,
+        );
+        assert!(matches!(
+            signer.sign_vote_transaction(&mut transaction, &new_ancestry(vec![0], &[])),
+            Err(VoteSignerError::Refused(_))
+        ));
+        signer
+            .sign_vote_transaction(&mut transaction, &new_ancestry(vec![0, 1], &[]))
+            .unwrap();
+
+        // Votes of other authorities are refused
+        let mut transaction =
+            new_vote_transaction(vec![3], &node_keypair, &vote_account, &Pubkey::new_unique());
+        assert!(matches!(
+            signer.sign_vote_transaction(&mut transaction, &new_ancestry(vec![0, 1, 2], &[])),
+            Err(VoteSignerError::Refused(_))
+        ));
+
+        // So are the votes of unauthorized nodes
+        let mut transaction = new_vote_transaction(
+            vec![3],
+            &Keypair::new(),
+            &vote_account,
+            &authorized_voter_pubkey,
+        );
+        assert!(matches!(
+            signer.sign_vote_transaction(&mut transaction, &new_ancestry(vec![0, 1, 2], &[])),
+            Err(VoteSignerError::Refused(_))
+        ));
+    }
+
+    #[test]
+    fn test_remote_vote_signer_failover() {
+        solana_logger::setup();
+        let protection_dir = TempDir::new().unwrap();
+        let authorized_voter = Keypair::new();
+        let authorized_voter_pubkey = authorized_voter.pubkey();
+        let node_keypair = Keypair::new();
+        let failover_node_keypair = Keypair::new();
+        let signer = start_server(
+            authorized_voter,
+            vec![node_keypair.pubkey(), failover_node_keypair.pubkey()]
+                .into_iter()
+                .collect(),
+            &protection_dir,
+        );
+
+        let vote_account = Pubkey::new_unique();
+        for (slot, ancestors) in vec![(1, vec![0]), (2, vec![0, 1])] {
+            let mut transaction = new_vote_transaction(
+                vec![slot],
+                &node_keypair,
+                &vote_account,
+                &authorized_voter_pubkey,
+            );
+            signer
+                .sign_vote_transaction(&mut transaction, &new_ancestry(ancestors, &[]))
+                .unwrap();
+        }
+
+        // The failover node started from a snapshot of slot 3, slots 1 and 2 are only in the
+        // slot history of its banks, which must show they are on the fork of the vote
+        let vote_failover = |slot_history: &[Slot]| {
+            let mut transaction = new_vote_transaction(
+                vec![4],
+                &failover_node_keypair,
+                &vote_account,
+                &authorized_voter_pubkey,
+            );
+            signer.sign_vote_transaction(&mut transaction, &new_ancestry(vec![3], slot_history))
+        };
+        assert!(matches!(
+            vote_failover(&[]),
+            Err(VoteSignerError::Refused(_))
+        ));
+        assert!(matches!(
+            vote_failover(&[1, 3, 4]),
+            Err(VoteSignerError::Refused(_))
+        ));
+        vote_failover(&[1, 2, 3, 4]).unwrap();
+    }
+
+    #[test]
+    fn test_run_vote_signer_server_non_loopback() {
+        let protection_dir = TempDir::new().unwrap();
+        let server = Arc::new(VoteSignerServer::new(
+            Keypair::new(),
+            HashSet::new(),
+            ProtectionDb::open(protection_dir.path().to_path_buf(), DEFAULT_MAX_SLOTS_AHEAD)
+                .unwrap(),
+        ));
+        let addr = VoteSignerAddr::Tcp("0.0.0.0:0".parse().unwrap());
+        assert_eq!(
+            run_vote_signer_server(server, &addr, false)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+}